@@ -0,0 +1,191 @@
+// Benchmarks of the javd binary on generated inputs. The crate is a binary, so these time whole
+// runs. JAVD_BIN points them at another build to compare against it, and running them again with
+// --features mmap compares mapped inputs with read ones.
+use std::{
+    env, fs,
+    io::Write,
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+fn javd(args: &[&PathBuf], sub: &str) {
+    let bin = env::var("JAVD_BIN").unwrap_or_else(|_| env!("CARGO_BIN_EXE_javd").to_owned());
+    let status = Command::new(bin)
+        .arg(sub)
+        .args(args)
+        .stdout(Stdio::null())
+        .status()
+        .expect("failed to run javd");
+    assert!(status.success(), "javd {} failed", sub);
+}
+
+fn tmp(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name)
+}
+
+// A class `A extends java/lang/Object` whose pool has `strings` Utf8 entries each with a String
+// entry pointing at it, 2 * strings + 4 entries in all.
+fn big_pool_class(strings: u16) -> Vec<u8> {
+    let mut b = Vec::new();
+    b.extend(0xCAFEBABEu32.to_be_bytes());
+    b.extend(0u16.to_be_bytes());
+    b.extend(52u16.to_be_bytes());
+    b.extend((5 + 2 * strings).to_be_bytes());
+    // #1 A, #2 Class A, #3 java/lang/Object, #4 Class java/lang/Object
+    for (name, index) in [("A", 1u16), ("java/lang/Object", 3)] {
+        b.push(1);
+        b.extend((name.len() as u16).to_be_bytes());
+        b.extend(name.as_bytes());
+        b.push(7);
+        b.extend(index.to_be_bytes());
+    }
+    for i in 0..strings {
+        let s = format!("string constant number {}", i);
+        b.push(1);
+        b.extend((s.len() as u16).to_be_bytes());
+        b.extend(s.as_bytes());
+    }
+    for i in 0..strings {
+        b.push(8);
+        b.extend((5 + i).to_be_bytes());
+    }
+    b.extend(0x0021u16.to_be_bytes()); // access flags
+    b.extend(2u16.to_be_bytes()); // this_class
+    b.extend(4u16.to_be_bytes()); // super_class
+    b.extend([0; 8]); // no interfaces, fields, methods or attributes
+    b
+}
+
+fn pool_20k(c: &mut Criterion) {
+    let class = tmp("pool_20k.class");
+    let json = tmp("pool_20k.json");
+    let out = tmp("pool_20k.out.class");
+    fs::write(&class, big_pool_class(10_000)).unwrap();
+    javd(&[&class, &json], "json");
+
+    let mut group = c.benchmark_group("pool_20k");
+    group.bench_function("json", |b| b.iter(|| javd(&[&class, &json], "json")));
+    group.bench_function("class", |b| b.iter(|| javd(&[&json, &out], "class")));
+    group.bench_function("pool", |b| b.iter(|| javd(&[&class], "pool")));
+    group.finish();
+}
+
+// A class A with `methods` static methods m()V, each pushing and popping a constant `pairs` times,
+// so that writing it back is mostly the Code attributes and their instructions.
+fn big_code_class(methods: u16, pairs: usize) -> Vec<u8> {
+    let mut b = Vec::new();
+    b.extend(0xCAFEBABEu32.to_be_bytes());
+    b.extend(0u16.to_be_bytes());
+    b.extend(52u16.to_be_bytes());
+    b.extend(8u16.to_be_bytes());
+    // #1 A, #2 Class A, #3 java/lang/Object, #4 Class java/lang/Object, #5 m, #6 ()V, #7 Code
+    for (name, index) in [("A", 1u16), ("java/lang/Object", 3)] {
+        b.push(1);
+        b.extend((name.len() as u16).to_be_bytes());
+        b.extend(name.as_bytes());
+        b.push(7);
+        b.extend(index.to_be_bytes());
+    }
+    for s in ["m", "()V", "Code"] {
+        b.push(1);
+        b.extend((s.len() as u16).to_be_bytes());
+        b.extend(s.as_bytes());
+    }
+    b.extend(0x0021u16.to_be_bytes()); // access flags
+    b.extend(2u16.to_be_bytes()); // this_class
+    b.extend(4u16.to_be_bytes()); // super_class
+    b.extend([0; 4]); // no interfaces or fields
+    b.extend(methods.to_be_bytes());
+    // iconst_1, pop, then return
+    let mut code = [0x04, 0x57].repeat(pairs);
+    code.push(0xB1);
+    for _ in 0..methods {
+        b.extend(0x0009u16.to_be_bytes()); // public static
+        b.extend(5u16.to_be_bytes());
+        b.extend(6u16.to_be_bytes());
+        b.extend(1u16.to_be_bytes());
+        b.extend(7u16.to_be_bytes());
+        b.extend((12 + code.len() as u32).to_be_bytes());
+        b.extend(1u16.to_be_bytes()); // max_stack
+        b.extend(0u16.to_be_bytes()); // max_locals
+        b.extend((code.len() as u32).to_be_bytes());
+        b.extend(&code);
+        b.extend([0; 4]); // no handlers or attributes
+    }
+    b.extend(0u16.to_be_bytes()); // attributes
+    b
+}
+
+// writing a class back out, which times the json parsing along with the serialization
+fn code_500k(c: &mut Criterion) {
+    let class = tmp("code_500k.class");
+    let json = tmp("code_500k.json");
+    let out = tmp("code_500k.out.class");
+    fs::write(&class, big_code_class(500, 500)).unwrap();
+    javd(&[&class, &json], "json");
+
+    let mut group = c.benchmark_group("code_500k");
+    group.sample_size(10);
+    group.bench_function("class", |b| b.iter(|| javd(&[&json, &out], "class")));
+    group.finish();
+}
+
+// A jar holding copies of every fixture class, compressed like the jars of a build, or stored
+// along with a resource of resource_mb megabytes.
+fn fixtures_jar(name: &str, copies: usize, resource_mb: usize) -> PathBuf {
+    let jar = tmp(name);
+    let fixtures = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let mut classes = Vec::new();
+    for entry in fs::read_dir(fixtures).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension() == Some("class".as_ref()) {
+            classes.push(fs::read(path).unwrap());
+        }
+    }
+    let mut options = zip::write::FileOptions::default();
+    if resource_mb > 0 {
+        options = options.compression_method(zip::CompressionMethod::Stored);
+    }
+    let mut zip = zip::ZipWriter::new(fs::File::create(&jar).unwrap());
+    for i in 0..copies {
+        for (j, class) in classes.iter().enumerate() {
+            zip.start_file(format!("copy{}/Class{}.class", i, j), options)
+                .unwrap();
+            zip.write_all(class).unwrap();
+        }
+    }
+    if resource_mb > 0 {
+        zip.start_file("resource.bin", options).unwrap();
+        let megabyte = vec![0x5a; 1 << 20];
+        for _ in 0..resource_mb {
+            zip.write_all(&megabyte).unwrap();
+        }
+    }
+    zip.finish().unwrap();
+    jar
+}
+
+// the scanning subcommands, which read classes with the borrowed parser
+fn jar_scan(c: &mut Criterion) {
+    let jar = fixtures_jar("fixtures.jar", 500, 0);
+    let mut group = c.benchmark_group("jar_scan");
+    group.sample_size(20);
+    group.bench_function("stats", |b| b.iter(|| javd(&[&jar], "stats")));
+    group.bench_function("strings", |b| b.iter(|| javd(&[&jar], "strings")));
+    group.finish();
+}
+
+// A 300 MB stored jar, mostly a resource that a mapping never reads while fs::read copies it.
+fn large_jar(c: &mut Criterion) {
+    let jar = fixtures_jar("large.jar", 500, 300);
+    let mut group = c.benchmark_group("large_jar");
+    group.sample_size(10);
+    group.bench_function("strings", |b| b.iter(|| javd(&[&jar], "strings")));
+    group.bench_function("stats", |b| b.iter(|| javd(&[&jar], "stats")));
+    group.finish();
+}
+
+criterion_group!(benches, pool_20k, code_500k, jar_scan, large_jar);
+criterion_main!(benches);
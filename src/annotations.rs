@@ -0,0 +1,179 @@
+use crate::{Annotation, Attribute, AttributeInfo, ConstantPool, Field, JavaClass, Method};
+
+// the annotations of the RuntimeVisibleAnnotations then RuntimeInvisibleAnnotations attributes
+fn annotations(attributes: &[Attribute]) -> Vec<&Annotation> {
+    let visible = attributes.iter().filter_map(|a| match &a.info {
+        AttributeInfo::RuntimeVisibleAnnotations(annotations) => Some(annotations),
+        _ => None,
+    });
+    let invisible = attributes.iter().filter_map(|a| match &a.info {
+        AttributeInfo::RuntimeInvisibleAnnotations(annotations) => Some(annotations),
+        _ => None,
+    });
+    visible.chain(invisible).flatten().collect()
+}
+
+// whether descriptor is the one of the class named binary_name, like Lcom/example/Foo; for
+// com.example.Foo
+fn is_descriptor_of(descriptor: &str, binary_name: &str) -> bool {
+    let Some(name) = descriptor
+        .strip_prefix('L')
+        .and_then(|d| d.strip_suffix(';'))
+    else {
+        return false;
+    };
+    name.len() == binary_name.len()
+        && name
+            .bytes()
+            .zip(binary_name.bytes())
+            .all(|(a, b)| a == if b == b'.' { b'/' } else { b })
+}
+
+// whether one of the annotations is of the annotation interface named type_binary_name
+fn has_annotation(attributes: &[Attribute], type_binary_name: &str, cp: &ConstantPool) -> bool {
+    annotations(attributes).into_iter().any(|a| {
+        a.type_index
+            .resolve(cp)
+            .is_ok_and(|d| is_descriptor_of(d, type_binary_name))
+    })
+}
+
+impl JavaClass {
+    // The annotations of the class, visible ones first. Those of its fields, methods and their
+    // parameters aren't included.
+    pub fn annotations(&self) -> Vec<&Annotation> {
+        annotations(&self.attributes)
+    }
+    // Whether the class is annotated with the annotation interface named type_binary_name, like
+    // com.example.Foo (or com.example.Outer$Foo when nested).
+    pub fn has_annotation(&self, type_binary_name: &str) -> bool {
+        has_annotation(&self.attributes, type_binary_name, &self.constant_pool)
+    }
+}
+
+impl Method {
+    // the annotations of the method, visible ones first, without those of its parameters
+    pub fn annotations(&self) -> Vec<&Annotation> {
+        annotations(&self.attributes)
+    }
+    // like JavaClass::has_annotation, cp is the constant pool of the class of the method
+    pub fn has_annotation(&self, type_binary_name: &str, cp: &ConstantPool) -> bool {
+        has_annotation(&self.attributes, type_binary_name, cp)
+    }
+}
+
+impl Field {
+    // the annotations of the field, visible ones first
+    pub fn annotations(&self) -> Vec<&Annotation> {
+        annotations(&self.attributes)
+    }
+    // like JavaClass::has_annotation, cp is the constant pool of the class of the field
+    pub fn has_annotation(&self, type_binary_name: &str, cp: &ConstantPool) -> bool {
+        has_annotation(&self.attributes, type_binary_name, cp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{rename::find_or_push_utf8, tests::fixture};
+
+    // the type descriptors of annotations
+    fn types<'a>(annotations: Vec<&Annotation>, cp: &'a ConstantPool) -> Vec<&'a str> {
+        let types = annotations
+            .into_iter()
+            .map(|a| a.type_index.resolve(cp).unwrap());
+        types.collect()
+    }
+
+    fn method<'a>(class: &'a JavaClass, name: &str) -> &'a Method {
+        let cp = &class.constant_pool;
+        let mut methods = class.methods.iter();
+        methods
+            .find(|m| m.name_index.resolve(cp).ok() == Some(name))
+            .unwrap()
+    }
+
+    // moves the annotations of the class to a RuntimeInvisibleAnnotations attribute, followed by
+    // a RuntimeVisibleAnnotations one holding an annotation of type_descriptor
+    fn make_invisible(class: &mut JavaClass, type_descriptor: &str) {
+        let cp = &mut class.constant_pool;
+        let type_index = find_or_push_utf8(cp, type_descriptor).unwrap();
+        let name_index = find_or_push_utf8(cp, "RuntimeVisibleAnnotations").unwrap();
+        let attribute = class
+            .attributes
+            .iter_mut()
+            .find(|a| matches!(a.info, AttributeInfo::RuntimeVisibleAnnotations(_)))
+            .unwrap();
+        let AttributeInfo::RuntimeVisibleAnnotations(annotations) = &attribute.info else {
+            unreachable!();
+        };
+        let visible = Annotation {
+            type_index,
+            element_value_pairs: Vec::new(),
+        };
+        attribute.info = AttributeInfo::RuntimeInvisibleAnnotations(annotations.clone());
+        class.attributes.push(Attribute {
+            name_index,
+            info: AttributeInfo::RuntimeVisibleAnnotations(vec![visible]),
+        });
+    }
+
+    #[test]
+    fn annotations() {
+        let class = fixture("Annotated");
+        let cp = &class.constant_pool;
+        assert_eq!(types(class.annotations(), cp), ["LConfig;"]);
+        assert_eq!(
+            types(class.fields[0].annotations(), cp),
+            ["Ljava/lang/Deprecated;"]
+        );
+        // without the one of the parameter
+        let of = method(&class, "of");
+        assert_eq!(types(of.annotations(), cp), ["Ljava/lang/SafeVarargs;"]);
+        assert!(method(&class, "<init>").annotations().is_empty());
+
+        // type annotations aren't there
+        let class = fixture("TypeAnnotated");
+        assert!(class.annotations().is_empty());
+        assert!(class.fields[0].annotations().is_empty());
+    }
+
+    #[test]
+    fn visible_first() {
+        let mut class = fixture("Annotated");
+        make_invisible(&mut class, "Lcom/example/Visible;");
+        let cp = &class.constant_pool;
+        assert_eq!(
+            types(class.annotations(), cp),
+            ["Lcom/example/Visible;", "LConfig;"]
+        );
+    }
+
+    #[test]
+    fn has_annotation() {
+        let class = fixture("Annotated");
+        let cp = &class.constant_pool;
+        assert!(class.has_annotation("Config"));
+        // whole names only
+        assert!(!class.has_annotation("Conf"));
+        assert!(!class.has_annotation("Configs"));
+        assert!(!class.has_annotation("LConfig;"));
+
+        let field = &class.fields[0];
+        assert!(field.has_annotation("java.lang.Deprecated", cp));
+        assert!(!field.has_annotation("java.lang.SafeVarargs", cp));
+        let of = method(&class, "of");
+        assert!(of.has_annotation("java.lang.SafeVarargs", cp));
+        // only on its parameter
+        assert!(!of.has_annotation("java.lang.Deprecated", cp));
+
+        // invisible annotations, and nested annotation interfaces
+        let mut class = fixture("Annotated");
+        make_invisible(&mut class, "Lcom/example/Outer$Inner;");
+        assert!(class.has_annotation("Config"));
+        assert!(class.has_annotation("com.example.Outer$Inner"));
+        assert!(!class.has_annotation("com.example.Outer.Inner"));
+        assert!(!class.has_annotation("com.example.Outer"));
+    }
+}
@@ -0,0 +1,436 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+};
+
+use crate::{AccessFlags, AttributeInfo, ConstantPool, JavaClass, Method, WrongEntry};
+
+// the flags of a class, field or method as written in java, which decides what the shared bits
+// mean (SUPER and SYNCHRONIZED, VOLATILE and BRIDGE, TRANSIENT and VARARGS)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MemberKind {
+    Class,
+    Field,
+    Method,
+}
+
+impl MemberKind {
+    fn name(self) -> &'static str {
+        match self {
+            MemberKind::Class => "class",
+            MemberKind::Field => "field",
+            MemberKind::Method => "method",
+        }
+    }
+}
+
+// the flags a kind of member can have, with their names in the order javap prints them
+fn flag_table(kind: MemberKind) -> &'static [(AccessFlags, &'static str)] {
+    match kind {
+        MemberKind::Class => &[
+            (AccessFlags::PUBLIC, "public"),
+            (AccessFlags::FINAL, "final"),
+            (AccessFlags::SUPER, "super"),
+            (AccessFlags::INTERFACE, "interface"),
+            (AccessFlags::ABSTRACT, "abstract"),
+            (AccessFlags::SYNTHETIC, "synthetic"),
+            (AccessFlags::ANNOTATION, "annotation"),
+            (AccessFlags::ENUM, "enum"),
+            (AccessFlags::MODULE, "module"),
+        ],
+        MemberKind::Field => &[
+            (AccessFlags::PUBLIC, "public"),
+            (AccessFlags::PRIVATE, "private"),
+            (AccessFlags::PROTECTED, "protected"),
+            (AccessFlags::STATIC, "static"),
+            (AccessFlags::FINAL, "final"),
+            (AccessFlags::VOLATILE, "volatile"),
+            (AccessFlags::TRANSIENT, "transient"),
+            (AccessFlags::SYNTHETIC, "synthetic"),
+            (AccessFlags::ENUM, "enum"),
+        ],
+        MemberKind::Method => &[
+            (AccessFlags::PUBLIC, "public"),
+            (AccessFlags::PRIVATE, "private"),
+            (AccessFlags::PROTECTED, "protected"),
+            (AccessFlags::STATIC, "static"),
+            (AccessFlags::FINAL, "final"),
+            (AccessFlags::SYNCHRONIZED, "synchronized"),
+            (AccessFlags::BRIDGE, "bridge"),
+            (AccessFlags::VARARGS, "varargs"),
+            (AccessFlags::NATIVE, "native"),
+            (AccessFlags::ABSTRACT, "abstract"),
+            (AccessFlags::STRICT, "strict"),
+            (AccessFlags::SYNTHETIC, "synthetic"),
+        ],
+    }
+}
+
+// the names of the flags set, in the order javap prints them
+pub fn flag_names(flags: AccessFlags, kind: MemberKind) -> Vec<&'static str> {
+    flag_table(kind)
+        .iter()
+        .filter(|(flag, _)| flags.contains(*flag))
+        .map(|(_, name)| *name)
+        .collect()
+}
+
+// the flags set that are reserved for this kind of member (like ACC_NATIVE on a class)
+pub fn unknown_flags(flags: AccessFlags, kind: MemberKind) -> AccessFlags {
+    let known = flag_table(kind)
+        .iter()
+        .fold(AccessFlags::empty(), |known, (flag, _)| known | *flag);
+    flags - known
+}
+
+fn visibility(flags: AccessFlags) -> &'static str {
+    if flags.contains(AccessFlags::PUBLIC) {
+        "public"
+    } else if flags.contains(AccessFlags::PROTECTED) {
+        "protected"
+    } else if flags.contains(AccessFlags::PRIVATE) {
+        "private"
+    } else {
+        "package"
+    }
+}
+
+// how far a visibility reaches, to tell narrowing from widening
+fn reach(visibility: &str) -> u8 {
+    match visibility {
+        "public" => 3,
+        "protected" => 2,
+        "package" => 1,
+        _ => 0,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Member {
+    kind: MemberKind,
+    access_flags: AccessFlags,
+    name: String,
+    descriptor: String,
+    throws: Vec<String>,
+}
+
+impl Display for Member {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.kind.name())?;
+        for flag in flag_names(self.access_flags, self.kind) {
+            write!(f, " {}", flag)?;
+        }
+        write!(f, " {} {}", self.name, self.descriptor)?;
+        if !self.throws.is_empty() {
+            write!(f, " throws {}", self.throws.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+// the members by kind, name and descriptor
+type Members = BTreeMap<(MemberKind, String, String), Member>;
+
+// the parts of a class its users can depend on
+struct Api {
+    access_flags: AccessFlags,
+    name: String,
+    super_class: Option<String>,
+    interfaces: BTreeSet<String>,
+    members: Members,
+}
+
+// A difference between two versions of a class' API. Breaking ones can stop code compiled
+// against the old version from linking or running with the new one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ApiChange {
+    // a member, as printed in the API signature
+    Added(String),
+    Removed(String),
+    DescriptorChanged {
+        member: String,
+        from: String,
+        to: String,
+    },
+    VisibilityChanged {
+        member: String,
+        from: &'static str,
+        to: &'static str,
+    },
+    // static, final or abstract on a member or the class
+    ModifiersChanged {
+        member: String,
+        from: String,
+        to: String,
+    },
+    SuperClassChanged {
+        from: Option<String>,
+        to: Option<String>,
+    },
+    InterfaceAdded(String),
+    InterfaceRemoved(String),
+}
+
+impl ApiChange {
+    pub fn is_breaking(&self) -> bool {
+        match self {
+            ApiChange::Added(_) | ApiChange::InterfaceAdded(_) => false,
+            ApiChange::VisibilityChanged { from, to, .. } => reach(to) < reach(from),
+            ApiChange::ModifiersChanged { from, to, .. } => {
+                // dropping final or abstract is fine, anything else isn't
+                let from = from.split(' ').collect::<BTreeSet<_>>();
+                let to = to.split(' ').collect::<BTreeSet<_>>();
+                from.contains("static") != to.contains("static")
+                    || to
+                        .difference(&from)
+                        .any(|m| *m == "final" || *m == "abstract")
+            }
+            _ => true,
+        }
+    }
+}
+
+impl Display for ApiChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let none = || "(none)".to_string();
+        match self {
+            ApiChange::Added(member) => write!(f, "added {}", member),
+            ApiChange::Removed(member) => write!(f, "removed {}", member),
+            ApiChange::DescriptorChanged { member, from, to } => {
+                write!(
+                    f,
+                    "changed the descriptor of {} from {} to {}",
+                    member, from, to
+                )
+            }
+            ApiChange::VisibilityChanged { member, from, to } => {
+                write!(f, "made {} {} instead of {}", member, to, from)
+            }
+            ApiChange::ModifiersChanged { member, from, to } => {
+                write!(
+                    f,
+                    "changed the modifiers of {} from [{}] to [{}]",
+                    member, from, to
+                )
+            }
+            ApiChange::SuperClassChanged { from, to } => write!(
+                f,
+                "changed the super class from {} to {}",
+                from.clone().unwrap_or_else(none),
+                to.clone().unwrap_or_else(none)
+            ),
+            ApiChange::InterfaceAdded(name) => write!(f, "added the interface {}", name),
+            ApiChange::InterfaceRemoved(name) => write!(f, "removed the interface {}", name),
+        }
+    }
+}
+
+// the flags compared by ModifiersChanged
+fn modifiers(flags: AccessFlags) -> String {
+    let mut res = Vec::new();
+    for (flag, name) in [
+        (AccessFlags::STATIC, "static"),
+        (AccessFlags::FINAL, "final"),
+        (AccessFlags::ABSTRACT, "abstract"),
+    ] {
+        if flags.contains(flag) {
+            res.push(name);
+        }
+    }
+    res.join(" ")
+}
+
+// how many members have each kind and name
+fn count_names(members: &Members) -> BTreeMap<(MemberKind, &str), usize> {
+    let mut res = BTreeMap::new();
+    for (kind, name, _) in members.keys() {
+        *res.entry((*kind, name.as_str())).or_default() += 1;
+    }
+    res
+}
+
+// the changes between two versions of a member with the same kind and name
+fn member_changes(old: &Member, new: &Member, out: &mut Vec<ApiChange>) {
+    let member = format!("{} {} {}", old.kind.name(), old.name, old.descriptor);
+    let (from, to) = (visibility(old.access_flags), visibility(new.access_flags));
+    if from != to {
+        out.push(ApiChange::VisibilityChanged {
+            member: member.clone(),
+            from,
+            to,
+        });
+    }
+    let (from, to) = (modifiers(old.access_flags), modifiers(new.access_flags));
+    if from != to {
+        out.push(ApiChange::ModifiersChanged { member, from, to });
+    }
+}
+
+impl Method {
+    // the classes in the Exceptions attribute, its throws clause
+    pub fn exceptions<'a>(&self, cp: &'a ConstantPool) -> Result<Vec<&'a str>, WrongEntry> {
+        let mut res = Vec::new();
+        for a in self.attributes.iter() {
+            if let AttributeInfo::Exceptions {
+                exception_index_table,
+            } = &a.info
+            {
+                for c in exception_index_table {
+                    res.push(c.name(cp)?);
+                }
+            }
+        }
+        Ok(res)
+    }
+}
+
+impl JavaClass {
+    fn api(&self) -> Result<Api, WrongEntry> {
+        let cp = &self.constant_pool;
+        let mut interfaces = BTreeSet::new();
+        for i in self.interfaces.iter() {
+            interfaces.insert(i.name(cp)?.to_string());
+        }
+
+        let mut members = Members::new();
+        let fields = self.fields.iter().map(|f| {
+            let throws = Ok(Vec::new());
+            (
+                MemberKind::Field,
+                f.access_flags,
+                f.name_index,
+                f.descriptor_index,
+                throws,
+            )
+        });
+        let methods = self.methods.iter().map(|m| {
+            let throws = m
+                .exceptions(cp)
+                .map(|e| e.into_iter().map(str::to_string).collect());
+            (
+                MemberKind::Method,
+                m.access_flags,
+                m.name_index,
+                m.descriptor_index,
+                throws,
+            )
+        });
+        for (kind, access_flags, name, descriptor, throws) in fields.chain(methods) {
+            // synthetic members (bridges, accessors) are up to the compiler
+            if access_flags.intersects(AccessFlags::PRIVATE | AccessFlags::SYNTHETIC) {
+                continue;
+            }
+            let member = Member {
+                kind,
+                access_flags,
+                name: name.resolve(cp)?.to_string(),
+                descriptor: descriptor.resolve(cp)?.to_string(),
+                throws: throws?,
+            };
+            members.insert(
+                (kind, member.name.clone(), member.descriptor.clone()),
+                member,
+            );
+        }
+
+        Ok(Api {
+            access_flags: self.access_flags,
+            name: self.this_class.name(cp)?.to_string(),
+            super_class: match self.super_class {
+                Some(c) => Some(c.name(cp)?.to_string()),
+                None => None,
+            },
+            interfaces,
+            members,
+        })
+    }
+    // The class, its super class and interfaces, then its non private (and non synthetic)
+    // fields and methods, one per line. Sorted, so that two versions can be compared with diff.
+    pub fn api_signature(&self) -> Result<String, WrongEntry> {
+        let api = self.api()?;
+        let mut res = String::from("class");
+        for flag in flag_names(api.access_flags, MemberKind::Class) {
+            res.push(' ');
+            res.push_str(flag);
+        }
+        res.push_str(&format!(" {}\n", api.name));
+        if let Some(super_class) = api.super_class {
+            res.push_str(&format!("extends {}\n", super_class));
+        }
+        for i in api.interfaces {
+            res.push_str(&format!("implements {}\n", i));
+        }
+        for member in api.members.values() {
+            res.push_str(&format!("{}\n", member));
+        }
+        Ok(res)
+    }
+    // What changed in the API from this version of the class to new. A member whose descriptor
+    // changed is only told apart from a removal when no other member has the same name.
+    pub fn api_diff(&self, new: &JavaClass) -> Result<Vec<ApiChange>, WrongEntry> {
+        let (old, new) = (self.api()?, new.api()?);
+        let mut res = Vec::new();
+
+        if old.super_class != new.super_class {
+            res.push(ApiChange::SuperClassChanged {
+                from: old.super_class,
+                to: new.super_class,
+            });
+        }
+        for i in old.interfaces.difference(&new.interfaces) {
+            res.push(ApiChange::InterfaceRemoved(i.clone()));
+        }
+        for i in new.interfaces.difference(&old.interfaces) {
+            res.push(ApiChange::InterfaceAdded(i.clone()));
+        }
+        let (from, to) = (visibility(old.access_flags), visibility(new.access_flags));
+        if from != to {
+            res.push(ApiChange::VisibilityChanged {
+                member: format!("class {}", old.name),
+                from,
+                to,
+            });
+        }
+        let (from, to) = (modifiers(old.access_flags), modifiers(new.access_flags));
+        if from != to {
+            res.push(ApiChange::ModifiersChanged {
+                member: format!("class {}", old.name),
+                from,
+                to,
+            });
+        }
+
+        // a member whose descriptor changed is removed and added under a new key, they are
+        // paired back when they are the only ones with their name
+        let (old_names, new_names) = (count_names(&old.members), count_names(&new.members));
+        let paired = |kind: MemberKind, name: &str| {
+            old_names.get(&(kind, name)) == Some(&1) && new_names.get(&(kind, name)) == Some(&1)
+        };
+
+        for (key, m) in old.members.iter() {
+            if let Some(n) = new.members.get(key) {
+                member_changes(m, n, &mut res);
+            } else if paired(m.kind, &m.name) {
+                let n = new
+                    .members
+                    .values()
+                    .find(|n| n.kind == m.kind && n.name == m.name)
+                    .expect("paired member");
+                res.push(ApiChange::DescriptorChanged {
+                    member: format!("{} {}", m.kind.name(), m.name),
+                    from: m.descriptor.clone(),
+                    to: n.descriptor.clone(),
+                });
+                member_changes(m, n, &mut res);
+            } else {
+                res.push(ApiChange::Removed(m.to_string()));
+            }
+        }
+        for (key, m) in new.members.iter() {
+            if !old.members.contains_key(key) && !paired(m.kind, &m.name) {
+                res.push(ApiChange::Added(m.to_string()));
+            }
+        }
+        Ok(res)
+    }
+}
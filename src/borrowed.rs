@@ -0,0 +1,453 @@
+use std::{
+    io::{Cursor, Error},
+    ops::Deref,
+};
+
+use crate::{
+    deserialization::{at_offset, remaining, Deserialize},
+    modified_utf8::{decode_modified_utf8, JavaString},
+    AccessFlags, Attribute, AttributeInfo, CPIndex, ClassIndex, ConstantPool, ConstantPoolEntry,
+    ConstantTag, Field, JavaClass, Method, Utf8Index, WrongEntry,
+};
+
+type Reader<'a> = Cursor<&'a [u8]>;
+
+// A class read without copying its strings and attribute bodies out of the input, for scanning
+// many classes quickly. Utf8 entries borrow the input unless their modified UTF-8 differs from
+// UTF-8, attributes are left as their raw bodies. to_owned gives the JavaClass from_bytes would.
+pub struct JavaClassRef<'a> {
+    // the whole class file
+    pub bytes: &'a [u8],
+    pub magic_bytes: u32,
+    pub minor_version: u16,
+    pub major_version: u16,
+    pub constant_pool: ConstantPoolRef<'a>,
+    pub access_flags: AccessFlags,
+    pub this_class: ClassIndex,
+    pub super_class: Option<ClassIndex>,
+    pub interfaces: Vec<ClassIndex>,
+    pub fields: Vec<Field<AttributeRef<'a>>>,
+    pub methods: Vec<Method<AttributeRef<'a>>>,
+    pub attributes: Vec<AttributeRef<'a>>,
+}
+
+pub enum ConstantRef<'a> {
+    Utf8(Utf8Ref<'a>),
+    // never a Utf8
+    Other(ConstantPoolEntry),
+}
+
+// the string of a Utf8 entry, borrowed from the input or decoded like a ConstantPoolEntry::Utf8
+pub enum Utf8Ref<'a> {
+    Borrowed(&'a str),
+    Owned(JavaString),
+}
+
+impl Utf8Ref<'_> {
+    pub fn to_owned(&self) -> JavaString {
+        match self {
+            Utf8Ref::Borrowed(s) => (*s).into(),
+            Utf8Ref::Owned(s) => s.clone(),
+        }
+    }
+}
+
+impl Deref for Utf8Ref<'_> {
+    type Target = str;
+    fn deref(&self) -> &str {
+        match self {
+            Utf8Ref::Borrowed(s) => s,
+            Utf8Ref::Owned(s) => s,
+        }
+    }
+}
+
+// like ConstantPool, indexed by slot
+pub struct ConstantPoolRef<'a> {
+    inner: Vec<Option<ConstantRef<'a>>>,
+}
+
+pub struct AttributeRef<'a> {
+    pub name_index: Utf8Index,
+    pub bytes: &'a [u8],
+}
+
+// the body of a Code attribute, borrowing the code
+pub struct CodeRef<'a> {
+    pub max_stack: u16,
+    pub max_locals: u16,
+    pub code: &'a [u8],
+    pub attributes: Vec<AttributeRef<'a>>,
+}
+
+impl<'a> ConstantPoolRef<'a> {
+    pub fn get(&self, index: CPIndex) -> Option<&ConstantRef<'a>> {
+        self.inner.get(index.0 as usize)?.as_ref()
+    }
+    // the entries in index order
+    pub fn iter(&self) -> impl Iterator<Item = (CPIndex, &ConstantRef<'a>)> {
+        self.inner
+            .iter()
+            .enumerate()
+            .filter_map(|(i, e)| Some((CPIndex(i as u16), e.as_ref()?)))
+    }
+    // the Utf8 entries with their index
+    pub fn utf8_values(&self) -> impl Iterator<Item = (CPIndex, &str)> {
+        self.iter().filter_map(|(i, e)| match e {
+            ConstantRef::Utf8(s) => Some((i, &**s)),
+            ConstantRef::Other(_) => None,
+        })
+    }
+    // the string at index, like Utf8Index::resolve
+    pub fn utf8(&self, index: Utf8Index) -> Result<&str, WrongEntry> {
+        match self.get(index.0) {
+            Some(ConstantRef::Utf8(s)) => Ok(s),
+            found => Err(WrongEntry {
+                index: index.0,
+                expected: "Utf8",
+                found: found.map(|e| match e {
+                    ConstantRef::Utf8(_) => "Utf8",
+                    ConstantRef::Other(e) => e.kind(),
+                }),
+            }),
+        }
+    }
+    // the name of the class at index, like ClassIndex::name
+    pub fn class_name(&self, index: ClassIndex) -> Result<&str, WrongEntry> {
+        match self.get(index.0) {
+            Some(ConstantRef::Other(ConstantPoolEntry::Class { name_index })) => {
+                self.utf8(*name_index)
+            }
+            found => Err(WrongEntry {
+                index: index.0,
+                expected: "Class",
+                found: found.map(|e| match e {
+                    ConstantRef::Utf8(_) => "Utf8",
+                    ConstantRef::Other(e) => e.kind(),
+                }),
+            }),
+        }
+    }
+    pub fn to_owned(&self) -> ConstantPool {
+        ConstantPool {
+            inner: self
+                .inner
+                .iter()
+                .map(|e| {
+                    e.as_ref().map(|e| match e {
+                        ConstantRef::Utf8(s) => ConstantPoolEntry::Utf8(s.to_owned()),
+                        ConstantRef::Other(e) => e.clone(),
+                    })
+                })
+                .collect(),
+        }
+    }
+}
+
+impl<'a> AttributeRef<'a> {
+    // the body read as a Code attribute, None if it isn't one like AttributeInfo::parse reads
+    pub fn code(&self) -> Option<CodeRef<'a>> {
+        let bytes = &mut Cursor::new(self.bytes);
+        let max_stack = u16::deserialize(bytes).ok()?;
+        let max_locals = u16::deserialize(bytes).ok()?;
+        let length = u32::deserialize(bytes).ok()?;
+        let code = borrow_bytes(bytes, length as usize).ok()?;
+        // start, end, handler and catch_type
+        let handlers = u16::deserialize(bytes).ok()?;
+        borrow_bytes(bytes, handlers as usize * 8).ok()?;
+        let attributes = attributes(bytes).ok()?;
+        (remaining(bytes) == 0).then_some(CodeRef {
+            max_stack,
+            max_locals,
+            code,
+            attributes,
+        })
+    }
+    // the attributes nested in the attribute if it is named Code or Record, as they would be
+    // parsed
+    pub fn nested(&self, name: &str) -> Option<Vec<AttributeRef<'a>>> {
+        match name {
+            "Code" => Some(self.code()?.attributes),
+            "Record" => {
+                let bytes = &mut Cursor::new(self.bytes);
+                let mut res = Vec::new();
+                for _ in 0..u16::deserialize(bytes).ok()? {
+                    // name and descriptor
+                    borrow_bytes(bytes, 4).ok()?;
+                    res.extend(attributes(bytes).ok()?);
+                }
+                (remaining(bytes) == 0).then_some(res)
+            }
+            _ => None,
+        }
+    }
+    // parsed, or kept as raw bytes, as when reading a JavaClass
+    pub fn to_owned(&self, cp: &ConstantPool) -> Attribute {
+        let mut a = Attribute {
+            name_index: self.name_index,
+            info: AttributeInfo::Any(self.bytes.to_vec()),
+        };
+        a.resolve_or_keep(cp);
+        a
+    }
+}
+
+impl JavaClassRef<'_> {
+    // the contents of the String entries in pool order, the ones pointing at something else than
+    // a Utf8 are skipped
+    pub fn string_constants(&self) -> Vec<&str> {
+        let cp = &self.constant_pool;
+        cp.iter()
+            .filter_map(|(_, e)| match e {
+                ConstantRef::Other(ConstantPoolEntry::String { string_index }) => {
+                    cp.utf8(*string_index).ok()
+                }
+                _ => None,
+            })
+            .collect()
+    }
+    pub fn to_owned(&self) -> JavaClass {
+        let cp = self.constant_pool.to_owned();
+        let attributes = |attributes: &[AttributeRef]| {
+            attributes
+                .iter()
+                .map(|a| a.to_owned(&cp))
+                .collect::<Vec<_>>()
+        };
+        JavaClass {
+            magic_bytes: self.magic_bytes,
+            minor_version: self.minor_version,
+            major_version: self.major_version,
+            access_flags: self.access_flags,
+            this_class: self.this_class,
+            super_class: self.super_class,
+            interfaces: self.interfaces.clone(),
+            fields: self
+                .fields
+                .iter()
+                .map(|f| Field {
+                    access_flags: f.access_flags,
+                    name_index: f.name_index,
+                    descriptor_index: f.descriptor_index,
+                    attributes: attributes(&f.attributes),
+                })
+                .collect(),
+            methods: self
+                .methods
+                .iter()
+                .map(|m| Method {
+                    access_flags: m.access_flags,
+                    name_index: m.name_index,
+                    descriptor_index: m.descriptor_index,
+                    attributes: attributes(&m.attributes),
+                })
+                .collect(),
+            attributes: attributes(&self.attributes),
+            constant_pool: cp,
+        }
+    }
+}
+
+// the next len bytes of the input, without copying them
+fn borrow_bytes<'a>(bytes: &mut Reader<'a>, len: usize) -> Result<&'a [u8], Error> {
+    let input: &'a [u8] = bytes.get_ref();
+    let start = bytes.position() as usize;
+    let res = input.get(start..start + len).ok_or_else(|| {
+        let e = Error::other(format!(
+            "Length of {} bytes exceeds the {} bytes left in the input.",
+            len,
+            input.len().saturating_sub(start)
+        ));
+        at_offset(e, start as u64)
+    })?;
+    bytes.set_position((start + len) as u64);
+    Ok(res)
+}
+
+// Borrowed when the modified UTF-8 is also UTF-8, which is the case unless the string has a null
+// or supplementary characters. Otherwise decoded the same way as a ConstantPoolEntry::Utf8.
+fn utf8<'a>(bytes: &'a [u8]) -> Result<Utf8Ref<'a>, Error> {
+    if !bytes.iter().any(|&b| b == 0 || b >= 0xF0) {
+        if let Ok(s) = std::str::from_utf8(bytes) {
+            return Ok(Utf8Ref::Borrowed(s));
+        }
+    }
+    Ok(Utf8Ref::Owned(decode_modified_utf8(bytes)?))
+}
+
+fn constant_pool<'a>(bytes: &mut Reader<'a>) -> Result<ConstantPoolRef<'a>, Error> {
+    let offset = bytes.position();
+    let count = u16::deserialize(bytes)?;
+    if count == 0 {
+        return Err(at_offset(Error::other("Constant pool count is 0."), offset));
+    }
+    let mut inner = Vec::with_capacity(count as usize);
+    inner.push(None);
+    let mut index = 1u16;
+    while index < count {
+        let entry = match ConstantTag::try_from(u8::deserialize(bytes)?) {
+            Ok(ConstantTag::Utf8) => {
+                let len = u16::deserialize(bytes)?;
+                ConstantRef::Utf8(utf8(borrow_bytes(bytes, len as usize)?)?)
+            }
+            _ => {
+                // read again along with the tag
+                bytes.set_position(bytes.position() - 1);
+                ConstantRef::Other(ConstantPoolEntry::deserialize(bytes)?)
+            }
+        };
+        let size = match &entry {
+            ConstantRef::Other(e) => e.size(),
+            ConstantRef::Utf8(_) => 1,
+        };
+        let next = index as u32 + size as u32;
+        if next > count as u32 {
+            return Err(Error::other(format!(
+                "Constant pool entry at index {} takes two slots but is the last entry \
+                 (constant pool count is {}).",
+                index, count
+            )));
+        }
+        inner.push(Some(entry));
+        if size == 2 {
+            inner.push(None);
+        }
+        index = next as u16;
+    }
+    Ok(ConstantPoolRef { inner })
+}
+
+fn attributes<'a>(bytes: &mut Reader<'a>) -> Result<Vec<AttributeRef<'a>>, Error> {
+    let count = u16::deserialize(bytes)?;
+    let mut res = Vec::with_capacity((count as usize).min(bytes.get_ref().len() / 6));
+    for _ in 0..count {
+        let name_index = Utf8Index::deserialize(bytes)?;
+        let len = u32::deserialize(bytes)?;
+        res.push(AttributeRef {
+            name_index,
+            bytes: borrow_bytes(bytes, len as usize)?,
+        });
+    }
+    Ok(res)
+}
+
+fn members<'a>(bytes: &mut Reader<'a>) -> Result<Vec<Field<AttributeRef<'a>>>, Error> {
+    let count = u16::deserialize(bytes)?;
+    let mut res = Vec::with_capacity((count as usize).min(bytes.get_ref().len() / 8));
+    for _ in 0..count {
+        res.push(Field {
+            access_flags: AccessFlags::deserialize(bytes)?,
+            name_index: Utf8Index::deserialize(bytes)?,
+            descriptor_index: Utf8Index::deserialize(bytes)?,
+            attributes: attributes(bytes)?,
+        });
+    }
+    Ok(res)
+}
+
+// reads a class like JavaClass::from_bytes, borrowing from bytes
+pub fn deserialize_borrowed(input: &[u8]) -> Result<JavaClassRef<'_>, Error> {
+    let bytes = &mut Cursor::new(input);
+    let magic_bytes = u32::deserialize(bytes)?;
+    let minor_version = u16::deserialize(bytes)?;
+    let major_version = u16::deserialize(bytes)?;
+    let constant_pool = constant_pool(bytes)?;
+    let access_flags = AccessFlags::deserialize(bytes)?;
+    let this_class = ClassIndex::deserialize(bytes)?;
+    let super_class = Option::<ClassIndex>::deserialize(bytes)?;
+    let interfaces = Vec::<ClassIndex>::deserialize(bytes)?;
+    let fields = members(bytes)?;
+    // fields and methods have the same layout
+    let methods = members(bytes)?
+        .into_iter()
+        .map(|m| Method {
+            access_flags: m.access_flags,
+            name_index: m.name_index,
+            descriptor_index: m.descriptor_index,
+            attributes: m.attributes,
+        })
+        .collect();
+    let attributes = attributes(bytes)?;
+
+    Ok(JavaClassRef {
+        bytes: input,
+        magic_bytes,
+        minor_version,
+        major_version,
+        constant_pool,
+        access_flags,
+        this_class,
+        super_class,
+        interfaces,
+        fields,
+        methods,
+        attributes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, path::Path};
+
+    // the bytes of every class in tests/fixtures
+    fn fixtures() -> Vec<Vec<u8>> {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        let mut res = Vec::new();
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension() == Some("class".as_ref()) {
+                res.push(fs::read(path).unwrap());
+            }
+        }
+        assert!(res.len() >= 11);
+        res
+    }
+
+    #[test]
+    fn to_owned_is_from_bytes() {
+        for bytes in fixtures() {
+            let borrowed = deserialize_borrowed(&bytes).unwrap();
+            assert_eq!(borrowed.to_owned(), JavaClass::from_bytes(&bytes).unwrap());
+        }
+    }
+
+    #[test]
+    fn nested_like_parsed() {
+        for bytes in fixtures() {
+            let borrowed = deserialize_borrowed(&bytes).unwrap();
+            let owned = borrowed.to_owned();
+            for (m, owned) in borrowed.methods.iter().zip(owned.methods.iter()) {
+                for (a, owned) in m.attributes.iter().zip(owned.attributes.iter()) {
+                    let name = borrowed.constant_pool.utf8(a.name_index).unwrap();
+                    match &owned.info {
+                        AttributeInfo::Code {
+                            max_stack,
+                            max_locals,
+                            code,
+                            attributes,
+                            ..
+                        } => {
+                            let c = a.code().unwrap();
+                            assert_eq!((c.max_stack, c.max_locals), (*max_stack, *max_locals));
+                            assert!(c.code.iter().eq(code.iter().map(|b| &b.0)));
+                            assert_eq!(a.nested(name).unwrap().len(), attributes.len());
+                        }
+                        _ => assert!(a.nested(name).is_none()),
+                    }
+                }
+            }
+            for (a, owned) in borrowed.attributes.iter().zip(owned.attributes.iter()) {
+                let name = borrowed.constant_pool.utf8(a.name_index).unwrap();
+                let nested = a.nested(name).map(|n| n.len());
+                match &owned.info {
+                    AttributeInfo::Record { components } => {
+                        let count = components.iter().map(|c| c.attributes.len()).sum();
+                        assert_eq!(nested, Some(count));
+                    }
+                    _ => assert_eq!(nested, None),
+                }
+            }
+        }
+    }
+}
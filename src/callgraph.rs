@@ -0,0 +1,216 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::{
+    entry::MethodView,
+    instruction::{decode_code, Instruction},
+    usages::method_ref,
+    AccessFlags, AttributeInfo, CPIndex, ConstantPoolEntry, JavaClass,
+};
+
+// a method called from another
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CallTarget {
+    // index of the method in JavaClass::methods
+    Internal(usize),
+    // class.name:descriptor
+    External(String),
+}
+
+// a method and the targets it invokes
+#[derive(Debug)]
+pub struct CallNode {
+    // index in JavaClass::methods
+    pub index: usize,
+    // name and descriptor, like main([Ljava/lang/String;)V
+    pub name: String,
+    pub targets: BTreeSet<CallTarget>,
+}
+
+#[derive(Debug)]
+pub struct CallGraph {
+    pub class: String,
+    // the methods whose name and descriptor resolve, in order
+    pub nodes: Vec<CallNode>,
+}
+
+// the bootstrap method and arguments of each entry of the BootstrapMethods attribute, which isn't
+// modeled so it is read from the raw bytes
+fn bootstrap_methods(class: &JavaClass) -> Vec<(CPIndex, Vec<CPIndex>)> {
+    let cp = &class.constant_pool;
+    let Some(AttributeInfo::Any(b)) = class
+        .attributes
+        .iter()
+        .find(|a| a.name(cp) == Some("BootstrapMethods"))
+        .map(|a| &a.info)
+    else {
+        return Vec::new();
+    };
+    // a u16 count, then for each the method handle index, a u16 count and the argument indices
+    let mut u16s = b.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]]));
+    let count = u16s.next().unwrap_or(0);
+    let mut res = Vec::new();
+    for _ in 0..count {
+        let (Some(method), Some(arguments)) = (u16s.next(), u16s.next()) else {
+            break;
+        };
+        let arguments = u16s
+            .by_ref()
+            .take(arguments as usize)
+            .map(CPIndex)
+            .collect();
+        res.push((CPIndex(method), arguments));
+    }
+    res
+}
+
+impl CallGraph {
+    // the name and descriptor of an internal target, or the external one as is
+    pub fn target_name<'a>(&'a self, target: &'a CallTarget) -> &'a str {
+        match target {
+            CallTarget::Internal(i) => self
+                .nodes
+                .iter()
+                .find(|n| n.index == *i)
+                .map_or("", |n| n.name.as_str()),
+            CallTarget::External(name) => name,
+        }
+    }
+    // the graph in graphviz's dot language, calls to other classes are dashed
+    pub fn to_dot(&self) -> String {
+        let mut res = format!("digraph {:?} {{\n", self.class);
+        for node in self.nodes.iter() {
+            for target in node.targets.iter() {
+                let style = match target {
+                    CallTarget::Internal(_) => "",
+                    CallTarget::External(_) => " [style=dashed]",
+                };
+                res += &format!(
+                    "    {:?} -> {:?}{};\n",
+                    node.name,
+                    self.target_name(target),
+                    style
+                );
+            }
+        }
+        res += "}\n";
+        res
+    }
+}
+
+impl JavaClass {
+    // The methods invoked by each method of the class, including through invokedynamic: those
+    // lead to the bootstrap method, and to the methods passed to it as handles (like the body of a
+    // lambda). Methods whose code can't be decoded have no targets.
+    pub fn call_graph(&self) -> CallGraph {
+        let cp = &self.constant_pool;
+        let class = self.this_class.name(cp).unwrap_or_default();
+        let views = self.method_views().collect::<Vec<_>>();
+        let declared = views
+            .iter()
+            .map(|m| ((m.name, m.descriptor), m.index))
+            .collect::<HashMap<_, _>>();
+        let bootstrap = bootstrap_methods(self);
+
+        // the target of a Methodref or InterfaceMethodref entry
+        let target = |index: CPIndex| match cp.get(index) {
+            Some(
+                ConstantPoolEntry::MethodRef {
+                    class_index,
+                    name_and_type_index,
+                }
+                | ConstantPoolEntry::InterfaceMethodRef {
+                    class_index,
+                    name_and_type_index,
+                },
+            ) => {
+                let (name, descriptor) = method_ref(cp, *class_index, *name_and_type_index)?;
+                let internal = name
+                    .strip_prefix(class)
+                    .and_then(|n| n.strip_prefix('.'))
+                    .and_then(|n| declared.get(&(n, descriptor)));
+                Some(match internal {
+                    Some(i) => CallTarget::Internal(*i),
+                    None => CallTarget::External(format!("{}:{}", name, descriptor)),
+                })
+            }
+            _ => None,
+        };
+        // the target of a MethodHandle entry, if it is a method
+        let handle = |index: &CPIndex| match cp.get(*index) {
+            Some(ConstantPoolEntry::MethodHandle {
+                reference_index, ..
+            }) => target(*reference_index),
+            _ => None,
+        };
+
+        let nodes = views
+            .iter()
+            .map(|m| {
+                let mut targets = BTreeSet::new();
+                let code = m.method.attributes.iter().find_map(|a| match &a.info {
+                    AttributeInfo::Code { code, .. } => {
+                        Some(code.iter().map(|b| b.0).collect::<Vec<_>>())
+                    }
+                    _ => None,
+                });
+                if let Some(Ok(instructions)) = code.map(|c| decode_code(&c)) {
+                    for (_, instruction) in instructions {
+                        match instruction {
+                            Instruction::Invokevirtual(index)
+                            | Instruction::Invokespecial(index)
+                            | Instruction::Invokestatic(index)
+                            | Instruction::Invokeinterface(index, _) => {
+                                targets.extend(target(index));
+                            }
+                            Instruction::Invokedynamic(index) => {
+                                let Some(ConstantPoolEntry::InvokeDynamic {
+                                    bootstrap_method_attr_index,
+                                    ..
+                                }) = cp.get(index)
+                                else {
+                                    continue;
+                                };
+                                if let Some((method, arguments)) =
+                                    bootstrap.get(*bootstrap_method_attr_index as usize)
+                                {
+                                    targets.extend(handle(method));
+                                    targets.extend(arguments.iter().filter_map(handle));
+                                }
+                            }
+                            _ => (),
+                        }
+                    }
+                }
+                CallNode {
+                    index: m.index,
+                    name: format!("{}{}", m.name, m.descriptor),
+                    targets,
+                }
+            })
+            .collect();
+
+        CallGraph {
+            class: class.to_string(),
+            nodes,
+        }
+    }
+    // the private methods no other method of the class invokes
+    pub fn unreachable_private_methods(&self) -> Vec<MethodView<'_>> {
+        let called = self
+            .call_graph()
+            .nodes
+            .iter()
+            .flat_map(|n| {
+                n.targets.iter().filter_map(move |t| match t {
+                    CallTarget::Internal(i) if *i != n.index => Some(*i),
+                    _ => None,
+                })
+            })
+            .collect::<BTreeSet<_>>();
+        self.method_views()
+            .filter(|m| {
+                m.method.access_flags.contains(AccessFlags::PRIVATE) && !called.contains(&m.index)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,240 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    io::Error,
+    ops::Range,
+};
+
+use crate::{
+    instruction::{decode_code, Instruction},
+    AttributeInfo, Method,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeKind {
+    // to the next instruction, after a jsr this is where the subroutine returns
+    FallThrough,
+    Branch,
+    // to a case or the default of a tableswitch or lookupswitch
+    Switch,
+    // to a subroutine, ret leaves the block without any edge
+    Jsr,
+    // to a handler, from the blocks of its range
+    Exception,
+}
+
+// instructions that only run one after the other
+#[derive(Debug)]
+pub struct BasicBlock {
+    // offset of the first instruction, and of the end of the last one
+    pub start: u32,
+    pub end: u32,
+    pub instructions: Vec<(u32, Instruction)>,
+}
+
+#[derive(Debug)]
+pub struct Cfg {
+    // in offset order, the first is the entry
+    pub blocks: Vec<BasicBlock>,
+    // indices in blocks of the source and destination
+    pub edges: Vec<(usize, usize, EdgeKind)>,
+    // the protected range and handler block of each exception table entry, in order
+    pub handlers: Vec<(Range<u32>, usize)>,
+}
+
+impl Method {
+    // The control flow graph of the method's code. Blocks start at the beginning of the code,
+    // branch targets, handlers and the bounds of their ranges, and after every instruction that
+    // branches or doesn't fall through.
+    pub fn cfg(&self) -> Result<Cfg, Error> {
+        let (code, exception_table) = self
+            .attributes
+            .iter()
+            .find_map(|a| match &a.info {
+                AttributeInfo::Code {
+                    code,
+                    exception_table,
+                    ..
+                } => Some((
+                    code.iter().map(|b| b.0).collect::<Vec<_>>(),
+                    exception_table,
+                )),
+                _ => None,
+            })
+            .ok_or_else(|| Error::other("The method has no Code attribute."))?;
+        let instructions = decode_code(&code)?;
+        let len = code.len() as u32;
+        let is_start = instructions
+            .iter()
+            .map(|(offset, _)| *offset)
+            .collect::<BTreeSet<_>>();
+        let target = |offset: u32, branch: i32| {
+            u32::try_from(offset as i64 + branch as i64)
+                .ok()
+                .filter(|t| is_start.contains(t))
+                .ok_or_else(|| {
+                    Error::other(format!(
+                        "Branch at offset {} doesn't go to an instruction.",
+                        offset
+                    ))
+                })
+        };
+
+        let mut leaders = BTreeSet::from([0]);
+        for (offset, instruction) in instructions.iter() {
+            let branches = instruction.branches();
+            for branch in branches.iter() {
+                leaders.insert(target(*offset, *branch)?);
+            }
+            if !branches.is_empty() || !instruction.falls_through() {
+                leaders.insert(offset + instruction.size(*offset));
+            }
+        }
+        for e in exception_table.iter() {
+            for offset in [e.start, e.end, e.handler] {
+                let offset = offset as u32;
+                if offset != len && !is_start.contains(&offset) {
+                    return Err(Error::other(format!(
+                        "Exception table offset {} isn't the start of an instruction.",
+                        offset
+                    )));
+                }
+                leaders.insert(offset);
+            }
+        }
+
+        let mut blocks = Vec::<BasicBlock>::new();
+        for (offset, instruction) in instructions {
+            let end = offset + instruction.size(offset);
+            match blocks.last_mut() {
+                Some(block) if !leaders.contains(&offset) => {
+                    block.end = end;
+                    block.instructions.push((offset, instruction));
+                }
+                _ => blocks.push(BasicBlock {
+                    start: offset,
+                    end,
+                    instructions: vec![(offset, instruction)],
+                }),
+            }
+        }
+        let at = blocks
+            .iter()
+            .enumerate()
+            .map(|(i, b)| (b.start, i))
+            .collect::<HashMap<_, _>>();
+
+        let mut edges = Vec::new();
+        let mut add = |edge| {
+            if !edges.contains(&edge) {
+                edges.push(edge);
+            }
+        };
+        for (i, block) in blocks.iter().enumerate() {
+            let (offset, last) = block.instructions.last().expect("blocks aren't empty");
+            let kind = match last {
+                Instruction::Tableswitch { .. } | Instruction::Lookupswitch { .. } => {
+                    EdgeKind::Switch
+                }
+                Instruction::Jsr(_) | Instruction::JsrW(_) => EdgeKind::Jsr,
+                _ => EdgeKind::Branch,
+            };
+            for branch in last.branches() {
+                add((i, at[&target(*offset, branch)?], kind));
+            }
+            if last.falls_through() && block.end < len {
+                add((i, at[&block.end], EdgeKind::FallThrough));
+            }
+        }
+        let mut handlers = Vec::new();
+        for e in exception_table.iter() {
+            let handler = at[&(e.handler as u32)];
+            for (i, block) in blocks.iter().enumerate() {
+                if block.start >= e.start as u32 && block.end <= e.end as u32 {
+                    add((i, handler, EdgeKind::Exception));
+                }
+            }
+            handlers.push((e.start as u32..e.end as u32, handler));
+        }
+        Ok(Cfg {
+            blocks,
+            edges,
+            handlers,
+        })
+    }
+}
+
+impl Cfg {
+    // which blocks can run, walking the edges from the entry and from every handler
+    pub fn reachable(&self) -> Vec<bool> {
+        let mut reachable = vec![false; self.blocks.len()];
+        let mut pending = vec![0];
+        pending.extend(self.handlers.iter().map(|(_, handler)| *handler));
+        while let Some(i) = pending.pop() {
+            if i >= reachable.len() || reachable[i] {
+                continue;
+            }
+            reachable[i] = true;
+            pending.extend(
+                self.edges
+                    .iter()
+                    .filter(|(from, _, _)| *from == i)
+                    .map(|(_, to, _)| *to),
+            );
+        }
+        reachable
+    }
+    // the offsets of the code that can't run, consecutive blocks are merged
+    pub fn unreachable_ranges(&self) -> Vec<Range<u32>> {
+        let mut res = Vec::<Range<u32>>::new();
+        for (block, reachable) in self.blocks.iter().zip(self.reachable()) {
+            match res.last_mut() {
+                _ if reachable => (),
+                Some(range) if range.end == block.start => range.end = block.end,
+                _ => res.push(block.start..block.end),
+            }
+        }
+        res
+    }
+    // the indices in handlers of the entries whose range has no reachable instruction
+    pub fn dead_handlers(&self) -> Vec<usize> {
+        let reachable = self.reachable();
+        self.handlers
+            .iter()
+            .enumerate()
+            .filter(|(_, (range, _))| {
+                !self
+                    .blocks
+                    .iter()
+                    .zip(reachable.iter())
+                    .any(|(block, reachable)| {
+                        *reachable && block.start >= range.start && block.end <= range.end
+                    })
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+    // the graph in graphviz's dot language, each block lists its instructions
+    pub fn to_dot(&self) -> String {
+        let mut res = String::from("digraph cfg {\n    node [shape=box];\n");
+        for (i, block) in self.blocks.iter().enumerate() {
+            let label = block
+                .instructions
+                .iter()
+                .map(|(offset, instruction)| format!("{}: {}\\l", offset, instruction.mnemonic()))
+                .collect::<String>();
+            res += &format!("    b{} [label=\"{}\"];\n", i, label);
+        }
+        for (from, to, kind) in self.edges.iter() {
+            let attributes = match kind {
+                EdgeKind::FallThrough => "",
+                EdgeKind::Branch => " [label=\"branch\"]",
+                EdgeKind::Switch => " [label=\"switch\"]",
+                EdgeKind::Jsr => " [label=\"jsr\"]",
+                EdgeKind::Exception => " [label=\"exception\", style=dashed]",
+            };
+            res += &format!("    b{} -> b{}{};\n", from, to, attributes);
+        }
+        res += "}\n";
+        res
+    }
+}
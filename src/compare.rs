@@ -0,0 +1,336 @@
+use std::mem::discriminant;
+
+use crate::{
+    instruction::{decode_code, Instruction},
+    AttributeInfo, CPIndex, CodeByte, ConstantPool, ConstantPoolEntry, ExceptionTableEntry, Method,
+};
+
+// MethodHandle entries point at any entry, this stops loops between them
+const MAX_CONSTANT_DEPTH: usize = 8;
+
+// whether the constants at a in cp_a and at b in cp_b have the same value, whatever their index
+fn constants_equal(cp_a: &ConstantPool, a: CPIndex, cp_b: &ConstantPool, b: CPIndex) -> bool {
+    constants_equal_nested(cp_a, a, cp_b, b, 0)
+}
+
+fn constants_equal_nested(
+    cp_a: &ConstantPool,
+    a: CPIndex,
+    cp_b: &ConstantPool,
+    b: CPIndex,
+    depth: usize,
+) -> bool {
+    let (Some(entry_a), Some(entry_b)) = (cp_a.get(a), cp_b.get(b)) else {
+        // 0 in optional indices
+        return a == b;
+    };
+    if depth >= MAX_CONSTANT_DEPTH || discriminant(entry_a) != discriminant(entry_b) {
+        return false;
+    }
+    let equal = |a: CPIndex, b: CPIndex| constants_equal_nested(cp_a, a, cp_b, b, depth + 1);
+    match (entry_a, entry_b) {
+        (ConstantPoolEntry::Utf8(a), ConstantPoolEntry::Utf8(b)) => a == b,
+        (ConstantPoolEntry::Integer(a), ConstantPoolEntry::Integer(b)) => a == b,
+        (ConstantPoolEntry::Long(a), ConstantPoolEntry::Long(b)) => a == b,
+        // by bits so that NaNs are equal and 0.0 isn't -0.0
+        (ConstantPoolEntry::Float(a), ConstantPoolEntry::Float(b)) => a == b,
+        (ConstantPoolEntry::Double(a), ConstantPoolEntry::Double(b)) => a == b,
+        (
+            ConstantPoolEntry::Class { name_index: a },
+            ConstantPoolEntry::Class { name_index: b },
+        )
+        | (
+            ConstantPoolEntry::String { string_index: a },
+            ConstantPoolEntry::String { string_index: b },
+        )
+        | (
+            ConstantPoolEntry::MethodType {
+                descriptor_index: a,
+            },
+            ConstantPoolEntry::MethodType {
+                descriptor_index: b,
+            },
+        )
+        | (
+            ConstantPoolEntry::Module { name_index: a },
+            ConstantPoolEntry::Module { name_index: b },
+        )
+        | (
+            ConstantPoolEntry::Package { name_index: a },
+            ConstantPoolEntry::Package { name_index: b },
+        ) => equal(a.0, b.0),
+        (
+            ConstantPoolEntry::FieldRef {
+                class_index: class_a,
+                name_and_type_index: nat_a,
+            },
+            ConstantPoolEntry::FieldRef {
+                class_index: class_b,
+                name_and_type_index: nat_b,
+            },
+        )
+        | (
+            ConstantPoolEntry::MethodRef {
+                class_index: class_a,
+                name_and_type_index: nat_a,
+            },
+            ConstantPoolEntry::MethodRef {
+                class_index: class_b,
+                name_and_type_index: nat_b,
+            },
+        )
+        | (
+            ConstantPoolEntry::InterfaceMethodRef {
+                class_index: class_a,
+                name_and_type_index: nat_a,
+            },
+            ConstantPoolEntry::InterfaceMethodRef {
+                class_index: class_b,
+                name_and_type_index: nat_b,
+            },
+        ) => equal(class_a.0, class_b.0) && equal(nat_a.0, nat_b.0),
+        (
+            ConstantPoolEntry::NameAndType {
+                name_index: name_a,
+                descriptor_index: descriptor_a,
+            },
+            ConstantPoolEntry::NameAndType {
+                name_index: name_b,
+                descriptor_index: descriptor_b,
+            },
+        ) => equal(name_a.0, name_b.0) && equal(descriptor_a.0, descriptor_b.0),
+        (
+            ConstantPoolEntry::MethodHandle {
+                reference_kind: kind_a,
+                reference_index: a,
+            },
+            ConstantPoolEntry::MethodHandle {
+                reference_kind: kind_b,
+                reference_index: b,
+            },
+        ) => *kind_a as u8 == *kind_b as u8 && equal(*a, *b),
+        (
+            ConstantPoolEntry::Dynamic {
+                bootstrap_method_attr_index: bootstrap_a,
+                name_and_type_index: nat_a,
+            },
+            ConstantPoolEntry::Dynamic {
+                bootstrap_method_attr_index: bootstrap_b,
+                name_and_type_index: nat_b,
+            },
+        )
+        | (
+            ConstantPoolEntry::InvokeDynamic {
+                bootstrap_method_attr_index: bootstrap_a,
+                name_and_type_index: nat_a,
+            },
+            ConstantPoolEntry::InvokeDynamic {
+                bootstrap_method_attr_index: bootstrap_b,
+                name_and_type_index: nat_b,
+            },
+        ) => bootstrap_a == bootstrap_b && equal(nat_a.0, nat_b.0),
+        _ => unreachable!("the kinds are the same"),
+    }
+}
+
+fn instructions_equal(
+    a: &Instruction,
+    cp_a: &ConstantPool,
+    b: &Instruction,
+    cp_b: &ConstantPool,
+) -> bool {
+    match (a.references_cp(), b.references_cp()) {
+        (Some(index_a), Some(index_b)) => {
+            // the other operands, the index is compared by value
+            let operands_equal = match (a, b) {
+                (Instruction::Invokeinterface(_, a), Instruction::Invokeinterface(_, b))
+                | (Instruction::Multianewarray(_, a), Instruction::Multianewarray(_, b)) => a == b,
+                _ => a.opcode() == b.opcode(),
+            };
+            operands_equal && constants_equal(cp_a, index_a, cp_b, index_b)
+        }
+        (None, None) => a == b,
+        _ => false,
+    }
+}
+
+// the code and exception table of the method
+fn code(method: &Method) -> Option<(&[CodeByte], &[ExceptionTableEntry])> {
+    method.attributes.iter().find_map(|a| match &a.info {
+        AttributeInfo::Code {
+            code,
+            exception_table,
+            ..
+        } => Some((code.as_slice(), exception_table.as_slice())),
+        _ => None,
+    })
+}
+
+impl Method {
+    // Whether the two methods run the same code: the same instructions (with the constants they
+    // reference compared by value, so the methods can come from classes with different constant
+    // pools) and the same exception table. The attributes of the code (LineNumberTable,
+    // LocalVariableTable, StackMapTable) are ignored, as are max_stack and max_locals. The
+    // bootstrap methods of invokedynamic and Dynamic constants are compared by index only.
+    pub fn code_equal(
+        &self,
+        other: &Method,
+        cp_self: &ConstantPool,
+        cp_other: &ConstantPool,
+    ) -> bool {
+        let (Some((code_a, table_a)), Some((code_b, table_b))) = (code(self), code(other)) else {
+            return code(self).is_none() && code(other).is_none();
+        };
+
+        let tables_equal = table_a.len() == table_b.len()
+            && table_a.iter().zip(table_b).all(|(a, b)| {
+                (a.start, a.end, a.handler) == (b.start, b.end, b.handler)
+                    && constants_equal(
+                        cp_self,
+                        a.catch_type.unwrap_or(CPIndex::NONE),
+                        cp_other,
+                        b.catch_type.unwrap_or(CPIndex::NONE),
+                    )
+            });
+        if !tables_equal {
+            return false;
+        }
+
+        let code_a = code_a.iter().map(|b| b.0).collect::<Vec<_>>();
+        let code_b = code_b.iter().map(|b| b.0).collect::<Vec<_>>();
+        match (decode_code(&code_a), decode_code(&code_b)) {
+            (Ok(a), Ok(b)) => {
+                a.len() == b.len()
+                    && a.iter()
+                        .zip(b.iter())
+                        .all(|((offset_a, a), (offset_b, b))| {
+                            offset_a == offset_b && instructions_equal(a, cp_self, b, cp_other)
+                        })
+            }
+            // the raw bytes when either can't be decoded
+            _ => code_a == code_b,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{instruction::tests::method, tests::fixture, AccessFlags, Utf8Index};
+
+    // pushes the entries of a String s after count unused ones, returns the String's index
+    fn string(cp: &mut ConstantPool, unused: usize, s: &str) -> CPIndex {
+        for i in 0..unused {
+            cp.push(ConstantPoolEntry::Integer(i as i32)).unwrap();
+        }
+        let value = cp.push(ConstantPoolEntry::Utf8(s.into())).unwrap();
+        let string = ConstantPoolEntry::String {
+            string_index: Utf8Index(value),
+        };
+        cp.push(string).unwrap()
+    }
+
+    // a method loading a String s into an empty pool, at an index moved by unused entries
+    fn ldc(unused: usize, s: &str) -> (Method, ConstantPool) {
+        // method always puts its name, descriptor and "Code" at #1 to #3
+        let (_, mut cp) = method("()V", AccessFlags::STATIC, vec![], vec![]);
+        let index = string(&mut cp, unused, s);
+        let code = vec![
+            Instruction::LdcW(index),
+            Instruction::Pop,
+            Instruction::Return,
+        ];
+        let (m, _) = method("()V", AccessFlags::STATIC, code, vec![]);
+        (m, cp)
+    }
+
+    #[test]
+    fn constants_by_value() {
+        let (a, cp_a) = ldc(0, "x");
+        let (b, cp_b) = ldc(3, "x");
+        assert_ne!(a, b);
+        assert!(a.code_equal(&b, &cp_a, &cp_b));
+
+        let (c, cp_c) = ldc(3, "y");
+        assert!(!a.code_equal(&c, &cp_a, &cp_c));
+    }
+
+    #[test]
+    fn exception_tables() {
+        let mut cp = ConstantPool::new();
+        let name = cp
+            .push(ConstantPoolEntry::Utf8("java/lang/Exception".into()))
+            .unwrap();
+        let class = cp
+            .push(ConstantPoolEntry::Class {
+                name_index: Utf8Index(name),
+            })
+            .unwrap();
+        let handler = |handler: u16, catch_type: Option<CPIndex>| {
+            let entry = ExceptionTableEntry {
+                start: 0,
+                end: 1,
+                handler,
+                catch_type,
+            };
+            let code = vec![Instruction::Nop, Instruction::Return, Instruction::Athrow];
+            method("()V", AccessFlags::STATIC, code, vec![entry]).0
+        };
+        let a = handler(2, Some(class));
+        assert!(a.code_equal(&handler(2, Some(class)), &cp, &cp));
+        assert!(!a.code_equal(&handler(1, Some(class)), &cp, &cp));
+        assert!(!a.code_equal(&handler(2, None), &cp, &cp));
+        // the same class at another index
+        let mut cp_b = cp.clone();
+        let other = cp_b
+            .push(ConstantPoolEntry::Class {
+                name_index: Utf8Index(name),
+            })
+            .unwrap();
+        assert!(a.code_equal(&handler(2, Some(other)), &cp, &cp_b));
+    }
+
+    #[test]
+    fn code_attributes_ignored() {
+        let class = fixture("Flow");
+        let cp = &class.constant_pool;
+        for m in class.methods.iter() {
+            let mut stripped = m.clone();
+            for a in stripped.attributes.iter_mut() {
+                if let AttributeInfo::Code {
+                    max_stack,
+                    attributes,
+                    ..
+                } = &mut a.info
+                {
+                    *max_stack += 1;
+                    attributes.clear();
+                }
+            }
+            assert_ne!(&stripped, m);
+            assert!(m.code_equal(&stripped, cp, cp));
+        }
+        // the methods of Flow all do something else
+        let methods = &class.methods;
+        for (i, a) in methods.iter().enumerate() {
+            for b in methods[i + 1..].iter() {
+                assert!(!a.code_equal(b, cp, cp));
+            }
+        }
+    }
+
+    #[test]
+    fn without_code() {
+        let (a, cp) = method(
+            "()V",
+            AccessFlags::STATIC,
+            vec![Instruction::Return],
+            vec![],
+        );
+        let mut abstract_method = a.clone();
+        abstract_method.attributes.clear();
+        assert!(abstract_method.code_equal(&abstract_method, &cp, &cp));
+        assert!(!a.code_equal(&abstract_method, &cp, &cp));
+        assert!(!abstract_method.code_equal(&a, &cp, &cp));
+    }
+}
@@ -0,0 +1,46 @@
+use std::collections::BTreeSet;
+
+use crate::{descriptor::class_names, JavaClass};
+
+// adds the classes of a descriptor or signature, nothing if it doesn't parse
+fn add_class_names<'a>(s: &'a str, names: &mut BTreeSet<&'a str>) {
+    for range in class_names(s).unwrap_or_default() {
+        names.insert(&s[range]);
+    }
+}
+
+impl JavaClass {
+    // The internal names of the classes this one refers to, sorted: the Class entries (which
+    // cover the exception tables), and the classes in descriptors, signatures and annotations.
+    // Arrays are unwrapped to their element class and the class itself isn't included.
+    pub fn referenced_classes(&self) -> Vec<&str> {
+        let cp = &self.constant_pool;
+        let mut names = BTreeSet::new();
+        for (_, name_index) in cp.class_entries() {
+            match name_index.resolve(cp) {
+                // array classes are named by their descriptor
+                Ok(name) if name.starts_with('[') => add_class_names(name, &mut names),
+                Ok(name) => {
+                    names.insert(name);
+                }
+                Err(_) => (),
+            }
+        }
+        for index in self.descriptor_indices() {
+            if let Ok(s) = index.resolve(cp) {
+                add_class_names(s, &mut names);
+            }
+        }
+        if let Ok(name) = self.this_class.name(cp) {
+            names.remove(name);
+        }
+        names.into_iter().collect()
+    }
+    // referenced_classes, owned
+    pub fn dependencies(&self) -> BTreeSet<String> {
+        self.referenced_classes()
+            .into_iter()
+            .map(str::to_string)
+            .collect()
+    }
+}
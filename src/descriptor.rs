@@ -0,0 +1,534 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::{self, Display, Formatter},
+    ops::Range,
+};
+
+use crate::{
+    Annotation, Attribute, AttributeInfo, CPIndex, ConstantPool, ConstantPoolEntry, ElementValue,
+    JavaClass, Method, Utf8Index,
+};
+
+// Finds the class names in a descriptor or a signature (JVMS 4.3 and 4.7.9.1), as byte ranges.
+// Only whole names are reported, so com/old/Name is never found inside com/old/NameTwo or inside
+// the simple name of an inner class (the Inner in Lcom/old/Name<TT;>.Inner;).
+struct NameFinder<'a> {
+    s: &'a [u8],
+    pos: usize,
+    names: Vec<Range<usize>>,
+}
+
+impl NameFinder<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.s.get(self.pos).copied()
+    }
+    fn eat(&mut self, c: u8) -> bool {
+        let eaten = self.peek() == Some(c);
+        if eaten {
+            self.pos += 1;
+        }
+        eaten
+    }
+    fn expect(&mut self, c: u8) -> Option<()> {
+        self.eat(c).then_some(())
+    }
+    // a non empty identifier, up to (not including) one of ends
+    fn identifier(&mut self, ends: &[u8]) -> Option<Range<usize>> {
+        let start = self.pos;
+        while !ends.contains(&self.peek()?) {
+            self.pos += 1;
+        }
+        (self.pos > start).then_some(start..self.pos)
+    }
+    fn signature(&mut self) -> Option<()> {
+        if self.peek() == Some(b'<') {
+            self.type_parameters()?;
+        }
+        if self.eat(b'(') {
+            while !self.eat(b')') {
+                self.field_type()?;
+            }
+            if !self.eat(b'V') {
+                self.field_type()?;
+            }
+            while self.eat(b'^') {
+                self.field_type()?;
+            }
+        } else {
+            // one type for fields, the super class followed by the interfaces for classes
+            self.field_type()?;
+            while self.pos < self.s.len() {
+                self.field_type()?;
+            }
+        }
+        (self.pos == self.s.len()).then_some(())
+    }
+    fn type_parameters(&mut self) -> Option<()> {
+        self.expect(b'<')?;
+        while !self.eat(b'>') {
+            self.identifier(b":")?;
+            self.expect(b':')?;
+            // the class bound can be empty, the interface bounds are each preceded by a :
+            if self.peek()? != b':' {
+                self.field_type()?;
+            }
+            while self.eat(b':') {
+                self.field_type()?;
+            }
+        }
+        Some(())
+    }
+    fn type_arguments(&mut self) -> Option<()> {
+        if !self.eat(b'<') {
+            return Some(());
+        }
+        while !self.eat(b'>') {
+            if !self.eat(b'*') {
+                if !self.eat(b'+') {
+                    self.eat(b'-');
+                }
+                self.field_type()?;
+            }
+        }
+        Some(())
+    }
+    fn field_type(&mut self) -> Option<()> {
+        match self.peek()? {
+            b'B' | b'C' | b'D' | b'F' | b'I' | b'J' | b'S' | b'Z' => {
+                self.pos += 1;
+                Some(())
+            }
+            b'[' => {
+                self.pos += 1;
+                self.field_type()
+            }
+            b'T' => {
+                self.pos += 1;
+                self.identifier(b";")?;
+                self.expect(b';')
+            }
+            b'L' => {
+                self.pos += 1;
+                let name = self.identifier(b";<.")?;
+                self.names.push(name);
+                self.type_arguments()?;
+                while self.eat(b'.') {
+                    self.identifier(b";<.")?;
+                    self.type_arguments()?;
+                }
+                self.expect(b';')
+            }
+            _ => None,
+        }
+    }
+}
+
+// the byte ranges of the class names in a descriptor or a signature, None if it doesn't parse
+pub fn class_names(s: &str) -> Option<Vec<Range<usize>>> {
+    let mut finder = NameFinder {
+        s: s.as_bytes(),
+        pos: 0,
+        names: Vec::new(),
+    };
+    finder.signature()?;
+    Some(finder.names)
+}
+
+// the local variable or operand stack slots taken by the type starting at pos (0 for V), and
+// where it ends
+fn type_slots(s: &[u8], pos: usize) -> Option<(u16, usize)> {
+    match *s.get(pos)? {
+        b'J' | b'D' => Some((2, pos + 1)),
+        b'B' | b'C' | b'F' | b'I' | b'S' | b'Z' => Some((1, pos + 1)),
+        b'V' => Some((0, pos + 1)),
+        b'L' => Some((1, pos + s[pos..].iter().position(|c| *c == b';')? + 1)),
+        b'[' => {
+            let element = pos + s[pos..].iter().position(|c| *c != b'[')?;
+            Some((1, type_slots(s, element)?.1))
+        }
+        _ => None,
+    }
+}
+
+// the slots taken by the value of a field descriptor
+pub fn field_slots(descriptor: &str) -> Option<u16> {
+    match type_slots(descriptor.as_bytes(), 0)? {
+        (slots, end) if slots > 0 && end == descriptor.len() => Some(slots),
+        _ => None,
+    }
+}
+
+// the slots taken by the arguments and by the return value of a method descriptor
+pub fn method_slots(descriptor: &str) -> Option<(u16, u16)> {
+    let s = descriptor.as_bytes();
+    if s.first() != Some(&b'(') {
+        return None;
+    }
+    let mut pos = 1;
+    let mut arguments = 0u16;
+    while *s.get(pos)? != b')' {
+        let (slots, end) = type_slots(s, pos)?;
+        if slots == 0 {
+            return None;
+        }
+        arguments = arguments.checked_add(slots)?;
+        pos = end;
+    }
+    let (ret, end) = type_slots(s, pos + 1)?;
+    (end == s.len()).then_some((arguments, ret))
+}
+
+// the type of a field, a parameter or a return value, parsed from a descriptor
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldType {
+    Byte,
+    Char,
+    Double,
+    Float,
+    Int,
+    Long,
+    Short,
+    Boolean,
+    // the internal name, like java/lang/String
+    Object(String),
+    Array(Box<FieldType>),
+}
+
+// the parameter types and the return type (None for void) of a method descriptor
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MethodDescriptor {
+    pub parameters: Vec<FieldType>,
+    pub return_type: Option<FieldType>,
+}
+
+impl FieldType {
+    // None if the descriptor isn't exactly one field type
+    pub fn parse(descriptor: &str) -> Option<Self> {
+        match FieldType::parse_at(descriptor, 0)? {
+            (t, end) if end == descriptor.len() => Some(t),
+            _ => None,
+        }
+    }
+    // the type starting at pos, and where it ends
+    fn parse_at(s: &str, pos: usize) -> Option<(Self, usize)> {
+        let t = match s.as_bytes().get(pos)? {
+            b'B' => FieldType::Byte,
+            b'C' => FieldType::Char,
+            b'D' => FieldType::Double,
+            b'F' => FieldType::Float,
+            b'I' => FieldType::Int,
+            b'J' => FieldType::Long,
+            b'S' => FieldType::Short,
+            b'Z' => FieldType::Boolean,
+            b'L' => {
+                let end = pos + s[pos..].find(';')?;
+                return Some((FieldType::Object(s[pos + 1..end].to_string()), end + 1));
+            }
+            b'[' => {
+                let (element, end) = FieldType::parse_at(s, pos + 1)?;
+                return Some((FieldType::Array(Box::new(element)), end));
+            }
+            _ => return None,
+        };
+        Some((t, pos + 1))
+    }
+}
+
+// as written in Java, like java.lang.String[] for [Ljava/lang/String;
+impl Display for FieldType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            FieldType::Byte => "byte",
+            FieldType::Char => "char",
+            FieldType::Double => "double",
+            FieldType::Float => "float",
+            FieldType::Int => "int",
+            FieldType::Long => "long",
+            FieldType::Short => "short",
+            FieldType::Boolean => "boolean",
+            FieldType::Object(name) => return write!(f, "{}", name.replace('/', ".")),
+            FieldType::Array(element) => return write!(f, "{}[]", element),
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl MethodDescriptor {
+    // None if the descriptor doesn't parse
+    pub fn parse(descriptor: &str) -> Option<Self> {
+        if !descriptor.starts_with('(') {
+            return None;
+        }
+        let mut pos = 1;
+        let mut parameters = Vec::new();
+        while *descriptor.as_bytes().get(pos)? != b')' {
+            let (parameter, end) = FieldType::parse_at(descriptor, pos)?;
+            parameters.push(parameter);
+            pos = end;
+        }
+        let return_type = match &descriptor[pos + 1..] {
+            "V" => None,
+            ret => Some(FieldType::parse(ret)?),
+        };
+        Some(MethodDescriptor {
+            parameters,
+            return_type,
+        })
+    }
+    // the method as declared in Java, like void foo(java.lang.String, int[])
+    pub fn java_method(&self, name: &str) -> String {
+        let parameters = self
+            .parameters
+            .iter()
+            .map(|p| p.to_string())
+            .collect::<Vec<_>>();
+        let ret = match &self.return_type {
+            Some(t) => t.to_string(),
+            None => "void".to_string(),
+        };
+        format!("{} {}({})", ret, name, parameters.join(", "))
+    }
+}
+
+impl Method {
+    // The method as declared in Java (see MethodDescriptor::java_method), or its name and raw
+    // descriptor when the descriptor doesn't parse.
+    pub fn signature_string(&self, cp: &ConstantPool) -> String {
+        let name = self.name_index.resolve(cp).unwrap_or("?");
+        let descriptor = self.descriptor_index.resolve(cp).unwrap_or("?");
+        match MethodDescriptor::parse(descriptor) {
+            Some(d) => d.java_method(name),
+            None => format!("{}{}", name, descriptor),
+        }
+    }
+}
+
+fn annotation_descriptors(annotation: &Annotation, out: &mut BTreeSet<Utf8Index>) {
+    out.insert(annotation.type_index);
+    for pair in annotation.element_value_pairs.iter() {
+        element_value_descriptors(&pair.value, out);
+    }
+}
+
+fn element_value_descriptors(value: &ElementValue, out: &mut BTreeSet<Utf8Index>) {
+    match value {
+        ElementValue::Enum {
+            type_name_index, ..
+        } => {
+            out.insert(*type_name_index);
+        }
+        ElementValue::Class(index) => {
+            out.insert(*index);
+        }
+        ElementValue::Annotation(annotation) => annotation_descriptors(annotation, out),
+        ElementValue::Array(values) => {
+            for v in values {
+                element_value_descriptors(v, out);
+            }
+        }
+        _ => (),
+    }
+}
+
+// adds the descriptors and signatures referenced from attributes, the ones that aren't modeled
+// are read from the raw bytes
+fn attribute_descriptors(
+    attributes: &[Attribute],
+    cp: &ConstantPool,
+    out: &mut BTreeSet<Utf8Index>,
+) {
+    for a in attributes {
+        match (&a.info, a.name(cp)) {
+            (AttributeInfo::Any(b), Some("Signature")) if b.len() == 2 => {
+                out.insert(Utf8Index(CPIndex(u16::from_be_bytes([b[0], b[1]]))));
+            }
+            (AttributeInfo::Any(b), Some("LocalVariableTable" | "LocalVariableTypeTable")) => {
+                // a u16 count followed by 10 byte entries, with the descriptor (or signature) index
+                // at offset 6
+                for e in b.get(2..).unwrap_or_default().chunks_exact(10) {
+                    out.insert(Utf8Index(CPIndex(u16::from_be_bytes([e[6], e[7]]))));
+                }
+            }
+            (AttributeInfo::Code { attributes, .. }, _) => {
+                attribute_descriptors(attributes, cp, out);
+            }
+            (AttributeInfo::Record { components }, _) => {
+                for c in components {
+                    out.insert(c.descriptor_index);
+                    attribute_descriptors(&c.attributes, cp, out);
+                }
+            }
+            (AttributeInfo::AnnotationDefault(value), _) => element_value_descriptors(value, out),
+            (
+                AttributeInfo::RuntimeVisibleAnnotations(annotations)
+                | AttributeInfo::RuntimeInvisibleAnnotations(annotations),
+                _,
+            ) => {
+                for annotation in annotations {
+                    annotation_descriptors(annotation, out);
+                }
+            }
+            (
+                AttributeInfo::RuntimeVisibleParameterAnnotations(parameters)
+                | AttributeInfo::RuntimeInvisibleParameterAnnotations(parameters),
+                _,
+            ) => {
+                for annotation in parameters.iter().flatten() {
+                    annotation_descriptors(annotation, out);
+                }
+            }
+            (
+                AttributeInfo::RuntimeVisibleTypeAnnotations(annotations)
+                | AttributeInfo::RuntimeInvisibleTypeAnnotations(annotations),
+                _,
+            ) => {
+                for a in annotations {
+                    annotation_descriptors(&a.annotation, out);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+// the entries the descriptors and signatures found above are moved to, see remap_descriptors
+type Remap = BTreeMap<Utf8Index, Utf8Index>;
+
+fn remap(index: &mut Utf8Index, map: &Remap) {
+    if let Some(new) = map.get(index) {
+        *index = *new;
+    }
+}
+
+// same for an index written in raw attribute bytes
+fn remap_raw(bytes: &mut [u8], map: &Remap) {
+    let mut index = Utf8Index(CPIndex(u16::from_be_bytes([bytes[0], bytes[1]])));
+    remap(&mut index, map);
+    bytes[..2].copy_from_slice(&index.0 .0.to_be_bytes());
+}
+
+fn remap_annotation(annotation: &mut Annotation, map: &Remap) {
+    remap(&mut annotation.type_index, map);
+    for pair in annotation.element_value_pairs.iter_mut() {
+        remap_element_value(&mut pair.value, map);
+    }
+}
+
+fn remap_element_value(value: &mut ElementValue, map: &Remap) {
+    match value {
+        ElementValue::Enum {
+            type_name_index, ..
+        } => remap(type_name_index, map),
+        ElementValue::Class(index) => remap(index, map),
+        ElementValue::Annotation(annotation) => remap_annotation(annotation, map),
+        ElementValue::Array(values) => {
+            for v in values {
+                remap_element_value(v, map);
+            }
+        }
+        _ => (),
+    }
+}
+
+fn remap_attributes(attributes: &mut [Attribute], cp: &ConstantPool, map: &Remap) {
+    for a in attributes {
+        let name = a.name(cp);
+        match (&mut a.info, name) {
+            (AttributeInfo::Any(b), Some("Signature")) if b.len() == 2 => remap_raw(b, map),
+            (AttributeInfo::Any(b), Some("LocalVariableTable" | "LocalVariableTypeTable")) => {
+                for e in b.get_mut(2..).unwrap_or_default().chunks_exact_mut(10) {
+                    remap_raw(&mut e[6..], map);
+                }
+            }
+            (AttributeInfo::Code { attributes, .. }, _) => remap_attributes(attributes, cp, map),
+            (AttributeInfo::Record { components }, _) => {
+                for c in components {
+                    remap(&mut c.descriptor_index, map);
+                    remap_attributes(&mut c.attributes, cp, map);
+                }
+            }
+            (AttributeInfo::AnnotationDefault(value), _) => remap_element_value(value, map),
+            (
+                AttributeInfo::RuntimeVisibleAnnotations(annotations)
+                | AttributeInfo::RuntimeInvisibleAnnotations(annotations),
+                _,
+            ) => {
+                for annotation in annotations {
+                    remap_annotation(annotation, map);
+                }
+            }
+            (
+                AttributeInfo::RuntimeVisibleParameterAnnotations(parameters)
+                | AttributeInfo::RuntimeInvisibleParameterAnnotations(parameters),
+                _,
+            ) => {
+                for annotation in parameters.iter_mut().flatten() {
+                    remap_annotation(annotation, map);
+                }
+            }
+            (
+                AttributeInfo::RuntimeVisibleTypeAnnotations(annotations)
+                | AttributeInfo::RuntimeInvisibleTypeAnnotations(annotations),
+                _,
+            ) => {
+                for a in annotations {
+                    remap_annotation(&mut a.annotation, map);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+impl JavaClass {
+    // The Utf8 entries used as descriptors or signatures: by the pool, the fields, the methods,
+    // the record components, the annotations, and the Signature and local variable attributes.
+    pub fn descriptor_indices(&self) -> BTreeSet<Utf8Index> {
+        let cp = &self.constant_pool;
+        let mut descriptors = BTreeSet::new();
+        for (_, e) in cp.iter() {
+            if let ConstantPoolEntry::NameAndType {
+                descriptor_index, ..
+            }
+            | ConstantPoolEntry::MethodType { descriptor_index } = e
+            {
+                descriptors.insert(*descriptor_index);
+            }
+        }
+        for f in self.fields.iter() {
+            descriptors.insert(f.descriptor_index);
+            attribute_descriptors(&f.attributes, cp, &mut descriptors);
+        }
+        for m in self.methods.iter() {
+            descriptors.insert(m.descriptor_index);
+            attribute_descriptors(&m.attributes, cp, &mut descriptors);
+        }
+        attribute_descriptors(&self.attributes, cp, &mut descriptors);
+        descriptors
+    }
+    // Points the descriptors and signatures (the ones of descriptor_indices) that are keys of
+    // map at the entry they map to. The Utf8 entries themselves are left as they are.
+    pub fn remap_descriptors(&mut self, map: &BTreeMap<Utf8Index, Utf8Index>) {
+        for index in self.constant_pool.indices_sorted() {
+            if let Some(
+                ConstantPoolEntry::NameAndType {
+                    descriptor_index, ..
+                }
+                | ConstantPoolEntry::MethodType { descriptor_index },
+            ) = self.constant_pool.get_mut(index)
+            {
+                remap(descriptor_index, map);
+            }
+        }
+        let cp = &self.constant_pool;
+        for f in self.fields.iter_mut() {
+            remap(&mut f.descriptor_index, map);
+            remap_attributes(&mut f.attributes, cp, map);
+        }
+        for m in self.methods.iter_mut() {
+            remap(&mut m.descriptor_index, map);
+            remap_attributes(&mut m.attributes, cp, map);
+        }
+        remap_attributes(&mut self.attributes, cp, map);
+    }
+}
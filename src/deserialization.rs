@@ -1,304 +1,630 @@
-use std::{
-    collections::HashMap,
-    io::{Cursor, Error, ErrorKind, Read},
-};
+use std::io::{Cursor, Error, Read};
 
 use crate::{
-    AccessFlags, Attribute, AttributeInfo, CPIndex, CodeByte, ConstantPool, ConstantPoolEntry,
-    ExceptionTableEntry, Field, JavaClass, Method, ReferenceKind,
+    modified_utf8::decode_modified_utf8, AccessFlags, Annotation, Attribute, AttributeInfo, CPIndex,
+    ClassIndex, CodeByte, ConstantPool, ConstantPoolEntry, ConstantTag, DoubleBits, ElementValue,
+    ElementValuePair, Field, FloatBits, JavaClass, LazyAttribute, LocalVarTarget, Method,
+    NameAndTypeIndex, ReferenceKind, TargetInfo, Utf8Index,
 };
 
 pub trait Deserialize {
-    fn deserialize(bytes: &mut Cursor<Vec<u8>>) -> Result<Self, Error>
+    // the fewest bytes a value takes in a class file, which bounds how many values the rest of
+    // the input can hold whatever count it claims
+    const MIN_SIZE: usize = 1;
+
+    fn deserialize(bytes: &mut Cursor<&[u8]>) -> Result<Self, Error>
     where
         Self: Sized;
 }
 
+// Adds where in the input the value that failed started to an error. The offset is from the start
+// of what is being read: the class file, or the body of an attribute when resolving one.
+pub fn at_offset(e: Error, offset: u64) -> Error {
+    let message = e.to_string();
+    let (text, end) = match message.strip_suffix('.') {
+        Some(text) => (text, "."),
+        None => (message.as_str(), ""),
+    };
+    Error::new(e.kind(), format!("{} (at byte {}){}", text, offset, end))
+}
+
+// number of bytes left to read in the input
+pub fn remaining(bytes: &Cursor<&[u8]>) -> usize {
+    (bytes.get_ref().len() as u64).saturating_sub(bytes.position()) as usize
+}
+
+// reads exactly len bytes, checking beforehand that the input is long enough so that a bogus
+// length can't make us allocate a huge buffer for nothing
+pub fn read_bytes(bytes: &mut Cursor<&[u8]>, len: usize) -> Result<Vec<u8>, Error> {
+    let offset = bytes.position();
+    let left = remaining(bytes);
+    if len > left {
+        let e = Error::other(format!(
+            "Length of {} bytes exceeds the {} bytes left in the input.",
+            len, left
+        ));
+        return Err(at_offset(e, offset));
+    }
+    let mut buf = vec![0u8; len];
+    bytes
+        .read_exact(buf.as_mut_slice())
+        .map_err(|e| at_offset(e, offset))?;
+    Ok(buf)
+}
+
 impl Deserialize for u8 {
-    fn deserialize(bytes: &mut Cursor<Vec<u8>>) -> Result<u8, Error> {
+    fn deserialize(bytes: &mut Cursor<&[u8]>) -> Result<u8, Error> {
         Ok(u8::from_be_bytes(Deserialize::deserialize(bytes)?))
     }
 }
 
 impl Deserialize for u16 {
-    fn deserialize(bytes: &mut Cursor<Vec<u8>>) -> Result<u16, Error> {
+    const MIN_SIZE: usize = 2;
+
+    fn deserialize(bytes: &mut Cursor<&[u8]>) -> Result<u16, Error> {
         Ok(u16::from_be_bytes(Deserialize::deserialize(bytes)?))
     }
 }
 
 impl Deserialize for u32 {
-    fn deserialize(bytes: &mut Cursor<Vec<u8>>) -> Result<u32, Error> {
+    const MIN_SIZE: usize = 4;
+
+    fn deserialize(bytes: &mut Cursor<&[u8]>) -> Result<u32, Error> {
         Ok(u32::from_be_bytes(Deserialize::deserialize(bytes)?))
     }
 }
 
 impl Deserialize for u64 {
-    fn deserialize(bytes: &mut Cursor<Vec<u8>>) -> Result<u64, Error> {
+    const MIN_SIZE: usize = 8;
+
+    fn deserialize(bytes: &mut Cursor<&[u8]>) -> Result<u64, Error> {
         Ok(u64::from_be_bytes(Deserialize::deserialize(bytes)?))
     }
 }
 
+impl Deserialize for i8 {
+    fn deserialize(bytes: &mut Cursor<&[u8]>) -> Result<i8, Error> {
+        Ok(i8::from_be_bytes(Deserialize::deserialize(bytes)?))
+    }
+}
+
+impl Deserialize for i16 {
+    const MIN_SIZE: usize = 2;
+
+    fn deserialize(bytes: &mut Cursor<&[u8]>) -> Result<i16, Error> {
+        Ok(i16::from_be_bytes(Deserialize::deserialize(bytes)?))
+    }
+}
+
 impl Deserialize for i32 {
-    fn deserialize(bytes: &mut Cursor<Vec<u8>>) -> Result<i32, Error> {
+    const MIN_SIZE: usize = 4;
+
+    fn deserialize(bytes: &mut Cursor<&[u8]>) -> Result<i32, Error> {
         Ok(i32::from_be_bytes(Deserialize::deserialize(bytes)?))
     }
 }
 
 impl Deserialize for i64 {
-    fn deserialize(bytes: &mut Cursor<Vec<u8>>) -> Result<i64, Error> {
+    const MIN_SIZE: usize = 8;
+
+    fn deserialize(bytes: &mut Cursor<&[u8]>) -> Result<i64, Error> {
         Ok(i64::from_be_bytes(Deserialize::deserialize(bytes)?))
     }
 }
 
 impl Deserialize for f32 {
-    fn deserialize(bytes: &mut Cursor<Vec<u8>>) -> Result<f32, Error> {
+    const MIN_SIZE: usize = 4;
+
+    fn deserialize(bytes: &mut Cursor<&[u8]>) -> Result<f32, Error> {
         Ok(f32::from_be_bytes(Deserialize::deserialize(bytes)?))
     }
 }
 
 impl Deserialize for f64 {
-    fn deserialize(bytes: &mut Cursor<Vec<u8>>) -> Result<f64, Error> {
+    const MIN_SIZE: usize = 8;
+
+    fn deserialize(bytes: &mut Cursor<&[u8]>) -> Result<f64, Error> {
         Ok(f64::from_be_bytes(Deserialize::deserialize(bytes)?))
     }
 }
 
 impl<const C: usize> Deserialize for [u8; C] {
-    fn deserialize(bytes: &mut Cursor<Vec<u8>>) -> Result<Self, Error> {
+    const MIN_SIZE: usize = C;
+
+    fn deserialize(bytes: &mut Cursor<&[u8]>) -> Result<Self, Error> {
+        let offset = bytes.position();
         let mut buf = [0u8; C];
-        bytes.read_exact(&mut buf)?;
+        bytes
+            .read_exact(&mut buf)
+            .map_err(|e| at_offset(e, offset))?;
         Ok(buf)
     }
 }
 
+// A list preceded by its u16 count, which is what most lists of the format are. The others (the
+// u8 counted parameters, the u32 counted code) must go through deserialize_vec_with_count.
 impl<T> Deserialize for Vec<T>
 where
     T: Deserialize,
 {
-    fn deserialize(bytes: &mut Cursor<Vec<u8>>) -> Result<Self, Error> {
-        let count = u16::deserialize(bytes)? as usize;
-        let mut res = Vec::with_capacity(count);
+    const MIN_SIZE: usize = 2;
 
-        for _ in 0..count {
-            res.push(T::deserialize(bytes)?);
-        }
-        Ok(res)
+    fn deserialize(bytes: &mut Cursor<&[u8]>) -> Result<Self, Error> {
+        deserialize_vec_with_count::<u16, T>(bytes)
     }
 }
 
+// reads count values
+pub fn read_vec<T: Deserialize>(bytes: &mut Cursor<&[u8]>, count: usize) -> Result<Vec<T>, Error> {
+    // don't trust count any further than what the input can hold
+    let mut res = Vec::with_capacity(count.min(remaining(bytes) / T::MIN_SIZE.max(1)));
+    for _ in 0..count {
+        res.push(T::deserialize(bytes)?);
+    }
+    Ok(res)
+}
+
+// a list preceded by a count of type C (u8 for MethodParameters and the parameter annotations,
+// u32 for the code of a Code attribute)
+pub fn deserialize_vec_with_count<C: Deserialize + Into<u64>, T: Deserialize>(
+    bytes: &mut Cursor<&[u8]>,
+) -> Result<Vec<T>, Error> {
+    let count = C::deserialize(bytes)?;
+    read_vec(bytes, count.into() as usize)
+}
+
 impl Deserialize for CPIndex {
-    fn deserialize(bytes: &mut Cursor<Vec<u8>>) -> Result<Self, Error> {
-        u16::deserialize(bytes)?.try_into().map_err(|_| {
-            Error::new(
-                ErrorKind::Other,
-                "Error when trying to convert u16 to CPIndex (value is 0).",
-            )
+    const MIN_SIZE: usize = 2;
+
+    fn deserialize(bytes: &mut Cursor<&[u8]>) -> Result<Self, Error> {
+        let offset = bytes.position();
+        CPIndex::new(u16::deserialize(bytes)?).ok_or_else(|| {
+            let e = Error::other("Error when trying to convert u16 to CPIndex (value is 0).");
+            at_offset(e, offset)
         })
     }
 }
 
+// 0 is read as None, for the places where the format allows "no entry"
+impl Deserialize for Option<CPIndex> {
+    const MIN_SIZE: usize = 2;
+
+    fn deserialize(bytes: &mut Cursor<&[u8]>) -> Result<Self, Error> {
+        Ok(CPIndex::new(u16::deserialize(bytes)?))
+    }
+}
+
+// the kind of the entry isn't checked here, see typed_index
+impl Deserialize for Utf8Index {
+    const MIN_SIZE: usize = 2;
+
+    fn deserialize(bytes: &mut Cursor<&[u8]>) -> Result<Self, Error> {
+        Ok(Self(CPIndex::deserialize(bytes)?))
+    }
+}
+impl Deserialize for ClassIndex {
+    const MIN_SIZE: usize = 2;
+
+    fn deserialize(bytes: &mut Cursor<&[u8]>) -> Result<Self, Error> {
+        Ok(Self(CPIndex::deserialize(bytes)?))
+    }
+}
+impl Deserialize for Option<ClassIndex> {
+    const MIN_SIZE: usize = 2;
+
+    fn deserialize(bytes: &mut Cursor<&[u8]>) -> Result<Self, Error> {
+        Ok(Option::<CPIndex>::deserialize(bytes)?.map(ClassIndex))
+    }
+}
+impl Deserialize for NameAndTypeIndex {
+    const MIN_SIZE: usize = 2;
+
+    fn deserialize(bytes: &mut Cursor<&[u8]>) -> Result<Self, Error> {
+        Ok(Self(CPIndex::deserialize(bytes)?))
+    }
+}
+
+impl Deserialize for FloatBits {
+    const MIN_SIZE: usize = 4;
+
+    fn deserialize(bytes: &mut Cursor<&[u8]>) -> Result<Self, Error> {
+        Ok(Self(u32::deserialize(bytes)?))
+    }
+}
+
+impl Deserialize for DoubleBits {
+    const MIN_SIZE: usize = 8;
+
+    fn deserialize(bytes: &mut Cursor<&[u8]>) -> Result<Self, Error> {
+        Ok(Self(u64::deserialize(bytes)?))
+    }
+}
+
 impl Deserialize for ReferenceKind {
-    fn deserialize(bytes: &mut Cursor<Vec<u8>>) -> Result<Self, Error> {
+    fn deserialize(bytes: &mut Cursor<&[u8]>) -> Result<Self, Error> {
+        let offset = bytes.position();
         u8::deserialize(bytes)?.try_into().map_err(|_| {
-            Error::new(
-                ErrorKind::Other,
-                "Error when trying to convert u8 to ReferenceKind",
-            )
+            let e = Error::other("Error when trying to convert u8 to ReferenceKind");
+            at_offset(e, offset)
         })
     }
 }
 
 impl Deserialize for ConstantPoolEntry {
-    fn deserialize(bytes: &mut Cursor<Vec<u8>>) -> Result<Self, Error> {
-        let tag = u8::deserialize(bytes)?;
+    fn deserialize(bytes: &mut Cursor<&[u8]>) -> Result<Self, Error> {
+        let offset = bytes.position();
+        let tag = ConstantTag::try_from(u8::deserialize(bytes)?)
+            .map_err(|_| at_offset(Error::other("Unkown tag on ConstantPoolEntry"), offset))?;
         match tag {
-            07 => Ok(ConstantPoolEntry::Class {
-                name_index: CPIndex::deserialize(bytes)?,
+            ConstantTag::Class => Ok(ConstantPoolEntry::Class {
+                name_index: Utf8Index::deserialize(bytes)?,
             }),
-            09 => Ok(ConstantPoolEntry::FieldRef {
-                class_index: CPIndex::deserialize(bytes)?,
-                name_and_type_index: CPIndex::deserialize(bytes)?,
+            ConstantTag::FieldRef => Ok(ConstantPoolEntry::FieldRef {
+                class_index: ClassIndex::deserialize(bytes)?,
+                name_and_type_index: NameAndTypeIndex::deserialize(bytes)?,
             }),
-            10 => Ok(ConstantPoolEntry::MethodRef {
-                class_index: CPIndex::deserialize(bytes)?,
-                name_and_type_index: CPIndex::deserialize(bytes)?,
+            ConstantTag::MethodRef => Ok(ConstantPoolEntry::MethodRef {
+                class_index: ClassIndex::deserialize(bytes)?,
+                name_and_type_index: NameAndTypeIndex::deserialize(bytes)?,
             }),
-            11 => Ok(ConstantPoolEntry::InterfaceMethodRef {
-                class_index: CPIndex::deserialize(bytes)?,
-                name_and_type_index: CPIndex::deserialize(bytes)?,
+            ConstantTag::InterfaceMethodRef => Ok(ConstantPoolEntry::InterfaceMethodRef {
+                class_index: ClassIndex::deserialize(bytes)?,
+                name_and_type_index: NameAndTypeIndex::deserialize(bytes)?,
             }),
-            08 => Ok(ConstantPoolEntry::String {
-                string_index: CPIndex::deserialize(bytes)?,
+            ConstantTag::String => Ok(ConstantPoolEntry::String {
+                string_index: Utf8Index::deserialize(bytes)?,
             }),
-            03 => Ok(ConstantPoolEntry::Integer(i32::deserialize(bytes)?)),
-            04 => Ok(ConstantPoolEntry::Float(f32::deserialize(bytes)?)),
-            05 => Ok(ConstantPoolEntry::Long(i64::deserialize(bytes)?)),
-            06 => Ok(ConstantPoolEntry::Double(f64::deserialize(bytes)?)),
-            12 => Ok(ConstantPoolEntry::NameAndType {
-                name_index: CPIndex::deserialize(bytes)?,
-                descriptor_index: CPIndex::deserialize(bytes)?,
+            ConstantTag::Integer => Ok(ConstantPoolEntry::Integer(i32::deserialize(bytes)?)),
+            ConstantTag::Float => Ok(ConstantPoolEntry::Float(FloatBits::deserialize(bytes)?)),
+            ConstantTag::Long => Ok(ConstantPoolEntry::Long(i64::deserialize(bytes)?)),
+            ConstantTag::Double => Ok(ConstantPoolEntry::Double(DoubleBits::deserialize(bytes)?)),
+            ConstantTag::NameAndType => Ok(ConstantPoolEntry::NameAndType {
+                name_index: Utf8Index::deserialize(bytes)?,
+                descriptor_index: Utf8Index::deserialize(bytes)?,
             }),
-            01 => {
+            ConstantTag::Utf8 => {
                 let len = u16::deserialize(bytes)?;
-                let mut buf = vec![0u8; len as usize];
-                bytes.read_exact(buf.as_mut_slice())?;
-                Ok(ConstantPoolEntry::Utf8(
-                    String::from_utf8_lossy(&buf).into(),
-                ))
+                let buf = read_bytes(bytes, len as usize)?;
+                Ok(ConstantPoolEntry::Utf8(decode_modified_utf8(&buf)?))
             }
-            15 => Ok(ConstantPoolEntry::MethodHandle {
+            ConstantTag::MethodHandle => Ok(ConstantPoolEntry::MethodHandle {
                 reference_kind: ReferenceKind::deserialize(bytes)?,
                 reference_index: CPIndex::deserialize(bytes)?,
             }),
-            16 => Ok(ConstantPoolEntry::MethodType {
-                descriptor_index: CPIndex::deserialize(bytes)?,
+            ConstantTag::MethodType => Ok(ConstantPoolEntry::MethodType {
+                descriptor_index: Utf8Index::deserialize(bytes)?,
+            }),
+            ConstantTag::Dynamic => Ok(ConstantPoolEntry::Dynamic {
+                bootstrap_method_attr_index: u16::deserialize(bytes)?,
+                name_and_type_index: NameAndTypeIndex::deserialize(bytes)?,
             }),
-            18 => Ok(ConstantPoolEntry::InvokeDynamic {
+            ConstantTag::InvokeDynamic => Ok(ConstantPoolEntry::InvokeDynamic {
                 bootstrap_method_attr_index: u16::deserialize(bytes)?,
-                name_and_type_index: CPIndex::deserialize(bytes)?,
+                name_and_type_index: NameAndTypeIndex::deserialize(bytes)?,
+            }),
+            ConstantTag::Module => Ok(ConstantPoolEntry::Module {
+                name_index: Utf8Index::deserialize(bytes)?,
+            }),
+            ConstantTag::Package => Ok(ConstantPoolEntry::Package {
+                name_index: Utf8Index::deserialize(bytes)?,
             }),
-            _ => Err(Error::new(
-                ErrorKind::Other,
-                "Unkown tag on ConstantPoolEntry",
-            )),
         }
     }
 }
 
 impl Deserialize for ConstantPool {
-    fn deserialize(bytes: &mut Cursor<Vec<u8>>) -> Result<ConstantPool, Error> {
+    fn deserialize(bytes: &mut Cursor<&[u8]>) -> Result<ConstantPool, Error> {
+        // count is the number of slots plus one, since slot 0 doesn't exist it can't be 0
+        let offset = bytes.position();
         let count = u16::deserialize(bytes)?;
+        if count == 0 {
+            return Err(at_offset(Error::other("Constant pool count is 0."), offset));
+        }
         let mut index = 1u16; // indices starts at 1
-        let mut map = HashMap::new();
+        let mut pool = ConstantPool::new();
 
         while index < count {
             let entry = ConstantPoolEntry::deserialize(bytes)?;
-            let size = entry.size();
+            // computed in u32 so that it can't overflow, count is at most u16::MAX
+            let next = index as u32 + entry.size() as u32;
+            if next > count as u32 {
+                return Err(Error::other(format!(
+                    "Constant pool entry at index {} takes two slots but is the last entry \
+                     (constant pool count is {}).",
+                    index, count
+                )));
+            }
 
-            map.insert(index.try_into().unwrap(), entry);
-            index += size;
+            pool.push(entry)?;
+            index = next as u16;
         }
 
-        Ok(Self { inner: map })
+        Ok(pool)
     }
 }
 
 impl Deserialize for AccessFlags {
-    fn deserialize(bytes: &mut Cursor<Vec<u8>>) -> Result<Self, Error> {
-        AccessFlags::from_bits(u16::deserialize(bytes)?).ok_or(Error::new(
-            ErrorKind::Other,
-            "Error when trying to convert u16 to AccessFlags",
-        ))
-    }
-}
-
-impl Deserialize for Field {
-    fn deserialize(bytes: &mut Cursor<Vec<u8>>) -> Result<Self, Error> {
-        let access_flags = AccessFlags::deserialize(bytes)?;
-        let name_index = CPIndex::deserialize(bytes)?;
-        let descriptor_index = CPIndex::deserialize(bytes)?;
-        let attributes = Vec::<Attribute>::deserialize(bytes)?;
-
-        Ok(Self {
-            access_flags,
-            name_index,
-            descriptor_index,
-            attributes,
-        })
+    const MIN_SIZE: usize = 2;
+
+    // Every bit has a meaning for some kind of member, the ones reserved for this kind (that some
+    // tools set anyway) are only reported by validate --strict.
+    fn deserialize(bytes: &mut Cursor<&[u8]>) -> Result<Self, Error> {
+        Ok(AccessFlags::from_bits_truncate(u16::deserialize(bytes)?))
     }
 }
 
-impl Deserialize for Method {
-    fn deserialize(bytes: &mut Cursor<Vec<u8>>) -> Result<Self, Error> {
-        let access_flags = AccessFlags::deserialize(bytes)?;
-        let name_index = CPIndex::deserialize(bytes)?;
-        let descriptor_index = CPIndex::deserialize(bytes)?;
-        let attributes = Vec::<Attribute>::deserialize(bytes)?;
+// Annotations can be nested in element values as deep as the input goes, past this depth they are
+// rejected rather than risking the stack.
+const MAX_ELEMENT_VALUE_DEPTH: usize = 64;
+
+fn deserialize_annotation(bytes: &mut Cursor<&[u8]>, depth: usize) -> Result<Annotation, Error> {
+    let type_index = Utf8Index::deserialize(bytes)?;
+    let count = u16::deserialize(bytes)? as usize;
+    // a pair takes 5 bytes at least, an element name and the smallest value
+    let mut element_value_pairs = Vec::with_capacity(count.min(remaining(bytes) / 5));
+    for _ in 0..count {
+        element_value_pairs.push(ElementValuePair {
+            element_name_index: Utf8Index::deserialize(bytes)?,
+            value: deserialize_element_value(bytes, depth)?,
+        });
+    }
 
-        Ok(Self {
-            access_flags,
-            name_index,
-            descriptor_index,
-            attributes,
-        })
+    Ok(Annotation {
+        type_index,
+        element_value_pairs,
+    })
+}
+
+fn deserialize_element_value(
+    bytes: &mut Cursor<&[u8]>,
+    depth: usize,
+) -> Result<ElementValue, Error> {
+    if depth >= MAX_ELEMENT_VALUE_DEPTH {
+        return Err(Error::other("Element values are nested too deep."));
+    }
+
+    let offset = bytes.position();
+    let tag = u8::deserialize(bytes)?;
+    Ok(match tag {
+        b'B' => ElementValue::Byte(CPIndex::deserialize(bytes)?),
+        b'C' => ElementValue::Char(CPIndex::deserialize(bytes)?),
+        b'D' => ElementValue::Double(CPIndex::deserialize(bytes)?),
+        b'F' => ElementValue::Float(CPIndex::deserialize(bytes)?),
+        b'I' => ElementValue::Int(CPIndex::deserialize(bytes)?),
+        b'J' => ElementValue::Long(CPIndex::deserialize(bytes)?),
+        b'S' => ElementValue::Short(CPIndex::deserialize(bytes)?),
+        b'Z' => ElementValue::Boolean(CPIndex::deserialize(bytes)?),
+        b's' => ElementValue::String(Utf8Index::deserialize(bytes)?),
+        b'e' => ElementValue::Enum {
+            type_name_index: Utf8Index::deserialize(bytes)?,
+            const_name_index: Utf8Index::deserialize(bytes)?,
+        },
+        b'c' => ElementValue::Class(Utf8Index::deserialize(bytes)?),
+        b'@' => ElementValue::Annotation(deserialize_annotation(bytes, depth + 1)?),
+        b'[' => {
+            let count = u16::deserialize(bytes)? as usize;
+            let mut values =
+                Vec::with_capacity(count.min(remaining(bytes) / ElementValue::MIN_SIZE));
+            for _ in 0..count {
+                values.push(deserialize_element_value(bytes, depth + 1)?);
+            }
+            ElementValue::Array(values)
+        }
+        _ => {
+            let e = Error::other(format!("Unknown element value tag {:?}.", tag as char));
+            return Err(at_offset(e, offset));
+        }
+    })
+}
+
+// the parameter count of the parameter annotation attributes is a single byte
+impl Deserialize for Annotation {
+    const MIN_SIZE: usize = 4;
+
+    fn deserialize(bytes: &mut Cursor<&[u8]>) -> Result<Self, Error> {
+        deserialize_annotation(bytes, 0)
+    }
+}
+
+impl Deserialize for ElementValue {
+    const MIN_SIZE: usize = 3;
+
+    fn deserialize(bytes: &mut Cursor<&[u8]>) -> Result<Self, Error> {
+        deserialize_element_value(bytes, 0)
     }
 }
 
-impl Deserialize for ExceptionTableEntry {
-    fn deserialize(bytes: &mut Cursor<Vec<u8>>) -> Result<Self, Error> {
-        Ok(Self {
-            start: u16::deserialize(bytes)?,
-            end: u16::deserialize(bytes)?,
-            handler: u16::deserialize(bytes)?,
-            catch_type: CPIndex::deserialize(bytes)?,
+impl Deserialize for TargetInfo {
+    // the target_type byte alone, for the targets without target_info
+    const MIN_SIZE: usize = 1;
+
+    fn deserialize(bytes: &mut Cursor<&[u8]>) -> Result<Self, Error> {
+        let offset = bytes.position();
+        let target_type = u8::deserialize(bytes)?;
+        let bound = |bytes: &mut Cursor<&[u8]>| -> Result<(u8, u8), Error> {
+            Ok((u8::deserialize(bytes)?, u8::deserialize(bytes)?))
+        };
+        let type_argument = |bytes: &mut Cursor<&[u8]>| -> Result<(u16, u8), Error> {
+            Ok((u16::deserialize(bytes)?, u8::deserialize(bytes)?))
+        };
+        Ok(match target_type {
+            0x00 => TargetInfo::ClassTypeParameter(u8::deserialize(bytes)?),
+            0x01 => TargetInfo::MethodTypeParameter(u8::deserialize(bytes)?),
+            0x10 => TargetInfo::Supertype(u16::deserialize(bytes)?),
+            0x11 => {
+                let (type_parameter_index, bound_index) = bound(bytes)?;
+                TargetInfo::ClassTypeParameterBound {
+                    type_parameter_index,
+                    bound_index,
+                }
+            }
+            0x12 => {
+                let (type_parameter_index, bound_index) = bound(bytes)?;
+                TargetInfo::MethodTypeParameterBound {
+                    type_parameter_index,
+                    bound_index,
+                }
+            }
+            0x13 => TargetInfo::Field,
+            0x14 => TargetInfo::MethodReturn,
+            0x15 => TargetInfo::MethodReceiver,
+            0x16 => TargetInfo::MethodFormalParameter(u8::deserialize(bytes)?),
+            0x17 => TargetInfo::Throws(u16::deserialize(bytes)?),
+            0x40 => TargetInfo::LocalVariable(Vec::<LocalVarTarget>::deserialize(bytes)?),
+            0x41 => TargetInfo::ResourceVariable(Vec::<LocalVarTarget>::deserialize(bytes)?),
+            0x42 => TargetInfo::ExceptionParameter(u16::deserialize(bytes)?),
+            0x43 => TargetInfo::InstanceOf(u16::deserialize(bytes)?),
+            0x44 => TargetInfo::New(u16::deserialize(bytes)?),
+            0x45 => TargetInfo::ConstructorReference(u16::deserialize(bytes)?),
+            0x46 => TargetInfo::MethodReference(u16::deserialize(bytes)?),
+            0x47..=0x4B => {
+                let (offset, type_argument_index) = type_argument(bytes)?;
+                match target_type {
+                    0x47 => TargetInfo::Cast {
+                        offset,
+                        type_argument_index,
+                    },
+                    0x48 => TargetInfo::ConstructorInvocationTypeArgument {
+                        offset,
+                        type_argument_index,
+                    },
+                    0x49 => TargetInfo::MethodInvocationTypeArgument {
+                        offset,
+                        type_argument_index,
+                    },
+                    0x4A => TargetInfo::ConstructorReferenceTypeArgument {
+                        offset,
+                        type_argument_index,
+                    },
+                    _ => TargetInfo::MethodReferenceTypeArgument {
+                        offset,
+                        type_argument_index,
+                    },
+                }
+            }
+            _ => {
+                let e = Error::other(format!(
+                    "Unknown type annotation target type {:#04x}.",
+                    target_type
+                ));
+                return Err(at_offset(e, offset));
+            }
         })
     }
 }
 
 impl Deserialize for CodeByte {
-    fn deserialize(bytes: &mut Cursor<Vec<u8>>) -> Result<Self, Error> {
+    fn deserialize(bytes: &mut Cursor<&[u8]>) -> Result<Self, Error> {
         Ok(Self(u8::deserialize(bytes)?))
     }
 }
 
 impl Deserialize for AttributeInfo {
-    fn deserialize(bytes: &mut Cursor<Vec<u8>>) -> Result<Self, Error> {
+    fn deserialize(bytes: &mut Cursor<&[u8]>) -> Result<Self, Error> {
         let size = u32::deserialize(bytes)?;
-        let mut buf = vec![0u8; size as usize];
-        bytes.read_exact(buf.as_mut_slice())?;
-        Ok(AttributeInfo::Any(buf))
+        Ok(AttributeInfo::Any(read_bytes(bytes, size as usize)?))
     }
 }
 
 impl Deserialize for Attribute {
-    fn deserialize(bytes: &mut Cursor<Vec<u8>>) -> Result<Self, Error> {
-        let name_index = CPIndex::deserialize(bytes)?;
+    const MIN_SIZE: usize = 6;
+
+    fn deserialize(bytes: &mut Cursor<&[u8]>) -> Result<Self, Error> {
+        let name_index = Utf8Index::deserialize(bytes)?;
         let info = AttributeInfo::deserialize(bytes)?;
 
         Ok(Self { name_index, info })
     }
 }
 
+impl Deserialize for LazyAttribute {
+    const MIN_SIZE: usize = 6;
+
+    fn deserialize(bytes: &mut Cursor<&[u8]>) -> Result<Self, Error> {
+        let name_index = Utf8Index::deserialize(bytes)?;
+        let size = u32::deserialize(bytes)?;
+        let bytes = read_bytes(bytes, size as usize)?;
+
+        Ok(Self::new(name_index, bytes))
+    }
+}
+
+// reads the structure of a class, attributes are left as A::deserialize returns them
+pub fn deserialize_class<A: Deserialize>(bytes: &mut Cursor<&[u8]>) -> Result<JavaClass<A>, Error> {
+    let magic_bytes = u32::deserialize(bytes)?;
+    let minor_version = u16::deserialize(bytes)?;
+    let major_version = u16::deserialize(bytes)?;
+    let constant_pool = ConstantPool::deserialize(bytes)?;
+    let access_flags = AccessFlags::deserialize(bytes)?;
+    let this_class = ClassIndex::deserialize(bytes)?;
+    let super_class = Option::<ClassIndex>::deserialize(bytes)?; // None for java/lang/Object
+    let interfaces = Vec::<ClassIndex>::deserialize(bytes)?;
+    let fields = Vec::<Field<A>>::deserialize(bytes)?;
+    let methods = Vec::<Method<A>>::deserialize(bytes)?;
+    let attributes = Vec::<A>::deserialize(bytes)?;
+
+    Ok(JavaClass {
+        magic_bytes,
+        minor_version,
+        major_version,
+        constant_pool,
+        access_flags,
+        this_class,
+        super_class,
+        interfaces,
+        fields,
+        methods,
+        attributes,
+    })
+}
+
 impl Deserialize for JavaClass {
-    fn deserialize(bytes: &mut Cursor<Vec<u8>>) -> Result<Self, Error> {
-        let magic_bytes = u32::deserialize(bytes)?;
-        let minor_version = u16::deserialize(bytes)?;
-        let major_version = u16::deserialize(bytes)?;
-        let constant_pool = ConstantPool::deserialize(bytes)?;
-        let access_flags = AccessFlags::deserialize(bytes)?;
-        let this_class = CPIndex::deserialize(bytes)?;
-        let super_class = CPIndex::deserialize(bytes).ok(); // optional
-        let interfaces = Vec::<CPIndex>::deserialize(bytes)?;
-        let mut fields = Vec::<Field>::deserialize(bytes)?;
-        let mut methods = Vec::<Method>::deserialize(bytes)?;
-        let mut attributes = Vec::<Attribute>::deserialize(bytes)?;
+    fn deserialize(bytes: &mut Cursor<&[u8]>) -> Result<Self, Error> {
+        let mut class = deserialize_class::<Attribute>(bytes)?;
+        let cp = &class.constant_pool;
 
         // resolve the attributes
-        for f in fields.iter_mut() {
+        for f in class.fields.iter_mut() {
             for a in f.attributes.iter_mut() {
-                let _ = a.resolve(&constant_pool);
+                a.resolve_or_keep(cp);
             }
         }
-        for m in methods.iter_mut() {
+        for m in class.methods.iter_mut() {
             for a in m.attributes.iter_mut() {
-                let _ = a.resolve(&constant_pool);
+                a.resolve_or_keep(cp);
             }
         }
-        for a in attributes.iter_mut() {
-            let _ = a.resolve(&constant_pool);
+        for a in class.attributes.iter_mut() {
+            a.resolve_or_keep(cp);
         }
 
-        Ok(Self {
-            magic_bytes,
-            minor_version,
-            major_version,
-            constant_pool,
-            access_flags,
-            this_class,
-            super_class,
-            interfaces,
-            fields,
-            methods,
-            attributes,
-        })
+        Ok(class)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serialization::Serialize;
+
+    #[test]
+    fn optional_indices() {
+        let bytes = [0, 0, 0, 7, 0];
+        let mut cursor = Cursor::new(&bytes[..]);
+        // 0 is no entry, unlike for a required index
+        assert_eq!(Option::<CPIndex>::deserialize(&mut cursor).unwrap(), None);
+        let class = Option::<ClassIndex>::deserialize(&mut cursor).unwrap();
+        assert_eq!(class, Some(ClassIndex(CPIndex(7))));
+        // a read that fails is an error, not None
+        let e = Option::<ClassIndex>::deserialize(&mut cursor).unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::UnexpectedEof);
+
+        cursor.set_position(0);
+        assert!(CPIndex::deserialize(&mut cursor).is_err());
+
+        let mut written = Vec::new();
+        None::<CPIndex>.serialize(&mut written).unwrap();
+        Some(CPIndex(7)).serialize(&mut written).unwrap();
+        assert_eq!(written, bytes[..4]);
     }
 }
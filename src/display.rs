@@ -1,18 +1,104 @@
 use std::fmt::Display;
 
-use crate::{CPIndex, ConstantPool, ReferenceKind, ConstantPoolEntry, Attribute, AttributeInfo};
+use bitflags::bitflags;
 
-pub struct DisplayCP<'a>(CPIndex, &'a ConstantPool);
-pub struct DisplayConstantPoolEntry<'a>(&'a ConstantPoolEntry, &'a ConstantPool);
-pub struct DisplayAttribute<'a>(&'a Attribute, &'a ConstantPool);
+use crate::{
+    api::{flag_names, MemberKind},
+    usages::method_ref,
+    AccessFlags, CPIndex, ConstantPool, ReferenceKind, ConstantPoolEntry, Attribute, AttributeInfo,
+};
+
+bitflags! {
+    // the parts of a class printed by JavaClass::write_pretty
+    pub struct Sections: u8 {
+        // magic and version
+        const HEADER        = 0x01;
+        const CONSTANT_POOL = 0x02;
+        // access flags, this and super class
+        const CLASS         = 0x04;
+        const INTERFACES    = 0x08;
+        const FIELDS        = 0x10;
+        const METHODS       = 0x20;
+        const ATTRIBUTES    = 0x40;
+    }
+}
+
+// how access flags are printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlagStyle {
+    // every flag whose bit is set, whatever the kind of member: PUBLIC | SUPER | SYNCHRONIZED
+    Debug,
+    // the flags of the kind of member, as javap names them: public super, (empty) if none
+    Java,
+    // the bits: 0x0021
+    Hex,
+}
+
+impl FlagStyle {
+    pub fn format(self, flags: AccessFlags, kind: MemberKind) -> String {
+        match self {
+            FlagStyle::Debug => format!("{:?}", flags),
+            FlagStyle::Java => match flag_names(flags, kind) {
+                names if names.is_empty() => "(empty)".to_string(),
+                names => names.join(" "),
+            },
+            FlagStyle::Hex => format!("{:#06x}", flags.bits()),
+        }
+    }
+}
+
+// how constants are shown, the ones nested in others included
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexMode {
+    // (class 'java/lang/Object')
+    Resolved,
+    // @0008
+    Index,
+    // (class 'java/lang/Object'@0004)@0008
+    Both,
+}
+
+#[derive(Debug, Clone)]
+pub struct PrintOptions {
+    pub sections: Sections,
+    pub indices: IndexMode,
+    pub flags: FlagStyle,
+}
+
+impl Default for PrintOptions {
+    fn default() -> Self {
+        PrintOptions {
+            sections: Sections::all(),
+            indices: IndexMode::Resolved,
+            flags: FlagStyle::Debug,
+        }
+    }
+}
+
+// Entries can point at each other in loops in crafted classes, displaying the entries nested
+// deeper than this (valid classes go 3 deep, from a MethodHandle to the Utf8 of a class name)
+// stops there.
+const MAX_DISPLAY_DEPTH: usize = 8;
+
+// the usize is how deep the constant is nested
+pub struct DisplayCP<'a>(CPIndex, &'a ConstantPool, IndexMode, usize);
+pub struct DisplayConstantPoolEntry<'a>(&'a ConstantPoolEntry, &'a ConstantPool, IndexMode, usize);
+pub struct DisplayAttribute<'a>(&'a Attribute, &'a ConstantPool, IndexMode);
+pub struct DisplayJavap<'a>(&'a ConstantPool);
 
 impl<'a> Display for DisplayCP<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self.1.get(&self.0) {
-            Some(v) => write!(f, "{}", v.display(self.1))?,
+        if self.2 == IndexMode::Index {
+            return write!(f, "@{}", self.0);
+        }
+        match self.1.get(self.0) {
+            Some(_) if self.3 >= MAX_DISPLAY_DEPTH => write!(f, "(...)")?,
+            Some(v) => write!(f, "{}", DisplayConstantPoolEntry(v, self.1, self.2, self.3))?,
             None => write!(f, "(NONE)")?,
         };
-        //write!(f, "@{}", self.0)?;
+        if self.2 == IndexMode::Both {
+            write!(f, "@{}", self.0)?;
+        }
         Ok(())
     }
 }
@@ -23,13 +109,20 @@ impl<'a> Display for DisplayConstantPoolEntry<'a> {
             ConstantPoolEntry::Integer(i) => write!(f, "(int {})", i),
             ConstantPoolEntry::Long(l) => write!(f, "(long {})", l),
             ConstantPoolEntry::Utf8(s) => write!(f, "'{}'", s),
-            ConstantPoolEntry::Float(d) => write!(f, "(float {})", d),
-            ConstantPoolEntry::Double(d) => write!(f, "(double {})", d),
+            // the payload of NaNs is shown, unless it's the one Java uses
+            ConstantPoolEntry::Float(d) if d.is_unusual_nan() => {
+                write!(f, "(float NaN({:#010x}))", d.0)
+            }
+            ConstantPoolEntry::Float(d) => write!(f, "(float {})", d.value()),
+            ConstantPoolEntry::Double(d) if d.is_unusual_nan() => {
+                write!(f, "(double NaN({:#018x}))", d.0)
+            }
+            ConstantPoolEntry::Double(d) => write!(f, "(double {})", d.value()),
             ConstantPoolEntry::Class { name_index } => {
-                write!(f, "(class {})", name_index.display(self.1))
+                write!(f, "(class {})", name_index.nested(self))
             }
             ConstantPoolEntry::String { string_index } => {
-                write!(f, "(string {})", string_index.display(self.1))
+                write!(f, "(string {})", string_index.nested(self))
             }
             ConstantPoolEntry::FieldRef {
                 class_index,
@@ -37,8 +130,8 @@ impl<'a> Display for DisplayConstantPoolEntry<'a> {
             } => write!(
                 f,
                 "(fieldref {} {})",
-                class_index.display(self.1),
-                name_and_type_index.display(self.1)
+                class_index.nested(self),
+                name_and_type_index.nested(self)
             ),
             ConstantPoolEntry::MethodRef {
                 class_index,
@@ -46,8 +139,8 @@ impl<'a> Display for DisplayConstantPoolEntry<'a> {
             } => write!(
                 f,
                 "(methodref {} {})",
-                class_index.display(self.1),
-                name_and_type_index.display(self.1)
+                class_index.nested(self),
+                name_and_type_index.nested(self)
             ),
             ConstantPoolEntry::InterfaceMethodRef {
                 class_index,
@@ -55,29 +148,44 @@ impl<'a> Display for DisplayConstantPoolEntry<'a> {
             } => write!(
                 f,
                 "(interfacemethodref {} {})",
-                class_index.display(self.1),
-                name_and_type_index.display(self.1)
+                class_index.nested(self),
+                name_and_type_index.nested(self)
             ),
             ConstantPoolEntry::MethodType { descriptor_index } => {
-                write!(f, "(methodtype {})", descriptor_index.display(self.1))
+                write!(f, "(methodtype {})", descriptor_index.nested(self))
             }
             ConstantPoolEntry::NameAndType {
                 name_index,
                 descriptor_index,
             } => write!(
                 f,
-                "(name {} {})",
-                name_index.display(self.1),
-                descriptor_index.display(self.1)
+                "(nameandtype name={} descriptor={})",
+                name_index.nested(self),
+                descriptor_index.nested(self)
             ),
+            // the member the handle points at, like javap does, unless indices are shown
             ConstantPoolEntry::MethodHandle {
                 reference_kind,
                 reference_index,
+            } => match member_ref(self.1, *reference_index) {
+                Some(member) if self.2 == IndexMode::Resolved => {
+                    write!(f, "(methodhandle {} {})", reference_kind.mnemonic(), member)
+                }
+                _ => write!(
+                    f,
+                    "(kind {} {})",
+                    reference_kind,
+                    reference_index.nested(self)
+                ),
+            },
+            ConstantPoolEntry::Dynamic {
+                bootstrap_method_attr_index,
+                name_and_type_index,
             } => write!(
                 f,
-                "(kind {} {})",
-                reference_kind,
-                reference_index.display(self.1)
+                "(dynamic attr {} {})",
+                bootstrap_method_attr_index,
+                name_and_type_index.nested(self)
             ),
             ConstantPoolEntry::InvokeDynamic {
                 bootstrap_method_attr_index,
@@ -86,20 +194,295 @@ impl<'a> Display for DisplayConstantPoolEntry<'a> {
                 f,
                 "(invokedyn attr {} {})",
                 bootstrap_method_attr_index,
-                name_and_type_index.display(self.1)
+                name_and_type_index.nested(self)
             ),
+            ConstantPoolEntry::Module { name_index } => {
+                write!(f, "(module {})", name_index.nested(self))
+            }
+            ConstantPoolEntry::Package { name_index } => {
+                write!(f, "(package {})", name_index.nested(self))
+            }
         }
     }
 }
 
 impl<'a> Display for DisplayAttribute<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}: {}",
-            self.0.name_index.display(&self.1),
-            self.0.info
-        )
+        let display = |index: &CPIndex| DisplayCP(*index, self.1, self.2, 0);
+        write!(f, "{}: ", display(&self.0.name_index))?;
+        match &self.0.info {
+            AttributeInfo::Record { components } => {
+                for (i, c) in components.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(
+                        f,
+                        "(component {} {})",
+                        display(&c.name_index),
+                        display(&c.descriptor_index)
+                    )?;
+                }
+                Ok(())
+            }
+            AttributeInfo::Exceptions {
+                exception_index_table,
+            } => {
+                write!(f, "throws ")?;
+                for (i, c) in exception_index_table.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    match c.name(self.1) {
+                        Ok(name) => write!(f, "{}", name)?,
+                        Err(_) => write!(f, "{}", display(c))?,
+                    }
+                }
+                Ok(())
+            }
+            info => write!(f, "{}", info),
+        }
+    }
+}
+
+// the field or method a FieldRef, MethodRef or InterfaceMethodRef is for, class.name:descriptor
+fn member_ref(cp: &ConstantPool, index: CPIndex) -> Option<String> {
+    match cp.get(index)? {
+        ConstantPoolEntry::FieldRef {
+            class_index,
+            name_and_type_index,
+        }
+        | ConstantPoolEntry::MethodRef {
+            class_index,
+            name_and_type_index,
+        }
+        | ConstantPoolEntry::InterfaceMethodRef {
+            class_index,
+            name_and_type_index,
+        } => {
+            let (name, descriptor) = method_ref(cp, *class_index, *name_and_type_index)?;
+            Some(format!("{}:{}", name, descriptor))
+        }
+        _ => None,
+    }
+}
+
+// escapes a string the way javap does in the constant pool listing
+fn javap_escape(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\t' => res.push_str("\\t"),
+            '\n' => res.push_str("\\n"),
+            '\r' => res.push_str("\\r"),
+            ' '..='~' => res.push(c),
+            _ => {
+                let mut units = [0u16; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    res.push_str(&format!("\\u{:04x}", unit));
+                }
+            }
+        }
+    }
+    res
+}
+
+// javap puts quotes around names that aren't plain identifiers, like "<init>" or "[I"
+fn javap_quote(s: &str) -> String {
+    if s.starts_with('<') || s.starts_with('[') {
+        format!("\"{}\"", s)
+    } else {
+        s.to_string()
+    }
+}
+
+fn javap_utf8(cp: &ConstantPool, index: &CPIndex) -> String {
+    match cp.get(*index) {
+        Some(ConstantPoolEntry::Utf8(s)) => javap_escape(s),
+        _ => "??".to_string(),
+    }
+}
+
+fn javap_class(cp: &ConstantPool, index: &CPIndex) -> String {
+    match cp.get(*index) {
+        Some(ConstantPoolEntry::Class { name_index }) => javap_quote(&javap_utf8(cp, name_index)),
+        _ => "??".to_string(),
+    }
+}
+
+fn javap_name_and_type(cp: &ConstantPool, index: &CPIndex) -> String {
+    match cp.get(*index) {
+        Some(ConstantPoolEntry::NameAndType {
+            name_index,
+            descriptor_index,
+        }) => format!(
+            "{}:{}",
+            javap_quote(&javap_utf8(cp, name_index)),
+            javap_utf8(cp, descriptor_index)
+        ),
+        _ => "??".to_string(),
+    }
+}
+
+// the comment after a member reference, class.name:descriptor
+fn javap_member(cp: &ConstantPool, index: &CPIndex) -> String {
+    match cp.get(*index) {
+        Some(
+            ConstantPoolEntry::FieldRef {
+                class_index,
+                name_and_type_index,
+            }
+            | ConstantPoolEntry::MethodRef {
+                class_index,
+                name_and_type_index,
+            }
+            | ConstantPoolEntry::InterfaceMethodRef {
+                class_index,
+                name_and_type_index,
+            },
+        ) => format!(
+            "{}.{}",
+            javap_class(cp, class_index),
+            javap_name_and_type(cp, name_and_type_index)
+        ),
+        _ => "??".to_string(),
+    }
+}
+
+// java's float formatting, which always has a fractional part
+fn javap_float(v: f64) -> String {
+    if v.is_nan() {
+        "NaN".to_string()
+    } else if v.is_infinite() {
+        if v > 0.0 { "Infinity" } else { "-Infinity" }.to_string()
+    } else {
+        format!("{:?}", v)
+    }
+}
+
+impl<'a> Display for DisplayJavap<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let cp = self.0;
+        let width = cp.iter().last().map_or(0, |(i, _)| format!("#{}", i.inner()).len()) + 2;
+
+        for (index, entry) in cp.iter() {
+            // kind, arguments, and the resolved comment if any
+            let (kind, args, comment) = match entry {
+                ConstantPoolEntry::Class { name_index } => (
+                    "Class",
+                    format!("#{}", name_index.0.inner()),
+                    Some(javap_class(cp, &index)),
+                ),
+                ConstantPoolEntry::FieldRef {
+                    class_index,
+                    name_and_type_index,
+                } => (
+                    "Fieldref",
+                    format!("#{}.#{}", class_index.0.inner(), name_and_type_index.0.inner()),
+                    Some(javap_member(cp, &index)),
+                ),
+                ConstantPoolEntry::MethodRef {
+                    class_index,
+                    name_and_type_index,
+                } => (
+                    "Methodref",
+                    format!("#{}.#{}", class_index.0.inner(), name_and_type_index.0.inner()),
+                    Some(javap_member(cp, &index)),
+                ),
+                ConstantPoolEntry::InterfaceMethodRef {
+                    class_index,
+                    name_and_type_index,
+                } => (
+                    "InterfaceMethodref",
+                    format!("#{}.#{}", class_index.0.inner(), name_and_type_index.0.inner()),
+                    Some(javap_member(cp, &index)),
+                ),
+                ConstantPoolEntry::String { string_index } => (
+                    "String",
+                    format!("#{}", string_index.0.inner()),
+                    Some(javap_utf8(cp, string_index)),
+                ),
+                ConstantPoolEntry::Integer(i) => ("Integer", i.to_string(), None),
+                ConstantPoolEntry::Float(v) => ("Float", javap_float(v.value() as f64) + "f", None),
+                ConstantPoolEntry::Long(l) => ("Long", format!("{}l", l), None),
+                ConstantPoolEntry::Double(v) => ("Double", javap_float(v.value()) + "d", None),
+                ConstantPoolEntry::NameAndType {
+                    name_index,
+                    descriptor_index,
+                } => (
+                    "NameAndType",
+                    format!("#{}:#{}", name_index.0.inner(), descriptor_index.0.inner()),
+                    Some(javap_name_and_type(cp, &index)),
+                ),
+                ConstantPoolEntry::Utf8(s) => ("Utf8", javap_escape(s), None),
+                ConstantPoolEntry::MethodHandle {
+                    reference_kind,
+                    reference_index,
+                } => (
+                    "MethodHandle",
+                    format!("{}:#{}", *reference_kind as u8, reference_index.inner()),
+                    Some(format!(
+                        "{} {}",
+                        reference_kind.javap_name(),
+                        javap_member(cp, reference_index)
+                    )),
+                ),
+                ConstantPoolEntry::MethodType { descriptor_index } => (
+                    "MethodType",
+                    format!("#{}", descriptor_index.0.inner()),
+                    Some(format!(" {}", javap_utf8(cp, descriptor_index))),
+                ),
+                ConstantPoolEntry::Dynamic {
+                    bootstrap_method_attr_index,
+                    name_and_type_index,
+                } => (
+                    "Dynamic",
+                    format!("#{}:#{}", bootstrap_method_attr_index, name_and_type_index.0.inner()),
+                    Some(format!(
+                        "#{}:{}",
+                        bootstrap_method_attr_index,
+                        javap_name_and_type(cp, name_and_type_index)
+                    )),
+                ),
+                ConstantPoolEntry::InvokeDynamic {
+                    bootstrap_method_attr_index,
+                    name_and_type_index,
+                } => (
+                    "InvokeDynamic",
+                    format!("#{}:#{}", bootstrap_method_attr_index, name_and_type_index.0.inner()),
+                    Some(format!(
+                        "#{}:{}",
+                        bootstrap_method_attr_index,
+                        javap_name_and_type(cp, name_and_type_index)
+                    )),
+                ),
+                ConstantPoolEntry::Module { name_index } => (
+                    "Module",
+                    format!("#{}", name_index.0.inner()),
+                    Some(javap_utf8(cp, name_index)),
+                ),
+                ConstantPoolEntry::Package { name_index } => (
+                    "Package",
+                    format!("#{}", name_index.0.inner()),
+                    Some(javap_utf8(cp, name_index)),
+                ),
+            };
+
+            let index = format!("#{}", index.0);
+            match comment {
+                Some(comment) => writeln!(
+                    f,
+                    "{:>width$} = {:<18} {:<14} // {}",
+                    index,
+                    kind,
+                    args,
+                    comment,
+                    width = width
+                )?,
+                None => writeln!(f, "{:>width$} = {:<18} {}", index, kind, args, width = width)?,
+            }
+        }
+        Ok(())
     }
 }
 
@@ -133,18 +516,77 @@ impl Display for AttributeInfo {
 
 impl<'a> CPIndex {
     pub fn display(&self, cp: &'a ConstantPool) -> DisplayCP<'a> {
-        DisplayCP(*self, cp)
+        DisplayCP(*self, cp, IndexMode::Resolved, 0)
+    }
+    pub fn display_with(&self, cp: &'a ConstantPool, opts: &PrintOptions) -> DisplayCP<'a> {
+        DisplayCP(*self, cp, opts.indices, 0)
+    }
+    // an index found in entry
+    fn nested(&self, entry: &DisplayConstantPoolEntry<'a>) -> DisplayCP<'a> {
+        DisplayCP(*self, entry.1, entry.2, entry.3 + 1)
     }
 }
 
 impl<'a> ConstantPoolEntry {
     pub fn display(&'a self, cp: &'a ConstantPool) -> DisplayConstantPoolEntry<'a> {
-        DisplayConstantPoolEntry(self, cp)
+        DisplayConstantPoolEntry(self, cp, IndexMode::Resolved, 0)
+    }
+    pub fn display_with(
+        &'a self,
+        cp: &'a ConstantPool,
+        opts: &PrintOptions,
+    ) -> DisplayConstantPoolEntry<'a> {
+        DisplayConstantPoolEntry(self, cp, opts.indices, 0)
+    }
+}
+
+impl ReferenceKind {
+    // the instruction the handle behaves like (newinvokespecial is a new then an invokespecial)
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            ReferenceKind::GetField => "getfield",
+            ReferenceKind::GetStatic => "getstatic",
+            ReferenceKind::PutField => "putfield",
+            ReferenceKind::PutStatic => "putstatic",
+            ReferenceKind::InvokeVirtual => "invokevirtual",
+            ReferenceKind::InvokeStatic => "invokestatic",
+            ReferenceKind::InvokeSpecial => "invokespecial",
+            ReferenceKind::NewInvokeSpecial => "newinvokespecial",
+            ReferenceKind::InvokeInterface => "invokeinterface",
+        }
+    }
+    // the name used by javap, like REF_invokeStatic
+    pub fn javap_name(&self) -> &'static str {
+        match self {
+            ReferenceKind::GetField => "REF_getField",
+            ReferenceKind::GetStatic => "REF_getStatic",
+            ReferenceKind::PutField => "REF_putField",
+            ReferenceKind::PutStatic => "REF_putStatic",
+            ReferenceKind::InvokeVirtual => "REF_invokeVirtual",
+            ReferenceKind::InvokeStatic => "REF_invokeStatic",
+            ReferenceKind::InvokeSpecial => "REF_invokeSpecial",
+            ReferenceKind::NewInvokeSpecial => "REF_newInvokeSpecial",
+            ReferenceKind::InvokeInterface => "REF_invokeInterface",
+        }
+    }
+}
+
+impl ConstantPool {
+    // the constant pool listing as printed by javap -v
+    pub fn javap(&self) -> DisplayJavap<'_> {
+        DisplayJavap(self)
     }
 }
 
 impl<'a> Attribute {
     pub fn display(&'a self, cp: &'a ConstantPool) -> DisplayAttribute<'a> {
-        DisplayAttribute(self, cp)
+        DisplayAttribute(self, cp, IndexMode::Resolved)
+    }
+    pub fn display_with(
+        &'a self,
+        cp: &'a ConstantPool,
+        opts: &PrintOptions,
+    ) -> DisplayAttribute<'a> {
+        DisplayAttribute(self, cp, opts.indices)
     }
 }
@@ -0,0 +1,526 @@
+use std::{collections::HashMap, io::Error};
+
+use crate::{
+    instruction::{decode_code, Instruction},
+    patch::{relocate_code_attribute, OffsetMap},
+    AttributeInfo, CPIndex, CodeByte, ConstantPool, ExceptionTableEntry, Method,
+};
+
+// An instruction of a CodeEditor. Labels stay valid for as long as the editor lives, even once
+// their instruction is removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Label(usize);
+
+struct Entry {
+    // None once removed, and for the end of the code
+    instruction: Option<Instruction>,
+    // where the branches of the instruction go, in the order of Instruction::branches
+    targets: Vec<Label>,
+    // the offset in the code the editor was made from
+    old: Option<u32>,
+}
+
+// Edits the code of a method as a list of instructions whose branches go to labels instead of
+// offsets, so that instructions can be inserted and removed without fixing every offset by
+// hand. finish lays the code out again: see there for what is updated.
+//
+// A label whose instruction was removed stands for the instruction that followed it, so the
+// branches and ranges that pointed at a removed instruction now start at the next one.
+pub struct CodeEditor {
+    entries: Vec<Entry>,
+    // the entries in code order, the end of the code is always last
+    order: Vec<Label>,
+    // start, end, handler and catch_type
+    exception_table: Vec<(Label, Label, Label, Option<CPIndex>)>,
+    // the old length of the code
+    code_length: u32,
+}
+
+// the branch instructions whose offset is an i16
+fn is_short_branch(instruction: &Instruction) -> bool {
+    matches!(instruction.opcode(), 0x99..=0xa8 | 0xc6 | 0xc7)
+}
+
+// the conditional branch taken when the one of opcode isn't
+fn inverse_condition(opcode: u8) -> u8 {
+    match opcode {
+        0x99..=0xa6 => ((opcode - 0x99) ^ 1) + 0x99,
+        // ifnull and ifnonnull
+        _ => opcode ^ 1,
+    }
+}
+
+impl CodeEditor {
+    pub fn new(method: &mut Method, cp: &ConstantPool) -> Result<Self, Error> {
+        let attribute = method
+            .attributes
+            .iter_mut()
+            .find(|a| a.name(cp) == Some("Code"))
+            .ok_or_else(|| Error::other("The method has no Code attribute."))?;
+        attribute.resolve(cp)?;
+        let AttributeInfo::Code {
+            code,
+            exception_table,
+            ..
+        } = &attribute.info
+        else {
+            unreachable!("resolved Code attribute")
+        };
+
+        let code = code.iter().map(|b| b.0).collect::<Vec<_>>();
+        let instructions = decode_code(&code)?;
+        let mut labels = HashMap::with_capacity(instructions.len() + 1);
+        for (i, (offset, _)) in instructions.iter().enumerate() {
+            labels.insert(*offset, Label(i));
+        }
+        labels.insert(code.len() as u32, Label(instructions.len()));
+        let label = |offset: u32| {
+            labels.get(&offset).copied().ok_or_else(|| {
+                Error::other(format!(
+                    "Offset {} isn't the start of an instruction.",
+                    offset
+                ))
+            })
+        };
+
+        let mut entries = Vec::with_capacity(instructions.len() + 1);
+        for (offset, instruction) in instructions {
+            let mut targets = Vec::new();
+            for branch in instruction.branches() {
+                let target = u32::try_from(offset as i64 + branch as i64).map_err(|_| {
+                    Error::other(format!("Branch at offset {} goes before the code.", offset))
+                })?;
+                targets.push(label(target)?);
+            }
+            entries.push(Entry {
+                instruction: Some(instruction),
+                targets,
+                old: Some(offset),
+            });
+        }
+        entries.push(Entry {
+            instruction: None,
+            targets: Vec::new(),
+            old: Some(code.len() as u32),
+        });
+
+        let mut table = Vec::with_capacity(exception_table.len());
+        for e in exception_table {
+            table.push((
+                label(e.start as u32)?,
+                label(e.end as u32)?,
+                label(e.handler as u32)?,
+                e.catch_type,
+            ));
+        }
+
+        Ok(CodeEditor {
+            order: (0..entries.len()).map(Label).collect(),
+            entries,
+            exception_table: table,
+            code_length: code.len() as u32,
+        })
+    }
+
+    // the label after the last instruction
+    pub fn end(&self) -> Label {
+        *self.order.last().expect("the end is always there")
+    }
+    // the label of the instruction at offset in the original code
+    pub fn at(&self, offset: u32) -> Option<Label> {
+        self.order
+            .iter()
+            .copied()
+            .find(|l| self.entries[l.0].old == Some(offset) && *l != self.end())
+    }
+    // the instructions left, in order
+    pub fn instructions(&self) -> impl Iterator<Item = (Label, &Instruction)> {
+        self.order
+            .iter()
+            .filter_map(|l| Some((*l, self.entries[l.0].instruction.as_ref()?)))
+    }
+    pub fn instruction(&self, label: Label) -> Option<&Instruction> {
+        self.entries.get(label.0)?.instruction.as_ref()
+    }
+    pub fn targets(&self, label: Label) -> &[Label] {
+        &self.entries[label.0].targets
+    }
+
+    // Inserts instructions before the one of at (which can be the end, or a removed instruction)
+    // and returns their labels. Their branches are ignored: the targets of the inserted branch
+    // instructions have to be given with set_targets.
+    pub fn insert_before(
+        &mut self,
+        at: Label,
+        instructions: Vec<Instruction>,
+    ) -> Result<Vec<Label>, Error> {
+        let position = self
+            .order
+            .iter()
+            .position(|l| *l == at)
+            .ok_or_else(|| Error::other(format!("There is no label {}.", at.0)))?;
+        let labels = (self.entries.len()..self.entries.len() + instructions.len())
+            .map(Label)
+            .collect::<Vec<_>>();
+        self.entries
+            .extend(instructions.into_iter().map(|instruction| Entry {
+                instruction: Some(instruction),
+                targets: Vec::new(),
+                old: None,
+            }));
+        self.order
+            .splice(position..position, labels.iter().copied());
+        Ok(labels)
+    }
+    // inserts instructions at the end of the code, see insert_before
+    pub fn append(&mut self, instructions: Vec<Instruction>) -> Result<Vec<Label>, Error> {
+        self.insert_before(self.end(), instructions)
+    }
+    pub fn remove(&mut self, label: Label) -> Result<Instruction, Error> {
+        self.entries
+            .get_mut(label.0)
+            .and_then(|e| e.instruction.take())
+            .ok_or_else(|| Error::other(format!("There is no instruction at label {}.", label.0)))
+    }
+    // sets where the branches of an instruction go, one label per branch
+    pub fn set_targets(&mut self, label: Label, targets: Vec<Label>) -> Result<(), Error> {
+        if let Some(t) = targets.iter().find(|t| t.0 >= self.entries.len()) {
+            return Err(Error::other(format!("There is no label {}.", t.0)));
+        }
+        let entry = self
+            .entries
+            .get_mut(label.0)
+            .filter(|e| e.instruction.is_some())
+            .ok_or_else(|| {
+                Error::other(format!("There is no instruction at label {}.", label.0))
+            })?;
+        let instruction = entry.instruction.as_ref().unwrap();
+        if instruction.branches().len() != targets.len() {
+            return Err(Error::other(format!(
+                "{} has {} branches, not {}.",
+                instruction.mnemonic(),
+                instruction.branches().len(),
+                targets.len()
+            )));
+        }
+        entry.targets = targets;
+        Ok(())
+    }
+
+    // the offset of every label for the current layout, removed instructions are at the offset
+    // of the next one
+    fn offsets(&self, long: &[bool]) -> Vec<u32> {
+        let mut offsets = vec![0; self.entries.len()];
+        let mut offset = 0;
+        for l in self.order.iter() {
+            offsets[l.0] = offset;
+            if let Some(instruction) = &self.entries[l.0].instruction {
+                offset += match (long[l.0], instruction.opcode()) {
+                    // goto_w and jsr_w
+                    (true, 0xa7 | 0xa8) => 5,
+                    // the inverse condition over a goto_w
+                    (true, _) => 8,
+                    (false, _) => instruction.size(offset),
+                };
+            }
+        }
+        offsets
+    }
+
+    // Lays the code out and writes it into the method along with the exception table and the
+    // LineNumberTable, LocalVariableTable, LocalVariableTypeTable, StackMapTable and type
+    // annotation attributes.
+    // The branches that end up too far for an i16 are widened: goto and jsr to goto_w and jsr_w,
+    // and the conditional branches to the inverse condition jumping over a goto_w. Exception
+    // handlers that no longer protect any instruction are dropped.
+    //
+    // As with Method::prepend_instructions, max_stack and max_locals are left as they are, and the
+    // StackMapTable won't have frames for the targets of inserted branches, nor for the
+    // instruction after a widened conditional branch. Nothing is changed on error.
+    pub fn finish(self, method: &mut Method, cp: &ConstantPool) -> Result<(), Error> {
+        for (l, instruction) in self.instructions() {
+            if self.entries[l.0].targets.len() != instruction.branches().len() {
+                return Err(Error::other(format!(
+                    "The targets of the inserted {} weren't set.",
+                    instruction.mnemonic()
+                )));
+            }
+        }
+
+        // widening a branch moves the code after it, which can push other branches out of range,
+        // so this goes on until nothing changes (only ever widening, so it ends)
+        let mut long = vec![false; self.entries.len()];
+        let offsets = loop {
+            let offsets = self.offsets(&long);
+            let mut changed = false;
+            for (l, instruction) in self.instructions() {
+                if long[l.0] || !is_short_branch(instruction) {
+                    continue;
+                }
+                let delta = offsets[self.entries[l.0].targets[0].0] as i64 - offsets[l.0] as i64;
+                if i16::try_from(delta).is_err() {
+                    long[l.0] = true;
+                    changed = true;
+                }
+            }
+            if !changed {
+                break offsets;
+            }
+        };
+
+        let mut code = Vec::new();
+        for (l, instruction) in self.instructions() {
+            let offset = offsets[l.0];
+            let mut targets = self.entries[l.0].targets.iter().map(|t| offsets[t.0]);
+            let mut instruction = instruction.clone();
+            if long[l.0] {
+                let target = targets.next().expect("a branch instruction");
+                let goto = match instruction.opcode() {
+                    0xa7 => Instruction::GotoW(target as i32 - offset as i32),
+                    0xa8 => Instruction::JsrW(target as i32 - offset as i32),
+                    opcode => {
+                        instruction.relocate_branches(|_| Ok(8))?;
+                        instruction.encode(&mut code)?;
+                        code[offset as usize] = inverse_condition(opcode);
+                        Instruction::GotoW(target as i32 - (offset as i32 + 3))
+                    }
+                };
+                goto.encode(&mut code)?;
+            } else {
+                instruction.relocate_branches(|_| {
+                    Ok(targets.next().expect("as many targets as branches") as i32 - offset as i32)
+                })?;
+                instruction.encode(&mut code)?;
+            }
+        }
+        if code.len() > u16::MAX as usize {
+            return Err(Error::other(format!(
+                "The code would be {} bytes long, more than the {} allowed.",
+                code.len(),
+                u16::MAX
+            )));
+        }
+
+        let map = OffsetMap(
+            self.entries
+                .iter()
+                .enumerate()
+                .filter_map(|(i, e)| Some((e.old?, offsets[i])))
+                .collect(),
+        );
+        debug_assert_eq!(map.get(self.code_length).ok(), Some(code.len() as u32));
+        let exception_table = self
+            .exception_table
+            .iter()
+            .map(|(start, end, handler, catch_type)| ExceptionTableEntry {
+                start: offsets[start.0] as u16,
+                end: offsets[end.0] as u16,
+                handler: offsets[handler.0] as u16,
+                catch_type: *catch_type,
+            })
+            .filter(|e| e.start < e.end)
+            .collect::<Vec<_>>();
+
+        let attribute = method
+            .attributes
+            .iter_mut()
+            .find(|a| a.name(cp) == Some("Code"))
+            .ok_or_else(|| Error::other("The method has no Code attribute."))?;
+        let AttributeInfo::Code {
+            code: old_code,
+            exception_table: old_exception_table,
+            attributes,
+            ..
+        } = &mut attribute.info
+        else {
+            return Err(Error::other(
+                "The Code attribute of the method isn't resolved.",
+            ));
+        };
+        let mut relocated = Vec::new();
+        for (i, a) in attributes.iter().enumerate() {
+            if let Some(info) = relocate_code_attribute(a, cp, &map)? {
+                relocated.push((i, info));
+            }
+        }
+
+        *old_code = code.into_iter().map(CodeByte).collect();
+        *old_exception_table = exception_table;
+        for (i, info) in relocated {
+            attributes[i].info = info;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        instruction::tests::{code, method},
+        AccessFlags,
+    };
+    use Instruction::*;
+
+    #[test]
+    fn switch_padding() {
+        // switch (x) { case 0: return 1; default: return 0; }, the tableswitch at 1 is padded
+        // to 4 and ends at 20
+        let switch = Tableswitch {
+            default: 21,
+            low: 0,
+            high: 0,
+            offsets: vec![19],
+        };
+        let instructions = vec![Iload0, switch, Iconst1, Ireturn, Iconst0, Ireturn];
+        for nops in 1..=4u32 {
+            let (mut m, cp) = method("(I)I", AccessFlags::STATIC, instructions.clone(), vec![]);
+            let mut editor = CodeEditor::new(&mut m, &cp).unwrap();
+            let start = editor.at(0).unwrap();
+            editor
+                .insert_before(start, vec![Nop; nops as usize])
+                .unwrap();
+            editor.finish(&mut m, &cp).unwrap();
+
+            let (code, _) = code(&m);
+            let (at, switch) = &code[nops as usize + 1];
+            assert_eq!(*at, nops + 1);
+            // the operands start at the next multiple of 4
+            let end = (nops + 2).next_multiple_of(4) + 16;
+            let expected = Tableswitch {
+                default: (end + 2 - at) as i32,
+                low: 0,
+                high: 0,
+                offsets: vec![(end - at) as i32],
+            };
+            assert_eq!(*switch, expected, "after {} nops", nops);
+            assert_eq!(
+                code[nops as usize + 2..],
+                [
+                    (end, Iconst1),
+                    (end + 1, Ireturn),
+                    (end + 2, Iconst0),
+                    (end + 3, Ireturn)
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn widened_branches() {
+        // if (x != 0) goto end; goto end; end: return
+        let instructions = vec![Iload0, Ifne(6), Goto(3), Return];
+        let (mut m, cp) = method("(I)V", AccessFlags::STATIC, instructions, vec![]);
+        let mut editor = CodeEditor::new(&mut m, &cp).unwrap();
+        // 40000 nops before the return put it out of reach of both branches
+        let end = editor.at(7).unwrap();
+        editor.insert_before(end, vec![Nop; 40_000]).unwrap();
+        editor.finish(&mut m, &cp).unwrap();
+
+        let (code, _) = code(&m);
+        // the ifne becomes an ifeq over a goto_w, the goto a goto_w
+        assert_eq!(
+            code[..4],
+            [
+                (0, Iload0),
+                (1, Ifeq(8)),
+                (4, GotoW(40_010)),
+                (9, GotoW(40_005))
+            ]
+        );
+        assert_eq!(code.len(), 40_005);
+        assert_eq!(code.last().unwrap(), &(40_014, Return));
+    }
+
+    #[test]
+    fn removed_target() {
+        // the ifeq goes to the second nop, which a handler's range starts at
+        let instructions = vec![Iload0, Ifeq(4), Nop, Nop, Return];
+        let handlers = vec![
+            ExceptionTableEntry {
+                start: 4,
+                end: 5,
+                handler: 6,
+                catch_type: None,
+            },
+            ExceptionTableEntry {
+                start: 5,
+                end: 6,
+                handler: 6,
+                catch_type: None,
+            },
+        ];
+        let (mut m, cp) = method("(I)V", AccessFlags::STATIC, instructions, handlers);
+        let mut editor = CodeEditor::new(&mut m, &cp).unwrap();
+        let target = editor.at(5).unwrap();
+        assert_eq!(editor.remove(target).unwrap(), Nop);
+        assert!(editor.remove(target).is_err());
+        editor.finish(&mut m, &cp).unwrap();
+
+        // the branch goes to the instruction after the removed one, and the handler that only
+        // protected it is dropped
+        let (code, exception_table) = code(&m);
+        assert_eq!(code, [(0, Iload0), (1, Ifeq(4)), (4, Nop), (5, Return)]);
+        assert_eq!(
+            exception_table,
+            [ExceptionTableEntry {
+                start: 4,
+                end: 5,
+                handler: 5,
+                catch_type: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn append_and_retarget() {
+        // if (x == 0) goto end; nop; end: return
+        let instructions = vec![Iload0, Ifeq(4), Nop, Return];
+        let (mut m, cp) = method("(I)V", AccessFlags::STATIC, instructions, vec![]);
+        let mut editor = CodeEditor::new(&mut m, &cp).unwrap();
+        let start = editor.at(0).unwrap();
+        let ifeq = editor.at(1).unwrap();
+        let ret = editor.at(5).unwrap();
+        assert_eq!(editor.instruction(ifeq), Some(&Ifeq(4)));
+        assert_eq!(editor.targets(ifeq), [ret]);
+        assert_eq!(editor.instruction(editor.end()), None);
+
+        let appended = editor.append(vec![Iconst0, Pop, Goto(0)]).unwrap();
+        let goto = appended[2];
+        assert_eq!(editor.instruction(goto), Some(&Goto(0)));
+        // inserted branches have no target until they are set
+        assert!(editor.targets(goto).is_empty());
+        assert!(editor.set_targets(goto, vec![]).is_err());
+        assert!(editor.set_targets(goto, vec![Label(100)]).is_err());
+        assert!(editor.set_targets(appended[0], vec![start]).is_err());
+        editor.set_targets(goto, vec![start]).unwrap();
+        // the ifeq now goes to the appended code
+        editor.set_targets(ifeq, vec![appended[0]]).unwrap();
+        assert_eq!(editor.targets(ifeq), [appended[0]]);
+        editor.finish(&mut m, &cp).unwrap();
+
+        let (code, _) = code(&m);
+        assert_eq!(
+            code,
+            [
+                (0, Iload0),
+                (1, Ifeq(5)),
+                (4, Nop),
+                (5, Return),
+                (6, Iconst0),
+                (7, Pop),
+                (8, Goto(-8))
+            ]
+        );
+    }
+
+    #[test]
+    fn unset_targets() {
+        let (mut m, cp) = method("()V", AccessFlags::STATIC, vec![Return], vec![]);
+        let mut editor = CodeEditor::new(&mut m, &cp).unwrap();
+        editor.append(vec![Goto(0)]).unwrap();
+        assert!(editor.finish(&mut m, &cp).is_err());
+        // nothing changed
+        assert_eq!(code(&m).0, [(0, Return)]);
+    }
+}
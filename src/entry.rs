@@ -0,0 +1,139 @@
+use crate::{descriptor::MethodDescriptor, AccessFlags, AttributeInfo, JavaClass, Method};
+
+// a method along with its resolved name and descriptor
+#[derive(Debug, Clone, Copy)]
+pub struct MethodView<'a> {
+    // index in JavaClass::methods
+    pub index: usize,
+    pub method: &'a Method,
+    pub name: &'a str,
+    pub descriptor: &'a str,
+}
+
+// a method with what analyses usually need of it resolved and parsed
+#[derive(Debug, Clone)]
+pub struct MethodInfo<'a> {
+    // index in JavaClass::methods
+    pub index: usize,
+    pub method: &'a Method,
+    pub name: &'a str,
+    pub descriptor: &'a str,
+    pub parsed_descriptor: MethodDescriptor,
+    pub access_flags: AccessFlags,
+    // the Code attribute, None for abstract and native methods (or when it couldn't be parsed)
+    pub code: Option<&'a AttributeInfo>,
+}
+
+impl JavaClass {
+    // the methods whose name and descriptor resolve, in order
+    pub fn method_views(&self) -> impl Iterator<Item = MethodView<'_>> {
+        let cp = &self.constant_pool;
+        self.methods
+            .iter()
+            .enumerate()
+            .filter_map(move |(index, method)| {
+                Some(MethodView {
+                    index,
+                    method,
+                    name: method.name_index.resolve(cp).ok()?,
+                    descriptor: method.descriptor_index.resolve(cp).ok()?,
+                })
+            })
+    }
+    // the methods whose name and descriptor resolve and whose descriptor parses, in order
+    pub fn methods_iter(&self) -> impl Iterator<Item = MethodInfo<'_>> {
+        self.method_views().filter_map(|view| {
+            Some(MethodInfo {
+                index: view.index,
+                method: view.method,
+                name: view.name,
+                descriptor: view.descriptor,
+                parsed_descriptor: MethodDescriptor::parse(view.descriptor)?,
+                access_flags: view.method.access_flags,
+                code: view
+                    .method
+                    .attributes
+                    .iter()
+                    .map(|a| &a.info)
+                    .find(|info| matches!(info, AttributeInfo::Code { .. })),
+            })
+        })
+    }
+    // The public static void main(String[]) the java launcher runs. Synthetic copies (which
+    // some obfuscators and compilers add next to the real one) are only picked when alone.
+    pub fn main_method(&self) -> Option<MethodView<'_>> {
+        let flags = AccessFlags::PUBLIC | AccessFlags::STATIC;
+        let mut candidates = self.method_views().filter(|m| {
+            m.name == "main"
+                && m.descriptor == "([Ljava/lang/String;)V"
+                && m.method.access_flags.contains(flags)
+        });
+        let first = candidates.next()?;
+        let synthetic = |m: &MethodView| m.method.access_flags.contains(AccessFlags::SYNTHETIC);
+        if !synthetic(&first) {
+            return Some(first);
+        }
+        Some(candidates.find(|m| !synthetic(m)).unwrap_or(first))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{descriptor::FieldType, tests::fixture, ConstantPoolEntry, Utf8Index};
+
+    #[test]
+    fn methods_iter() {
+        let class = fixture("Calls");
+        let methods: Vec<_> = class
+            .methods_iter()
+            .map(|m| (m.index, m.name, m.descriptor))
+            .collect();
+        assert_eq!(
+            methods,
+            [
+                (0, "<init>", "()V"),
+                (1, "fact", "(I)I"),
+                (2, "helper", "()I"),
+                (3, "spin", "(I)I"),
+                (4, "unused", "()V"),
+                (5, "main", "([Ljava/lang/String;)V"),
+                (6, "lambda$main$0", "()I"),
+            ]
+        );
+
+        let main = class.methods_iter().find(|m| m.name == "main").unwrap();
+        assert!(std::ptr::eq(main.method, &class.methods[5]));
+        assert_eq!(main.access_flags, AccessFlags::PUBLIC | AccessFlags::STATIC);
+        let string = FieldType::Object("java/lang/String".to_string());
+        assert_eq!(
+            main.parsed_descriptor.parameters,
+            [FieldType::Array(Box::new(string))]
+        );
+        assert_eq!(main.parsed_descriptor.return_type, None);
+        assert!(matches!(main.code, Some(AttributeInfo::Code { .. })));
+
+        // the elements of an annotation interface are abstract
+        let config = fixture("Config");
+        let required = config
+            .methods_iter()
+            .find(|m| m.name == "required")
+            .unwrap();
+        assert_eq!(required.parsed_descriptor.return_type, Some(FieldType::Int));
+        assert!(required.code.is_none());
+    }
+
+    #[test]
+    fn methods_iter_skips_bad_descriptors() {
+        let mut class = fixture("Calls");
+        let index = class
+            .constant_pool
+            .push(ConstantPoolEntry::Utf8("(X".into()))
+            .unwrap();
+        class.methods[1].descriptor_index = Utf8Index(index);
+        assert_eq!(class.method_views().count(), 7);
+        let names: Vec<_> = class.methods_iter().map(|m| m.name).collect();
+        assert!(!names.contains(&"fact"));
+        assert_eq!(names.len(), 6);
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+
+use crate::{
+    descriptor::FieldType,
+    instruction::{decode_code, Instruction},
+    AccessFlags, AttributeInfo, CPIndex, ConstantPool, ConstantPoolEntry, Field, JavaClass,
+};
+
+// a field with its name and descriptor resolved, like MethodInfo for methods
+#[derive(Debug, Clone)]
+pub struct FieldInfo<'a> {
+    // index in JavaClass::fields
+    pub index: usize,
+    pub field: &'a Field,
+    pub name: &'a str,
+    pub descriptor: &'a str,
+    pub field_type: FieldType,
+    pub access_flags: AccessFlags,
+    // the entry the ConstantValue attribute points at, for constant static final fields
+    pub constant_value: Option<&'a ConstantPoolEntry>,
+}
+
+// a field as a FieldRef names it
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FieldKey {
+    pub class: String,
+    pub name: String,
+    pub descriptor: String,
+}
+
+// an instruction accessing a field
+#[derive(Debug, Clone, Copy)]
+pub struct AccessSite {
+    // index of the method in JavaClass::methods
+    pub method: usize,
+    pub offset: u32,
+}
+
+// the instructions reading and writing a field, in method then offset order
+#[derive(Debug, Default)]
+pub struct FieldAccess {
+    pub reads: Vec<AccessSite>,
+    pub writes: Vec<AccessSite>,
+}
+
+// the field a FieldRef entry points at
+fn field_ref(cp: &ConstantPool, index: CPIndex) -> Option<FieldKey> {
+    let Some(ConstantPoolEntry::FieldRef {
+        class_index,
+        name_and_type_index,
+    }) = cp.get(index)
+    else {
+        return None;
+    };
+    let (name, descriptor) = name_and_type_index.resolve(cp).ok()?;
+    Some(FieldKey {
+        class: class_index.name(cp).ok()?.to_string(),
+        name: name.resolve(cp).ok()?.to_string(),
+        descriptor: descriptor.resolve(cp).ok()?.to_string(),
+    })
+}
+
+impl JavaClass {
+    // the fields whose name and descriptor resolve and whose descriptor parses, in order
+    pub fn fields_iter(&self) -> impl Iterator<Item = FieldInfo<'_>> {
+        let cp = &self.constant_pool;
+        self.fields
+            .iter()
+            .enumerate()
+            .filter_map(move |(index, field)| {
+                let descriptor = field.descriptor_index.resolve(cp).ok()?;
+                Some(FieldInfo {
+                    index,
+                    field,
+                    name: field.name_index.resolve(cp).ok()?,
+                    descriptor,
+                    field_type: FieldType::parse(descriptor)?,
+                    access_flags: field.access_flags,
+                    constant_value: field.attributes.iter().find_map(|a| match a.info {
+                        AttributeInfo::ConstantValue { index } => cp.get(index),
+                        _ => None,
+                    }),
+                })
+            })
+    }
+    // The fields accessed by the code of the class, its own as well as the ones of other classes
+    // (see declares_field). Methods whose code can't be decoded are skipped.
+    pub fn field_access_map(&self) -> HashMap<FieldKey, FieldAccess> {
+        let cp = &self.constant_pool;
+        let mut res = HashMap::<FieldKey, FieldAccess>::new();
+        for (method, m) in self.methods.iter().enumerate() {
+            let code = m.attributes.iter().find_map(|a| match &a.info {
+                AttributeInfo::Code { code, .. } => {
+                    Some(code.iter().map(|b| b.0).collect::<Vec<_>>())
+                }
+                _ => None,
+            });
+            let Some(Ok(instructions)) = code.map(|c| decode_code(&c)) else {
+                continue;
+            };
+            for (offset, instruction) in instructions {
+                let (index, write) = match instruction {
+                    Instruction::Getfield(index) | Instruction::Getstatic(index) => (index, false),
+                    Instruction::Putfield(index) | Instruction::Putstatic(index) => (index, true),
+                    _ => continue,
+                };
+                let Some(key) = field_ref(cp, index) else {
+                    continue;
+                };
+                let access = res.entry(key).or_default();
+                let site = AccessSite { method, offset };
+                if write {
+                    access.writes.push(site);
+                } else {
+                    access.reads.push(site);
+                }
+            }
+        }
+        res
+    }
+    // whether the field is one of the fields of this class, rather than inherited or of another
+    pub fn declares_field(&self, key: &FieldKey) -> bool {
+        let cp = &self.constant_pool;
+        self.this_class.name(cp).is_ok_and(|c| c == key.class)
+            && self.fields.iter().any(|f| {
+                f.name_index.resolve(cp).is_ok_and(|n| n == key.name)
+                    && f.descriptor_index
+                        .resolve(cp)
+                        .is_ok_and(|d| d == key.descriptor)
+            })
+    }
+    // The own non final fields only written by the constructors (or the static initializer for
+    // static ones), which could then be final.
+    pub fn final_candidates(&self, map: &HashMap<FieldKey, FieldAccess>) -> Vec<FieldKey> {
+        let cp = &self.constant_pool;
+        let mut res = Vec::new();
+        for f in self.fields.iter() {
+            if f.access_flags.contains(AccessFlags::FINAL) {
+                continue;
+            }
+            let (Ok(class), Ok(name), Ok(descriptor)) = (
+                self.this_class.name(cp),
+                f.name_index.resolve(cp),
+                f.descriptor_index.resolve(cp),
+            ) else {
+                continue;
+            };
+            let key = FieldKey {
+                class: class.to_string(),
+                name: name.to_string(),
+                descriptor: descriptor.to_string(),
+            };
+            let initializer = if f.access_flags.contains(AccessFlags::STATIC) {
+                "<clinit>"
+            } else {
+                "<init>"
+            };
+            let Some(access) = map.get(&key) else {
+                continue;
+            };
+            if !access.writes.is_empty()
+                && access.writes.iter().all(|w| {
+                    self.methods[w.method].name_index.resolve(cp).ok() == Some(initializer)
+                })
+            {
+                res.push(key);
+            }
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tests::fixture, DoubleBits, FloatBits};
+
+    #[test]
+    fn fields_iter() {
+        let class = fixture("Fields");
+        let fields: Vec<_> = class
+            .fields_iter()
+            .map(|f| (f.index, f.name, f.descriptor, f.field_type))
+            .collect();
+        let object = |name: &str| FieldType::Object(name.to_string());
+        assert_eq!(
+            fields,
+            [
+                (0, "lock", "Ljava/lang/Object;", object("java/lang/Object")),
+                (1, "created", "I", FieldType::Int),
+                (2, "name", "Ljava/lang/String;", object("java/lang/String")),
+                (3, "id", "I", FieldType::Int),
+                (4, "count", "I", FieldType::Int),
+                (5, "lastBump", "J", FieldType::Long),
+                (6, "unused", "Z", FieldType::Boolean),
+            ]
+        );
+        let name = class.fields_iter().nth(2).unwrap();
+        assert!(std::ptr::eq(name.field, &class.fields[2]));
+        assert_eq!(name.access_flags, AccessFlags::PRIVATE | AccessFlags::FINAL);
+        assert!(class.fields_iter().all(|f| f.constant_value.is_none()));
+    }
+
+    #[test]
+    fn constant_values() {
+        let class = fixture("Constants");
+        let cp = &class.constant_pool;
+        let values: Vec<_> = class
+            .fields_iter()
+            .map(|f| (f.name, f.constant_value))
+            .collect();
+        let string = match values[4].1 {
+            Some(ConstantPoolEntry::String { string_index }) => string_index.resolve(cp).ok(),
+            _ => None,
+        };
+        assert_eq!(string, Some("constant"));
+        assert_eq!(
+            values,
+            [
+                ("INT", Some(&ConstantPoolEntry::Integer(42))),
+                ("LONG", Some(&ConstantPoolEntry::Long(1234567890123))),
+                (
+                    "FLOAT",
+                    Some(&ConstantPoolEntry::Float(FloatBits::new(1.5)))
+                ),
+                (
+                    "DOUBLE",
+                    Some(&ConstantPoolEntry::Double(DoubleBits::new(2.25)))
+                ),
+                ("STRING", values[4].1),
+                ("counter", None),
+            ]
+        );
+    }
+}
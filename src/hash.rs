@@ -0,0 +1,76 @@
+use crate::{serialization::Serialize, JavaClass};
+
+// 64 bit FNV-1a, which is deterministic across runs and platforms unlike the std hashers
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash = 0xcbf29ce484222325u64;
+    for b in bytes {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+// the class file bytes of value, serializing into a Vec can't fail
+fn bytes<T: Serialize>(value: &T) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    value
+        .serialize(&mut bytes)
+        .expect("serializing into a Vec failed");
+    bytes
+}
+
+impl JavaClass {
+    // A fingerprint of the constant pool entries in index order, as they would be written in the
+    // class file. Stable across runs, meant to cache analysis results.
+    pub fn compute_cp_hash(&self) -> u64 {
+        fnv1a(&bytes(&self.constant_pool))
+    }
+    // same for the whole class
+    pub fn compute_class_hash(&self) -> u64 {
+        fnv1a(&bytes(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::fixture;
+    use crate::{AccessFlags, ConstantPoolEntry};
+
+    #[test]
+    fn fnv1a_vectors() {
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x85944171f73967e8);
+    }
+
+    #[test]
+    fn hashes() {
+        let hello = fixture("Hello");
+        // identical classes, parsed separately
+        let same = fixture("Hello");
+        assert_eq!(hello.compute_cp_hash(), same.compute_cp_hash());
+        assert_eq!(hello.compute_class_hash(), same.compute_class_hash());
+        assert_ne!(
+            hello.compute_class_hash(),
+            fixture("Flow").compute_class_hash()
+        );
+
+        // one constant changed
+        let mut changed = fixture("Hello");
+        let (index, _) = changed
+            .constant_pool
+            .utf8_values()
+            .find(|(_, s)| *s == "Hello, world!")
+            .unwrap();
+        *changed.constant_pool.get_mut(index).unwrap() = ConstantPoolEntry::Utf8("Hello!".into());
+        assert_ne!(hello.compute_cp_hash(), changed.compute_cp_hash());
+        assert_ne!(hello.compute_class_hash(), changed.compute_class_hash());
+
+        // outside the constant pool, only the class hash changes
+        let mut changed = fixture("Hello");
+        changed.access_flags |= AccessFlags::FINAL;
+        assert_eq!(hello.compute_cp_hash(), changed.compute_cp_hash());
+        assert_ne!(hello.compute_class_hash(), changed.compute_class_hash());
+    }
+}
@@ -0,0 +1,34 @@
+use std::{fs, io::Error, ops::Deref, path::Path};
+
+// The contents of an input file, mapped in memory with the mmap feature or read otherwise.
+pub enum FileBytes {
+    #[cfg(feature = "mmap")]
+    Mapped(memmap2::Mmap),
+    Read(Vec<u8>),
+}
+
+impl Deref for FileBytes {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        match self {
+            #[cfg(feature = "mmap")]
+            FileBytes::Mapped(m) => m,
+            FileBytes::Read(b) => b,
+        }
+    }
+}
+
+// Maps the file when the mmap feature is on, falling back to reading it when that fails (empty
+// files, or files on systems or filesystems that can't be mapped).
+pub fn read<P: AsRef<Path>>(path: P) -> Result<FileBytes, Error> {
+    #[cfg(feature = "mmap")]
+    {
+        let file = fs::File::open(&path)?;
+        // SAFETY: the mapping is read-only. A file truncated while mapped makes reading past its
+        // new end fault, so inputs are expected not to change while javd runs, as for from_mmap.
+        if let Ok(m) = unsafe { memmap2::Mmap::map(&file) } {
+            return Ok(FileBytes::Mapped(m));
+        }
+    }
+    Ok(FileBytes::Read(fs::read(path)?))
+}
@@ -0,0 +1,807 @@
+use std::{
+    borrow::Cow,
+    io::{Cursor, Error},
+};
+
+use crate::{
+    deserialization::{read_vec, Deserialize},
+    serialization::Serialize,
+    Attribute, AttributeInfo, CPIndex, ClassIndex, CodeByte, ExceptionTableEntry,
+};
+
+// Defines Instruction from the table of the instructions whose operands don't depend on anything
+// (opcode, variant, mnemonic, operands in the order they are written after the opcode), plus the
+// few instructions that need special handling.
+macro_rules! instructions {
+    ($($opcode:literal $name:ident $mnemonic:literal $(($($operand:ident: $ty:ty),+))?,)*) => {
+        #[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+        pub enum Instruction {
+            $($name $(($($ty),+))?,)*
+            // the index is a single byte
+            Ldc(CPIndex),
+            // index and count (the number of argument slots), followed by a 0 byte
+            Invokeinterface(CPIndex, u8),
+            // followed by two 0 bytes
+            Invokedynamic(CPIndex),
+            // offsets are relative to the opcode, like for every branch
+            Tableswitch {
+                default: i32,
+                low: i32,
+                high: i32,
+                offsets: Vec<i32>,
+            },
+            Lookupswitch {
+                default: i32,
+                pairs: Vec<(i32, i32)>,
+            },
+            // wide iload, fload, aload, lload, dload, istore, fstore, astore, lstore, dstore or ret
+            Wide {
+                opcode: u8,
+                index: u16,
+            },
+            WideIinc {
+                index: u16,
+                constant: i16,
+            },
+        }
+
+        impl Instruction {
+            fn decode_fixed(opcode: u8, bytes: &mut Cursor<&[u8]>) -> Result<Option<Self>, Error> {
+                Ok(Some(match opcode {
+                    $($opcode => Instruction::$name $(($(<$ty>::deserialize(bytes)?),+))?,)*
+                    _ => return Ok(None),
+                }))
+            }
+            // false if the instruction isn't one of the table
+            fn encode_fixed(&self, code: &mut Vec<u8>) -> Result<bool, Error> {
+                match self {
+                    $(Instruction::$name $(($($operand),+))? => {
+                        ($opcode as u8).serialize(code)?;
+                        $($($operand.serialize(code)?;)+)?
+                    })*
+                    _ => return Ok(false),
+                }
+                Ok(true)
+            }
+            pub fn opcode(&self) -> u8 {
+                match self {
+                    $(Instruction::$name { .. } => $opcode,)*
+                    Instruction::Ldc(_) => 0x12,
+                    Instruction::Invokeinterface(..) => 0xb9,
+                    Instruction::Invokedynamic(_) => 0xba,
+                    Instruction::Tableswitch { .. } => 0xaa,
+                    Instruction::Lookupswitch { .. } => 0xab,
+                    Instruction::Wide { .. } | Instruction::WideIinc { .. } => 0xc4,
+                }
+            }
+            pub fn mnemonic(&self) -> &'static str {
+                match self {
+                    $(Instruction::$name { .. } => $mnemonic,)*
+                    Instruction::Ldc(_) => "ldc",
+                    Instruction::Invokeinterface(..) => "invokeinterface",
+                    Instruction::Invokedynamic(_) => "invokedynamic",
+                    Instruction::Tableswitch { .. } => "tableswitch",
+                    Instruction::Lookupswitch { .. } => "lookupswitch",
+                    Instruction::Wide { .. } | Instruction::WideIinc { .. } => "wide",
+                }
+            }
+            // the number of bytes the instruction takes when placed at offset
+            pub fn size(&self, offset: u32) -> u32 {
+                match self {
+                    $(Instruction::$name { .. } => {
+                        1 $($(+ std::mem::size_of::<$ty>() as u32)+)?
+                    })*
+                    Instruction::Ldc(_) => 2,
+                    Instruction::Invokeinterface(..) | Instruction::Invokedynamic(_) => 5,
+                    Instruction::Tableswitch { offsets, .. } => {
+                        1 + padding(offset) + 12 + 4 * offsets.len() as u32
+                    }
+                    Instruction::Lookupswitch { pairs, .. } => {
+                        1 + padding(offset) + 8 + 8 * pairs.len() as u32
+                    }
+                    Instruction::Wide { .. } => 4,
+                    Instruction::WideIinc { .. } => 6,
+                }
+            }
+        }
+    };
+}
+
+instructions! {
+    0x00 Nop "nop",
+    0x01 AconstNull "aconst_null",
+    0x02 IconstM1 "iconst_m1",
+    0x03 Iconst0 "iconst_0",
+    0x04 Iconst1 "iconst_1",
+    0x05 Iconst2 "iconst_2",
+    0x06 Iconst3 "iconst_3",
+    0x07 Iconst4 "iconst_4",
+    0x08 Iconst5 "iconst_5",
+    0x09 Lconst0 "lconst_0",
+    0x0a Lconst1 "lconst_1",
+    0x0b Fconst0 "fconst_0",
+    0x0c Fconst1 "fconst_1",
+    0x0d Fconst2 "fconst_2",
+    0x0e Dconst0 "dconst_0",
+    0x0f Dconst1 "dconst_1",
+    0x10 Bipush "bipush" (value: i8),
+    0x11 Sipush "sipush" (value: i16),
+    0x13 LdcW "ldc_w" (index: CPIndex),
+    0x14 Ldc2W "ldc2_w" (index: CPIndex),
+    0x15 Iload "iload" (index: u8),
+    0x16 Lload "lload" (index: u8),
+    0x17 Fload "fload" (index: u8),
+    0x18 Dload "dload" (index: u8),
+    0x19 Aload "aload" (index: u8),
+    0x1a Iload0 "iload_0",
+    0x1b Iload1 "iload_1",
+    0x1c Iload2 "iload_2",
+    0x1d Iload3 "iload_3",
+    0x1e Lload0 "lload_0",
+    0x1f Lload1 "lload_1",
+    0x20 Lload2 "lload_2",
+    0x21 Lload3 "lload_3",
+    0x22 Fload0 "fload_0",
+    0x23 Fload1 "fload_1",
+    0x24 Fload2 "fload_2",
+    0x25 Fload3 "fload_3",
+    0x26 Dload0 "dload_0",
+    0x27 Dload1 "dload_1",
+    0x28 Dload2 "dload_2",
+    0x29 Dload3 "dload_3",
+    0x2a Aload0 "aload_0",
+    0x2b Aload1 "aload_1",
+    0x2c Aload2 "aload_2",
+    0x2d Aload3 "aload_3",
+    0x2e Iaload "iaload",
+    0x2f Laload "laload",
+    0x30 Faload "faload",
+    0x31 Daload "daload",
+    0x32 Aaload "aaload",
+    0x33 Baload "baload",
+    0x34 Caload "caload",
+    0x35 Saload "saload",
+    0x36 Istore "istore" (index: u8),
+    0x37 Lstore "lstore" (index: u8),
+    0x38 Fstore "fstore" (index: u8),
+    0x39 Dstore "dstore" (index: u8),
+    0x3a Astore "astore" (index: u8),
+    0x3b Istore0 "istore_0",
+    0x3c Istore1 "istore_1",
+    0x3d Istore2 "istore_2",
+    0x3e Istore3 "istore_3",
+    0x3f Lstore0 "lstore_0",
+    0x40 Lstore1 "lstore_1",
+    0x41 Lstore2 "lstore_2",
+    0x42 Lstore3 "lstore_3",
+    0x43 Fstore0 "fstore_0",
+    0x44 Fstore1 "fstore_1",
+    0x45 Fstore2 "fstore_2",
+    0x46 Fstore3 "fstore_3",
+    0x47 Dstore0 "dstore_0",
+    0x48 Dstore1 "dstore_1",
+    0x49 Dstore2 "dstore_2",
+    0x4a Dstore3 "dstore_3",
+    0x4b Astore0 "astore_0",
+    0x4c Astore1 "astore_1",
+    0x4d Astore2 "astore_2",
+    0x4e Astore3 "astore_3",
+    0x4f Iastore "iastore",
+    0x50 Lastore "lastore",
+    0x51 Fastore "fastore",
+    0x52 Dastore "dastore",
+    0x53 Aastore "aastore",
+    0x54 Bastore "bastore",
+    0x55 Castore "castore",
+    0x56 Sastore "sastore",
+    0x57 Pop "pop",
+    0x58 Pop2 "pop2",
+    0x59 Dup "dup",
+    0x5a DupX1 "dup_x1",
+    0x5b DupX2 "dup_x2",
+    0x5c Dup2 "dup2",
+    0x5d Dup2X1 "dup2_x1",
+    0x5e Dup2X2 "dup2_x2",
+    0x5f Swap "swap",
+    0x60 Iadd "iadd",
+    0x61 Ladd "ladd",
+    0x62 Fadd "fadd",
+    0x63 Dadd "dadd",
+    0x64 Isub "isub",
+    0x65 Lsub "lsub",
+    0x66 Fsub "fsub",
+    0x67 Dsub "dsub",
+    0x68 Imul "imul",
+    0x69 Lmul "lmul",
+    0x6a Fmul "fmul",
+    0x6b Dmul "dmul",
+    0x6c Idiv "idiv",
+    0x6d Ldiv "ldiv",
+    0x6e Fdiv "fdiv",
+    0x6f Ddiv "ddiv",
+    0x70 Irem "irem",
+    0x71 Lrem "lrem",
+    0x72 Frem "frem",
+    0x73 Drem "drem",
+    0x74 Ineg "ineg",
+    0x75 Lneg "lneg",
+    0x76 Fneg "fneg",
+    0x77 Dneg "dneg",
+    0x78 Ishl "ishl",
+    0x79 Lshl "lshl",
+    0x7a Ishr "ishr",
+    0x7b Lshr "lshr",
+    0x7c Iushr "iushr",
+    0x7d Lushr "lushr",
+    0x7e Iand "iand",
+    0x7f Land "land",
+    0x80 Ior "ior",
+    0x81 Lor "lor",
+    0x82 Ixor "ixor",
+    0x83 Lxor "lxor",
+    0x84 Iinc "iinc" (index: u8, constant: i8),
+    0x85 I2l "i2l",
+    0x86 I2f "i2f",
+    0x87 I2d "i2d",
+    0x88 L2i "l2i",
+    0x89 L2f "l2f",
+    0x8a L2d "l2d",
+    0x8b F2i "f2i",
+    0x8c F2l "f2l",
+    0x8d F2d "f2d",
+    0x8e D2i "d2i",
+    0x8f D2l "d2l",
+    0x90 D2f "d2f",
+    0x91 I2b "i2b",
+    0x92 I2c "i2c",
+    0x93 I2s "i2s",
+    0x94 Lcmp "lcmp",
+    0x95 Fcmpl "fcmpl",
+    0x96 Fcmpg "fcmpg",
+    0x97 Dcmpl "dcmpl",
+    0x98 Dcmpg "dcmpg",
+    0x99 Ifeq "ifeq" (offset: i16),
+    0x9a Ifne "ifne" (offset: i16),
+    0x9b Iflt "iflt" (offset: i16),
+    0x9c Ifge "ifge" (offset: i16),
+    0x9d Ifgt "ifgt" (offset: i16),
+    0x9e Ifle "ifle" (offset: i16),
+    0x9f IfIcmpeq "if_icmpeq" (offset: i16),
+    0xa0 IfIcmpne "if_icmpne" (offset: i16),
+    0xa1 IfIcmplt "if_icmplt" (offset: i16),
+    0xa2 IfIcmpge "if_icmpge" (offset: i16),
+    0xa3 IfIcmpgt "if_icmpgt" (offset: i16),
+    0xa4 IfIcmple "if_icmple" (offset: i16),
+    0xa5 IfAcmpeq "if_acmpeq" (offset: i16),
+    0xa6 IfAcmpne "if_acmpne" (offset: i16),
+    0xa7 Goto "goto" (offset: i16),
+    0xa8 Jsr "jsr" (offset: i16),
+    0xa9 Ret "ret" (index: u8),
+    0xac Ireturn "ireturn",
+    0xad Lreturn "lreturn",
+    0xae Freturn "freturn",
+    0xaf Dreturn "dreturn",
+    0xb0 Areturn "areturn",
+    0xb1 Return "return",
+    0xb2 Getstatic "getstatic" (index: CPIndex),
+    0xb3 Putstatic "putstatic" (index: CPIndex),
+    0xb4 Getfield "getfield" (index: CPIndex),
+    0xb5 Putfield "putfield" (index: CPIndex),
+    0xb6 Invokevirtual "invokevirtual" (index: CPIndex),
+    0xb7 Invokespecial "invokespecial" (index: CPIndex),
+    0xb8 Invokestatic "invokestatic" (index: CPIndex),
+    0xbb New "new" (index: ClassIndex),
+    0xbc Newarray "newarray" (atype: u8),
+    0xbd Anewarray "anewarray" (index: ClassIndex),
+    0xbe Arraylength "arraylength",
+    0xbf Athrow "athrow",
+    0xc0 Checkcast "checkcast" (index: ClassIndex),
+    0xc1 Instanceof "instanceof" (index: ClassIndex),
+    0xc2 Monitorenter "monitorenter",
+    0xc3 Monitorexit "monitorexit",
+    0xc5 Multianewarray "multianewarray" (index: ClassIndex, dimensions: u8),
+    0xc6 Ifnull "ifnull" (offset: i16),
+    0xc7 Ifnonnull "ifnonnull" (offset: i16),
+    0xc8 GotoW "goto_w" (offset: i32),
+    0xc9 JsrW "jsr_w" (offset: i32),
+}
+
+// the switches are padded so that their operands start at a multiple of 4 in the code
+fn padding(offset: u32) -> u32 {
+    (4 - (offset + 1) % 4) % 4
+}
+
+// reads the count elements of a switch, without trusting count for the allocation
+impl Deserialize for (i32, i32) {
+    const MIN_SIZE: usize = 8;
+
+    fn deserialize(bytes: &mut Cursor<&[u8]>) -> Result<Self, Error> {
+        Ok((i32::deserialize(bytes)?, i32::deserialize(bytes)?))
+    }
+}
+
+impl Instruction {
+    // Decodes the instruction at the cursor, which must be over the code array alone since the
+    // position is used as the offset of the instruction.
+    pub fn decode(bytes: &mut Cursor<&[u8]>) -> Result<Self, Error> {
+        let offset = bytes.position() as u32;
+        let opcode = u8::deserialize(bytes)?;
+        if let Some(instruction) = Self::decode_fixed(opcode, bytes)? {
+            return Ok(instruction);
+        }
+
+        match opcode {
+            0x12 => CPIndex::new(u8::deserialize(bytes)? as u16)
+                .map(Instruction::Ldc)
+                .ok_or_else(|| Error::other(format!("ldc of index 0 at offset {}.", offset))),
+            0xb9 => {
+                let index = CPIndex::deserialize(bytes)?;
+                let count = u8::deserialize(bytes)?;
+                u8::deserialize(bytes)?;
+                Ok(Instruction::Invokeinterface(index, count))
+            }
+            0xba => {
+                let index = CPIndex::deserialize(bytes)?;
+                u16::deserialize(bytes)?;
+                Ok(Instruction::Invokedynamic(index))
+            }
+            0xaa => {
+                bytes.set_position(bytes.position() + padding(offset) as u64);
+                let default = i32::deserialize(bytes)?;
+                let low = i32::deserialize(bytes)?;
+                let high = i32::deserialize(bytes)?;
+                if high < low {
+                    return Err(Error::other(format!(
+                        "tableswitch at offset {} has its high ({}) lower than its low ({}).",
+                        offset, high, low
+                    )));
+                }
+                let count = (high as i64 - low as i64 + 1) as usize;
+                Ok(Instruction::Tableswitch {
+                    default,
+                    low,
+                    high,
+                    offsets: read_vec(bytes, count)?,
+                })
+            }
+            0xab => {
+                bytes.set_position(bytes.position() + padding(offset) as u64);
+                let default = i32::deserialize(bytes)?;
+                let count = i32::deserialize(bytes)?;
+                if count < 0 {
+                    return Err(Error::other(format!(
+                        "lookupswitch at offset {} has a negative number of pairs.",
+                        offset
+                    )));
+                }
+                Ok(Instruction::Lookupswitch {
+                    default,
+                    pairs: read_vec(bytes, count as usize)?,
+                })
+            }
+            0xc4 => match u8::deserialize(bytes)? {
+                0x84 => Ok(Instruction::WideIinc {
+                    index: u16::deserialize(bytes)?,
+                    constant: i16::deserialize(bytes)?,
+                }),
+                opcode @ (0x15..=0x19 | 0x36..=0x3a | 0xa9) => Ok(Instruction::Wide {
+                    opcode,
+                    index: u16::deserialize(bytes)?,
+                }),
+                opcode => Err(Error::other(format!(
+                    "Opcode {:#04x} can't be widened (at offset {}).",
+                    opcode, offset
+                ))),
+            },
+            _ => Err(Error::other(format!(
+                "Unknown opcode {:#04x} at offset {}.",
+                opcode, offset
+            ))),
+        }
+    }
+    // Appends the instruction to code, which is the code array so far (its length is the offset
+    // of the instruction).
+    pub fn encode(&self, code: &mut Vec<u8>) -> Result<(), Error> {
+        if self.encode_fixed(code)? {
+            return Ok(());
+        }
+
+        let offset = code.len() as u32;
+        self.opcode().serialize(code)?;
+        match self {
+            Instruction::Ldc(index) => u8::try_from(index.0)
+                .map_err(|_| Error::other(format!("Index {} is too big for ldc.", index.0)))?
+                .serialize(code),
+            Instruction::Invokeinterface(index, count) => {
+                index.serialize(code)?;
+                count.serialize(code)?;
+                0u8.serialize(code)
+            }
+            Instruction::Invokedynamic(index) => {
+                index.serialize(code)?;
+                0u16.serialize(code)
+            }
+            Instruction::Tableswitch {
+                default,
+                low,
+                high,
+                offsets,
+            } => {
+                if offsets.len() as i64 != *high as i64 - *low as i64 + 1 {
+                    return Err(Error::other(format!(
+                        "tableswitch from {} to {} needs {} offsets, not {}.",
+                        low,
+                        high,
+                        *high as i64 - *low as i64 + 1,
+                        offsets.len()
+                    )));
+                }
+                code.resize(code.len() + padding(offset) as usize, 0);
+                default.serialize(code)?;
+                low.serialize(code)?;
+                high.serialize(code)?;
+                for o in offsets {
+                    o.serialize(code)?;
+                }
+                Ok(())
+            }
+            Instruction::Lookupswitch { default, pairs } => {
+                code.resize(code.len() + padding(offset) as usize, 0);
+                default.serialize(code)?;
+                (pairs.len() as i32).serialize(code)?;
+                for (key, o) in pairs {
+                    key.serialize(code)?;
+                    o.serialize(code)?;
+                }
+                Ok(())
+            }
+            Instruction::Wide { opcode, index } => {
+                opcode.serialize(code)?;
+                index.serialize(code)
+            }
+            Instruction::WideIinc { index, constant } => {
+                0x84u8.serialize(code)?;
+                index.serialize(code)?;
+                constant.serialize(code)
+            }
+            _ => unreachable!("encoded by encode_fixed"),
+        }
+    }
+    // the offsets of the branches of the instruction, relative to the instruction
+    pub fn branches(&self) -> Vec<i32> {
+        match self {
+            Instruction::Ifeq(o)
+            | Instruction::Ifne(o)
+            | Instruction::Iflt(o)
+            | Instruction::Ifge(o)
+            | Instruction::Ifgt(o)
+            | Instruction::Ifle(o)
+            | Instruction::IfIcmpeq(o)
+            | Instruction::IfIcmpne(o)
+            | Instruction::IfIcmplt(o)
+            | Instruction::IfIcmpge(o)
+            | Instruction::IfIcmpgt(o)
+            | Instruction::IfIcmple(o)
+            | Instruction::IfAcmpeq(o)
+            | Instruction::IfAcmpne(o)
+            | Instruction::Goto(o)
+            | Instruction::Jsr(o)
+            | Instruction::Ifnull(o)
+            | Instruction::Ifnonnull(o) => vec![*o as i32],
+            Instruction::GotoW(o) | Instruction::JsrW(o) => vec![*o],
+            Instruction::Tableswitch {
+                default, offsets, ..
+            } => std::iter::once(*default)
+                .chain(offsets.iter().copied())
+                .collect(),
+            Instruction::Lookupswitch { default, pairs } => std::iter::once(*default)
+                .chain(pairs.iter().map(|(_, o)| *o))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+    // whether execution can go on to the next instruction
+    pub fn falls_through(&self) -> bool {
+        !matches!(
+            self.opcode(),
+            0xa7 | 0xa9 | 0xaa | 0xab | 0xac..=0xb1 | 0xbf | 0xc8
+        ) && !matches!(self, Instruction::Wide { opcode: 0xa9, .. })
+    }
+    // the constant pool index the instruction uses, there is never more than one
+    pub fn references_cp(&self) -> Option<CPIndex> {
+        match self {
+            Instruction::Ldc(index)
+            | Instruction::LdcW(index)
+            | Instruction::Ldc2W(index)
+            | Instruction::Getstatic(index)
+            | Instruction::Putstatic(index)
+            | Instruction::Getfield(index)
+            | Instruction::Putfield(index)
+            | Instruction::Invokevirtual(index)
+            | Instruction::Invokespecial(index)
+            | Instruction::Invokestatic(index)
+            | Instruction::Invokeinterface(index, _)
+            | Instruction::Invokedynamic(index) => Some(*index),
+            Instruction::New(index)
+            | Instruction::Anewarray(index)
+            | Instruction::Checkcast(index)
+            | Instruction::Instanceof(index)
+            | Instruction::Multianewarray(index, _) => Some(index.0),
+            _ => None,
+        }
+    }
+    // replaces the relative offset of every branch by f(offset), in the order of branches
+    pub fn relocate_branches(
+        &mut self,
+        mut f: impl FnMut(i32) -> Result<i32, Error>,
+    ) -> Result<(), Error> {
+        let mnemonic = self.mnemonic();
+        match self {
+            Instruction::Ifeq(o)
+            | Instruction::Ifne(o)
+            | Instruction::Iflt(o)
+            | Instruction::Ifge(o)
+            | Instruction::Ifgt(o)
+            | Instruction::Ifle(o)
+            | Instruction::IfIcmpeq(o)
+            | Instruction::IfIcmpne(o)
+            | Instruction::IfIcmplt(o)
+            | Instruction::IfIcmpge(o)
+            | Instruction::IfIcmpgt(o)
+            | Instruction::IfIcmple(o)
+            | Instruction::IfAcmpeq(o)
+            | Instruction::IfAcmpne(o)
+            | Instruction::Goto(o)
+            | Instruction::Jsr(o)
+            | Instruction::Ifnull(o)
+            | Instruction::Ifnonnull(o) => {
+                let new = f(*o as i32)?;
+                *o = i16::try_from(new).map_err(|_| {
+                    Error::other(format!(
+                        "Branch offset {} is too far for {}.",
+                        new, mnemonic
+                    ))
+                })?;
+            }
+            Instruction::GotoW(o) | Instruction::JsrW(o) => *o = f(*o)?,
+            Instruction::Tableswitch {
+                default, offsets, ..
+            } => {
+                *default = f(*default)?;
+                for o in offsets.iter_mut() {
+                    *o = f(*o)?;
+                }
+            }
+            Instruction::Lookupswitch { default, pairs } => {
+                *default = f(*default)?;
+                for (_, o) in pairs.iter_mut() {
+                    *o = f(*o)?;
+                }
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+}
+
+// Decodes the instructions of a code array one at a time, each along with its offset. An
+// instruction that can't be decoded gives an error, which is the last item.
+pub struct InstructionIter<'a> {
+    code: Cow<'a, [u8]>,
+    position: u64,
+    failed: bool,
+}
+
+impl<'a> InstructionIter<'a> {
+    pub fn new(code: impl Into<Cow<'a, [u8]>>) -> Self {
+        InstructionIter {
+            code: code.into(),
+            position: 0,
+            failed: false,
+        }
+    }
+}
+
+impl Iterator for InstructionIter<'_> {
+    type Item = Result<(u32, Instruction), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.position as usize >= self.code.len() {
+            return None;
+        }
+        let mut bytes = Cursor::new(&self.code[..]);
+        bytes.set_position(self.position);
+        let res = Instruction::decode(&mut bytes).map(|i| (self.position as u32, i));
+        self.position = bytes.position();
+        self.failed = res.is_err();
+        Some(res)
+    }
+}
+
+impl AttributeInfo {
+    // The instructions of a Code attribute, decoded as they are iterated so that scanning for
+    // one can stop early. None for the other attributes.
+    pub fn instructions(&self) -> Option<InstructionIter<'_>> {
+        let AttributeInfo::Code { code, .. } = self else {
+            return None;
+        };
+        let bytes: Vec<u8> = code.iter().map(|b| b.0).collect();
+        Some(InstructionIter::new(bytes))
+    }
+    // a Code attribute without instructions, to be filled later
+    pub fn new_empty_code(max_stack: u16, max_locals: u16) -> AttributeInfo {
+        AttributeInfo::Code {
+            max_stack,
+            max_locals,
+            code: Vec::new(),
+            exception_table: Vec::new(),
+            attributes: Vec::new(),
+        }
+    }
+    // A Code attribute running instructions, laid out one after the other (their branches are
+    // relative so they are kept as they are). Errors when an instruction can't be encoded or when
+    // the code is longer than the 65535 bytes a method can have.
+    pub fn new_code(
+        max_stack: u16,
+        max_locals: u16,
+        instructions: Vec<Instruction>,
+        exception_table: Vec<ExceptionTableEntry>,
+        attributes: Vec<Attribute>,
+    ) -> Result<AttributeInfo, Error> {
+        let mut code = Vec::new();
+        for instruction in instructions.iter() {
+            instruction.encode(&mut code)?;
+        }
+        if code.len() > u16::MAX as usize {
+            return Err(Error::other(format!(
+                "The code is {} bytes long, more than the {} allowed.",
+                code.len(),
+                u16::MAX
+            )));
+        }
+        Ok(AttributeInfo::Code {
+            max_stack,
+            max_locals,
+            code: code.into_iter().map(CodeByte).collect(),
+            exception_table,
+            attributes,
+        })
+    }
+}
+
+// decodes a whole code array, each instruction along with its offset
+pub fn decode_code(code: &[u8]) -> Result<Vec<(u32, Instruction)>, Error> {
+    InstructionIter::new(code).collect()
+}
+
+// encodes instructions into a code array, checking that each one lands at its offset
+pub fn encode_code(instructions: &[(u32, Instruction)]) -> Result<Vec<u8>, Error> {
+    let mut code = Vec::new();
+    for (offset, instruction) in instructions {
+        if code.len() as u32 != *offset {
+            return Err(Error::other(format!(
+                "{} is at offset {} but the previous instructions end at {}.",
+                instruction.mnemonic(),
+                offset,
+                code.len()
+            )));
+        }
+        instruction.encode(&mut code)?;
+    }
+    Ok(code)
+}
+
+// helpers for the tests of the modules working on code
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::{AccessFlags, ConstantPool, ConstantPoolEntry, Method, Utf8Index};
+
+    // a method m with the descriptor and code, in a pool holding its name, descriptor and "Code"
+    pub fn method(
+        descriptor: &str,
+        flags: AccessFlags,
+        instructions: Vec<Instruction>,
+        exception_table: Vec<ExceptionTableEntry>,
+    ) -> (Method, ConstantPool) {
+        let mut cp = ConstantPool::new();
+        let mut utf8 = |s: &str| Utf8Index(cp.push(ConstantPoolEntry::Utf8(s.into())).unwrap());
+        let (name_index, descriptor_index, code) = (utf8("m"), utf8(descriptor), utf8("Code"));
+        let info = AttributeInfo::new_code(0, 0, instructions, exception_table, vec![]).unwrap();
+        let method = Method {
+            access_flags: flags,
+            name_index,
+            descriptor_index,
+            attributes: vec![Attribute {
+                name_index: code,
+                info,
+            }],
+        };
+        (method, cp)
+    }
+
+    // the decoded code of a method made by method, with its exception table
+    pub fn code(method: &Method) -> (Vec<(u32, Instruction)>, Vec<ExceptionTableEntry>) {
+        match &method.attributes[0].info {
+            AttributeInfo::Code {
+                code,
+                exception_table,
+                ..
+            } => {
+                let code = code.iter().map(|b| b.0).collect::<Vec<_>>();
+                (decode_code(&code).unwrap(), exception_table.clone())
+            }
+            _ => panic!("not a Code attribute"),
+        }
+    }
+
+    #[test]
+    fn empty_code() {
+        let info = AttributeInfo::new_empty_code(2, 3);
+        let AttributeInfo::Code {
+            max_stack: 2,
+            max_locals: 3,
+            code,
+            exception_table,
+            attributes,
+        } = &info
+        else {
+            panic!("not the Code attribute asked for: {:?}", info);
+        };
+        assert!(code.is_empty() && exception_table.is_empty() && attributes.is_empty());
+        assert_eq!(info.instructions().unwrap().count(), 0);
+    }
+
+    #[test]
+    fn code_layout() {
+        let switch = Instruction::Tableswitch {
+            default: 27,
+            low: 0,
+            high: 1,
+            offsets: vec![23, 25],
+        };
+        let instructions = vec![
+            Instruction::Iload0,
+            // padded to the next multiple of 4
+            switch,
+            Instruction::Iconst0,
+            Instruction::Ireturn,
+            Instruction::Ldc(CPIndex(1)),
+            Instruction::Ireturn,
+            Instruction::Goto(-4),
+        ];
+        let handler = ExceptionTableEntry {
+            start: 0,
+            end: 24,
+            handler: 28,
+            catch_type: None,
+        };
+        let (m, _) = method(
+            "(I)I",
+            AccessFlags::STATIC,
+            instructions.clone(),
+            vec![handler],
+        );
+        let (decoded, exception_table) = code(&m);
+        let offsets: Vec<_> = decoded.iter().map(|(o, _)| *o).collect();
+        assert_eq!(offsets, [0, 1, 24, 25, 26, 28, 29]);
+        let decoded: Vec<_> = decoded.into_iter().map(|(_, i)| i).collect();
+        assert_eq!(decoded, instructions);
+        assert_eq!(exception_table[0].handler, 28);
+    }
+
+    #[test]
+    fn code_errors() {
+        let new_code = |instructions| AttributeInfo::new_code(0, 0, instructions, vec![], vec![]);
+        assert!(new_code(vec![Instruction::Ldc(CPIndex(256))]).is_err());
+        let switch = Instruction::Tableswitch {
+            default: 0,
+            low: 0,
+            high: 2,
+            offsets: vec![0],
+        };
+        assert!(new_code(vec![switch]).is_err());
+        assert!(new_code(vec![Instruction::Nop; u16::MAX as usize]).is_ok());
+        assert!(new_code(vec![Instruction::Nop; u16::MAX as usize + 1]).is_err());
+    }
+}
@@ -0,0 +1,196 @@
+#[cfg(feature = "jar")]
+use std::io::{Cursor, Seek, Write};
+use std::{
+    borrow::Cow,
+    fs::File,
+    io::{Error, Read},
+    path::Path,
+};
+
+#[cfg(feature = "jar")]
+use zip::{result::ZipError, write::FileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+#[cfg(feature = "jar")]
+use crate::serialization::Serialize;
+use crate::JavaClass;
+
+// whether the file is a jar (or any zip) rather than a class, from its magic
+pub fn is_jar<P: AsRef<Path>>(path: P) -> Result<bool, Error> {
+    let mut magic = [0; 4];
+    let read = File::open(path)?.read(&mut magic)?;
+    Ok(has_jar_magic(&magic[..read]))
+}
+
+// like is_jar for a file already read, which is the only way for pipes since reading the magic
+// would consume it
+pub fn has_jar_magic(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"PK\x03\x04")
+}
+
+// the bytes of every class in a jar, with the path of its entry
+#[cfg(feature = "jar")]
+pub fn class_bytes<P: AsRef<Path>>(path: P) -> Result<Vec<(String, Vec<u8>)>, Error> {
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+    let mut classes = Vec::new();
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        if !entry.is_file() || !entry.name().ends_with(".class") {
+            continue;
+        }
+        let mut bytes = Vec::with_capacity(entry.size().min(1 << 24) as usize);
+        entry.read_to_end(&mut bytes)?;
+        classes.push((entry.name().to_string(), bytes));
+    }
+    Ok(classes)
+}
+
+// the path of a class entry, and its bytes
+pub type ClassSlice<'a> = (String, Cow<'a, [u8]>);
+
+// The bytes of every class in a jar already in memory, like class_bytes. The entries stored
+// without compression are borrowed from the jar, the others are inflated.
+#[cfg(feature = "jar")]
+pub fn class_slices(jar: &[u8]) -> Result<Vec<ClassSlice<'_>>, Error> {
+    let mut archive = ZipArchive::new(Cursor::new(jar))?;
+    let mut classes = Vec::new();
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        if !entry.is_file() || !entry.name().ends_with(".class") {
+            continue;
+        }
+        let stored = match entry.compression() {
+            CompressionMethod::Stored => usize::try_from(entry.data_start())
+                .ok()
+                .zip(usize::try_from(entry.compressed_size()).ok())
+                .and_then(|(start, len)| jar.get(start..start.checked_add(len)?)),
+            _ => None,
+        };
+        let bytes = match stored {
+            Some(b) => Cow::Borrowed(b),
+            None => {
+                let mut bytes = Vec::with_capacity(entry.size().min(1 << 24) as usize);
+                entry.read_to_end(&mut bytes)?;
+                Cow::Owned(bytes)
+            }
+        };
+        classes.push((entry.name().to_string(), bytes));
+    }
+    Ok(classes)
+}
+
+// the error of reading the class at name, with its name
+pub fn class_error(name: &str, e: Error) -> Error {
+    Error::new(e.kind(), format!("{}: {}", name, e))
+}
+
+// the path of a class entry, and the class or why it couldn't be read
+#[cfg(feature = "jar")]
+pub type ClassEntry = (String, Result<JavaClass, Error>);
+
+// Every class in the jar read by reader with the path of its entry, each parsed on its own so
+// that one failing doesn't stop the others. Only failing to read the jar itself is an error.
+#[cfg(feature = "jar")]
+pub fn read_classes<R: Read + Seek>(reader: R) -> Result<Vec<ClassEntry>, Error> {
+    let mut archive = ZipArchive::new(reader)?;
+    let mut classes = Vec::new();
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        if !entry.is_file() || !entry.name().ends_with(".class") {
+            continue;
+        }
+        let mut bytes = Vec::with_capacity(entry.size().min(1 << 24) as usize);
+        let class = entry
+            .read_to_end(&mut bytes)
+            .and_then(|_| JavaClass::from_bytes(&bytes));
+        classes.push((entry.name().to_string(), class));
+    }
+    Ok(classes)
+}
+
+// every class in a jar, with the path of its entry, failing on the first that can't be read
+#[cfg(feature = "jar")]
+pub fn classes<P: AsRef<Path>>(path: P) -> Result<Vec<(String, JavaClass)>, Error> {
+    read_classes(File::open(path)?)?
+        .into_iter()
+        .map(|(name, class)| match class {
+            Ok(class) => Ok((name, class)),
+            Err(e) => Err(class_error(&name, e)),
+        })
+        .collect()
+}
+
+// The Main-Class of the jar's manifest, as a binary name (com.example.App). None if there is no
+// manifest or it has no Main-Class.
+#[cfg(feature = "jar")]
+pub fn main_class<P: AsRef<Path>>(path: P) -> Result<Option<String>, Error> {
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+    let mut manifest = String::new();
+    match archive.by_name("META-INF/MANIFEST.MF") {
+        Ok(mut entry) => entry.read_to_string(&mut manifest)?,
+        Err(ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    // long values are split on lines starting with a space
+    let manifest = manifest.replace("\r\n", "\n").replace("\n ", "");
+    Ok(manifest
+        .lines()
+        .find_map(|l| l.strip_prefix("Main-Class:"))
+        .map(|c| c.trim().to_string()))
+}
+
+// Writes a copy of the jar at input to output with f applied to every class. The other entries
+// are copied as they are.
+#[cfg(feature = "jar")]
+pub fn map_classes<P: AsRef<Path>, Q: AsRef<Path>>(
+    input: P,
+    output: Q,
+    mut f: impl FnMut(&mut JavaClass) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let mut archive = ZipArchive::new(File::open(input)?)?;
+    let mut out = ZipWriter::new(File::create(output)?);
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        if !entry.is_file() || !entry.name().ends_with(".class") {
+            out.raw_copy_file(entry)?;
+            continue;
+        }
+        let mut bytes = Vec::with_capacity(entry.size().min(1 << 24) as usize);
+        entry.read_to_end(&mut bytes)?;
+        let name = entry.name().to_string();
+        let class = JavaClass::from_bytes(&bytes)
+            .and_then(|mut c| f(&mut c).map(|_| c))
+            .map_err(|e| class_error(&name, e))?;
+        bytes.clear();
+        class.serialize(&mut bytes)?;
+        out.start_file(name, FileOptions::default())?;
+        out.write_all(&bytes)?;
+    }
+    out.finish()?;
+    Ok(())
+}
+
+// without the jar feature, the jars themselves can't be read or written
+#[cfg(not(feature = "jar"))]
+fn no_jar_support() -> Error {
+    Error::other("javd was built without the jar feature, jars can't be read.")
+}
+#[cfg(not(feature = "jar"))]
+pub fn class_slices(_jar: &[u8]) -> Result<Vec<ClassSlice<'_>>, Error> {
+    Err(no_jar_support())
+}
+#[cfg(not(feature = "jar"))]
+pub fn classes<P: AsRef<Path>>(_path: P) -> Result<Vec<(String, JavaClass)>, Error> {
+    Err(no_jar_support())
+}
+#[cfg(not(feature = "jar"))]
+pub fn main_class<P: AsRef<Path>>(_path: P) -> Result<Option<String>, Error> {
+    Err(no_jar_support())
+}
+#[cfg(not(feature = "jar"))]
+pub fn map_classes<P: AsRef<Path>, Q: AsRef<Path>>(
+    _input: P,
+    _output: Q,
+    _f: impl FnMut(&mut JavaClass) -> Result<(), Error>,
+) -> Result<(), Error> {
+    Err(no_jar_support())
+}
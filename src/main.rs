@@ -2,24 +2,134 @@
 use bitflags::bitflags;
 use clap::{Parser, Subcommand};
 use std::{
-    collections::HashMap,
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
     fs::{self, File},
-    io::{Cursor, Error, ErrorKind, BufReader},
-    ops::Deref,
+    io::{Cursor, Error, ErrorKind, BufReader, Write},
     path::{Path, PathBuf},
+    sync::OnceLock,
 };
 
+mod annotations;
+mod api;
+mod borrowed;
+mod callgraph;
+mod cfg;
+mod compare;
 mod deserialization;
+mod deps;
+mod descriptor;
+mod editor;
+mod entry;
+mod fields;
 mod serialization;
+mod stats;
+mod strings;
+mod stub;
 mod display;
+mod input;
+mod instruction;
+mod hash;
+mod jar;
+mod maxes;
+mod modified_utf8;
+mod patch;
+mod references;
+mod rename;
+mod retarget;
+mod usages;
+mod validate;
+mod visitor;
+mod writer;
+#[cfg(feature = "parallel")]
+mod parallel;
+#[cfg(feature = "sha256")]
+mod structural_hash;
 
-use deserialization::Deserialize;
+use api::MemberKind;
+use deserialization::{deserialize_vec_with_count, remaining, Deserialize};
+use display::{FlagStyle, IndexMode, PrintOptions, Sections};
+use modified_utf8::{decode_modified_utf8, JavaString};
+use retarget::ClassVersion;
 use serialization::Serialize;
 
 #[derive(Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, serde::Deserialize, serde::Serialize)]
 struct CPIndex(u16);
 
-#[derive(Debug, Clone, Copy, serde::Deserialize, serde::Serialize)]
+// Indices that must point at a given kind of entry. Nothing is checked when reading a class (so
+// malformed ones can still be inspected), the kind is checked by resolve.
+macro_rules! typed_index {
+    ($name:ident) => {
+        #[derive(Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+        #[derive(serde::Deserialize, serde::Serialize)]
+        struct $name(CPIndex);
+
+        impl std::ops::Deref for $name {
+            type Target = CPIndex;
+            fn deref(&self) -> &CPIndex {
+                &self.0
+            }
+        }
+    };
+}
+typed_index!(Utf8Index);
+typed_index!(ClassIndex);
+typed_index!(NameAndTypeIndex);
+
+// Defines a struct that is read and written as its fields one after the other, which most of the
+// structures of the class file format are. Vec fields are preceded by their count, a u16 unless
+// the field is marked #[len(u8)] or #[len(u32)], and Option<CPIndex> fields are 0 when None.
+macro_rules! binary_struct {
+    (@min_size ; $ty:ty) => { <$ty as Deserialize>::MIN_SIZE };
+    (@min_size $len:ident ; $ty:ty) => { <$len as Deserialize>::MIN_SIZE };
+    (@read $bytes:ident) => { Deserialize::deserialize($bytes)? };
+    (@read $bytes:ident $len:ident) => {
+        deserialization::deserialize_vec_with_count::<$len, _>($bytes)?
+    };
+    (@write $bytes:ident $value:expr) => { $value.serialize($bytes)? };
+    (@write $bytes:ident $value:expr, $len:ident) => {
+        serialization::serialize_vec_with_count::<$len, _>(&$value, $bytes)?
+    };
+    (
+        $(#[$meta:meta])*
+        struct $name:ident $(<$a:ident = $default:ty>)? {
+            $($(#[len($len:ident)])? $field:ident: $ty:ty,)*
+        }
+    ) => {
+        $(#[$meta])*
+        struct $name $(<$a = $default>)? {
+            $($field: $ty,)*
+        }
+
+        impl<$($a: Deserialize)?> Deserialize for $name<$($a)?> {
+            const MIN_SIZE: usize = 0 $(+ binary_struct!(@min_size $($len)? ; $ty))*;
+
+            fn deserialize(bytes: &mut Cursor<&[u8]>) -> Result<Self, Error> {
+                Ok(Self {
+                    $($field: binary_struct!(@read bytes $($len)?),)*
+                })
+            }
+        }
+
+        impl<$($a: Serialize)?> Serialize for $name<$($a)?> {
+            fn serialize(&self, bytes: &mut Vec<u8>) -> Result<(), Error> {
+                $(binary_struct!(@write bytes self.$field $(, $len)?);)*
+                Ok(())
+            }
+        }
+    };
+}
+
+// returned by resolve when an index doesn't point at the expected kind of entry
+#[derive(Debug)]
+struct WrongEntry {
+    index: CPIndex,
+    expected: &'static str,
+    // None if there is no entry at index
+    found: Option<&'static str>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Deserialize, serde::Serialize)]
 enum ReferenceKind {
     GetField = 1,
     GetStatic = 2,
@@ -32,53 +142,72 @@ enum ReferenceKind {
     InvokeInterface = 9,
 }
 
-#[derive(Debug, serde::Deserialize, serde::Serialize)]
+// The bits of Float and Double constants, kept as they are written so that NaN payloads and the
+// sign of zero survive comparisons and JSON (which has no NaN).
+#[derive(Debug, Hash, PartialEq, Eq, Clone, Copy, serde::Deserialize, serde::Serialize)]
+struct FloatBits(u32);
+#[derive(Debug, Hash, PartialEq, Eq, Clone, Copy, serde::Deserialize, serde::Serialize)]
+struct DoubleBits(u64);
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
 enum ConstantPoolEntry {
     Class {
-        name_index: CPIndex,
+        name_index: Utf8Index,
     },
     FieldRef {
-        class_index: CPIndex,
-        name_and_type_index: CPIndex,
+        class_index: ClassIndex,
+        name_and_type_index: NameAndTypeIndex,
     },
     MethodRef {
-        class_index: CPIndex,
-        name_and_type_index: CPIndex,
+        class_index: ClassIndex,
+        name_and_type_index: NameAndTypeIndex,
     },
     InterfaceMethodRef {
-        class_index: CPIndex,
-        name_and_type_index: CPIndex,
+        class_index: ClassIndex,
+        name_and_type_index: NameAndTypeIndex,
     },
     String {
-        string_index: CPIndex,
+        string_index: Utf8Index,
     },
     Integer(i32),
-    Float(f32),
+    Float(FloatBits),
     Long(i64),
-    Double(f64),
+    Double(DoubleBits),
     NameAndType {
-        name_index: CPIndex,
-        descriptor_index: CPIndex,
+        name_index: Utf8Index,
+        descriptor_index: Utf8Index,
     },
-    Utf8(String),
+    Utf8(JavaString),
     MethodHandle {
         reference_kind: ReferenceKind,
         reference_index: CPIndex,
     },
     MethodType {
-        descriptor_index: CPIndex,
+        descriptor_index: Utf8Index,
+    },
+    // a constant computed by a bootstrap method (condy), the type is the descriptor
+    Dynamic {
+        bootstrap_method_attr_index: u16,
+        name_and_type_index: NameAndTypeIndex,
     },
     InvokeDynamic {
         bootstrap_method_attr_index: u16,
-        name_and_type_index: CPIndex,
+        name_and_type_index: NameAndTypeIndex,
+    },
+    // the names of a module and of a package, only found in module-info classes
+    Module {
+        name_index: Utf8Index,
+    },
+    Package {
+        name_index: Utf8Index,
     },
 }
 
-#[derive(Debug, serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
 struct ConstantPool {
-    // HashMap and not Vec, because ConstantPoolEntry's indices begin at 1, and some indices are
-    // invalid (i.e with Double and Long constants).
-    inner: HashMap<CPIndex, ConstantPoolEntry>,
+    // indexed by slot, ConstantPoolEntry's indices begin at 1 and some indices are invalid (i.e
+    // the slot after Double and Long constants), those slots are None.
+    inner: Vec<Option<ConstantPoolEntry>>,
 }
 
 bitflags! {
@@ -102,37 +231,45 @@ bitflags! {
         const SYNTHETIC    = 0x1000; // ---1 ---- ---- ----
         const ANNOTATION   = 0x2000; // --1- ---- ---- ----
         const ENUM         = 0x4000; // -1-- ---- ---- ----
+        const MODULE       = 0x8000; // 1--- ---- ---- ----
     }
 }
 
-#[derive(Debug, serde::Deserialize, serde::Serialize)]
-struct Field {
-    access_flags: AccessFlags,
-    name_index: CPIndex,
-    descriptor_index: CPIndex,
-    attributes: Vec<Attribute>,
+binary_struct! {
+    #[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+    struct Field<A = Attribute> {
+        access_flags: AccessFlags,
+        name_index: Utf8Index,
+        descriptor_index: Utf8Index,
+        attributes: Vec<A>,
+    }
 }
 
-#[derive(Debug, serde::Deserialize, serde::Serialize)]
-struct Method {
-    access_flags: AccessFlags,
-    name_index: CPIndex,
-    descriptor_index: CPIndex,
-    attributes: Vec<Attribute>,
+binary_struct! {
+    #[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+    struct Method<A = Attribute> {
+        access_flags: AccessFlags,
+        name_index: Utf8Index,
+        descriptor_index: Utf8Index,
+        attributes: Vec<A>,
+    }
 }
 
-#[derive(Debug, serde::Deserialize, serde::Serialize)]
-struct ExceptionTableEntry {
-    start: u16,
-    end: u16,
-    handler: u16,
-    catch_type: CPIndex,
+binary_struct! {
+    #[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+    struct ExceptionTableEntry {
+        start: u16,
+        end: u16,
+        handler: u16,
+        // None for the handlers of finally blocks, which catch everything
+        catch_type: Option<CPIndex>,
+    }
 }
 
-#[derive(Debug, serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
 struct CodeByte(u8);
 
-#[derive(Debug, serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
 enum AttributeInfo {
     Any(Vec<u8>),
     ConstantValue {
@@ -147,45 +284,232 @@ enum AttributeInfo {
         attributes: Vec<Attribute>,
     },
     Exceptions {
-        exception_index_table: Vec<CPIndex>,
+        exception_index_table: Vec<ClassIndex>,
     },
+    // the whole attribute body is the string, there is no length prefix
+    SourceDebugExtension(JavaString),
+    Record {
+        components: Vec<RecordComponent>,
+    },
+    // the default value of an annotation interface method
+    AnnotationDefault(ElementValue),
+    RuntimeVisibleAnnotations(Vec<Annotation>),
+    RuntimeInvisibleAnnotations(Vec<Annotation>),
+    // the annotations of each parameter
+    RuntimeVisibleParameterAnnotations(Vec<Vec<Annotation>>),
+    RuntimeInvisibleParameterAnnotations(Vec<Vec<Annotation>>),
+    // annotations on the uses of types (JSR 308), also found in Code
+    RuntimeVisibleTypeAnnotations(Vec<TypeAnnotation>),
+    RuntimeInvisibleTypeAnnotations(Vec<TypeAnnotation>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+struct Annotation {
+    // the field descriptor of the annotation interface
+    type_index: Utf8Index,
+    element_value_pairs: Vec<ElementValuePair>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+struct ElementValuePair {
+    element_name_index: Utf8Index,
+    value: ElementValue,
+}
+
+// the value of an annotation element, the constants point at the entry of their type (Integer
+// for Byte, Char, Short, Int and Boolean)
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+enum ElementValue {
+    Byte(CPIndex),
+    Char(CPIndex),
+    Double(CPIndex),
+    Float(CPIndex),
+    Int(CPIndex),
+    Long(CPIndex),
+    Short(CPIndex),
+    Boolean(CPIndex),
+    String(Utf8Index),
+    Enum {
+        // the field descriptor of the enum
+        type_name_index: Utf8Index,
+        const_name_index: Utf8Index,
+    },
+    // the return descriptor of the class, like Ljava/lang/Object; or V
+    Class(Utf8Index),
+    Annotation(Annotation),
+    Array(Vec<ElementValue>),
+}
+
+binary_struct! {
+    #[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+    struct TypeAnnotation {
+        // what is annotated, written with its target_type
+        target_info: TargetInfo,
+        // where in the type the annotation is, empty when on the type itself
+        #[len(u8)]
+        target_path: Vec<TypePathEntry>,
+        annotation: Annotation,
+    }
+}
+
+// The target_type of a type annotation with its target_info, one variant per target_type (JVMS
+// 4.7.20.1). The offsets are those of instructions in the Code the annotation is found in.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+enum TargetInfo {
+    // 0x00 and 0x01, the index of the type parameter
+    ClassTypeParameter(u8),
+    MethodTypeParameter(u8),
+    // 0x10, the index in interfaces, or 65535 for the superclass
+    Supertype(u16),
+    // 0x11 and 0x12
+    ClassTypeParameterBound {
+        type_parameter_index: u8,
+        bound_index: u8,
+    },
+    MethodTypeParameterBound {
+        type_parameter_index: u8,
+        bound_index: u8,
+    },
+    // 0x13 to 0x15, nothing more to say which
+    Field,
+    MethodReturn,
+    MethodReceiver,
+    // 0x16, the index of the parameter
+    MethodFormalParameter(u8),
+    // 0x17, the index in the Exceptions attribute
+    Throws(u16),
+    // 0x40 and 0x41, the ranges of code the variable lives in
+    LocalVariable(Vec<LocalVarTarget>),
+    ResourceVariable(Vec<LocalVarTarget>),
+    // 0x42, the index in the exception table
+    ExceptionParameter(u16),
+    // 0x43 to 0x46, the offset of the instruction
+    InstanceOf(u16),
+    New(u16),
+    ConstructorReference(u16),
+    MethodReference(u16),
+    // 0x47 to 0x4B, the offset of the instruction and the index of the type argument
+    Cast {
+        offset: u16,
+        type_argument_index: u8,
+    },
+    ConstructorInvocationTypeArgument {
+        offset: u16,
+        type_argument_index: u8,
+    },
+    MethodInvocationTypeArgument {
+        offset: u16,
+        type_argument_index: u8,
+    },
+    ConstructorReferenceTypeArgument {
+        offset: u16,
+        type_argument_index: u8,
+    },
+    MethodReferenceTypeArgument {
+        offset: u16,
+        type_argument_index: u8,
+    },
+}
+
+binary_struct! {
+    #[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+    struct LocalVarTarget {
+        start_pc: u16,
+        length: u16,
+        // the local variable slot
+        index: u16,
+    }
 }
 
-#[derive(Debug, serde::Deserialize, serde::Serialize)]
+binary_struct! {
+    #[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+    struct TypePathEntry {
+        // 0 deeper in an array type, 1 deeper in a nested type, 2 on the bound of a wildcard,
+        // 3 on a type argument
+        type_path_kind: u8,
+        type_argument_index: u8,
+    }
+}
+
+binary_struct! {
+    #[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+    struct RecordComponent {
+        name_index: Utf8Index,
+        descriptor_index: Utf8Index,
+        attributes: Vec<Attribute>,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
 struct Attribute {
-    name_index: CPIndex,
+    name_index: Utf8Index,
     info: AttributeInfo,
 }
 
-#[derive(Debug, serde::Deserialize, serde::Serialize)]
-struct JavaClass {
+// An attribute kept as raw bytes and only parsed the first time its info is asked for.
+#[derive(Debug, Clone)]
+struct LazyAttribute {
+    name_index: Utf8Index,
+    bytes: Vec<u8>,
+    info: OnceLock<AttributeInfo>,
+}
+
+// A is the attribute type, LazyAttribute for classes loaded with from_file_lazy
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+struct JavaClass<A = Attribute> {
     magic_bytes: u32,
     minor_version: u16,
     major_version: u16,
     constant_pool: ConstantPool,
     access_flags: AccessFlags,
-    this_class: CPIndex,
-    super_class: Option<CPIndex>,
-    interfaces: Vec<CPIndex>,
-    fields: Vec<Field>,
-    methods: Vec<Method>,
-    attributes: Vec<Attribute>,
+    this_class: ClassIndex,
+    super_class: Option<ClassIndex>,
+    interfaces: Vec<ClassIndex>,
+    fields: Vec<Field<A>>,
+    methods: Vec<Method<A>>,
+    attributes: Vec<A>,
 }
 
-impl CPIndex {
-    fn none() -> Self {
-        Self(0)
+impl FloatBits {
+    fn new(v: f32) -> Self {
+        Self(v.to_bits())
+    }
+    fn value(self) -> f32 {
+        f32::from_bits(self.0)
+    }
+    // a NaN other than the one of Float.NaN
+    fn is_unusual_nan(self) -> bool {
+        self.value().is_nan() && self.0 != f32::NAN.to_bits()
     }
 }
 
-impl TryFrom<u16> for CPIndex {
-    type Error = ();
-    fn try_from(v: u16) -> Result<Self, Self::Error> {
+impl DoubleBits {
+    fn new(v: f64) -> Self {
+        Self(v.to_bits())
+    }
+    fn value(self) -> f64 {
+        f64::from_bits(self.0)
+    }
+    // a NaN other than the one of Double.NaN
+    fn is_unusual_nan(self) -> bool {
+        self.value().is_nan() && self.0 != f64::NAN.to_bits()
+    }
+}
+
+impl CPIndex {
+    // 0, which no entry is at, written for the optional indices that are absent
+    const NONE: CPIndex = CPIndex(0);
+
+    // None for 0, which no entry is at
+    fn new(v: u16) -> Option<Self> {
         match v {
-            0 => Err(()),
-            _ => Ok(Self(v)),
+            0 => None,
+            _ => Some(Self(v)),
         }
     }
+    fn inner(&self) -> u16 {
+        self.0
+    }
 }
 
 impl TryFrom<u8> for ReferenceKind {
@@ -206,13 +530,61 @@ impl TryFrom<u8> for ReferenceKind {
     }
 }
 
-impl Into<u8> for ReferenceKind {
-    fn into(self) -> u8 {
-        self as u8
+impl From<ReferenceKind> for u8 {
+    fn from(val: ReferenceKind) -> Self {
+        val as u8
+    }
+}
+
+// the kind of a constant pool entry, the values are the tags of the class file format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum ConstantTag {
+    Utf8 = 1,
+    Integer = 3,
+    Float = 4,
+    Long = 5,
+    Double = 6,
+    Class = 7,
+    String = 8,
+    FieldRef = 9,
+    MethodRef = 10,
+    InterfaceMethodRef = 11,
+    NameAndType = 12,
+    MethodHandle = 15,
+    MethodType = 16,
+    Dynamic = 17,
+    InvokeDynamic = 18,
+    Module = 19,
+    Package = 20,
+}
+
+impl TryFrom<u8> for ConstantTag {
+    type Error = ();
+    fn try_from(v: u8) -> Result<Self, Self::Error> {
+        match v {
+            1 => Ok(ConstantTag::Utf8),
+            3 => Ok(ConstantTag::Integer),
+            4 => Ok(ConstantTag::Float),
+            5 => Ok(ConstantTag::Long),
+            6 => Ok(ConstantTag::Double),
+            7 => Ok(ConstantTag::Class),
+            8 => Ok(ConstantTag::String),
+            9 => Ok(ConstantTag::FieldRef),
+            10 => Ok(ConstantTag::MethodRef),
+            11 => Ok(ConstantTag::InterfaceMethodRef),
+            12 => Ok(ConstantTag::NameAndType),
+            15 => Ok(ConstantTag::MethodHandle),
+            16 => Ok(ConstantTag::MethodType),
+            17 => Ok(ConstantTag::Dynamic),
+            18 => Ok(ConstantTag::InvokeDynamic),
+            19 => Ok(ConstantTag::Module),
+            20 => Ok(ConstantTag::Package),
+            _ => Err(()),
+        }
     }
 }
 
-impl<'a> ConstantPoolEntry {
+impl ConstantPoolEntry {
     // returns the 'size' of this entry, because some java is weird
     fn size(&self) -> u16 {
         match self {
@@ -220,164 +592,642 @@ impl<'a> ConstantPoolEntry {
             _ => 1u16,
         }
     }
+    fn tag(&self) -> ConstantTag {
+        match self {
+            ConstantPoolEntry::Class { .. } => ConstantTag::Class,
+            ConstantPoolEntry::FieldRef { .. } => ConstantTag::FieldRef,
+            ConstantPoolEntry::MethodRef { .. } => ConstantTag::MethodRef,
+            ConstantPoolEntry::InterfaceMethodRef { .. } => ConstantTag::InterfaceMethodRef,
+            ConstantPoolEntry::String { .. } => ConstantTag::String,
+            ConstantPoolEntry::Integer(_) => ConstantTag::Integer,
+            ConstantPoolEntry::Float(_) => ConstantTag::Float,
+            ConstantPoolEntry::Long(_) => ConstantTag::Long,
+            ConstantPoolEntry::Double(_) => ConstantTag::Double,
+            ConstantPoolEntry::NameAndType { .. } => ConstantTag::NameAndType,
+            ConstantPoolEntry::Utf8(_) => ConstantTag::Utf8,
+            ConstantPoolEntry::MethodHandle { .. } => ConstantTag::MethodHandle,
+            ConstantPoolEntry::MethodType { .. } => ConstantTag::MethodType,
+            ConstantPoolEntry::Dynamic { .. } => ConstantTag::Dynamic,
+            ConstantPoolEntry::InvokeDynamic { .. } => ConstantTag::InvokeDynamic,
+            ConstantPoolEntry::Module { .. } => ConstantTag::Module,
+            ConstantPoolEntry::Package { .. } => ConstantTag::Package,
+        }
+    }
+    // the tag the entry is written with
+    fn tag_byte(&self) -> u8 {
+        self.tag() as u8
+    }
+    // the name of the entry's kind, as in javap
+    fn kind(&self) -> &'static str {
+        match self {
+            ConstantPoolEntry::Class { .. } => "Class",
+            ConstantPoolEntry::FieldRef { .. } => "Fieldref",
+            ConstantPoolEntry::MethodRef { .. } => "Methodref",
+            ConstantPoolEntry::InterfaceMethodRef { .. } => "InterfaceMethodref",
+            ConstantPoolEntry::String { .. } => "String",
+            ConstantPoolEntry::Integer(_) => "Integer",
+            ConstantPoolEntry::Float(_) => "Float",
+            ConstantPoolEntry::Long(_) => "Long",
+            ConstantPoolEntry::Double(_) => "Double",
+            ConstantPoolEntry::NameAndType { .. } => "NameAndType",
+            ConstantPoolEntry::Utf8(_) => "Utf8",
+            ConstantPoolEntry::MethodHandle { .. } => "MethodHandle",
+            ConstantPoolEntry::MethodType { .. } => "MethodType",
+            ConstantPoolEntry::Dynamic { .. } => "Dynamic",
+            ConstantPoolEntry::InvokeDynamic { .. } => "InvokeDynamic",
+            ConstantPoolEntry::Module { .. } => "Module",
+            ConstantPoolEntry::Package { .. } => "Package",
+        }
+    }
+}
+
+impl WrongEntry {
+    fn new(index: CPIndex, expected: &'static str, cp: &ConstantPool) -> Self {
+        Self {
+            index,
+            expected,
+            found: cp.get(index).map(ConstantPoolEntry::kind),
+        }
+    }
+}
+
+impl std::fmt::Display for WrongEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.found {
+            Some(found) => write!(
+                f,
+                "Constant pool entry #{} is a {}, expected a {}.",
+                self.index.0, found, self.expected
+            ),
+            None => write!(
+                f,
+                "There is no constant pool entry #{}, expected a {}.",
+                self.index.0, self.expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for WrongEntry {}
+
+impl From<WrongEntry> for Error {
+    fn from(e: WrongEntry) -> Self {
+        Error::new(ErrorKind::InvalidData, e)
+    }
+}
+
+impl Utf8Index {
+    fn resolve<'a>(&self, cp: &'a ConstantPool) -> Result<&'a str, WrongEntry> {
+        match cp.get(self.0) {
+            Some(ConstantPoolEntry::Utf8(s)) => Ok(s),
+            _ => Err(WrongEntry::new(self.0, "Utf8", cp)),
+        }
+    }
 }
 
-impl Deref for ConstantPool {
-    type Target = HashMap<CPIndex, ConstantPoolEntry>;
+impl ClassIndex {
+    // the index of the class name
+    fn resolve(&self, cp: &ConstantPool) -> Result<Utf8Index, WrongEntry> {
+        match cp.get(self.0) {
+            Some(ConstantPoolEntry::Class { name_index }) => Ok(*name_index),
+            _ => Err(WrongEntry::new(self.0, "Class", cp)),
+        }
+    }
+    fn name<'a>(&self, cp: &'a ConstantPool) -> Result<&'a str, WrongEntry> {
+        self.resolve(cp)?.resolve(cp)
+    }
+}
 
-    fn deref(&self) -> &Self::Target {
-        &self.inner
+impl NameAndTypeIndex {
+    // the indices of the name and of the descriptor
+    fn resolve(&self, cp: &ConstantPool) -> Result<(Utf8Index, Utf8Index), WrongEntry> {
+        match cp.get(self.0) {
+            Some(ConstantPoolEntry::NameAndType {
+                name_index,
+                descriptor_index,
+            }) => Ok((*name_index, *descriptor_index)),
+            _ => Err(WrongEntry::new(self.0, "NameAndType", cp)),
+        }
     }
 }
 
 impl ConstantPool {
+    fn new() -> Self {
+        // slot 0 is never used
+        Self { inner: vec![None] }
+    }
+    // the constant pool count as written in the class file (number of slots + 1)
     fn size(&self) -> u16 {
-        self.iter().map(|(_, v)| v.size()).fold(1u16, |a, v| a + v)
+        self.inner.len() as u16
+    }
+    // None past the end, at 0 and on the slot after a Long or Double
+    fn get(&self, index: CPIndex) -> Option<&ConstantPoolEntry> {
+        self.inner.get(index.0 as usize)?.as_ref()
+    }
+    // like get, with an error saying which index is missing
+    fn get_checked(&self, index: CPIndex) -> Result<&ConstantPoolEntry, Error> {
+        self.get(index).ok_or_else(|| {
+            Error::other(format!("There is no constant pool entry at #{}.", index.0))
+        })
+    }
+    fn get_mut(&mut self, index: CPIndex) -> Option<&mut ConstantPoolEntry> {
+        self.inner.get_mut(index.0 as usize)?.as_mut()
+    }
+    // whether there is an entry at index
+    fn contains(&self, index: CPIndex) -> bool {
+        self.get(index).is_some()
+    }
+    // the highest index with an entry, None if the pool is empty
+    fn max_index(&self) -> Option<CPIndex> {
+        let i = self.inner.iter().rposition(Option::is_some)?;
+        Some(CPIndex(i as u16))
+    }
+    // the entries in index order
+    fn iter(&self) -> impl Iterator<Item = (CPIndex, &ConstantPoolEntry)> {
+        self.inner
+            .iter()
+            .enumerate()
+            .filter_map(|(i, e)| Some((CPIndex(i as u16), e.as_ref()?)))
+    }
+    // same as iter, which is already in ascending index order
+    fn iter_sorted(&self) -> impl Iterator<Item = (CPIndex, &ConstantPoolEntry)> {
+        self.iter()
+    }
+    // the indices that have an entry, ascending
+    fn indices_sorted(&self) -> Vec<CPIndex> {
+        self.iter().map(|(i, _)| i).collect()
+    }
+    // the Utf8 entries with their index
+    fn utf8_values(&self) -> impl Iterator<Item = (CPIndex, &str)> {
+        self.iter().filter_map(|(i, e)| match e {
+            ConstantPoolEntry::Utf8(s) => Some((i, s.as_str())),
+            _ => None,
+        })
+    }
+    // every string of the pool (class and member names, descriptors, literals...) for grepping
+    fn utf8_entries(&self) -> impl Iterator<Item = (CPIndex, &str)> {
+        self.utf8_values()
+    }
+    // the Class entries with their index
+    fn class_entries(&self) -> impl Iterator<Item = (CPIndex, Utf8Index)> + '_ {
+        self.iter().filter_map(|(i, e)| match e {
+            ConstantPoolEntry::Class { name_index } => Some((i, *name_index)),
+            _ => None,
+        })
+    }
+    // the Methodref and InterfaceMethodref entries with their index
+    fn method_refs(&self) -> impl Iterator<Item = (CPIndex, ClassIndex, NameAndTypeIndex)> + '_ {
+        self.iter().filter_map(|(i, e)| match e {
+            ConstantPoolEntry::MethodRef {
+                class_index,
+                name_and_type_index,
+            }
+            | ConstantPoolEntry::InterfaceMethodRef {
+                class_index,
+                name_and_type_index,
+            } => Some((i, *class_index, *name_and_type_index)),
+            _ => None,
+        })
+    }
+    // Adds an entry in the next free slot and returns its index. The constant pool count is a
+    // u16, so the slots (and the unused slot 0) can't go past u16::MAX.
+    fn push(&mut self, entry: ConstantPoolEntry) -> Result<CPIndex, Error> {
+        let size = entry.size();
+        if self.inner.len() + size as usize > u16::MAX as usize {
+            return Err(Error::other(format!(
+                "Constant pool too large: no room for a {} after {} slots.",
+                entry.kind(),
+                self.inner.len()
+            )));
+        }
+        let index = CPIndex(self.inner.len() as u16);
+        self.inner.push(Some(entry));
+        if size == 2 {
+            self.inner.push(None);
+        }
+        Ok(index)
     }
 }
 
+// Panics when there is no entry at index, which any bogus index of an untrusted class leads to:
+// only use it for indices already checked (or pushed), and get or get_checked otherwise.
 impl std::ops::Index<CPIndex> for ConstantPool {
     type Output = ConstantPoolEntry;
 
     fn index(&self, index: CPIndex) -> &Self::Output {
-        self.inner.get(&index).unwrap()
+        self.get_checked(index).unwrap_or_else(|e| panic!("{}", e))
     }
 }
 
-impl<'a> Attribute {
-    fn resolve(&mut self, cp: &ConstantPool) -> Result<(), Error> {
-        if let AttributeInfo::Any(ref a) = self.info {
-            let _size = a.len();
-            let mut bytes = Cursor::new(a.clone());
-            let bytes = &mut bytes;
-
-            if let ConstantPoolEntry::Utf8(ref name) = cp[self.name_index] {
-                let info = match name.as_str() {
-                    "ConstantValue" => Ok(AttributeInfo::ConstantValue {
-                        index: CPIndex::deserialize(bytes)?,
-                    }),
-                    "Code" => {
-                        let max_stack = u16::deserialize(bytes)?;
-                        let max_locals = u16::deserialize(bytes)?;
-
-                        let code_length = u32::deserialize(bytes)?;
-                        let mut code = Vec::with_capacity(code_length as usize);
-                        for _ in 0..code_length {
-                            code.push(CodeByte::deserialize(bytes)?);
-                        }
+// Attributes nested in other attributes (in Code or in record components) are resolved up to this
+// depth, which is as deep as valid classes go. Deeper ones are kept raw, which also bounds the
+// recursion on crafted input.
+const MAX_ATTRIBUTE_DEPTH: usize = 1;
 
-                        let exception_table = Vec::<ExceptionTableEntry>::deserialize(bytes)?;
-                        let mut attributes = Vec::<Attribute>::deserialize(bytes)?;
+// resolves the attributes found in an attribute parsed at depth
+fn resolve_nested(attributes: &mut [Attribute], cp: &ConstantPool, depth: usize) {
+    if depth >= MAX_ATTRIBUTE_DEPTH {
+        return;
+    }
+    for a in attributes.iter_mut() {
+        if let AttributeInfo::Any(ref bytes) = a.info {
+            if let Ok(info) = AttributeInfo::parse_nested(a.name_index, bytes, cp, depth + 1) {
+                a.info = info;
+            }
+        }
+    }
+}
 
-                        for a in attributes.iter_mut() {
-                            let _ = a.resolve(cp);
-                        }
+impl AttributeInfo {
+    // parses the body of the attribute named by name_index
+    fn parse(name_index: Utf8Index, a: &[u8], cp: &ConstantPool) -> Result<Self, Error> {
+        Self::parse_nested(name_index, a, cp, 0)
+    }
+    fn parse_nested(
+        name_index: Utf8Index,
+        a: &[u8],
+        cp: &ConstantPool,
+        depth: usize,
+    ) -> Result<Self, Error> {
+        let mut bytes = Cursor::new(a);
+        let bytes = &mut bytes;
 
-                        Ok(AttributeInfo::Code {
-                            max_stack,
-                            max_locals,
-                            code,
-                            exception_table,
-                            attributes,
-                        })
-                    }
-                    "Exceptions" => Ok(AttributeInfo::Exceptions {
-                        exception_index_table: Vec::<CPIndex>::deserialize(bytes)?,
-                    }),
-                    _ => Err(Error::new(ErrorKind::Other, "unkown attribute")),
-                };
-                let info = info?;
+        if let Ok(name) = name_index.resolve(cp) {
+            let info = match name {
+                "ConstantValue" => Ok(AttributeInfo::ConstantValue {
+                    index: CPIndex::deserialize(bytes)?,
+                }),
+                "Code" => {
+                    let max_stack = u16::deserialize(bytes)?;
+                    let max_locals = u16::deserialize(bytes)?;
 
-                self.info = info;
-                Ok(())
-            } else {
-                Err(Error::new(
-                    ErrorKind::Other,
-                    "Error when trying to access Attribute name.",
-                ))
+                    let code = deserialize_vec_with_count::<u32, CodeByte>(bytes)?;
+
+                    let exception_table = Vec::<ExceptionTableEntry>::deserialize(bytes)?;
+                    let mut attributes = Vec::<Attribute>::deserialize(bytes)?;
+                    resolve_nested(&mut attributes, cp, depth);
+
+                    Ok(AttributeInfo::Code {
+                        max_stack,
+                        max_locals,
+                        code,
+                        exception_table,
+                        attributes,
+                    })
+                }
+                "Exceptions" => Ok(AttributeInfo::Exceptions {
+                    exception_index_table: Vec::<ClassIndex>::deserialize(bytes)?,
+                }),
+                "SourceDebugExtension" => {
+                    // the whole body, there is no length prefix
+                    bytes.set_position(a.len() as u64);
+                    Ok(AttributeInfo::SourceDebugExtension(decode_modified_utf8(a)?))
+                }
+                "Record" => {
+                    let mut components = Vec::<RecordComponent>::deserialize(bytes)?;
+                    for c in components.iter_mut() {
+                        resolve_nested(&mut c.attributes, cp, depth);
+                    }
+                    Ok(AttributeInfo::Record { components })
+                }
+                "AnnotationDefault" => Ok(AttributeInfo::AnnotationDefault(
+                    ElementValue::deserialize(bytes)?,
+                )),
+                "RuntimeVisibleAnnotations" => Ok(AttributeInfo::RuntimeVisibleAnnotations(
+                    Vec::<Annotation>::deserialize(bytes)?,
+                )),
+                "RuntimeInvisibleAnnotations" => Ok(AttributeInfo::RuntimeInvisibleAnnotations(
+                    Vec::<Annotation>::deserialize(bytes)?,
+                )),
+                "RuntimeVisibleParameterAnnotations" => {
+                    Ok(AttributeInfo::RuntimeVisibleParameterAnnotations(
+                        deserialize_vec_with_count::<u8, _>(bytes)?,
+                    ))
+                }
+                "RuntimeInvisibleParameterAnnotations" => {
+                    Ok(AttributeInfo::RuntimeInvisibleParameterAnnotations(
+                        deserialize_vec_with_count::<u8, _>(bytes)?,
+                    ))
+                }
+                "RuntimeVisibleTypeAnnotations" => {
+                    Ok(AttributeInfo::RuntimeVisibleTypeAnnotations(
+                        Vec::<TypeAnnotation>::deserialize(bytes)?,
+                    ))
+                }
+                "RuntimeInvisibleTypeAnnotations" => {
+                    Ok(AttributeInfo::RuntimeInvisibleTypeAnnotations(
+                        Vec::<TypeAnnotation>::deserialize(bytes)?,
+                    ))
+                }
+                _ => Err(Error::other("unkown attribute")),
+            }?;
+            // the attribute_length is checked against the structure, a parse that doesn't use
+            // every byte would lose the rest
+            if remaining(bytes) != 0 {
+                return Err(Error::other(format!(
+                    "{} bytes left after the {} attribute.",
+                    remaining(bytes),
+                    name
+                )));
             }
+            Ok(info)
         } else {
-            // already resolved
-            Ok(())
+            Err(Error::other("Error when trying to access Attribute name."))
+        }
+    }
+}
+
+impl Attribute {
+    // the name is added to the constant pool if it isn't there yet
+    fn new(name: &str, info: AttributeInfo, cp: &mut ConstantPool) -> Result<Attribute, Error> {
+        Ok(Attribute {
+            name_index: rename::find_or_push_utf8(cp, name)?,
+            info,
+        })
+    }
+    fn resolve(&mut self, cp: &ConstantPool) -> Result<(), Error> {
+        if let AttributeInfo::Any(ref a) = self.info {
+            self.info = AttributeInfo::parse(self.name_index, a, cp)?;
+        }
+        // otherwise already resolved
+        Ok(())
+    }
+    // false only if the info is still raw bytes
+    fn is_resolved(&self) -> bool {
+        !matches!(self.info, AttributeInfo::Any(_))
+    }
+    // like resolve, but an unknown or malformed attribute is kept as raw bytes instead of
+    // erroring, returns whether the attribute ended up resolved
+    fn resolve_or_keep(&mut self, cp: &ConstantPool) -> bool {
+        self.resolve(cp).is_ok() && self.is_resolved()
+    }
+    fn name<'a>(&self, cp: &'a ConstantPool) -> Option<&'a str> {
+        self.name_index.resolve(cp).ok()
+    }
+}
+
+impl Field {
+    // a field without attributes, the name and descriptor are added to the constant pool if they
+    // aren't there yet
+    fn new(
+        name: &str,
+        descriptor: &str,
+        flags: AccessFlags,
+        cp: &mut ConstantPool,
+    ) -> Result<Field, Error> {
+        Ok(Field {
+            access_flags: flags,
+            name_index: rename::find_or_push_utf8(cp, name)?,
+            descriptor_index: rename::find_or_push_utf8(cp, descriptor)?,
+            attributes: Vec::new(),
+        })
+    }
+}
+
+// removes the attributes named in names from the list, and from the lists nested in Code and
+// record components
+fn strip_attributes(attributes: &mut Vec<Attribute>, names: &[&str], cp: &ConstantPool) {
+    attributes.retain(|a| !a.name(cp).is_some_and(|n| names.contains(&n)));
+    for a in attributes.iter_mut() {
+        match &mut a.info {
+            AttributeInfo::Code { attributes, .. } => strip_attributes(attributes, names, cp),
+            AttributeInfo::Record { components } => {
+                for c in components.iter_mut() {
+                    strip_attributes(&mut c.attributes, names, cp);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+impl LazyAttribute {
+    fn new(name_index: Utf8Index, bytes: Vec<u8>) -> Self {
+        Self {
+            name_index,
+            bytes,
+            info: OnceLock::new(),
+        }
+    }
+    // parses the attribute on the first call, an attribute that can't be parsed stays Any
+    fn info(&self, cp: &ConstantPool) -> &AttributeInfo {
+        self.info.get_or_init(|| {
+            AttributeInfo::parse(self.name_index, &self.bytes, cp)
+                .unwrap_or_else(|_| AttributeInfo::Any(self.bytes.clone()))
+        })
+    }
+    // whether info has been called already
+    fn is_parsed(&self) -> bool {
+        self.info.get().is_some()
+    }
+    fn into_attribute(self, cp: &ConstantPool) -> Attribute {
+        self.info(cp);
+        Attribute {
+            name_index: self.name_index,
+            info: self.info.into_inner().unwrap(),
         }
     }
 }
 
 impl JavaClass {
+    // parses bytes already in memory without copying them
+    fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        JavaClass::deserialize(&mut Cursor::new(bytes))
+    }
     fn from_file<P: AsRef<Path>>(file: P) -> Result<Self, Error> {
+        JavaClass::from_bytes(&fs::read(file)?)
+    }
+    // doesn't parse any attribute upfront, see LazyAttribute
+    fn from_file_lazy<P: AsRef<Path>>(file: P) -> Result<JavaClass<LazyAttribute>, Error> {
         let bytes = fs::read(file)?;
-        let mut cursor = Cursor::new(bytes);
-        Ok(JavaClass::deserialize(&mut cursor)?)
+        deserialization::deserialize_class(&mut Cursor::new(bytes.as_slice()))
+    }
+    // parses straight from a memory mapping of the file, the mapping is handed back so the
+    // caller decides how long it stays alive
+    #[cfg(feature = "mmap")]
+    fn from_mmap(file: &Path) -> Result<(Self, memmap2::Mmap), Error> {
+        let file = File::open(file)?;
+        // SAFETY: the mapping is read-only, and the file is expected not to be modified while
+        // we parse it (the same assumption fs::read makes about getting a consistent view).
+        let mmap = unsafe { memmap2::Mmap::map(&file)? };
+        let cls = JavaClass::from_bytes(&mmap)?;
+        Ok((cls, mmap))
+    }
+    // Like from_file, reading through a memory mapping with the mmap feature unless the file
+    // can't be mapped (pipes, empty files...), see input::read.
+    fn from_file_mmap<P: AsRef<Path>>(file: P) -> Result<Self, Error> {
+        JavaClass::from_bytes(&input::read(file)?)
     }
     fn to_file<P: AsRef<Path>>(&self, file: P) -> Result<(), Error> {
         let mut buf = Vec::new();
         self.serialize(&mut buf)?;
         fs::write(file, buf)
     }
-    fn print(&self) {
-        println!("JavaClass {{");
-        println!("--magic_bytes: {:08X}", self.magic_bytes);
-        println!("--version: {}.{}", self.major_version, self.minor_version);
-        println!("");
-        println!("--ConstantPool:");
-        let mut entries = self
-            .constant_pool
+    // removes the named attributes from the class, its fields, its methods, and everything nested
+    fn strip_attributes(&mut self, names: &[&str]) {
+        let cp = &self.constant_pool;
+        for f in self.fields.iter_mut() {
+            strip_attributes(&mut f.attributes, names, cp);
+        }
+        for m in self.methods.iter_mut() {
+            strip_attributes(&mut m.attributes, names, cp);
+        }
+        strip_attributes(&mut self.attributes, names, cp);
+    }
+    fn strip_debug_info(&mut self) {
+        self.strip_attributes(&[
+            "LineNumberTable",
+            "LocalVariableTable",
+            "LocalVariableTypeTable",
+            "SourceFile",
+        ]);
+    }
+    fn strip_annotations(&mut self) {
+        self.strip_attributes(&[
+            "RuntimeVisibleAnnotations",
+            "RuntimeInvisibleAnnotations",
+            "RuntimeVisibleParameterAnnotations",
+            "RuntimeInvisibleParameterAnnotations",
+        ]);
+    }
+    // type annotations (JSR 308), which can also be found in Code
+    fn strip_type_annotations(&mut self) {
+        self.strip_attributes(&[
+            "RuntimeVisibleTypeAnnotations",
+            "RuntimeInvisibleTypeAnnotations",
+        ]);
+    }
+    fn version(&self) -> ClassVersion {
+        ClassVersion {
+            major: self.major_version,
+            minor: self.minor_version,
+        }
+    }
+    // compiled with --enable-preview
+    fn is_preview(&self) -> bool {
+        self.version().is_preview()
+    }
+    fn is_interface(&self) -> bool {
+        self.access_flags.contains(AccessFlags::INTERFACE)
+    }
+    fn is_abstract(&self) -> bool {
+        self.access_flags.contains(AccessFlags::ABSTRACT)
+    }
+    fn is_enum(&self) -> bool {
+        self.access_flags.contains(AccessFlags::ENUM)
+    }
+    fn is_annotation(&self) -> bool {
+        self.access_flags.contains(AccessFlags::ANNOTATION)
+    }
+    fn is_synthetic(&self) -> bool {
+        self.access_flags.contains(AccessFlags::SYNTHETIC)
+    }
+    // module-info.class
+    fn is_module(&self) -> bool {
+        self.access_flags.contains(AccessFlags::MODULE)
+    }
+    // the internal names of the interfaces, without the ones that don't point at a class name
+    fn interface_names(&self) -> Vec<&str> {
+        self.interfaces
             .iter()
-            .collect::<Vec<(&CPIndex, &ConstantPoolEntry)>>();
-        entries.sort_by(|(a, _), (b, _)| a.cmp(&b));
-        for (k, v) in entries {
-            println!("      {}: {}", k, v.display(&self.constant_pool));
-        }
-        println!("");
-        println!("--This Class:");
-        println!("    access_flags: {:?}", self.access_flags);
-        println!(
-            "    this_class: {}",
-            self.this_class.display(&self.constant_pool)
-        );
-        println!(
-            "    super_class: {}",
-            self.super_class
-                .unwrap_or(CPIndex(0))
-                .display(&self.constant_pool)
-        );
-        println!("");
-        println!("--Interfaces:");
-        for i in self.interfaces.iter() {
-            println!("    {:?}", i);
-        }
-        println!("");
-        println!("--Fields:");
-        for i in self.fields.iter() {
-            println!(
-                "    {}: {:?} ({})",
-                i.name_index.display(&self.constant_pool),
-                i.access_flags,
-                i.descriptor_index.display(&self.constant_pool)
-            );
-            for j in i.attributes.iter() {
-                println!("      {}", j.display(&self.constant_pool));
-            }
-        }
-        println!("");
-        println!("--Methods:");
-        for i in self.methods.iter() {
-            println!(
-                "    {}: {:?} ({})",
-                i.name_index.display(&self.constant_pool),
-                i.access_flags,
-                i.descriptor_index.display(&self.constant_pool)
-            );
-            for j in i.attributes.iter() {
-                println!("      {}", j.display(&self.constant_pool));
-            }
-        }
-        println!("");
-        println!("--Attributes:");
-        for i in self.attributes.iter() {
-            println!("  {}", i.display(&self.constant_pool));
-        }
-        println!("}}");
+            .filter_map(|i| i.name(&self.constant_pool).ok())
+            .collect()
+    }
+    // everything in the class, the constant pool and the attributes as they are displayed
+    fn write_pretty<W: Write>(&self, w: &mut W, opts: &PrintOptions) -> Result<(), Error> {
+        let cp = &self.constant_pool;
+        let flags = |flags, kind| opts.flags.format(flags, kind);
+        writeln!(w, "JavaClass {{")?;
+        // the sections are separated by a blank line
+        let mut first = true;
+        let mut section = |w: &mut W, section| -> Result<bool, Error> {
+            if !opts.sections.contains(section) {
+                return Ok(false);
+            }
+            if !first {
+                writeln!(w)?;
+            }
+            first = false;
+            Ok(true)
+        };
+        if section(w, Sections::HEADER)? {
+            writeln!(w, "--magic_bytes: {:08X}", self.magic_bytes)?;
+            writeln!(w, "--version: {}.{}", self.major_version, self.minor_version)?;
+        }
+        if section(w, Sections::CONSTANT_POOL)? {
+            writeln!(w, "--ConstantPool:")?;
+            for (k, v) in cp.iter() {
+                writeln!(w, "      {}: {}", k, v.display_with(cp, opts))?;
+            }
+        }
+        if section(w, Sections::CLASS)? {
+            writeln!(w, "--This Class:")?;
+            writeln!(
+                w,
+                "    access_flags: {}",
+                flags(self.access_flags, MemberKind::Class)
+            )?;
+            writeln!(
+                w,
+                "    this_class: {}",
+                self.this_class.display_with(cp, opts)
+            )?;
+            match self.super_class {
+                Some(c) => writeln!(w, "    super_class: {}", c.display_with(cp, opts))?,
+                None => writeln!(
+                    w,
+                    "    super_class: (none — this is java/lang/Object or a module)"
+                )?,
+            }
+        }
+        if section(w, Sections::INTERFACES)? {
+            writeln!(w, "--Interfaces:")?;
+            for i in self.interfaces.iter() {
+                writeln!(w, "    {}", i.display_with(cp, opts))?;
+            }
+        }
+        if section(w, Sections::FIELDS)? {
+            writeln!(w, "--Fields:")?;
+            for i in self.fields.iter() {
+                writeln!(
+                    w,
+                    "    {}: {} ({})",
+                    i.name_index.display_with(cp, opts),
+                    flags(i.access_flags, MemberKind::Field),
+                    i.descriptor_index.display_with(cp, opts)
+                )?;
+                for j in i.attributes.iter() {
+                    writeln!(w, "      {}", j.display_with(cp, opts))?;
+                }
+            }
+        }
+        if section(w, Sections::METHODS)? {
+            writeln!(w, "--Methods:")?;
+            for i in self.methods.iter() {
+                writeln!(
+                    w,
+                    "    {}: {} ({}) // {}",
+                    i.name_index.display_with(cp, opts),
+                    flags(i.access_flags, MemberKind::Method),
+                    i.descriptor_index.display_with(cp, opts),
+                    i.signature_string(cp)
+                )?;
+                for j in i.attributes.iter() {
+                    writeln!(w, "      {}", j.display_with(cp, opts))?;
+                }
+            }
+        }
+        if section(w, Sections::ATTRIBUTES)? {
+            writeln!(w, "--Attributes:")?;
+            for i in self.attributes.iter() {
+                writeln!(w, "  {}", i.display_with(cp, opts))?;
+            }
+        }
+        writeln!(w, "}}")
+    }
+    // write_pretty to stdout
+    fn print(&self, opts: &PrintOptions) -> Result<(), Error> {
+        self.write_pretty(&mut std::io::stdout().lock(), opts)
     }
 }
 
@@ -406,22 +1256,932 @@ enum Command {
         /// path to the class
         #[clap(parse(from_os_str))]
         class: PathBuf
+    },
+    /// print the constant pool of a class like javap -v does
+    Pool {
+        /// path to the class
+        #[clap(parse(from_os_str))]
+        class: PathBuf,
+    },
+    /// print every part of a class, with the constants it refers to
+    Print {
+        /// path to the class
+        #[clap(parse(from_os_str))]
+        class: PathBuf,
+        /// follow the constants with their index, or with =only show the index instead
+        #[clap(
+            long,
+            value_name = "only",
+            min_values = 0,
+            require_equals = true,
+            default_missing_value = "both"
+        )]
+        show_indices: Option<String>,
+        /// how access flags are shown: debug (PUBLIC | SUPER), java (public) or hex (0x0021)
+        #[clap(long, value_name = "style", default_value = "debug")]
+        flags: String,
+    },
+    /// list the instructions referencing a constant
+    Uses {
+        /// path to the class
+        #[clap(parse(from_os_str))]
+        class: PathBuf,
+        /// index of the constant, like #42
+        #[clap(required_unless_present = "method")]
+        index: Option<String>,
+        /// the method instead, like java/io/PrintStream.println (a descriptor can follow a :)
+        #[clap(long, conflicts_with = "index")]
+        method: Option<String>,
+    },
+    /// list the classes a class depends on
+    Deps {
+        /// path to the class
+        #[clap(parse(from_os_str), required_unless_present = "jar")]
+        class: Option<PathBuf>,
+        /// every class of a jar instead, each followed by one of its dependencies per line
+        #[clap(long, parse(from_os_str), conflicts_with = "class")]
+        jar: Option<PathBuf>,
+        /// print a graphviz graph, limited to the classes of the jar with --jar
+        #[clap(long)]
+        dot: bool,
+    },
+    /// print the string constants of a class or of every class in a jar
+    Strings {
+        /// path to the class or jar
+        #[clap(parse(from_os_str))]
+        path: PathBuf,
+        /// every Utf8 entry instead, including names and descriptors
+        #[clap(long)]
+        all_utf8: bool,
+        /// print each string once, for the first class it is in
+        #[clap(long)]
+        unique: bool,
+        /// only the strings containing this
+        #[clap(long)]
+        grep: Option<String>,
+    },
+    /// print statistics about a class, or about every class in a jar followed by their total
+    Stats {
+        /// path to the class or jar
+        #[clap(parse(from_os_str))]
+        path: PathBuf,
+        /// print them as json
+        #[clap(long)]
+        json: bool,
+    },
+    /// replace the code of every method by a stub throwing UnsupportedOperationException, in a
+    /// class or in every class of a jar
+    Stub {
+        /// path to the class or jar
+        #[clap(parse(from_os_str))]
+        path: PathBuf,
+        /// where to write the stubbed class or jar
+        #[clap(short, long, parse(from_os_str))]
+        output: PathBuf,
+    },
+    /// print a summary of a class or jar, including whether it can be run
+    Info {
+        /// path to the class or jar
+        #[clap(parse(from_os_str))]
+        path: PathBuf,
+    },
+    /// print the public and protected API of a class, or how it changed in a new version
+    Api {
+        /// path to the class
+        #[clap(parse(from_os_str))]
+        class: PathBuf,
+        /// the new version of the class, fails if there are breaking changes
+        #[clap(long, parse(from_os_str))]
+        diff: Option<PathBuf>,
+    },
+    /// print the methods each method of a class calls
+    Callgraph {
+        /// path to the class
+        #[clap(parse(from_os_str))]
+        class: PathBuf,
+        /// print the graph in graphviz's dot language
+        #[clap(long)]
+        dot: bool,
+        /// only print the private methods that are never called
+        #[clap(long)]
+        unreachable: bool,
+    },
+    /// print the control flow graph of a method in graphviz's dot language
+    Cfg {
+        /// path to the class
+        #[clap(parse(from_os_str))]
+        class: PathBuf,
+        /// name of the method, followed by its descriptor if there are several (like foo(I)V)
+        #[clap(long, required_unless_present = "lint")]
+        method: Option<String>,
+        /// warn about unreachable code and dead exception handlers instead, in every method
+        /// unless one is given
+        #[clap(long)]
+        lint: bool,
+    },
+    /// list the fields of a class
+    Fields {
+        /// path to the class
+        #[clap(parse(from_os_str))]
+        class: PathBuf,
+        /// print the methods reading and writing each field, including the fields of other classes
+        #[clap(long)]
+        access: bool,
+    },
+    /// check that a class is well formed beyond what reading it requires
+    Validate {
+        /// path to the class
+        #[clap(parse(from_os_str))]
+        class: PathBuf,
+        /// also reject what is legal but no compiler writes, like duplicate constants or a
+        /// max_stack larger than the code needs
+        #[clap(long)]
+        strict: bool,
+    },
+}
+
+fn run(command: Command) -> Result<(), Error> {
+    match command {
+        Command::Json { class, json } => {
+            let cls = JavaClass::from_file_mmap(class)?;
+            fs::write(json, serde_json::to_string_pretty(&cls)?)
+        }
+        Command::Class { json, class } => {
+            let file = File::open(json)?;
+            let reader = BufReader::new(file);
+            let cls: JavaClass = serde_json::from_reader(reader)?;
+            cls.to_file(class)
+        }
+        Command::Pool { class } => {
+            let cls = JavaClass::from_file_mmap(class)?;
+            print!("{}", cls.constant_pool.javap());
+            Ok(())
+        }
+        Command::Print {
+            class,
+            show_indices,
+            flags,
+        } => {
+            let indices = match show_indices.as_deref() {
+                None => IndexMode::Resolved,
+                Some("both") => IndexMode::Both,
+                Some("only") => IndexMode::Index,
+                Some(mode) => {
+                    return Err(Error::other(format!(
+                        "Unknown --show-indices mode {}, expected both or only.",
+                        mode
+                    )))
+                }
+            };
+            let flags = match flags.as_str() {
+                "debug" => FlagStyle::Debug,
+                "java" => FlagStyle::Java,
+                "hex" => FlagStyle::Hex,
+                style => {
+                    return Err(Error::other(format!(
+                        "Unknown --flags style {}, expected debug, java or hex.",
+                        style
+                    )))
+                }
+            };
+            let opts = PrintOptions {
+                indices,
+                flags,
+                ..Default::default()
+            };
+            JavaClass::from_file_mmap(class)?.print(&opts)
+        }
+        Command::Uses {
+            class,
+            index,
+            method,
+        } => {
+            let cls = JavaClass::from_file_mmap(class)?;
+            let cp = &cls.constant_pool;
+            let targets = match (index, method) {
+                (_, Some(method)) => cp.find_method_refs(&method),
+                (Some(index), None) => {
+                    let index = index.trim_start_matches('#').parse::<u16>().ok();
+                    match index.and_then(CPIndex::new) {
+                        Some(index) => vec![index],
+                        None => return Err(Error::other("The index must be like #42.")),
+                    }
+                }
+                (None, None) => unreachable!("required by clap"),
+            };
+            for target in targets {
+                for usage in cls.find_usages(target) {
+                    let m = &cls.methods[usage.method];
+                    println!(
+                        "{}{} {}: {} #{}",
+                        m.name_index.resolve(cp)?,
+                        m.descriptor_index.resolve(cp)?,
+                        usage.offset,
+                        usage.mnemonic,
+                        usage.index.0
+                    );
+                }
+            }
+            Ok(())
+        }
+        Command::Deps { class, jar, dot } => {
+            let in_jar = jar.is_some();
+            let classes = match (class, jar) {
+                (_, Some(jar)) => jar::classes(jar)?.into_iter().map(|(_, c)| c).collect(),
+                (Some(class), None) => vec![JavaClass::from_file_mmap(class)?],
+                (None, None) => unreachable!("required by clap"),
+            };
+            let names = classes
+                .iter()
+                .map(|c| c.this_class.name(&c.constant_pool))
+                .collect::<Result<BTreeSet<_>, _>>()?;
+            if dot {
+                println!("digraph dependencies {{");
+            }
+            for cls in classes.iter() {
+                let name = cls.this_class.name(&cls.constant_pool)?;
+                for dep in cls.dependencies() {
+                    if dot && (!in_jar || names.contains(dep.as_str())) {
+                        println!("    {:?} -> {:?};", name, dep);
+                    } else if !dot && in_jar {
+                        println!("{} {}", name, dep);
+                    } else if !dot {
+                        println!("{}", dep);
+                    }
+                }
+            }
+            if dot {
+                println!("}}");
+            }
+            Ok(())
+        }
+        Command::Strings {
+            path,
+            all_utf8,
+            unique,
+            grep,
+        } => {
+            // mapped with the mmap feature, in which case the classes stored in a jar aren't
+            // copied either
+            let input = input::read(&path)?;
+            let in_jar = jar::has_jar_magic(&input);
+            let files = if in_jar {
+                jar::class_slices(&input)?
+            } else {
+                vec![(path.display().to_string(), Cow::Borrowed(&input[..]))]
+            };
+            // only the constant pool is needed, which is read without copying the strings
+            let classes = files
+                .iter()
+                .map(|(name, bytes)| match borrowed::deserialize_borrowed(bytes) {
+                    Err(e) if in_jar => Err(jar::class_error(name, e)),
+                    res => res,
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let mut seen = BTreeSet::new();
+            for cls in classes.iter() {
+                let cp = &cls.constant_pool;
+                let strings = if all_utf8 {
+                    cp.utf8_values().map(|(_, s)| s).collect()
+                } else {
+                    cls.string_constants()
+                };
+                for s in strings {
+                    if grep.as_ref().is_some_and(|g| !s.contains(g.as_str()))
+                        || (unique && !seen.insert(s))
+                    {
+                        continue;
+                    }
+                    if in_jar {
+                        println!("{}: {}", cp.class_name(cls.this_class)?, strings::escape(s));
+                    } else {
+                        println!("{}", strings::escape(s));
+                    }
+                }
+            }
+            Ok(())
+        }
+        Command::Stub { path, output } => {
+            if jar::is_jar(&path)? {
+                return jar::map_classes(path, output, |c| c.strip_method_bodies().map(|_| ()));
+            }
+            let mut cls = JavaClass::from_file(path)?;
+            cls.strip_method_bodies()?;
+            cls.to_file(output)
+        }
+        Command::Stats { path, json } => {
+            // read without copying the strings and attributes, like strings
+            let input = input::read(&path)?;
+            if !jar::has_jar_magic(&input) {
+                let stats = borrowed::deserialize_borrowed(&input)?.stats();
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&stats)?);
+                } else {
+                    print!("{}", stats);
+                }
+                return Ok(());
+            }
+
+            let mut classes = BTreeMap::new();
+            let mut total = stats::ClassStats::default();
+            for (name, bytes) in jar::class_slices(&input)? {
+                let cls = borrowed::deserialize_borrowed(&bytes)
+                    .map_err(|e| jar::class_error(&name, e))?;
+                let stats = cls.stats();
+                total.merge(&stats);
+                let cp = &cls.constant_pool;
+                classes.insert(cp.class_name(cls.this_class)?.to_string(), stats);
+            }
+            if json {
+                let out = serde_json::json!({ "classes": classes, "total": total });
+                println!("{}", serde_json::to_string_pretty(&out)?);
+            } else {
+                for (name, stats) in classes {
+                    println!("{}:", name);
+                    for line in stats.to_string().lines() {
+                        println!("    {}", line);
+                    }
+                    println!();
+                }
+                println!("total:");
+                for line in total.to_string().lines() {
+                    println!("    {}", line);
+                }
+            }
+            Ok(())
+        }
+        Command::Info { path } => {
+            if !jar::is_jar(&path)? {
+                let cls = JavaClass::from_file(path)?;
+                println!("class: {}", cls.this_class.name(&cls.constant_pool)?);
+                println!("version: {}.{}", cls.major_version, cls.minor_version);
+                let runnable = if cls.main_method().is_some() { "yes" } else { "no" };
+                println!("runnable: {}", runnable);
+                return Ok(());
+            }
+
+            let classes = jar::classes(&path)?;
+            println!("classes: {}", classes.len());
+            let Some(main_class) = jar::main_class(&path)? else {
+                println!("runnable: no (no Main-Class in the manifest)");
+                return Ok(());
+            };
+            println!("main-class: {}", main_class);
+            let internal = main_class.replace('.', "/");
+            let cls = classes
+                .iter()
+                .map(|(_, c)| c)
+                .find(|c| c.this_class.name(&c.constant_pool).ok() == Some(internal.as_str()));
+            match cls {
+                Some(cls) if cls.main_method().is_some() => println!("runnable: yes"),
+                Some(_) => println!(
+                    "runnable: no (manifest says {} but that class has no main)",
+                    main_class
+                ),
+                None => println!(
+                    "runnable: no (manifest says {} but the jar has no such class)",
+                    main_class
+                ),
+            }
+            Ok(())
+        }
+        Command::Api { class, diff } => {
+            let cls = JavaClass::from_file_mmap(class)?;
+            let Some(new) = diff else {
+                print!("{}", cls.api_signature()?);
+                return Ok(());
+            };
+            let changes = cls.api_diff(&JavaClass::from_file_mmap(new)?)?;
+            for change in changes.iter() {
+                let kind = if change.is_breaking() { "breaking" } else { "additive" };
+                println!("{}: {}", kind, change);
+            }
+            match changes.iter().filter(|c| c.is_breaking()).count() {
+                0 => Ok(()),
+                n => Err(Error::other(format!("{} breaking changes.", n))),
+            }
+        }
+        Command::Callgraph {
+            class,
+            dot,
+            unreachable,
+        } => {
+            let cls = JavaClass::from_file_mmap(class)?;
+            if unreachable {
+                for m in cls.unreachable_private_methods() {
+                    println!("{}{}", m.name, m.descriptor);
+                }
+                return Ok(());
+            }
+            let graph = cls.call_graph();
+            if dot {
+                print!("{}", graph.to_dot());
+                return Ok(());
+            }
+            for node in graph.nodes.iter() {
+                for target in node.targets.iter() {
+                    println!("{} -> {}", node.name, graph.target_name(target));
+                }
+            }
+            Ok(())
+        }
+        Command::Cfg {
+            class,
+            method,
+            lint,
+        } => {
+            let cls = JavaClass::from_file_mmap(class)?;
+            let views = match &method {
+                Some(method) => {
+                    let matching = cls
+                        .method_views()
+                        .filter(|m| {
+                            m.name == method || format!("{}{}", m.name, m.descriptor) == *method
+                        })
+                        .collect::<Vec<_>>();
+                    match matching.as_slice() {
+                        [_] => matching,
+                        [] => return Err(Error::other(format!("No method {}.", method))),
+                        [m, ..] => {
+                            return Err(Error::other(format!(
+                                "Several methods are named {}, add the descriptor (like {}{}).",
+                                method, m.name, m.descriptor
+                            )))
+                        }
+                    }
+                }
+                None => cls.method_views().collect(),
+            };
+            if !lint {
+                print!("{}", views[0].method.cfg()?.to_dot());
+                return Ok(());
+            }
+
+            let this = cls.this_class.name(&cls.constant_pool)?;
+            for m in views {
+                // nothing to lint without code, or when it can't be decoded
+                let Ok(cfg) = m.method.cfg() else {
+                    continue;
+                };
+                let name = format!("{}.{}{}", this, m.name, m.descriptor);
+                for range in cfg.unreachable_ranges() {
+                    println!("{}: bytes {}..{} unreachable", name, range.start, range.end);
+                }
+                for i in cfg.dead_handlers() {
+                    let (range, handler) = &cfg.handlers[i];
+                    println!(
+                        "{}: handler at {} protects bytes {}..{} which are unreachable",
+                        name, cfg.blocks[*handler].start, range.start, range.end
+                    );
+                }
+            }
+            Ok(())
+        }
+        Command::Fields { class, access } => {
+            let cls = JavaClass::from_file_mmap(class)?;
+            let cp = &cls.constant_pool;
+            let this = cls.this_class.name(cp)?;
+            if !access {
+                for f in cls.fields.iter() {
+                    let mut flags = api::flag_names(f.access_flags, api::MemberKind::Field);
+                    flags.push("");
+                    println!(
+                        "{}{}:{}",
+                        flags.join(" "),
+                        f.name_index.resolve(cp)?,
+                        f.descriptor_index.resolve(cp)?
+                    );
+                }
+                return Ok(());
+            }
+
+            let map = cls.field_access_map();
+            let candidates = cls.final_candidates(&map);
+            let methods = cls
+                .method_views()
+                .map(|m| (m.index, format!("{}{}", m.name, m.descriptor)))
+                .collect::<BTreeMap<_, _>>();
+            let print_access = |access: &fields::FieldAccess| {
+                for (verb, sites) in [("read", &access.reads), ("written", &access.writes)] {
+                    for site in sites {
+                        let method = methods.get(&site.method).map_or("?", |m| m.as_str());
+                        println!("    {} by {} at {}", verb, method, site.offset);
+                    }
+                }
+            };
+            // the own fields in declaration order, then the others
+            for f in cls.fields.iter() {
+                let key = fields::FieldKey {
+                    class: this.to_string(),
+                    name: f.name_index.resolve(cp)?.to_string(),
+                    descriptor: f.descriptor_index.resolve(cp)?.to_string(),
+                };
+                let note = match map.get(&key) {
+                    None => " (never accessed)",
+                    Some(a) if a.reads.is_empty() => " (written but never read)",
+                    _ if candidates.contains(&key) => " (could be final)",
+                    _ => "",
+                };
+                println!("{}:{}{}", key.name, key.descriptor, note);
+                if let Some(access) = map.get(&key) {
+                    print_access(access);
+                }
+            }
+            let mut others = map
+                .iter()
+                .filter(|(key, _)| !cls.declares_field(key))
+                .collect::<Vec<_>>();
+            others.sort_by_key(|(key, _)| *key);
+            for (key, access) in others {
+                println!("{}.{}:{}", key.class, key.name, key.descriptor);
+                print_access(access);
+            }
+            Ok(())
+        }
+        Command::Validate { class, strict } => {
+            let cls = JavaClass::from_file_mmap(class)?;
+            cls.validate()?;
+            if !strict {
+                return Ok(());
+            }
+            let duplicates = cls.constant_pool.duplicates();
+            for group in duplicates.iter() {
+                let indices = group.iter().map(|i| format!("#{}", i.0)).collect::<Vec<_>>();
+                println!("duplicate constants: {}", indices.join(", "));
+            }
+            let unknown = cls.unknown_flags();
+            for (what, flags) in unknown.iter() {
+                println!("unknown access flags: {:#06x} on {}", flags.bits(), what);
+            }
+            let stale = cls.stale_limits()?;
+            for (what, (stack, locals), (new_stack, new_locals)) in stale.iter() {
+                println!(
+                    "max_stack {} and max_locals {} of {}, the code needs {} and {}",
+                    stack, locals, what, new_stack, new_locals
+                );
+            }
+            let mut problems = Vec::new();
+            if !duplicates.is_empty() {
+                problems.push(format!("{} groups of duplicate constants", duplicates.len()));
+            }
+            if !unknown.is_empty() {
+                problems.push(format!("{} sets of unknown access flags", unknown.len()));
+            }
+            if !stale.is_empty() {
+                problems.push(format!("{} methods with stale frame limits", stale.len()));
+            }
+            match problems.is_empty() {
+                true => Ok(()),
+                false => Err(Error::other(format!("{}.", problems.join(", ")))),
+            }
+        }
     }
 }
 
 fn main() {
     let args = Args::parse();
 
-    match args.command {
-        Command::Json { class, json } => {
-            let cls = JavaClass::from_file(class).unwrap();
-            fs::write(json, serde_json::to_string_pretty(&cls).unwrap()).unwrap();
+    if let Err(e) = run(args.command) {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the path of a class of tests/fixtures
+    pub fn fixture_path(name: &str) -> PathBuf {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        path.join(format!("{}.class", name))
+    }
+
+    // a class of tests/fixtures, by name
+    pub fn fixture(name: &str) -> JavaClass {
+        JavaClass::from_file(fixture_path(name)).unwrap()
+    }
+
+    #[test]
+    fn sorted_indices_skip_long_slots() {
+        let mut cp = ConstantPool::new();
+        cp.push(ConstantPoolEntry::Utf8("a".into())).unwrap();
+        cp.push(ConstantPoolEntry::Long(1)).unwrap();
+        cp.push(ConstantPoolEntry::Utf8("b".into())).unwrap();
+        assert_eq!(cp.indices_sorted(), vec![CPIndex(1), CPIndex(2), CPIndex(4)]);
+        assert!(cp.iter_sorted().eq(cp.iter()));
+    }
+
+    #[test]
+    fn contains_and_max_index() {
+        let mut cp = ConstantPool::new();
+        assert!(!cp.contains(CPIndex(0)));
+        assert_eq!(cp.max_index(), None);
+
+        cp.push(ConstantPoolEntry::Utf8("a".into())).unwrap();
+        cp.push(ConstantPoolEntry::Long(1)).unwrap();
+        // index 0 is never an entry, and neither is the slot after a long
+        assert!(!cp.contains(CPIndex(0)));
+        assert!(cp.contains(CPIndex(1)));
+        assert!(cp.contains(CPIndex(2)));
+        assert!(!cp.contains(CPIndex(3)));
+        assert!(!cp.contains(CPIndex(4)));
+        assert_eq!(cp.max_index(), Some(CPIndex(2)));
+
+        cp.push(ConstantPoolEntry::Double(DoubleBits::new(1.0))).unwrap();
+        assert!(cp.contains(CPIndex(4)));
+        assert!(!cp.contains(CPIndex(5)));
+        assert_eq!(cp.max_index(), Some(CPIndex(4)));
+        cp.push(ConstantPoolEntry::Integer(1)).unwrap();
+        assert_eq!(cp.max_index(), Some(CPIndex(6)));
+    }
+
+    #[test]
+    fn nan_payloads() {
+        let float = FloatBits::new(f32::from_bits(0x7fc0_1234));
+        let double = DoubleBits::new(f64::from_bits(0xfff8_0000_dead_beef));
+        assert_eq!(float.0, 0x7fc0_1234);
+        assert_eq!(double.0, 0xfff8_0000_dead_beef);
+        assert!(float.is_unusual_nan() && double.is_unusual_nan());
+        assert!(!FloatBits::new(f32::NAN).is_unusual_nan());
+
+        let mut class = fixture("Hello");
+        let f = class.constant_pool.push(ConstantPoolEntry::Float(float)).unwrap();
+        let d = class.constant_pool.push(ConstantPoolEntry::Double(double)).unwrap();
+        let mut bytes = Vec::new();
+        class.serialize(&mut bytes).unwrap();
+        let cp = JavaClass::from_bytes(&bytes).unwrap().constant_pool;
+        assert_eq!(cp.get(f), Some(&ConstantPoolEntry::Float(FloatBits(0x7fc0_1234))));
+        let double = DoubleBits(0xfff8_0000_dead_beef);
+        assert_eq!(cp.get(d), Some(&ConstantPoolEntry::Double(double)));
+    }
+
+    #[test]
+    fn utf8_entries() {
+        let class = fixture("Hello");
+        let entries: Vec<_> = class.constant_pool.utf8_entries().collect();
+        assert!(entries.contains(&(CPIndex(14), "Hello, world!")));
+        assert!(entries.iter().any(|(_, s)| *s == "java/io/PrintStream"));
+        let utf8 = |e: &ConstantPoolEntry| matches!(e, ConstantPoolEntry::Utf8(_));
+        assert_eq!(entries.len(), class.constant_pool.iter().filter(|(_, e)| utf8(e)).count());
+        for (i, s) in entries {
+            assert_eq!(class.constant_pool.get(i), Some(&ConstantPoolEntry::Utf8(s.into())));
         }
-        Command::Class { json, class } => {
-            let file = File::open(json).unwrap();
-            let reader = BufReader::new(file);
-            let cls: JavaClass = serde_json::from_reader(reader).unwrap();
-            cls.to_file(class).unwrap();
+    }
+
+    // the attributes of the class, its fields and its methods
+    fn all_attributes<A>(class: &JavaClass<A>) -> impl Iterator<Item = &A> {
+        let fields = class.fields.iter().flat_map(|f| f.attributes.iter());
+        let methods = class.methods.iter().flat_map(|m| m.attributes.iter());
+        fields.chain(methods).chain(class.attributes.iter())
+    }
+
+    #[test]
+    fn lazy_attributes() {
+        for name in ["Hello", "Flow", "Annotated", "TypeAnnotated", "Point"] {
+            let bytes = fs::read(fixture_path(name)).unwrap();
+            let lazy = JavaClass::from_file_lazy(fixture_path(name)).unwrap();
+            let cp = &lazy.constant_pool;
+            assert!(all_attributes(&lazy).all(|a| !a.is_parsed()));
+            let mut written = Vec::new();
+            lazy.serialize(&mut written).unwrap();
+            assert!(written == bytes, "{}", name);
+
+            // parsed like from_file does, once
+            let eager = fixture(name);
+            for (a, e) in all_attributes(&lazy).zip(all_attributes(&eager)) {
+                let info = a.info(cp);
+                assert!(a.is_parsed());
+                assert_eq!(info, &e.info, "{}", name);
+                assert!(std::ptr::eq(a.info(cp), info));
+                assert_eq!(&a.clone().into_attribute(cp), e);
+            }
+            // still written from the bytes read
+            let mut written = Vec::new();
+            lazy.serialize(&mut written).unwrap();
+            assert!(written == bytes, "{}", name);
         }
     }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn from_mmap() {
+        for name in ["Hello", "Flow", "Point", "TypeAnnotated"] {
+            let (cls, mmap) = JavaClass::from_mmap(&fixture_path(name)).unwrap();
+            assert_eq!(&mmap[..], &fs::read(fixture_path(name)).unwrap()[..]);
+            assert_eq!(cls, JavaClass::from_bytes(&mmap).unwrap(), "{}", name);
+            assert_eq!(cls, fixture(name), "{}", name);
+        }
+        assert!(JavaClass::from_mmap(&fixture_path("Missing")).is_err());
+    }
+
+    // the names of the attributes of the class, including those nested in Code and Record
+    fn attribute_names(class: &JavaClass) -> Vec<String> {
+        fn walk(attributes: &[Attribute], cp: &ConstantPool, names: &mut Vec<String>) {
+            for a in attributes {
+                names.push(a.name(cp).unwrap().to_owned());
+                match &a.info {
+                    AttributeInfo::Code { attributes, .. } => walk(attributes, cp, names),
+                    AttributeInfo::Record { components } => {
+                        for c in components {
+                            walk(&c.attributes, cp, names);
+                        }
+                    }
+                    _ => (),
+                }
+            }
+        }
+        let mut names = Vec::new();
+        let cp = &class.constant_pool;
+        for a in all_attributes(class) {
+            walk(std::slice::from_ref(a), cp, &mut names);
+        }
+        names
+    }
+
+    #[test]
+    fn strip_debug_info() {
+        // compiled with -g
+        let mut class = fixture("Debug");
+        let debug = [
+            "LineNumberTable",
+            "LocalVariableTable",
+            "LocalVariableTypeTable",
+            "SourceFile",
+        ];
+        let names = attribute_names(&class);
+        assert!(debug.iter().all(|d| names.iter().any(|n| n == d)), "{:?}", names);
+
+        class.strip_debug_info();
+        let names = attribute_names(&class);
+        assert!(names.iter().all(|n| !debug.contains(&n.as_str())), "{:?}", names);
+        assert!(names.iter().any(|n| n == "Code"));
+        assert!(names.iter().any(|n| n == "StackMapTable"));
+
+        // still a valid class
+        let mut bytes = Vec::new();
+        class.serialize(&mut bytes).unwrap();
+        let reparsed = JavaClass::deserialize(&mut Cursor::new(&bytes[..])).unwrap();
+        assert_eq!(attribute_names(&reparsed), names);
+    }
+
+    // strips names from a copy of the fixture, checking they were there and that nothing else went
+    fn check_strip(name: &str, strip: fn(&mut JavaClass), names: &[&str]) {
+        let mut class = fixture(name);
+        let before = attribute_names(&class);
+        assert!(names.iter().all(|n| before.iter().any(|b| b == n)), "{:?}", before);
+        strip(&mut class);
+        let after = attribute_names(&class);
+        let kept: Vec<_> = before.into_iter().filter(|n| !names.contains(&n.as_str())).collect();
+        assert_eq!(after, kept);
+    }
+
+    #[test]
+    fn strip_annotations() {
+        let annotations = [
+            "RuntimeVisibleAnnotations",
+            "RuntimeInvisibleAnnotations",
+            "RuntimeVisibleParameterAnnotations",
+            "RuntimeInvisibleParameterAnnotations",
+        ];
+        check_strip("Retained", JavaClass::strip_annotations, &annotations);
+        let visible = [annotations[0], annotations[2]];
+        check_strip("Annotated", JavaClass::strip_annotations, &visible);
+    }
+
+    #[test]
+    fn strip_type_annotations() {
+        let annotations = ["RuntimeVisibleTypeAnnotations", "RuntimeInvisibleTypeAnnotations"];
+        check_strip("Retained", JavaClass::strip_type_annotations, &annotations);
+        check_strip("TypeAnnotated", JavaClass::strip_type_annotations, &annotations);
+        // including those in Code
+        let mut class = fixture("Retained");
+        class.strip_type_annotations();
+        let make = &class.methods[1];
+        assert_eq!(make.name_index.resolve(&class.constant_pool).ok(), Some("make"));
+        let code = make.attributes.iter().find_map(|a| match &a.info {
+            AttributeInfo::Code { attributes, .. } => Some(attributes),
+            _ => None,
+        });
+        // LineNumberTable and StackMapTable
+        assert_eq!(code.unwrap().len(), 2);
+    }
+
+    // the predicates true for a fixture, in the order of the names
+    fn predicates(class: &JavaClass) -> Vec<&'static str> {
+        let all = [
+            ("interface", class.is_interface()),
+            ("abstract", class.is_abstract()),
+            ("enum", class.is_enum()),
+            ("annotation", class.is_annotation()),
+            ("synthetic", class.is_synthetic()),
+            ("module", class.is_module()),
+        ];
+        all.into_iter().filter(|(_, p)| *p).map(|(n, _)| n).collect()
+    }
+
+    #[test]
+    fn is_interface() {
+        assert_eq!(predicates(&fixture("Config")), ["interface", "abstract", "annotation"]);
+        assert!(!fixture("TypeAnnotated").is_interface());
+    }
+
+    #[test]
+    fn is_abstract() {
+        assert!(fixture("Config").is_abstract());
+        assert!(!fixture("Hello").is_abstract());
+    }
+
+    #[test]
+    fn is_enum() {
+        assert_eq!(predicates(&fixture("Color")), ["enum"]);
+        assert!(!fixture("Point").is_enum());
+    }
+
+    #[test]
+    fn is_annotation() {
+        assert!(fixture("Config").is_annotation());
+        assert!(!fixture("Annotated").is_annotation());
+    }
+
+    #[test]
+    fn is_synthetic() {
+        // javac doesn't emit synthetic classes anymore
+        let mut class = fixture("Hello");
+        assert!(!class.is_synthetic());
+        class.access_flags |= AccessFlags::SYNTHETIC;
+        assert_eq!(predicates(&class), ["synthetic"]);
+    }
+
+    #[test]
+    fn is_module() {
+        let module = JavaClass::from_file(fixture_path("module/module-info")).unwrap();
+        assert_eq!(predicates(&module), ["module"]);
+        assert!(["Hello", "Config", "Color", "Point"].iter().all(|n| !fixture(n).is_module()));
+    }
+
+    #[test]
+    fn interface_names() {
+        let mut class = fixture("TypeAnnotated");
+        assert_eq!(class.interface_names(), ["java/lang/Runnable"]);
+        assert!(fixture("Hello").interface_names().is_empty());
+        // an interface that isn't a Class entry is left out
+        let index = class.constant_pool.push(ConstantPoolEntry::Integer(1)).unwrap();
+        class.interfaces.insert(0, ClassIndex(index));
+        assert_eq!(class.interfaces.len(), 2);
+        assert_eq!(class.interface_names(), ["java/lang/Runnable"]);
+    }
+
+    #[test]
+    fn new_attribute() {
+        let mut class = fixture("Hello");
+        let size = class.constant_pool.size();
+        // the name is reused when it's already there
+        let info = AttributeInfo::new_empty_code(0, 0);
+        let code = Attribute::new("Code", info, &mut class.constant_pool).unwrap();
+        assert_eq!(code.name(&class.constant_pool), Some("Code"));
+        assert_eq!(class.constant_pool.size(), size);
+
+        let info = AttributeInfo::SourceDebugExtension("SMAP\nHello.java\n".into());
+        let debug = Attribute::new("SourceDebugExtension", info, &mut class.constant_pool).unwrap();
+        assert_eq!(class.constant_pool.size(), size + 1);
+        class.attributes.push(debug.clone());
+
+        let mut bytes = Vec::new();
+        class.serialize(&mut bytes).unwrap();
+        let reparsed = JavaClass::from_bytes(&bytes).unwrap();
+        assert_eq!(reparsed.attributes.last(), Some(&debug));
+        assert_eq!(reparsed, class);
+    }
+
+    #[test]
+    fn new_field() {
+        let mut class = fixture("Hello");
+        let cp = &mut class.constant_pool;
+        let size = cp.size();
+        let flags = AccessFlags::PRIVATE | AccessFlags::STATIC | AccessFlags::FINAL;
+        let mut field = Field::new("LIMIT", "I", flags, cp).unwrap();
+        assert_eq!(field.name_index.resolve(cp).ok(), Some("LIMIT"));
+        assert_eq!(field.descriptor_index.resolve(cp).ok(), Some("I"));
+        assert!(field.attributes.is_empty());
+        assert_eq!(cp.size(), size + 2);
+        // a second field of the same name and type doesn't add anything
+        let again = Field::new("LIMIT", "I", AccessFlags::empty(), cp).unwrap();
+        assert_eq!(again.name_index, field.name_index);
+        assert_eq!(again.descriptor_index, field.descriptor_index);
+        assert_eq!(cp.size(), size + 2);
+
+        let index = cp.push(ConstantPoolEntry::Integer(100)).unwrap();
+        let value = AttributeInfo::ConstantValue { index };
+        field.attributes.push(Attribute::new("ConstantValue", value, cp).unwrap());
+        class.fields.push(field);
+
+        let mut bytes = Vec::new();
+        class.serialize(&mut bytes).unwrap();
+        let reparsed = JavaClass::from_bytes(&bytes).unwrap();
+        assert_eq!(reparsed, class);
+        let limit = reparsed.fields_iter().find(|f| f.name == "LIMIT").unwrap();
+        assert_eq!(limit.access_flags, flags);
+        assert_eq!(limit.constant_value, Some(&ConstantPoolEntry::Integer(100)));
+    }
 }
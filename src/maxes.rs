@@ -0,0 +1,464 @@
+use std::{collections::HashMap, io::Error};
+
+use crate::{
+    descriptor::{field_slots, method_slots},
+    instruction::{decode_code, Instruction},
+    AccessFlags, AttributeInfo, CPIndex, ConstantPool, ConstantPoolEntry, ExceptionTableEntry,
+    JavaClass, Method,
+};
+
+// max_stack and max_locals
+type Limits = (u16, u16);
+
+// the descriptor of the field or method an instruction refers to
+fn member_descriptor(cp: &ConstantPool, index: CPIndex) -> Result<&str, Error> {
+    let name_and_type = match cp.get(index) {
+        Some(
+            ConstantPoolEntry::FieldRef {
+                name_and_type_index,
+                ..
+            }
+            | ConstantPoolEntry::MethodRef {
+                name_and_type_index,
+                ..
+            }
+            | ConstantPoolEntry::InterfaceMethodRef {
+                name_and_type_index,
+                ..
+            }
+            | ConstantPoolEntry::InvokeDynamic {
+                name_and_type_index,
+                ..
+            },
+        ) => name_and_type_index,
+        _ => {
+            return Err(Error::other(format!(
+                "#{} isn't a field, method or invokedynamic constant.",
+                index.0
+            )))
+        }
+    };
+    Ok(name_and_type.resolve(cp)?.1.resolve(cp)?)
+}
+
+fn field(cp: &ConstantPool, index: CPIndex) -> Result<u16, Error> {
+    let descriptor = member_descriptor(cp, index)?;
+    field_slots(descriptor)
+        .ok_or_else(|| Error::other(format!("Invalid field descriptor {}.", descriptor)))
+}
+
+// the argument and return value slots of the method
+fn method(cp: &ConstantPool, index: CPIndex) -> Result<(u16, u16), Error> {
+    let descriptor = member_descriptor(cp, index)?;
+    method_slots(descriptor)
+        .ok_or_else(|| Error::other(format!("Invalid method descriptor {}.", descriptor)))
+}
+
+// the operand stack slots an instruction pops and then pushes
+fn stack_effect(instruction: &Instruction, cp: &ConstantPool) -> Result<(u16, u16), Error> {
+    Ok(match instruction {
+        Instruction::Getstatic(index) => (0, field(cp, *index)?),
+        Instruction::Putstatic(index) => (field(cp, *index)?, 0),
+        Instruction::Getfield(index) => (1, field(cp, *index)?),
+        Instruction::Putfield(index) => (1 + field(cp, *index)?, 0),
+        Instruction::Invokevirtual(index)
+        | Instruction::Invokespecial(index)
+        | Instruction::Invokeinterface(index, _) => {
+            let (arguments, ret) = method(cp, *index)?;
+            (arguments + 1, ret)
+        }
+        Instruction::Invokestatic(index) | Instruction::Invokedynamic(index) => method(cp, *index)?,
+        Instruction::Multianewarray(_, dimensions) => (*dimensions as u16, 1),
+        Instruction::Wide { opcode, .. } => match opcode {
+            0x16 | 0x18 => (0, 2),
+            0x15..=0x19 => (0, 1),
+            0x37 | 0x39 => (2, 0),
+            0x36..=0x3a => (1, 0),
+            _ => (0, 0),
+        },
+        instruction => match instruction.opcode() {
+            0x00 => (0, 0),
+            0x01..=0x08 => (0, 1),
+            0x09..=0x0a => (0, 2),
+            0x0b..=0x0d => (0, 1),
+            0x0e..=0x0f => (0, 2),
+            0x10..=0x13 => (0, 1),
+            0x14 => (0, 2),
+            0x16 | 0x18 => (0, 2),
+            0x15..=0x19 => (0, 1),
+            // the load_<n> instructions by type: int, long, float, double, reference
+            0x1a..=0x2d => match (instruction.opcode() - 0x1a) / 4 {
+                1 | 3 => (0, 2),
+                _ => (0, 1),
+            },
+            0x2f | 0x31 => (2, 2),
+            0x2e..=0x35 => (2, 1),
+            0x37 | 0x39 => (2, 0),
+            0x36..=0x3a => (1, 0),
+            0x3b..=0x4e => match (instruction.opcode() - 0x3b) / 4 {
+                1 | 3 => (2, 0),
+                _ => (1, 0),
+            },
+            0x50 | 0x52 => (4, 0),
+            0x4f..=0x56 => (3, 0),
+            0x57 => (1, 0),
+            0x58 => (2, 0),
+            0x59 => (1, 2),
+            0x5a => (2, 3),
+            0x5b => (3, 4),
+            0x5c => (2, 4),
+            0x5d => (3, 5),
+            0x5e => (4, 6),
+            0x5f => (2, 2),
+            // add, sub, mul, div and rem by type: int, long, float, double
+            0x60..=0x73 => match (instruction.opcode() - 0x60) % 4 {
+                1 | 3 => (4, 2),
+                _ => (2, 1),
+            },
+            0x74 | 0x76 => (1, 1),
+            0x75 | 0x77 => (2, 2),
+            0x78 | 0x7a | 0x7c => (2, 1),
+            0x79 | 0x7b | 0x7d => (3, 2),
+            0x7e | 0x80 | 0x82 => (2, 1),
+            0x7f | 0x81 | 0x83 => (4, 2),
+            0x84 => (0, 0),
+            0x85 | 0x87 | 0x8c | 0x8d => (1, 2),
+            0x86 | 0x8b => (1, 1),
+            0x88 | 0x89 | 0x8e | 0x90 => (2, 1),
+            0x8a | 0x8f => (2, 2),
+            0x91..=0x93 => (1, 1),
+            0x94 | 0x97 | 0x98 => (4, 1),
+            0x95 | 0x96 => (2, 1),
+            0x99..=0x9e => (1, 0),
+            0x9f..=0xa6 => (2, 0),
+            0xa7 => (0, 0),
+            // the return address, for the subroutine
+            0xa8 | 0xc9 => (0, 1),
+            0xa9 => (0, 0),
+            0xaa | 0xab => (1, 0),
+            0xad | 0xaf => (2, 0),
+            0xac..=0xb0 => (1, 0),
+            0xb1 => (0, 0),
+            0xbb => (0, 1),
+            0xbc..=0xbe => (1, 1),
+            0xbf => (1, 0),
+            0xc0 | 0xc1 => (1, 1),
+            0xc2 | 0xc3 => (1, 0),
+            0xc4 => (0, 0),
+            0xc6 | 0xc7 => (1, 0),
+            0xc8 => (0, 0),
+            opcode => unreachable!("opcode {:#04x} is handled above", opcode),
+        },
+    })
+}
+
+// the end (index + size) of the local variable slots an instruction uses, if any
+fn local_end(instruction: &Instruction) -> Option<u32> {
+    let (index, wide) = match instruction {
+        Instruction::Iload(i)
+        | Instruction::Fload(i)
+        | Instruction::Aload(i)
+        | Instruction::Istore(i)
+        | Instruction::Fstore(i)
+        | Instruction::Astore(i)
+        | Instruction::Ret(i)
+        | Instruction::Iinc(i, _) => (*i as u32, false),
+        Instruction::Lload(i)
+        | Instruction::Dload(i)
+        | Instruction::Lstore(i)
+        | Instruction::Dstore(i) => (*i as u32, true),
+        Instruction::Wide { opcode, index } => {
+            (*index as u32, matches!(opcode, 0x16 | 0x18 | 0x37 | 0x39))
+        }
+        Instruction::WideIinc { index, .. } => (*index as u32, false),
+        instruction => match instruction.opcode() {
+            // the <n> of load_<n> and store_<n>, by type: int, long, float, double, reference
+            op @ (0x1a..=0x2d | 0x3b..=0x4e) => {
+                let op = if op >= 0x3b { op - 0x3b } else { op - 0x1a };
+                ((op % 4) as u32, matches!(op / 4, 1 | 3))
+            }
+            _ => return None,
+        },
+    };
+    Some(index + if wide { 2 } else { 1 })
+}
+
+// The max_locals code needs: the argument slots of its method (along with this if it isn't
+// static) and every slot it loads or stores.
+pub fn compute_max_locals(
+    code: &[u8],
+    method_descriptor: &str,
+    is_static: bool,
+) -> Result<u16, Error> {
+    let (arguments, _) = method_slots(method_descriptor)
+        .ok_or_else(|| Error::other(format!("Invalid method descriptor {}.", method_descriptor)))?;
+    let mut locals = arguments as u32 + if is_static { 0 } else { 1 };
+    for (_, instruction) in decode_code(code)?.iter() {
+        locals = locals.max(local_end(instruction).unwrap_or(0));
+    }
+    u16::try_from(locals).map_err(|_| Error::other("The locals don't fit in max_locals."))
+}
+
+// The max_stack code needs. The stack height is followed along every branch and exception
+// handler, every path to an instruction has to reach it with the same height.
+pub fn compute_max_stack(
+    code: &[u8],
+    exception_table: &[ExceptionTableEntry],
+    cp: &ConstantPool,
+) -> Result<u16, Error> {
+    let instructions = decode_code(code)?;
+    let at = instructions
+        .iter()
+        .enumerate()
+        .map(|(i, (offset, _))| (*offset, i))
+        .collect::<HashMap<_, _>>();
+
+    // the height on entry of each instruction reached so far, by index in instructions
+    let mut heights = vec![None; instructions.len()];
+    let mut pending = vec![(0u32, 0u16)];
+    // a handler starts with the exception alone on the stack
+    pending.extend(exception_table.iter().map(|e| (e.handler as u32, 1)));
+    let mut stack = 0;
+    while let Some((offset, height)) = pending.pop() {
+        let i = *at.get(&offset).ok_or_else(|| {
+            Error::other(format!(
+                "Offset {} isn't the start of an instruction.",
+                offset
+            ))
+        })?;
+        match heights[i] {
+            Some(h) if h == height => continue,
+            Some(h) => {
+                return Err(Error::other(format!(
+                    "The stack is {} or {} high at offset {} depending on the path.",
+                    h, height, offset
+                )))
+            }
+            None => heights[i] = Some(height),
+        }
+
+        stack = stack.max(height);
+        let instruction = &instructions[i].1;
+        let (pops, pushes) = stack_effect(instruction, cp)?;
+        let after = height.checked_sub(pops).ok_or_else(|| {
+            Error::other(format!(
+                "{} at offset {} pops more than the stack holds.",
+                instruction.mnemonic(),
+                offset
+            ))
+        })? as u32
+            + pushes as u32;
+        let after = u16::try_from(after)
+            .map_err(|_| Error::other(format!("The stack overflows at offset {}.", offset)))?;
+        stack = stack.max(after);
+
+        let is_jsr = matches!(instruction, Instruction::Jsr(_) | Instruction::JsrW(_));
+        for branch in instruction.branches() {
+            let target = u32::try_from(offset as i64 + branch as i64).map_err(|_| {
+                Error::other(format!("Branch at offset {} goes before the code.", offset))
+            })?;
+            pending.push((target, after));
+        }
+        if instruction.falls_through() {
+            // the subroutine of a jsr returns with the stack as it was before
+            let next = offset + instruction.size(offset);
+            if (next as usize) < code.len() {
+                pending.push((next, if is_jsr { height } else { after }));
+            } else {
+                return Err(Error::other(format!(
+                    "{} at offset {} falls off the end of the code.",
+                    instruction.mnemonic(),
+                    offset
+                )));
+            }
+        }
+    }
+    Ok(stack)
+}
+
+impl Method {
+    // Recomputes max_stack and max_locals of the method's code from its instructions, for after
+    // they were edited, see compute_max_stack and compute_max_locals. The code must be valid: the
+    // stack can't underflow, and every path to an instruction has to reach it with the same height.
+    pub fn recompute_maxes(&mut self, cp: &ConstantPool) -> Result<(), Error> {
+        let descriptor = self.descriptor_index.resolve(cp)?;
+        let is_static = self.access_flags.contains(AccessFlags::STATIC);
+
+        let attribute = self
+            .attributes
+            .iter_mut()
+            .find(|a| a.name(cp) == Some("Code"))
+            .ok_or_else(|| Error::other("The method has no Code attribute."))?;
+        attribute.resolve(cp)?;
+        let AttributeInfo::Code {
+            max_stack,
+            max_locals,
+            code,
+            exception_table,
+            ..
+        } = &mut attribute.info
+        else {
+            unreachable!("resolved Code attribute")
+        };
+
+        let code = code.iter().map(|b| b.0).collect::<Vec<_>>();
+        let stack = compute_max_stack(&code, exception_table, cp)?;
+        *max_locals = compute_max_locals(&code, descriptor, is_static)?;
+        *max_stack = stack;
+        Ok(())
+    }
+}
+
+impl JavaClass {
+    // Recomputes the frame limits (max_stack and max_locals) of every method with code, see
+    // Method::recompute_maxes. The StackMapTable is left as it is. On error the methods before the
+    // failing one are already updated.
+    pub fn recompute_frames_and_limits(&mut self) -> Result<(), Error> {
+        let cp = &self.constant_pool;
+        for m in self.methods.iter_mut() {
+            if !m.attributes.iter().any(|a| a.name(cp) == Some("Code")) {
+                continue;
+            }
+            m.recompute_maxes(cp).map_err(|e| {
+                let name = m.name_index.resolve(cp).unwrap_or("(invalid name)");
+                let descriptor = m.descriptor_index.resolve(cp).unwrap_or("");
+                Error::other(format!("{}{}: {}", name, descriptor, e))
+            })?;
+        }
+        Ok(())
+    }
+    // The methods whose written max_stack and max_locals differ from the recomputed ones,
+    // described like "method foo()V", with the written and recomputed (max_stack, max_locals).
+    // javac writes exactly what the code needs, so these are only checked by validate --strict.
+    pub fn stale_limits(&self) -> Result<Vec<(String, Limits, Limits)>, Error> {
+        let mut recomputed = self.clone();
+        recomputed.recompute_frames_and_limits()?;
+        let cp = &self.constant_pool;
+        let limits = |m: &Method| {
+            m.attributes.iter().find_map(|a| match a.info {
+                AttributeInfo::Code {
+                    max_stack,
+                    max_locals,
+                    ..
+                } => Some((max_stack, max_locals)),
+                _ => None,
+            })
+        };
+        let mut res = Vec::new();
+        for (m, new) in self.methods.iter().zip(recomputed.methods.iter()) {
+            let (Some(old), Some(new)) = (limits(m), limits(new)) else {
+                continue;
+            };
+            if old != new {
+                let name = m.name_index.resolve(cp)?;
+                let descriptor = m.descriptor_index.resolve(cp)?;
+                res.push((format!("method {}{}", name, descriptor), old, new));
+            }
+        }
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::tests::method;
+
+    fn maxes(descriptor: &str, flags: AccessFlags, instructions: Vec<Instruction>) -> Limits {
+        let (mut m, cp) = method(descriptor, flags, instructions, vec![]);
+        m.recompute_maxes(&cp).unwrap();
+        match m.attributes[0].info {
+            AttributeInfo::Code {
+                max_stack,
+                max_locals,
+                ..
+            } => (max_stack, max_locals),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn branches() {
+        use Instruction::*;
+        // return x == 0 ? 0 : 1 + 2
+        let code = vec![
+            Iload0,
+            Ifeq(7),
+            Iconst1,
+            Iconst2,
+            Iadd,
+            Ireturn,
+            Iconst0,
+            Ireturn,
+        ];
+        assert_eq!(maxes("(I)I", AccessFlags::STATIC, code), (2, 1));
+
+        // the return is reached with 1 value on the stack by the branch and 0 after the pop
+        let code = vec![Iconst0, Iconst0, Ifeq(4), Pop, Return];
+        let (mut m, cp) = method("()V", AccessFlags::STATIC, code, vec![]);
+        assert_eq!(
+            m.recompute_maxes(&cp).unwrap_err().to_string(),
+            "The stack is 0 or 1 high at offset 6 depending on the path."
+        );
+    }
+
+    #[test]
+    fn exception_handlers() {
+        use Instruction::*;
+        // throw null, caught by a handler that needs 2 stack slots
+        let code = vec![AconstNull, Athrow, Dup, Pop2, Return];
+        let handler = ExceptionTableEntry {
+            start: 0,
+            end: 2,
+            handler: 2,
+            catch_type: None,
+        };
+        let (mut m, cp) = method("()V", AccessFlags::empty(), code.clone(), vec![handler]);
+        m.recompute_maxes(&cp).unwrap();
+        assert!(matches!(
+            m.attributes[0].info,
+            AttributeInfo::Code {
+                max_stack: 2,
+                max_locals: 1,
+                ..
+            }
+        ));
+        // without the handler its code is never reached
+        assert_eq!(maxes("()V", AccessFlags::empty(), code), (1, 1));
+    }
+
+    #[test]
+    fn wide_locals() {
+        use Instruction::*;
+        let code = vec![
+            Iconst0,
+            Wide {
+                opcode: 0x36,
+                index: 300,
+            },
+            Wide {
+                opcode: 0x16,
+                index: 400,
+            },
+            Pop2,
+            WideIinc {
+                index: 500,
+                constant: 1,
+            },
+            Return,
+        ];
+        assert_eq!(maxes("()V", AccessFlags::STATIC, code), (2, 501));
+    }
+
+    #[test]
+    fn two_slot_values() {
+        use Instruction::*;
+        // the long and double arguments take slots 0-1 and 2-3
+        let code = vec![Lload0, Dload2, Pop2, Pop2, Lconst1, Lstore(4), Return];
+        assert_eq!(maxes("(JD)V", AccessFlags::STATIC, code), (4, 6));
+        let code = vec![Dconst0, Dstore3, Return];
+        assert_eq!(maxes("(J)V", AccessFlags::STATIC, code), (2, 5));
+        // this comes first, then the long
+        assert_eq!(maxes("(J)V", AccessFlags::empty(), vec![Return]), (0, 3));
+    }
+}
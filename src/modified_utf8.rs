@@ -0,0 +1,195 @@
+use std::{fmt, io::Error, ops::Deref};
+
+// The JVM stores strings as "modified UTF-8": the null character is encoded on two bytes
+// (C0 80), and supplementary characters are encoded as two three-byte surrogates instead of a
+// single four-byte sequence.
+
+// The string of a Utf8 entry. Modified UTF-8 can hold lone surrogates (javac writes them for
+// literals like "a\uD800b"), which a String can't: such a string keeps its UTF-16 units to be
+// written back as they were, and its text has U+FFFD in place of the lone surrogates.
+#[derive(Debug, Hash, PartialEq, Eq, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(from = "JavaStringRepr", into = "JavaStringRepr")]
+pub struct JavaString {
+    text: String,
+    units: Option<Box<[u16]>>,
+}
+
+// a JSON string, or the UTF-16 units when there are lone surrogates
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(untagged)]
+enum JavaStringRepr {
+    Text(String),
+    Units(Vec<u16>),
+}
+
+impl JavaString {
+    pub fn from_units(units: Vec<u16>) -> Self {
+        match String::from_utf16(&units) {
+            Ok(text) => text.into(),
+            Err(_) => JavaString {
+                text: String::from_utf16_lossy(&units),
+                units: Some(units.into()),
+            },
+        }
+    }
+    // the text, with U+FFFD in place of lone surrogates
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+    // whether the string has lone surrogates, which as_str doesn't show
+    pub fn is_lossy(&self) -> bool {
+        self.units.is_some()
+    }
+}
+
+impl From<String> for JavaString {
+    fn from(text: String) -> Self {
+        JavaString { text, units: None }
+    }
+}
+
+impl From<&str> for JavaString {
+    fn from(text: &str) -> Self {
+        text.to_owned().into()
+    }
+}
+
+impl From<JavaStringRepr> for JavaString {
+    fn from(repr: JavaStringRepr) -> Self {
+        match repr {
+            JavaStringRepr::Text(text) => text.into(),
+            JavaStringRepr::Units(units) => JavaString::from_units(units),
+        }
+    }
+}
+
+impl From<JavaString> for JavaStringRepr {
+    fn from(s: JavaString) -> Self {
+        match s.units {
+            Some(units) => JavaStringRepr::Units(units.into()),
+            None => JavaStringRepr::Text(s.text),
+        }
+    }
+}
+
+impl Deref for JavaString {
+    type Target = str;
+    fn deref(&self) -> &str {
+        &self.text
+    }
+}
+
+impl PartialEq<str> for JavaString {
+    fn eq(&self, other: &str) -> bool {
+        !self.is_lossy() && self.text == other
+    }
+}
+
+impl PartialEq<&str> for JavaString {
+    fn eq(&self, other: &&str) -> bool {
+        self == *other
+    }
+}
+
+impl fmt::Display for JavaString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+pub fn decode_modified_utf8(bytes: &[u8]) -> Result<JavaString, Error> {
+    let invalid = || Error::other("Invalid modified UTF-8 sequence.");
+    // continuation byte at position i, or an error
+    let cont = |i: usize| match bytes.get(i) {
+        Some(b) if b & 0xC0 == 0x80 => Ok((b & 0x3F) as u16),
+        _ => Err(invalid()),
+    };
+
+    let mut units = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let b = bytes[i];
+        match b {
+            0x01..=0x7F => {
+                units.push(b as u16);
+                i += 1;
+            }
+            0xC0..=0xDF => {
+                let unit = ((b & 0x1F) as u16) << 6 | cont(i + 1)?;
+                // only the null character may use an overlong encoding
+                if unit != 0 && unit < 0x80 {
+                    return Err(invalid());
+                }
+                units.push(unit);
+                i += 2;
+            }
+            0xE0..=0xEF => {
+                let unit = ((b & 0x0F) as u16) << 12 | cont(i + 1)? << 6 | cont(i + 2)?;
+                if unit < 0x800 {
+                    return Err(invalid());
+                }
+                units.push(unit);
+                i += 3;
+            }
+            _ => return Err(invalid()),
+        }
+    }
+
+    // surrogate pairs are recombined here, lone ones are kept as units
+    Ok(JavaString::from_units(units))
+}
+
+pub fn encode_modified_utf8(s: &JavaString) -> Vec<u8> {
+    match &s.units {
+        Some(units) => encode_units(units.iter().copied(), s.text.len()),
+        None => encode_units(s.text.encode_utf16(), s.text.len()),
+    }
+}
+
+fn encode_units(units: impl Iterator<Item = u16>, len: usize) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(len);
+    for unit in units {
+        match unit {
+            0x01..=0x7F => bytes.push(unit as u8),
+            0x00 | 0x80..=0x7FF => {
+                bytes.push(0xC0 | (unit >> 6) as u8);
+                bytes.push(0x80 | (unit & 0x3F) as u8);
+            }
+            _ => {
+                bytes.push(0xE0 | (unit >> 12) as u8);
+                bytes.push(0x80 | (unit >> 6 & 0x3F) as u8);
+                bytes.push(0x80 | (unit & 0x3F) as u8);
+            }
+        }
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lone_surrogates() {
+        // a low surrogate alone, a high one alone, then a pair
+        let bytes = [
+            b'a', 0xED, 0xB0, 0x80, 0xED, 0xA0, 0x80, 0xED, 0xA0, 0xBD, 0xED, 0xB8, 0x80,
+        ];
+        let s = decode_modified_utf8(&bytes).unwrap();
+        assert!(s.is_lossy());
+        assert_eq!(s.as_str(), "a\u{FFFD}\u{FFFD}\u{1F600}");
+        assert!(s != "a\u{FFFD}\u{FFFD}\u{1F600}");
+        assert_eq!(encode_modified_utf8(&s), bytes);
+
+        let json = serde_json::to_string(&s).unwrap();
+        assert_eq!(serde_json::from_str::<JavaString>(&json).unwrap(), s);
+    }
+
+    #[test]
+    fn paired_surrogates() {
+        let s = decode_modified_utf8(&[0xC0, 0x80, 0xED, 0xA0, 0xBD, 0xED, 0xB8, 0x80]).unwrap();
+        assert!(!s.is_lossy());
+        assert!(s == "\0\u{1F600}");
+        assert_eq!(serde_json::to_string(&s).unwrap(), "\"\\u0000😀\"");
+    }
+}
@@ -0,0 +1,85 @@
+use std::{
+    fs,
+    io::Error,
+    path::{Path, PathBuf},
+};
+
+use rayon::prelude::*;
+
+use crate::JavaClass;
+
+// all the .class files under dir, recursively
+fn class_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), Error> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            class_files(&path, files)?;
+        } else if path.extension().is_some_and(|e| e == "class") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+// Parses every class file under dir in parallel. All the files are parsed even if some fail, in
+// which case every failure is returned along with the file it comes from.
+pub fn parse_directory(dir: &Path) -> Result<Vec<JavaClass>, Vec<(PathBuf, Error)>> {
+    let mut files = Vec::new();
+    class_files(dir, &mut files).map_err(|e| vec![(dir.to_path_buf(), e)])?;
+    files.sort();
+
+    let results = files
+        .into_par_iter()
+        .map(|path| JavaClass::from_file(&path).map_err(|e| (path, e)))
+        .collect::<Vec<_>>();
+
+    let mut classes = Vec::with_capacity(results.len());
+    let mut errors = Vec::new();
+    for res in results {
+        match res {
+            Ok(class) => classes.push(class),
+            Err(e) => errors.push(e),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(classes)
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{fixture, fixture_path};
+
+    #[test]
+    fn parse_directory() {
+        let dir = std::env::temp_dir().join(format!("javd-parallel-{}", std::process::id()));
+        let nested = dir.join("nested");
+        fs::create_dir_all(&nested).unwrap();
+        fs::copy(fixture_path("Hello"), dir.join("Hello.class")).unwrap();
+        fs::copy(fixture_path("Flow"), nested.join("Flow.class")).unwrap();
+        fs::write(dir.join("notes.txt"), "not a class").unwrap();
+
+        let classes = super::parse_directory(&dir).unwrap();
+        assert_eq!(classes, vec![fixture("Hello"), fixture("Flow")]);
+
+        // every failure is reported, with its file
+        let bytes = fs::read(fixture_path("Point")).unwrap();
+        fs::write(dir.join("Broken.class"), &bytes[..bytes.len() / 2]).unwrap();
+        fs::write(nested.join("Empty.class"), []).unwrap();
+        let errors = super::parse_directory(&dir).unwrap_err();
+        let paths: Vec<_> = errors.iter().map(|(p, _)| p.clone()).collect();
+        assert_eq!(
+            paths,
+            vec![dir.join("Broken.class"), nested.join("Empty.class")]
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+        let errors = super::parse_directory(&dir).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, dir);
+    }
+}
@@ -0,0 +1,467 @@
+use std::{
+    collections::HashMap,
+    io::{Cursor, Error},
+};
+
+use crate::{
+    deserialization::{remaining, Deserialize},
+    instruction::{decode_code, encode_code, Instruction},
+    serialization::Serialize,
+    Attribute, AttributeInfo, CodeByte, ConstantPool, Method, TargetInfo, TypeAnnotation,
+};
+
+// Where the instructions of a code array moved to after an edit, by their old offset. The end of
+// the code is included since ranges (exception handlers, local variables) can end there.
+pub struct OffsetMap(pub HashMap<u32, u32>);
+
+impl OffsetMap {
+    pub fn get(&self, old: u32) -> Result<u32, Error> {
+        self.0.get(&old).copied().ok_or_else(|| {
+            Error::other(format!("Offset {} isn't the start of an instruction.", old))
+        })
+    }
+    // for the offsets stored as u16 (the code is never longer than that)
+    fn get_u16(&self, old: u16) -> Result<u16, Error> {
+        Ok(self.get(old as u32)? as u16)
+    }
+}
+
+// Lays instructions out from offset start, returns them with their new offsets and branches
+// along with where each old offset went.
+fn relayout(
+    instructions: Vec<(u32, Instruction)>,
+    start: u32,
+    code_length: u32,
+) -> Result<(Vec<(u32, Instruction)>, OffsetMap), Error> {
+    let mut offsets = HashMap::with_capacity(instructions.len() + 1);
+    let mut offset = start;
+    for (old, instruction) in instructions.iter() {
+        offsets.insert(*old, offset);
+        // switches can change size since their padding depends on where they are
+        offset += instruction.size(offset);
+    }
+    offsets.insert(code_length, offset);
+    let map = OffsetMap(offsets);
+
+    let mut res = Vec::with_capacity(instructions.len());
+    for (old, mut instruction) in instructions {
+        let new = map.get(old)?;
+        instruction.relocate_branches(|branch| {
+            let target = u32::try_from(old as i64 + branch as i64).map_err(|_| {
+                Error::other(format!("Branch at offset {} goes before the code.", old))
+            })?;
+            Ok(map.get(target)? as i32 - new as i32)
+        })?;
+        res.push((new, instruction));
+    }
+    Ok((res, map))
+}
+
+// copies count verification_type_info of a StackMapTable frame, relocating the offsets of the
+// Uninitialized ones (which point at the new instruction that created the value)
+fn relocate_verification_types(
+    input: &mut Cursor<&[u8]>,
+    out: &mut Vec<u8>,
+    count: u16,
+    map: &OffsetMap,
+) -> Result<(), Error> {
+    for _ in 0..count {
+        let tag = u8::deserialize(input)?;
+        tag.serialize(out)?;
+        match tag {
+            0..=6 => (),
+            7 => u16::deserialize(input)?.serialize(out)?,
+            8 => map.get_u16(u16::deserialize(input)?)?.serialize(out)?,
+            _ => {
+                return Err(Error::other(format!(
+                    "Unknown verification type {} in StackMapTable.",
+                    tag
+                )))
+            }
+        }
+    }
+    Ok(())
+}
+
+// Rewrites the frames of a StackMapTable for their new offsets. Frames are placed by the offset
+// from the previous one, which can grow past what fits in the tag of the compact frame kinds, in
+// which case they are written in their extended form.
+fn relocate_stack_map(bytes: &[u8], map: &OffsetMap) -> Result<Vec<u8>, Error> {
+    let mut input = Cursor::new(bytes);
+    let mut out = Vec::with_capacity(bytes.len());
+    let count = u16::deserialize(&mut input)?;
+    count.serialize(&mut out)?;
+
+    // the old and new offsets of the previous frame
+    let mut previous: Option<(u32, u32)> = None;
+    for _ in 0..count {
+        let tag = u8::deserialize(&mut input)?;
+        let delta = match tag {
+            0..=63 => tag as u32,
+            64..=127 => tag as u32 - 64,
+            247..=255 => u16::deserialize(&mut input)? as u32,
+            _ => {
+                return Err(Error::other(format!(
+                    "Reserved frame type {} in StackMapTable.",
+                    tag
+                )))
+            }
+        };
+        let old = previous.map_or(delta, |(old, _)| old + delta + 1);
+        let new = map.get(old)?;
+        let new_delta = match previous {
+            None => new,
+            // removing instructions can bring two frames together
+            Some((_, new_previous)) => new.checked_sub(new_previous + 1).ok_or_else(|| {
+                Error::other(format!(
+                    "Two frames of the StackMapTable end up at {}.",
+                    new
+                ))
+            })?,
+        };
+        previous = Some((old, new));
+
+        match tag {
+            0..=63 if new_delta <= 63 => (new_delta as u8).serialize(&mut out)?,
+            // same_frame_extended
+            0..=63 => {
+                251u8.serialize(&mut out)?;
+                (new_delta as u16).serialize(&mut out)?;
+            }
+            64..=127 if new_delta <= 63 => (64 + new_delta as u8).serialize(&mut out)?,
+            // same_locals_1_stack_item_frame_extended
+            64..=127 => {
+                247u8.serialize(&mut out)?;
+                (new_delta as u16).serialize(&mut out)?;
+            }
+            _ => {
+                tag.serialize(&mut out)?;
+                (new_delta as u16).serialize(&mut out)?;
+            }
+        }
+
+        match tag {
+            64..=127 | 247 => relocate_verification_types(&mut input, &mut out, 1, map)?,
+            252..=254 => relocate_verification_types(&mut input, &mut out, tag as u16 - 251, map)?,
+            255 => {
+                for _ in 0..2 {
+                    // locals then stack
+                    let count = u16::deserialize(&mut input)?;
+                    count.serialize(&mut out)?;
+                    relocate_verification_types(&mut input, &mut out, count, map)?;
+                }
+            }
+            _ => (),
+        }
+    }
+    Ok(out)
+}
+
+// the type annotations with their offsets moved, the targets outside of Code hold none
+fn relocate_type_annotations(
+    annotations: &[TypeAnnotation],
+    map: &OffsetMap,
+) -> Result<Vec<TypeAnnotation>, Error> {
+    let mut res = annotations.to_vec();
+    for a in res.iter_mut() {
+        match &mut a.target_info {
+            TargetInfo::LocalVariable(table) | TargetInfo::ResourceVariable(table) => {
+                for t in table.iter_mut() {
+                    let start = map.get_u16(t.start_pc)?;
+                    let end = map.get(t.start_pc as u32 + t.length as u32)?;
+                    t.start_pc = start;
+                    t.length = end as u16 - start;
+                }
+            }
+            TargetInfo::InstanceOf(offset)
+            | TargetInfo::New(offset)
+            | TargetInfo::ConstructorReference(offset)
+            | TargetInfo::MethodReference(offset)
+            | TargetInfo::Cast { offset, .. }
+            | TargetInfo::ConstructorInvocationTypeArgument { offset, .. }
+            | TargetInfo::MethodInvocationTypeArgument { offset, .. }
+            | TargetInfo::ConstructorReferenceTypeArgument { offset, .. }
+            | TargetInfo::MethodReferenceTypeArgument { offset, .. } => {
+                *offset = map.get_u16(*offset)?
+            }
+            _ => (),
+        }
+    }
+    Ok(res)
+}
+
+// The info of an attribute nested in Code with its offsets moved, None if the attribute doesn't
+// hold any.
+pub fn relocate_code_attribute(
+    a: &Attribute,
+    cp: &ConstantPool,
+    map: &OffsetMap,
+) -> Result<Option<AttributeInfo>, Error> {
+    Ok(match (&a.info, a.name(cp)) {
+        (AttributeInfo::Any(bytes), Some(name)) => {
+            relocate_raw_attribute(name, bytes, map)?.map(AttributeInfo::Any)
+        }
+        (AttributeInfo::RuntimeVisibleTypeAnnotations(annotations), _) => {
+            Some(AttributeInfo::RuntimeVisibleTypeAnnotations(
+                relocate_type_annotations(annotations, map)?,
+            ))
+        }
+        (AttributeInfo::RuntimeInvisibleTypeAnnotations(annotations), _) => {
+            Some(AttributeInfo::RuntimeInvisibleTypeAnnotations(
+                relocate_type_annotations(annotations, map)?,
+            ))
+        }
+        _ => None,
+    })
+}
+
+// Rewrites the offsets in the raw body of an attribute nested in Code, None if the attribute
+// doesn't hold any.
+fn relocate_raw_attribute(
+    name: &str,
+    bytes: &[u8],
+    map: &OffsetMap,
+) -> Result<Option<Vec<u8>>, Error> {
+    let mut input = Cursor::new(bytes);
+    let input = &mut input;
+    let mut out = Vec::with_capacity(bytes.len());
+    match name {
+        "LineNumberTable" => {
+            let count = u16::deserialize(input)?;
+            count.serialize(&mut out)?;
+            for _ in 0..count {
+                map.get_u16(u16::deserialize(input)?)?.serialize(&mut out)?;
+                u16::deserialize(input)?.serialize(&mut out)?;
+            }
+        }
+        "LocalVariableTable" | "LocalVariableTypeTable" => {
+            let count = u16::deserialize(input)?;
+            count.serialize(&mut out)?;
+            for _ in 0..count {
+                let start = u16::deserialize(input)?;
+                let length = u16::deserialize(input)?;
+                let new_start = map.get_u16(start)?;
+                let new_end = map.get(start as u32 + length as u32)?;
+                new_start.serialize(&mut out)?;
+                (new_end as u16 - new_start).serialize(&mut out)?;
+                // name, descriptor (or signature) and slot
+                out.extend(<[u8; 6]>::deserialize(input)?);
+            }
+        }
+        "StackMapTable" => return relocate_stack_map(bytes, map).map(Some),
+        // left raw when they couldn't be parsed
+        "RuntimeVisibleTypeAnnotations" | "RuntimeInvisibleTypeAnnotations" => {
+            return Err(Error::other(format!("Can't move the offsets of {}.", name)))
+        }
+        _ => return Ok(None),
+    }
+    if remaining(input) != 0 {
+        return Err(Error::other(format!("Trailing bytes after {}.", name)));
+    }
+    Ok(Some(out))
+}
+
+impl Method {
+    // Inserts instructions at the start of the method's code. Their offsets are the ones they
+    // will have, so the first one is at 0. The existing code is moved after them, along with
+    // every offset into it: branches, the exception table, and the LineNumberTable,
+    // LocalVariableTable, LocalVariableTypeTable, StackMapTable and type annotation attributes.
+    //
+    // max_stack and max_locals are left as they are (see recompute_maxes), and the StackMapTable
+    // won't have frames for the targets of branches in the inserted instructions. Nothing is
+    // changed on error.
+    pub fn prepend_instructions(
+        &mut self,
+        instructions: &[(u32, Instruction)],
+        cp: &mut ConstantPool,
+    ) -> Result<(), Error> {
+        let attribute = self
+            .attributes
+            .iter_mut()
+            .find(|a| a.name(cp) == Some("Code"))
+            .ok_or_else(|| Error::other("The method has no Code attribute."))?;
+        // edited as a copy, which only replaces the attribute once everything went through
+        let mut edited = attribute.clone();
+        edited.resolve(cp)?;
+        let AttributeInfo::Code {
+            code,
+            exception_table,
+            attributes,
+            ..
+        } = &mut edited.info
+        else {
+            unreachable!("resolved Code attribute")
+        };
+
+        let old = code.iter().map(|b| b.0).collect::<Vec<_>>();
+        let start = encode_code(instructions)?.len() as u32;
+        let (moved, map) = relayout(decode_code(&old)?, start, old.len() as u32)?;
+        let new = encode_code(
+            &instructions
+                .iter()
+                .cloned()
+                .chain(moved)
+                .collect::<Vec<_>>(),
+        )?;
+        if new.len() > u16::MAX as usize {
+            return Err(Error::other(format!(
+                "The code would be {} bytes long, more than the {} allowed.",
+                new.len(),
+                u16::MAX
+            )));
+        }
+
+        *code = new.into_iter().map(CodeByte).collect();
+        for e in exception_table.iter_mut() {
+            e.start = map.get_u16(e.start)?;
+            e.end = map.get_u16(e.end)?;
+            e.handler = map.get_u16(e.handler)?;
+        }
+        for a in attributes.iter_mut() {
+            if let Some(info) = relocate_code_attribute(a, cp, &map)? {
+                a.info = info;
+            }
+        }
+        *attribute = edited;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        instruction::tests::{code, method},
+        AccessFlags, Annotation, ConstantPoolEntry, LocalVarTarget, Utf8Index,
+    };
+    use Instruction::*;
+
+    // a Code nested attribute named name
+    fn nested(cp: &mut ConstantPool, name: &str, info: AttributeInfo) -> Attribute {
+        let name_index = cp.push(ConstantPoolEntry::Utf8(name.into())).unwrap();
+        Attribute {
+            name_index: Utf8Index(name_index),
+            info,
+        }
+    }
+
+    // the attributes nested in the method's Code
+    fn nested_attributes(method: &Method) -> &[Attribute] {
+        match &method.attributes[0].info {
+            AttributeInfo::Code { attributes, .. } => attributes,
+            _ => panic!("not a Code attribute"),
+        }
+    }
+
+    // annotations of type_index on each target
+    fn type_annotations(type_index: Utf8Index, targets: Vec<TargetInfo>) -> AttributeInfo {
+        AttributeInfo::RuntimeVisibleTypeAnnotations(
+            targets
+                .into_iter()
+                .map(|target_info| TypeAnnotation {
+                    target_info,
+                    target_path: vec![],
+                    annotation: Annotation {
+                        type_index,
+                        element_value_pairs: vec![],
+                    },
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn relocated_attributes() {
+        // if (x != 0) nop; return, with a frame at the nop (and an Uninitialized at 4) and at
+        // the return
+        let instructions = vec![Iload0, Ifeq(4), Nop, Return];
+        let (mut m, mut cp) = method("(I)V", AccessFlags::STATIC, instructions, vec![]);
+        let stack_map = vec![0, 2, 64 + 4, 8, 0, 4, 0];
+        let line_numbers = vec![0, 2, 0, 0, 0, 10, 0, 5, 0, 11];
+        // x from 0 to the end of the code, name #1 and descriptor #2 in slot 0
+        let local_variables = vec![0, 1, 0, 0, 0, 6, 0, 1, 0, 2, 0, 0];
+        let annotation = Utf8Index(cp.push(ConstantPoolEntry::Utf8("LA;".into())).unwrap());
+        let local = LocalVarTarget {
+            start_pc: 0,
+            length: 6,
+            index: 0,
+        };
+        let annotations = type_annotations(
+            annotation,
+            vec![TargetInfo::New(4), TargetInfo::LocalVariable(vec![local])],
+        );
+        let attributes = vec![
+            nested(&mut cp, "StackMapTable", AttributeInfo::Any(stack_map)),
+            nested(&mut cp, "LineNumberTable", AttributeInfo::Any(line_numbers)),
+            nested(
+                &mut cp,
+                "LocalVariableTable",
+                AttributeInfo::Any(local_variables),
+            ),
+            nested(&mut cp, "RuntimeVisibleTypeAnnotations", annotations),
+        ];
+        let AttributeInfo::Code { attributes: a, .. } = &mut m.attributes[0].info else {
+            unreachable!()
+        };
+        *a = attributes;
+
+        // 60 nops put the first frame 64 bytes in, past what fits in the tag of its kind
+        let nops = (0..60).map(|i| (i, Nop)).collect::<Vec<_>>();
+        m.prepend_instructions(&nops, &mut cp).unwrap();
+
+        let (code, _) = code(&m);
+        assert_eq!(
+            code[60..],
+            [(60, Iload0), (61, Ifeq(4)), (64, Nop), (65, Return)]
+        );
+        let attributes = nested_attributes(&m);
+        // same_locals_1_stack_item_frame_extended then a same_frame right after it
+        assert_eq!(
+            attributes[0].info,
+            AttributeInfo::Any(vec![0, 2, 247, 0, 64, 8, 0, 64, 0])
+        );
+        assert_eq!(
+            attributes[1].info,
+            AttributeInfo::Any(vec![0, 2, 0, 60, 0, 10, 0, 65, 0, 11])
+        );
+        // the variable now starts after the inserted code, with the same length
+        assert_eq!(
+            attributes[2].info,
+            AttributeInfo::Any(vec![0, 1, 0, 60, 0, 6, 0, 1, 0, 2, 0, 0])
+        );
+        let local = LocalVarTarget {
+            start_pc: 60,
+            length: 6,
+            index: 0,
+        };
+        assert_eq!(
+            attributes[3].info,
+            type_annotations(
+                annotation,
+                vec![TargetInfo::New(64), TargetInfo::LocalVariable(vec![local])]
+            )
+        );
+    }
+
+    #[test]
+    fn unchanged_on_error() {
+        // the Code attribute still raw, as it is until something needs it
+        let (mut m, mut cp) = method("()V", AccessFlags::STATIC, vec![Nop, Return], vec![]);
+        let mut bytes = Vec::new();
+        m.attributes[0].info.serialize(&mut bytes).unwrap();
+        m.attributes[0].info = AttributeInfo::Any(bytes);
+        let before = m.clone();
+
+        let nops = (0..u16::MAX as u32).map(|i| (i, Nop)).collect::<Vec<_>>();
+        assert_eq!(
+            m.prepend_instructions(&nops, &mut cp)
+                .unwrap_err()
+                .to_string(),
+            "The code would be 65537 bytes long, more than the 65535 allowed."
+        );
+        assert_eq!(m, before);
+
+        m.prepend_instructions(&[(0, Nop)], &mut cp).unwrap();
+        assert_eq!(code(&m).0, [(0, Nop), (1, Nop), (2, Return)]);
+    }
+}
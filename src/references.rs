@@ -0,0 +1,442 @@
+use std::{
+    collections::HashSet,
+    io::{Cursor, Error},
+};
+
+use crate::{
+    deserialization::{read_bytes, Deserialize},
+    instruction::InstructionIter,
+    Annotation, Attribute, AttributeInfo, CPIndex, ConstantPool, ConstantPoolEntry, ElementValue,
+    JavaClass,
+};
+
+type Reader<'b> = Cursor<&'b [u8]>;
+
+// Collects the constant pool indices found in a class, along with the ones the entries at those
+// indices point at.
+struct References<'a> {
+    cp: &'a ConstantPool,
+    out: HashSet<CPIndex>,
+}
+
+impl References<'_> {
+    fn constant(&mut self, index: CPIndex) {
+        // 0 in the optional indices, and entries already seen (which also stops loops between
+        // MethodHandle entries)
+        if index == CPIndex::NONE || !self.out.insert(index) {
+            return;
+        }
+        match self.cp.get(index) {
+            Some(
+                ConstantPoolEntry::Class { name_index }
+                | ConstantPoolEntry::Module { name_index }
+                | ConstantPoolEntry::Package { name_index },
+            ) => self.constant(name_index.0),
+            Some(ConstantPoolEntry::String { string_index }) => self.constant(string_index.0),
+            Some(
+                ConstantPoolEntry::FieldRef {
+                    class_index,
+                    name_and_type_index,
+                }
+                | ConstantPoolEntry::MethodRef {
+                    class_index,
+                    name_and_type_index,
+                }
+                | ConstantPoolEntry::InterfaceMethodRef {
+                    class_index,
+                    name_and_type_index,
+                },
+            ) => {
+                self.constant(class_index.0);
+                self.constant(name_and_type_index.0);
+            }
+            Some(ConstantPoolEntry::NameAndType {
+                name_index,
+                descriptor_index,
+            }) => {
+                self.constant(name_index.0);
+                self.constant(descriptor_index.0);
+            }
+            Some(ConstantPoolEntry::MethodHandle {
+                reference_index, ..
+            }) => self.constant(*reference_index),
+            Some(ConstantPoolEntry::MethodType { descriptor_index }) => {
+                self.constant(descriptor_index.0)
+            }
+            // the bootstrap method is found through the BootstrapMethods attribute
+            Some(
+                ConstantPoolEntry::Dynamic {
+                    name_and_type_index,
+                    ..
+                }
+                | ConstantPoolEntry::InvokeDynamic {
+                    name_and_type_index,
+                    ..
+                },
+            ) => self.constant(name_and_type_index.0),
+            _ => (),
+        }
+    }
+    fn attributes(&mut self, attributes: &[Attribute]) {
+        for a in attributes {
+            self.constant(a.name_index.0);
+            self.attribute(a);
+        }
+    }
+    fn attribute(&mut self, a: &Attribute) {
+        match &a.info {
+            AttributeInfo::Any(b) => {
+                let name = a.name(self.cp).unwrap_or_default();
+                // what comes before bytes that don't follow the layout is still collected
+                let _ = self.raw_attribute(name, &mut Cursor::new(b.as_slice()));
+            }
+            AttributeInfo::ConstantValue { index } => self.constant(*index),
+            AttributeInfo::Code {
+                exception_table,
+                attributes,
+                ..
+            } => {
+                for instruction in a.info.instructions().into_iter().flatten() {
+                    let Ok((_, instruction)) = instruction else {
+                        break;
+                    };
+                    self.constant_opt(instruction.references_cp());
+                }
+                for e in exception_table {
+                    self.constant_opt(e.catch_type);
+                }
+                self.attributes(attributes);
+            }
+            AttributeInfo::Exceptions {
+                exception_index_table,
+            } => {
+                for c in exception_index_table {
+                    self.constant(c.0);
+                }
+            }
+            AttributeInfo::SourceDebugExtension(_) => (),
+            AttributeInfo::Record { components } => {
+                for c in components {
+                    self.constant(c.name_index.0);
+                    self.constant(c.descriptor_index.0);
+                    self.attributes(&c.attributes);
+                }
+            }
+            AttributeInfo::AnnotationDefault(value) => self.element_value(value),
+            AttributeInfo::RuntimeVisibleAnnotations(annotations)
+            | AttributeInfo::RuntimeInvisibleAnnotations(annotations) => {
+                annotations.iter().for_each(|a| self.annotation(a))
+            }
+            AttributeInfo::RuntimeVisibleParameterAnnotations(parameters)
+            | AttributeInfo::RuntimeInvisibleParameterAnnotations(parameters) => {
+                parameters.iter().flatten().for_each(|a| self.annotation(a))
+            }
+            AttributeInfo::RuntimeVisibleTypeAnnotations(annotations)
+            | AttributeInfo::RuntimeInvisibleTypeAnnotations(annotations) => annotations
+                .iter()
+                .for_each(|a| self.annotation(&a.annotation)),
+        }
+    }
+    fn constant_opt(&mut self, index: Option<CPIndex>) {
+        if let Some(index) = index {
+            self.constant(index);
+        }
+    }
+    fn annotation(&mut self, annotation: &Annotation) {
+        self.constant(annotation.type_index.0);
+        for pair in annotation.element_value_pairs.iter() {
+            self.constant(pair.element_name_index.0);
+            self.element_value(&pair.value);
+        }
+    }
+    fn element_value(&mut self, value: &ElementValue) {
+        match value {
+            ElementValue::Byte(index)
+            | ElementValue::Char(index)
+            | ElementValue::Double(index)
+            | ElementValue::Float(index)
+            | ElementValue::Int(index)
+            | ElementValue::Long(index)
+            | ElementValue::Short(index)
+            | ElementValue::Boolean(index) => self.constant(*index),
+            ElementValue::String(index) | ElementValue::Class(index) => self.constant(index.0),
+            ElementValue::Enum {
+                type_name_index,
+                const_name_index,
+            } => {
+                self.constant(type_name_index.0);
+                self.constant(const_name_index.0);
+            }
+            ElementValue::Annotation(annotation) => self.annotation(annotation),
+            ElementValue::Array(values) => values.iter().for_each(|v| self.element_value(v)),
+        }
+    }
+    // a constant pool index from the raw bytes
+    fn index(&mut self, r: &mut Reader) -> Result<(), Error> {
+        let index = u16::deserialize(r)?;
+        self.constant(CPIndex(index));
+        Ok(())
+    }
+    // a u16 count of items, each made of skip_before bytes, indices indices then skip_after bytes
+    fn list(
+        &mut self,
+        r: &mut Reader,
+        skip_before: u64,
+        indices: usize,
+        skip_after: u64,
+    ) -> Result<(), Error> {
+        let count = u16::deserialize(r)?;
+        for _ in 0..count {
+            r.set_position(r.position() + skip_before);
+            for _ in 0..indices {
+                self.index(r)?;
+            }
+            r.set_position(r.position() + skip_after);
+        }
+        Ok(())
+    }
+    // the attributes that aren't modeled (or couldn't be parsed) but hold indices, see
+    // structural_hash for their layouts
+    fn raw_attribute(&mut self, name: &str, r: &mut Reader) -> Result<(), Error> {
+        match name {
+            "SourceFile" | "Signature" | "NestHost" | "ModuleMainClass" => self.index(r)?,
+            "NestMembers" | "PermittedSubclasses" | "ModulePackages" => self.list(r, 0, 1, 0)?,
+            "EnclosingMethod" => {
+                self.index(r)?;
+                self.index(r)?;
+            }
+            "InnerClasses" => self.list(r, 0, 3, 2)?,
+            "LocalVariableTable" | "LocalVariableTypeTable" => self.list(r, 4, 2, 2)?,
+            "MethodParameters" => {
+                let count = u8::deserialize(r)?;
+                for _ in 0..count {
+                    self.index(r)?;
+                    u16::deserialize(r)?;
+                }
+            }
+            "BootstrapMethods" => {
+                let count = u16::deserialize(r)?;
+                for _ in 0..count {
+                    self.index(r)?;
+                    self.list(r, 0, 1, 0)?;
+                }
+            }
+            "StackMapTable" => {
+                let count = u16::deserialize(r)?;
+                for _ in 0..count {
+                    self.stack_map_frame(r)?;
+                }
+            }
+            // left raw when it couldn't be parsed
+            "Code" => {
+                r.set_position(r.position() + 4);
+                let length = u32::deserialize(r)?;
+                let code = read_bytes(r, length as usize)?;
+                for instruction in InstructionIter::new(&code) {
+                    let Ok((_, instruction)) = instruction else {
+                        break;
+                    };
+                    self.constant_opt(instruction.references_cp());
+                }
+                self.list(r, 6, 1, 0)?;
+                let count = u16::deserialize(r)?;
+                for _ in 0..count {
+                    let index = u16::deserialize(r)?;
+                    self.constant(CPIndex(index));
+                    let length = u32::deserialize(r)?;
+                    let body = read_bytes(r, length as usize)?;
+                    let name = self.cp.get(CPIndex(index)).and_then(|e| match e {
+                        ConstantPoolEntry::Utf8(s) => Some(s.as_str()),
+                        _ => None,
+                    });
+                    let _ = self.raw_attribute(name.unwrap_or_default(), &mut Cursor::new(&body));
+                }
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+    fn stack_map_frame(&mut self, r: &mut Reader) -> Result<(), Error> {
+        let kind = u8::deserialize(r)?;
+        match kind {
+            0..=63 => (),
+            64..=127 => self.verification_type(r)?,
+            247 => {
+                u16::deserialize(r)?;
+                self.verification_type(r)?;
+            }
+            248..=251 => {
+                u16::deserialize(r)?;
+            }
+            252..=254 => {
+                u16::deserialize(r)?;
+                for _ in 251..kind {
+                    self.verification_type(r)?;
+                }
+            }
+            255 => {
+                u16::deserialize(r)?;
+                for _ in 0..2 {
+                    // locals then stack
+                    let count = u16::deserialize(r)?;
+                    for _ in 0..count {
+                        self.verification_type(r)?;
+                    }
+                }
+            }
+            _ => return Err(Error::other(format!("Unknown frame type {}.", kind))),
+        }
+        Ok(())
+    }
+    fn verification_type(&mut self, r: &mut Reader) -> Result<(), Error> {
+        match u8::deserialize(r)? {
+            0..=6 => (),
+            // Object, of a class
+            7 => self.index(r)?,
+            // Uninitialized, the offset of the new
+            8 => {
+                u16::deserialize(r)?;
+            }
+            tag => return Err(Error::other(format!("Unknown verification type {}.", tag))),
+        }
+        Ok(())
+    }
+}
+
+impl JavaClass {
+    // Every constant pool index used by the class, directly or through another entry. The
+    // entries missing from it aren't needed, except for the indices held by attributes whose
+    // layout isn't known (Module, type annotations or attributes javd doesn't know of).
+    pub fn all_cp_references(&self) -> HashSet<CPIndex> {
+        let mut r = References {
+            cp: &self.constant_pool,
+            out: HashSet::new(),
+        };
+        r.constant(self.this_class.0);
+        r.constant_opt(self.super_class.map(|s| s.0));
+        for i in self.interfaces.iter() {
+            r.constant(i.0);
+        }
+        for f in self.fields.iter() {
+            r.constant(f.name_index.0);
+            r.constant(f.descriptor_index.0);
+            r.attributes(&f.attributes);
+        }
+        for m in self.methods.iter() {
+            r.constant(m.name_index.0);
+            r.constant(m.descriptor_index.0);
+            r.attributes(&m.attributes);
+        }
+        r.attributes(&self.attributes);
+        r.out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tests::fixture, AccessFlags, Field, ReferenceKind, Utf8Index};
+
+    fn utf8(class: &mut JavaClass, s: &str) -> CPIndex {
+        let cp = &mut class.constant_pool;
+        cp.push(ConstantPoolEntry::Utf8(s.into())).unwrap()
+    }
+
+    #[test]
+    fn fixtures_fully_referenced() {
+        // javac only writes the entries a class uses
+        for name in [
+            "Annotated",
+            "Constants",
+            "Exceptions",
+            "Flow",
+            "Point",
+            "TypeAnnotated",
+        ] {
+            let class = fixture(name);
+            let refs = class.all_cp_references();
+            let indices: HashSet<_> = class.constant_pool.iter().map(|(i, _)| i).collect();
+            assert_eq!(refs, indices, "{}", name);
+        }
+    }
+
+    #[test]
+    fn through_entries_and_attributes() {
+        let mut class = fixture("Hello");
+        let before = class.all_cp_references();
+
+        // not used by anything
+        let unused = utf8(&mut class, "Unused");
+        let unused_class = class
+            .constant_pool
+            .push(ConstantPoolEntry::Class {
+                name_index: Utf8Index(unused),
+            })
+            .unwrap();
+
+        // a field with a ConstantValue, reaching a Utf8 through a String, and a Signature left raw
+        let name = utf8(&mut class, "f");
+        let descriptor = utf8(&mut class, "Ljava/lang/String;");
+        let value = utf8(&mut class, "value");
+        let string = class
+            .constant_pool
+            .push(ConstantPoolEntry::String {
+                string_index: Utf8Index(value),
+            })
+            .unwrap();
+        let constant_value = utf8(&mut class, "ConstantValue");
+        let signature = utf8(&mut class, "Signature");
+        let generic = utf8(&mut class, "TT;");
+        class.fields.push(Field {
+            access_flags: AccessFlags::STATIC | AccessFlags::FINAL,
+            name_index: Utf8Index(name),
+            descriptor_index: Utf8Index(descriptor),
+            attributes: vec![
+                Attribute {
+                    name_index: Utf8Index(constant_value),
+                    info: AttributeInfo::ConstantValue { index: string },
+                },
+                Attribute {
+                    name_index: Utf8Index(signature),
+                    info: AttributeInfo::Any(generic.0.to_be_bytes().to_vec()),
+                },
+            ],
+        });
+
+        let refs = class.all_cp_references();
+        assert!(before.is_subset(&refs));
+        for index in [
+            name,
+            descriptor,
+            value,
+            string,
+            constant_value,
+            signature,
+            generic,
+        ] {
+            assert!(refs.contains(&index), "{:?}", index);
+        }
+        assert!(!refs.contains(&unused));
+        assert!(!refs.contains(&unused_class));
+        assert_eq!(refs.len(), before.len() + 7);
+    }
+
+    #[test]
+    fn handle_loop() {
+        let mut class = fixture("Hello");
+        let handle = CPIndex(class.constant_pool.size());
+        // a MethodHandle pointing at itself, which isn't valid but mustn't loop, found through an
+        // index of an attribute
+        let entry = ConstantPoolEntry::MethodHandle {
+            reference_kind: ReferenceKind::InvokeStatic,
+            reference_index: handle,
+        };
+        class.constant_pool.push(entry).unwrap();
+        let constant_value = utf8(&mut class, "ConstantValue");
+        class.methods[0].attributes.push(Attribute {
+            name_index: Utf8Index(constant_value),
+            info: AttributeInfo::ConstantValue { index: handle },
+        });
+        assert!(class.all_cp_references().contains(&handle));
+    }
+}
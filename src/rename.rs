@@ -0,0 +1,495 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::Error,
+};
+
+use crate::{
+    descriptor::class_names, ConstantPool, ConstantPoolEntry, JavaClass, NameAndTypeIndex,
+    Utf8Index,
+};
+
+// replaces the class name from with to in a descriptor or signature, returns the new string and
+// the number of names replaced, or None if there is nothing to replace (or s doesn't parse)
+fn rename_in_signature(s: &str, from: &str, to: &str) -> Option<(String, usize)> {
+    let mut out = String::with_capacity(s.len());
+    let mut last = 0;
+    let mut count = 0;
+    for name in class_names(s)? {
+        if &s[name.clone()] == from {
+            out.push_str(&s[last..name.start]);
+            out.push_str(to);
+            last = name.end;
+            count += 1;
+        }
+    }
+    out.push_str(&s[last..]);
+    (count > 0).then_some((out, count))
+}
+
+// the index of a Utf8 entry holding s, which is added if there is none
+pub fn find_or_push_utf8(cp: &mut ConstantPool, s: &str) -> Result<Utf8Index, Error> {
+    let found = cp.utf8_values().find(|(_, u)| *u == s);
+    Ok(Utf8Index(match found {
+        Some((index, _)) => index,
+        None => cp.push(ConstantPoolEntry::Utf8(s.into()))?,
+    }))
+}
+
+// same for a NameAndType entry
+pub fn find_or_push_name_and_type(
+    cp: &mut ConstantPool,
+    name: Utf8Index,
+    descriptor: Utf8Index,
+) -> Result<NameAndTypeIndex, Error> {
+    let found = cp.iter().find(|(_, e)| {
+        matches!(e, ConstantPoolEntry::NameAndType { name_index, descriptor_index }
+            if *name_index == name && *descriptor_index == descriptor)
+    });
+    Ok(NameAndTypeIndex(match found {
+        Some((index, _)) => index,
+        None => cp.push(ConstantPoolEntry::NameAndType {
+            name_index: name,
+            descriptor_index: descriptor,
+        })?,
+    }))
+}
+
+impl JavaClass {
+    // Renames the class from to to (both internal names, like com/old/Name) in the Class entries
+    // and in the descriptors and signatures (see descriptor_indices). Returns the number of names
+    // replaced.
+    //
+    // The Utf8 entries can be shared with string constants and member names, so the renamed ones
+    // are added (or existing ones reused) and only the Class entries and the descriptors are
+    // pointed at them. Errors, leaving the class as it was, when the pool has no room for them.
+    pub fn rename_class(&mut self, from: &str, to: &str) -> Result<usize, Error> {
+        let class_names = self
+            .constant_pool
+            .class_entries()
+            .map(|(_, name_index)| name_index)
+            .collect::<BTreeSet<_>>();
+        let descriptors = self.descriptor_indices();
+
+        // the entries to replace, with their new value and the number of names replaced in them
+        let mut renamed = Vec::new();
+        for index in class_names
+            .iter()
+            .chain(descriptors.difference(&class_names))
+        {
+            let Some(ConstantPoolEntry::Utf8(s)) = self.constant_pool.get(index.0) else {
+                continue;
+            };
+            // unpaired surrogates can't be in a name, and rewriting the string would lose them
+            if s.is_lossy() {
+                continue;
+            }
+            // array classes are named by their descriptor
+            let new = if class_names.contains(index) && !s.starts_with('[') {
+                (s.as_str() == from).then(|| (to.to_string(), 1))
+            } else {
+                rename_in_signature(s, from, to)
+            };
+            if let Some((new, n)) = new {
+                renamed.push((*index, new, n));
+            }
+        }
+        if self.constant_pool.size() as usize + renamed.len() > u16::MAX as usize {
+            return Err(Error::other(
+                "The constant pool has no room for the renamed names.",
+            ));
+        }
+
+        let mut map = BTreeMap::new();
+        let mut count = 0;
+        for (index, new, n) in renamed {
+            // there is room for these, checked above
+            let new = find_or_push_utf8(&mut self.constant_pool, &new).expect("checked room");
+            map.insert(index, new);
+            count += n;
+        }
+        for index in self.constant_pool.indices_sorted() {
+            if let Some(ConstantPoolEntry::Class { name_index }) = self.constant_pool.get_mut(index)
+            {
+                *name_index = map.get(name_index).copied().unwrap_or(*name_index);
+            }
+        }
+        self.remap_descriptors(&map);
+        Ok(count)
+    }
+    // Renames the class itself to new_name, along with every reference to it (see rename_class).
+    // Returns the number of names replaced.
+    pub fn rename_this_class(&mut self, new_name: &str) -> Result<usize, Error> {
+        let old_name = self.this_class.name(&self.constant_pool)?.to_string();
+        self.rename_class(&old_name, new_name)
+    }
+    // Renames the method old_name with the descriptor old_descriptor of the class class_name, in
+    // the class' methods when it is this one and in the Methodref and InterfaceMethodref entries.
+    // Returns whether anything was renamed, nothing is changed when the pool has no room for the
+    // new entries.
+    //
+    // The name and NameAndType entries can be shared with other members, so new ones are added
+    // (or existing ones reused) instead of changing them in place.
+    pub fn rename_method(
+        &mut self,
+        class_name: &str,
+        old_name: &str,
+        old_descriptor: &str,
+        new_name: &str,
+    ) -> bool {
+        let cp = &self.constant_pool;
+        let matches = |name: Utf8Index, descriptor: Utf8Index| {
+            name.resolve(cp).ok() == Some(old_name)
+                && descriptor.resolve(cp).ok() == Some(old_descriptor)
+        };
+
+        let mut methods = Vec::new();
+        if self.this_class.name(cp).ok() == Some(class_name) {
+            for (i, m) in self.methods.iter().enumerate() {
+                if matches(m.name_index, m.descriptor_index) {
+                    methods.push(i);
+                }
+            }
+        }
+        // the refs along with the descriptor of their NameAndType
+        let mut refs = Vec::new();
+        for (index, e) in cp.iter() {
+            if let ConstantPoolEntry::MethodRef {
+                class_index,
+                name_and_type_index,
+            }
+            | ConstantPoolEntry::InterfaceMethodRef {
+                class_index,
+                name_and_type_index,
+            } = e
+            {
+                if class_index.name(cp).ok() != Some(class_name) {
+                    continue;
+                }
+                if let Ok((name, descriptor)) = name_and_type_index.resolve(cp) {
+                    if matches(name, descriptor) {
+                        refs.push((index, descriptor));
+                    }
+                }
+            }
+        }
+        if methods.is_empty() && refs.is_empty() {
+            return false;
+        }
+        // at most a Utf8 and a NameAndType for each descriptor entry
+        let descriptors = refs.iter().map(|(_, d)| *d).collect::<BTreeSet<_>>();
+        if cp.size() as usize + 1 + descriptors.len() > u16::MAX as usize {
+            return false;
+        }
+
+        let cp = &mut self.constant_pool;
+        // there is room for these, checked above
+        let name_index = find_or_push_utf8(cp, new_name).expect("checked room");
+        for i in methods {
+            self.methods[i].name_index = name_index;
+        }
+        for (index, descriptor_index) in refs {
+            let new =
+                find_or_push_name_and_type(cp, name_index, descriptor_index).expect("checked room");
+            if let Some(
+                ConstantPoolEntry::MethodRef {
+                    name_and_type_index,
+                    ..
+                }
+                | ConstantPoolEntry::InterfaceMethodRef {
+                    name_and_type_index,
+                    ..
+                },
+            ) = cp.get_mut(index)
+            {
+                *name_and_type_index = new;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        modified_utf8::JavaString, tests::fixture, visitor::InvocationCounter, AccessFlags,
+        CPIndex, ClassIndex, Field, Method,
+    };
+
+    fn utf8(class: &mut JavaClass, s: &str) -> Utf8Index {
+        find_or_push_utf8(&mut class.constant_pool, s).unwrap()
+    }
+    fn class_entry(class: &mut JavaClass, name: &str) -> CPIndex {
+        let name_index = utf8(class, name);
+        let entry = ConstantPoolEntry::Class { name_index };
+        class.constant_pool.push(entry).unwrap()
+    }
+    fn utf8_at(class: &JavaClass, index: Utf8Index) -> &str {
+        index.resolve(&class.constant_pool).unwrap()
+    }
+    // the class names and descriptors holding part, the Utf8 entries rename_class moved them
+    // away from are left in the pool
+    fn containing(class: &JavaClass, part: &str) -> Vec<String> {
+        let cp = &class.constant_pool;
+        let names = cp.class_entries().map(|(_, name_index)| name_index);
+        let mut indices = class.descriptor_indices();
+        indices.extend(names);
+        indices
+            .iter()
+            .filter_map(|i| i.resolve(cp).ok())
+            .filter(|s| s.contains(part))
+            .map(|s| s.to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn rename_class_whole_names() {
+        let mut class = fixture("Hello");
+        let name = class_entry(&mut class, "com/old/Name");
+        let longer = class_entry(&mut class, "com/old/NameX");
+        let array = class_entry(&mut class, "[[Lcom/old/Name;");
+        let (f, g) = (utf8(&mut class, "f"), utf8(&mut class, "g"));
+        let field_descriptor = utf8(&mut class, "Lcom/old/Name;");
+        let method_descriptor = utf8(
+            &mut class,
+            "(Lcom/old/Name;[Lcom/old/NameX;)[Lcom/old/Name;",
+        );
+        class.fields.push(Field {
+            access_flags: AccessFlags::PRIVATE,
+            name_index: f,
+            descriptor_index: field_descriptor,
+            attributes: vec![],
+        });
+        class.methods.push(Method {
+            access_flags: AccessFlags::ABSTRACT,
+            name_index: g,
+            descriptor_index: method_descriptor,
+            attributes: vec![],
+        });
+
+        assert_eq!(
+            class.rename_class("com/old/Name", "com/new/Name").unwrap(),
+            5
+        );
+        let cp = &class.constant_pool;
+        let class_name = |index: CPIndex| ClassIndex(index).name(cp).unwrap();
+        assert_eq!(class_name(name), "com/new/Name");
+        assert_eq!(class_name(longer), "com/old/NameX");
+        assert_eq!(class_name(array), "[[Lcom/new/Name;");
+        let field = class.fields.last().unwrap();
+        assert_eq!(utf8_at(&class, field.descriptor_index), "Lcom/new/Name;");
+        let method = class.methods.last().unwrap();
+        assert_eq!(
+            utf8_at(&class, method.descriptor_index),
+            "(Lcom/new/Name;[Lcom/old/NameX;)[Lcom/new/Name;"
+        );
+        // the old entries are still there, unused
+        assert_eq!(utf8_at(&class, field_descriptor), "Lcom/old/Name;");
+        // nothing left to rename
+        assert_eq!(
+            class.rename_class("com/old/Name", "com/new/Name").unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn rename_class_signatures() {
+        let mut class = fixture("Point");
+        // in the descriptors of fields, methods and NameAndType entries, and in the Signature
+        // attributes of the record components and their accessors
+        assert_eq!(
+            class.rename_class("java/lang/String", "my/Text").unwrap(),
+            10
+        );
+        assert!(containing(&class, "java/lang/String").is_empty());
+        let renamed = containing(&class, "my/Text");
+        assert!(renamed.contains(&"Ljava/util/List<Lmy/Text;>;".to_owned()));
+        assert!(renamed.contains(&"(ILmy/Text;Ljava/util/List<Lmy/Text;>;)V".to_owned()));
+    }
+
+    #[test]
+    fn rename_this_class() {
+        let mut class = fixture("Point");
+        let other = class_entry(&mut class, "PointX");
+        // the class and the descriptors of the record's bootstrap methods
+        assert_eq!(class.rename_this_class("geo/Point").unwrap(), 4);
+        let cp = &class.constant_pool;
+        assert_eq!(class.this_class.name(cp).unwrap(), "geo/Point");
+        assert_eq!(ClassIndex(other).name(cp).unwrap(), "PointX");
+        let left = containing(&class, "Point");
+        assert!(left
+            .iter()
+            .all(|s| s.contains("geo/Point") || s == "PointX"));
+
+        // this_class not pointing at a Class entry
+        let mut class = fixture("Point");
+        class.this_class = ClassIndex(CPIndex(1));
+        let before = class.clone();
+        assert!(class.rename_this_class("geo/Point").is_err());
+        assert_eq!(class, before);
+    }
+
+    #[test]
+    fn rename_class_shared_utf8() {
+        // a string literal sharing the Utf8 of the class name
+        let mut class = fixture("Hello");
+        let Some(&ConstantPoolEntry::Class { name_index }) =
+            class.constant_pool.get(class.this_class.0)
+        else {
+            panic!("not a Class");
+        };
+        let string = ConstantPoolEntry::String {
+            string_index: name_index,
+        };
+        let string = class.constant_pool.push(string).unwrap();
+        assert_eq!(class.rename_this_class("greet/Hello").unwrap(), 1);
+        let cp = &class.constant_pool;
+        assert_eq!(class.this_class.name(cp).unwrap(), "greet/Hello");
+        let Some(ConstantPoolEntry::String { string_index }) = cp.get(string) else {
+            panic!("not a String");
+        };
+        assert_eq!(string_index.resolve(cp).unwrap(), "Hello");
+    }
+
+    #[test]
+    fn rename_class_lossy() {
+        // "a" and an unpaired surrogate, shown as a\u{FFFD}
+        let mut class = fixture("Hello");
+        let lossy = JavaString::from_units(vec![0x61, 0xd800]);
+        let name_index = class
+            .constant_pool
+            .push(ConstantPoolEntry::Utf8(lossy.clone()))
+            .unwrap();
+        let entry = ConstantPoolEntry::Class {
+            name_index: Utf8Index(name_index),
+        };
+        class.constant_pool.push(entry).unwrap();
+        let before = class.clone();
+        assert_eq!(class.rename_class("a\u{FFFD}", "b").unwrap(), 0);
+        assert_eq!(class, before);
+    }
+
+    #[test]
+    fn rename_class_no_room() {
+        let mut class = fixture("Hello");
+        while class.constant_pool.size() < u16::MAX {
+            class
+                .constant_pool
+                .push(ConstantPoolEntry::Integer(0))
+                .unwrap();
+        }
+        let before = class.clone();
+        assert!(class.rename_this_class("greet/Hello").is_err());
+        assert_eq!(class, before);
+        // nothing to add
+        assert_eq!(class.rename_class("Missing", "greet/Missing").unwrap(), 0);
+    }
+
+    // the calls made by the class, see InvocationCounter
+    fn calls(class: &JavaClass) -> Vec<(String, usize)> {
+        let mut counter = InvocationCounter::default();
+        class.accept(&mut counter);
+        counter
+            .counts
+            .into_iter()
+            .filter(|(m, _)| m.starts_with("Calls."))
+            .collect()
+    }
+
+    #[test]
+    fn rename_method() {
+        let mut class = fixture("Calls");
+        // a ref to a method of another class sharing the NameAndType of Calls.fact
+        let fact = utf8(&mut class, "fact");
+        let name_and_type = class
+            .constant_pool
+            .iter()
+            .find_map(|(index, e)| match e {
+                ConstantPoolEntry::NameAndType { name_index, .. } if *name_index == fact => {
+                    Some(NameAndTypeIndex(index))
+                }
+                _ => None,
+            })
+            .unwrap();
+        let other = ClassIndex(class_entry(&mut class, "Other"));
+        let other_ref = class
+            .constant_pool
+            .push(ConstantPoolEntry::MethodRef {
+                class_index: other,
+                name_and_type_index: name_and_type,
+            })
+            .unwrap();
+
+        assert!(class.rename_method("Calls", "fact", "(I)I", "factorial"));
+        let cp = &class.constant_pool;
+        let names = class
+            .methods
+            .iter()
+            .map(|m| m.name_index.resolve(cp).unwrap());
+        assert!(names.clone().any(|n| n == "factorial"));
+        assert!(!names.clone().any(|n| n == "fact"));
+        // both calls, in fact and in the lambda
+        assert_eq!(
+            calls(&class),
+            [
+                ("Calls.factorial:(I)I".to_owned(), 2),
+                ("Calls.helper:()I".to_owned(), 1),
+                ("Calls.spin:(I)I".to_owned(), 1),
+            ]
+        );
+        let Some(ConstantPoolEntry::MethodRef {
+            name_and_type_index,
+            ..
+        }) = cp.get(other_ref)
+        else {
+            panic!("not a MethodRef");
+        };
+        let (name, _) = name_and_type_index.resolve(cp).unwrap();
+        assert_eq!(name.resolve(cp).unwrap(), "fact");
+    }
+
+    #[test]
+    fn rename_method_nothing() {
+        let mut class = fixture("Calls");
+        let before = class.clone();
+        // another descriptor, another class
+        assert!(!class.rename_method("Calls", "fact", "(J)J", "factorial"));
+        assert!(!class.rename_method("Other", "fact", "(I)I", "factorial"));
+        assert_eq!(class, before);
+
+        // no room left for the new entries
+        while class.constant_pool.size() < u16::MAX - 1 {
+            let n = class.constant_pool.size() as i32;
+            class
+                .constant_pool
+                .push(ConstantPoolEntry::Integer(n))
+                .unwrap();
+        }
+        let before = class.clone();
+        assert!(!class.rename_method("Calls", "fact", "(I)I", "factorial"));
+        assert_eq!(class, before);
+    }
+
+    #[test]
+    fn find_or_push() {
+        let mut cp = ConstantPool::new();
+        let a = find_or_push_utf8(&mut cp, "a").unwrap();
+        let b = find_or_push_utf8(&mut cp, "b").unwrap();
+        assert_ne!(a, b);
+        assert_eq!(find_or_push_utf8(&mut cp, "a").unwrap(), a);
+        assert_eq!(cp.size(), 3);
+
+        let ab = find_or_push_name_and_type(&mut cp, a, b).unwrap();
+        let ba = find_or_push_name_and_type(&mut cp, b, a).unwrap();
+        assert_ne!(ab, ba);
+        assert_eq!(find_or_push_name_and_type(&mut cp, a, b).unwrap(), ab);
+        assert_eq!(cp.size(), 5);
+
+        while cp.size() < u16::MAX {
+            cp.push(ConstantPoolEntry::Integer(0)).unwrap();
+        }
+        assert_eq!(find_or_push_utf8(&mut cp, "b").unwrap(), b);
+        assert!(find_or_push_utf8(&mut cp, "c").is_err());
+        assert!(find_or_push_name_and_type(&mut cp, a, a).is_err());
+    }
+}
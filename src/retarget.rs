@@ -0,0 +1,316 @@
+use std::{collections::BTreeSet, io::Error};
+
+use crate::{instruction::Instruction, Attribute, AttributeInfo, ConstantPool, JavaClass};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ClassVersion {
+    pub major: u16,
+    pub minor: u16,
+}
+
+// the minor version of classes compiled with --enable-preview, from major version 56 (Java 12)
+pub const PREVIEW_MINOR: u16 = 0xFFFF;
+
+impl ClassVersion {
+    // whether the class uses the preview features of its Java version
+    pub fn is_preview(&self) -> bool {
+        self.minor == PREVIEW_MINOR
+    }
+}
+
+// The attributes that didn't exist in every version, with the first major version they are read
+// in, and whether they can be dropped for older versions without changing what the class does.
+// The JVM ignores the attributes it doesn't know of, so the ones that are only metadata (for
+// debuggers, reflection or the compiler) can go, but classes relying on the others would break.
+const ATTRIBUTES: &[(&str, u16, bool)] = &[
+    ("EnclosingMethod", 49, true),
+    ("Signature", 49, true),
+    ("SourceDebugExtension", 49, true),
+    ("LocalVariableTypeTable", 49, true),
+    ("RuntimeVisibleAnnotations", 49, true),
+    ("RuntimeInvisibleAnnotations", 49, true),
+    ("RuntimeVisibleParameterAnnotations", 49, true),
+    ("RuntimeInvisibleParameterAnnotations", 49, true),
+    ("AnnotationDefault", 49, true),
+    // older versions use the type inferring verifier
+    ("StackMapTable", 50, true),
+    ("BootstrapMethods", 51, false),
+    ("RuntimeVisibleTypeAnnotations", 52, true),
+    ("RuntimeInvisibleTypeAnnotations", 52, true),
+    ("MethodParameters", 52, true),
+    ("Module", 53, false),
+    ("ModulePackages", 53, false),
+    ("ModuleMainClass", 53, false),
+    // private members are only shared between nestmates through these
+    ("NestHost", 55, false),
+    ("NestMembers", 55, false),
+    ("Record", 60, true),
+    ("PermittedSubclasses", 61, true),
+];
+
+// the first major version the constants of this kind are allowed in
+fn constant_since(kind: &str) -> Option<u16> {
+    match kind {
+        "MethodHandle" | "MethodType" | "InvokeDynamic" => Some(51),
+        "Module" | "Package" => Some(53),
+        "Dynamic" => Some(55),
+        _ => None,
+    }
+}
+
+// the names of the attributes in the list, and in the lists nested in it
+fn attribute_names<'a>(
+    attributes: &[Attribute],
+    cp: &'a ConstantPool,
+    out: &mut BTreeSet<&'a str>,
+) {
+    for a in attributes {
+        out.extend(a.name(cp));
+        match &a.info {
+            AttributeInfo::Code { attributes, .. } => attribute_names(attributes, cp, out),
+            AttributeInfo::Record { components } => {
+                for c in components {
+                    attribute_names(&c.attributes, cp, out);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+impl JavaClass {
+    // the names of the attributes found anywhere in the class
+    pub fn attribute_names(&self) -> BTreeSet<&str> {
+        let cp = &self.constant_pool;
+        let mut names = BTreeSet::new();
+        attribute_names(&self.attributes, cp, &mut names);
+        for f in self.fields.iter() {
+            attribute_names(&f.attributes, cp, &mut names);
+        }
+        for m in self.methods.iter() {
+            attribute_names(&m.attributes, cp, &mut names);
+        }
+        names
+    }
+    // Sets the version of the class, dropping the attributes that don't exist yet in it and only
+    // hold metadata. Errors, without changing anything, when the class needs something the
+    // version doesn't have (invokedynamic, nestmates, modules...), or when moving to 51 or later
+    // a class that uses jsr or has no StackMapTable where one is required.
+    pub fn retarget(&mut self, version: ClassVersion) -> Result<(), Error> {
+        let cp = &self.constant_pool;
+        let names = self.attribute_names();
+
+        let mut strip = Vec::new();
+        for &(name, since, optional) in ATTRIBUTES {
+            if version.major >= since || !names.contains(name) {
+                continue;
+            }
+            if !optional {
+                return Err(Error::other(format!(
+                    "The class has a {} attribute, which needs version {} or later.",
+                    name, since
+                )));
+            }
+            strip.push(name);
+        }
+        for (i, e) in cp.iter() {
+            match constant_since(e.kind()) {
+                Some(since) if version.major < since => {
+                    return Err(Error::other(format!(
+                        "Constant #{} is a {}, which needs version {} or later.",
+                        i.0,
+                        e.kind(),
+                        since
+                    )))
+                }
+                _ => (),
+            }
+        }
+
+        // classes already at 51 or later were checked by the compiler
+        if version.major >= 51 && self.major_version < 51 {
+            for m in self.methods.iter() {
+                let Some(a) = m.attributes.iter().find(|a| a.name(cp) == Some("Code")) else {
+                    continue;
+                };
+                let AttributeInfo::Code {
+                    exception_table,
+                    attributes,
+                    ..
+                } = &a.info
+                else {
+                    return Err(Error::other("A Code attribute couldn't be parsed."));
+                };
+                let name = m.name_index.resolve(cp)?;
+                // frames are needed at every branch target and exception handler
+                let mut branches = !exception_table.is_empty();
+                for instruction in a.info.instructions().into_iter().flatten() {
+                    let (_, instruction) = instruction?;
+                    if matches!(
+                        instruction,
+                        Instruction::Jsr(_)
+                            | Instruction::JsrW(_)
+                            | Instruction::Ret(_)
+                            | Instruction::Wide { opcode: 0xa9, .. }
+                    ) {
+                        return Err(Error::other(format!(
+                            "{} uses {}, which isn't allowed from version 51.",
+                            name,
+                            instruction.mnemonic()
+                        )));
+                    }
+                    branches |= !instruction.branches().is_empty();
+                }
+                if branches
+                    && !attributes
+                        .iter()
+                        .any(|a| a.name(cp) == Some("StackMapTable"))
+                {
+                    return Err(Error::other(format!(
+                        "{} needs a StackMapTable from version 51.",
+                        name
+                    )));
+                }
+            }
+        }
+
+        self.strip_attributes(&strip);
+        self.major_version = version.major;
+        self.minor_version = version.minor;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tests::fixture, CPIndex, ConstantPoolEntry, Utf8Index};
+
+    const V49: ClassVersion = ClassVersion {
+        major: 49,
+        minor: 0,
+    };
+    const V51: ClassVersion = ClassVersion {
+        major: 51,
+        minor: 0,
+    };
+
+    // retargets a copy of class, checking that an error left it unchanged
+    fn retarget(class: &JavaClass, version: ClassVersion) -> Result<JavaClass, String> {
+        let mut res = class.clone();
+        match res.retarget(version) {
+            Ok(()) => Ok(res),
+            Err(e) => {
+                assert_eq!(&res, class);
+                Err(e.to_string())
+            }
+        }
+    }
+
+    #[test]
+    fn optional_attributes() {
+        let flow = fixture("Flow");
+        assert!(flow.attribute_names().contains("StackMapTable"));
+        let old = retarget(&flow, V49).unwrap();
+        assert_eq!((old.major_version, old.minor_version), (49, 0));
+        assert!(!old.attribute_names().contains("StackMapTable"));
+
+        // back to 51, where the branches need the frames that were dropped
+        assert_eq!(
+            retarget(&old, V51).unwrap_err(),
+            "sum needs a StackMapTable from version 51."
+        );
+    }
+
+    #[test]
+    fn newer_attributes() {
+        let mut hello = fixture("Hello");
+        let name = hello
+            .constant_pool
+            .push(ConstantPoolEntry::Utf8("NestHost".into()))
+            .unwrap();
+        hello.attributes.push(Attribute {
+            name_index: Utf8Index(name),
+            info: AttributeInfo::Any(vec![0, 2]),
+        });
+        assert_eq!(
+            retarget(&hello, V51).unwrap_err(),
+            "The class has a NestHost attribute, which needs version 55 or later."
+        );
+        assert!(retarget(
+            &hello,
+            ClassVersion {
+                major: 55,
+                minor: 0
+            }
+        )
+        .is_ok());
+
+        let calls = fixture("Calls");
+        assert_eq!(
+            retarget(
+                &calls,
+                ClassVersion {
+                    major: 50,
+                    minor: 0
+                }
+            )
+            .unwrap_err(),
+            "The class has a BootstrapMethods attribute, which needs version 51 or later."
+        );
+    }
+
+    #[test]
+    fn newer_constants() {
+        let mut hello = fixture("Hello");
+        let index = hello
+            .constant_pool
+            .push(ConstantPoolEntry::MethodType {
+                descriptor_index: Utf8Index(CPIndex(1)),
+            })
+            .unwrap();
+        assert_eq!(
+            retarget(
+                &hello,
+                ClassVersion {
+                    major: 50,
+                    minor: 0
+                }
+            )
+            .unwrap_err(),
+            format!(
+                "Constant #{} is a MethodType, which needs version 51 or later.",
+                index.0
+            )
+        );
+        assert!(retarget(&hello, V51).is_ok());
+    }
+
+    #[test]
+    fn jsr() {
+        // main runs a subroutine: jsr 4; return; astore_1; ret 1
+        let mut hello = fixture("Hello");
+        hello.major_version = 49;
+        let instructions = vec![
+            Instruction::Jsr(4),
+            Instruction::Return,
+            Instruction::Astore1,
+            Instruction::Ret(1),
+        ];
+        let main = &mut hello.methods[1];
+        main.attributes[0].info =
+            AttributeInfo::new_code(1, 2, instructions, vec![], vec![]).unwrap();
+        assert!(retarget(
+            &hello,
+            ClassVersion {
+                major: 50,
+                minor: 0
+            }
+        )
+        .is_ok());
+        assert_eq!(
+            retarget(&hello, V51).unwrap_err(),
+            "main uses jsr, which isn't allowed from version 51."
+        );
+    }
+}
@@ -1,6 +1,8 @@
 use crate::{
-    AccessFlags, Attribute, AttributeInfo, CPIndex, CodeByte, ConstantPool, ConstantPoolEntry,
-    ExceptionTableEntry, Field, JavaClass, Method, ReferenceKind,
+    modified_utf8::encode_modified_utf8, AccessFlags, Annotation, Attribute, AttributeInfo,
+    CPIndex, ClassIndex, CodeByte, ConstantPool, ConstantPoolEntry, DoubleBits, ElementValue,
+    ElementValuePair, FloatBits, JavaClass, LazyAttribute, NameAndTypeIndex, ReferenceKind,
+    TargetInfo, Utf8Index,
 };
 use std::io::{Error, Write};
 
@@ -27,6 +29,16 @@ impl Serialize for u64 {
         bytes.write_all(&self.to_be_bytes())
     }
 }
+impl Serialize for i8 {
+    fn serialize(&self, bytes: &mut Vec<u8>) -> Result<(), Error> {
+        bytes.write_all(&self.to_be_bytes())
+    }
+}
+impl Serialize for i16 {
+    fn serialize(&self, bytes: &mut Vec<u8>) -> Result<(), Error> {
+        bytes.write_all(&self.to_be_bytes())
+    }
+}
 impl Serialize for i32 {
     fn serialize(&self, bytes: &mut Vec<u8>) -> Result<(), Error> {
         bytes.write_all(&self.to_be_bytes())
@@ -47,22 +59,34 @@ impl Serialize for f64 {
         bytes.write_all(&self.to_be_bytes())
     }
 }
-impl Serialize for String {
-    fn serialize(&self, bytes: &mut Vec<u8>) -> Result<(), Error> {
-        bytes.write_all(&self.as_bytes()[..])
-    }
-}
+// a list preceded by its u16 count, see Vec::deserialize
 impl<T> Serialize for Vec<T>
 where
     T: Serialize,
 {
     fn serialize(&self, bytes: &mut Vec<u8>) -> Result<(), Error> {
-        (self.len() as u16).serialize(bytes)?;
-        for i in self.iter() {
-            i.serialize(bytes)?;
-        }
-        Ok(())
+        serialize_vec_with_count::<u16, T>(self, bytes)
+    }
+}
+
+// a list preceded by its count as a C, erroring when the list is too long for it
+pub fn serialize_vec_with_count<C: TryFrom<usize> + Serialize, T: Serialize>(
+    values: &[T],
+    bytes: &mut Vec<u8>,
+) -> Result<(), Error> {
+    C::try_from(values.len())
+        .map_err(|_| {
+            Error::other(format!(
+                "{} values don't fit in a {} count.",
+                values.len(),
+                std::any::type_name::<C>()
+            ))
+        })?
+        .serialize(bytes)?;
+    for v in values {
+        v.serialize(bytes)?;
     }
+    Ok(())
 }
 
 impl Serialize for CPIndex {
@@ -74,10 +98,35 @@ impl Serialize for Option<CPIndex> {
     fn serialize(&self, bytes: &mut Vec<u8>) -> Result<(), Error> {
         match self {
             Some(cpi) => cpi.serialize(bytes),
-            None => CPIndex::none().serialize(bytes),
+            None => CPIndex::NONE.serialize(bytes),
         }
     }
 }
+impl Serialize for Utf8Index {
+    fn serialize(&self, bytes: &mut Vec<u8>) -> Result<(), Error> {
+        self.0.serialize(bytes)
+    }
+}
+impl Serialize for ClassIndex {
+    fn serialize(&self, bytes: &mut Vec<u8>) -> Result<(), Error> {
+        self.0.serialize(bytes)
+    }
+}
+impl Serialize for NameAndTypeIndex {
+    fn serialize(&self, bytes: &mut Vec<u8>) -> Result<(), Error> {
+        self.0.serialize(bytes)
+    }
+}
+impl Serialize for FloatBits {
+    fn serialize(&self, bytes: &mut Vec<u8>) -> Result<(), Error> {
+        self.0.serialize(bytes)
+    }
+}
+impl Serialize for DoubleBits {
+    fn serialize(&self, bytes: &mut Vec<u8>) -> Result<(), Error> {
+        self.0.serialize(bytes)
+    }
+}
 impl Serialize for ReferenceKind {
     fn serialize(&self, bytes: &mut Vec<u8>) -> Result<(), Error> {
         (*self as u8).serialize(bytes)
@@ -85,16 +134,13 @@ impl Serialize for ReferenceKind {
 }
 impl Serialize for ConstantPoolEntry {
     fn serialize(&self, bytes: &mut Vec<u8>) -> Result<(), Error> {
+        self.tag_byte().serialize(bytes)?;
         match self {
-            ConstantPoolEntry::Class { name_index } => {
-                (7u8).serialize(bytes)?;
-                name_index.serialize(bytes)
-            }
+            ConstantPoolEntry::Class { name_index } => name_index.serialize(bytes),
             ConstantPoolEntry::FieldRef {
                 class_index,
                 name_and_type_index,
             } => {
-                (9u8).serialize(bytes)?;
                 class_index.serialize(bytes)?;
                 name_and_type_index.serialize(bytes)
             }
@@ -102,7 +148,6 @@ impl Serialize for ConstantPoolEntry {
                 class_index,
                 name_and_type_index,
             } => {
-                (10u8).serialize(bytes)?;
                 class_index.serialize(bytes)?;
                 name_and_type_index.serialize(bytes)
             }
@@ -110,75 +155,73 @@ impl Serialize for ConstantPoolEntry {
                 class_index,
                 name_and_type_index,
             } => {
-                (11u8).serialize(bytes)?;
                 class_index.serialize(bytes)?;
                 name_and_type_index.serialize(bytes)
             }
-            ConstantPoolEntry::String { string_index } => {
-                (8u8).serialize(bytes)?;
-                string_index.serialize(bytes)
-            }
-            ConstantPoolEntry::Integer(n) => {
-                (3u8).serialize(bytes)?;
-                n.serialize(bytes)
-            }
-            ConstantPoolEntry::Float(n) => {
-                (4u8).serialize(bytes)?;
-                n.serialize(bytes)
-            }
-            ConstantPoolEntry::Long(n) => {
-                (5u8).serialize(bytes)?;
-                n.serialize(bytes)
-            }
-            ConstantPoolEntry::Double(n) => {
-                (6u8).serialize(bytes)?;
-                n.serialize(bytes)
-            }
+            ConstantPoolEntry::String { string_index } => string_index.serialize(bytes),
+            ConstantPoolEntry::Integer(n) => n.serialize(bytes),
+            ConstantPoolEntry::Float(n) => n.serialize(bytes),
+            ConstantPoolEntry::Long(n) => n.serialize(bytes),
+            ConstantPoolEntry::Double(n) => n.serialize(bytes),
             ConstantPoolEntry::NameAndType {
                 name_index,
                 descriptor_index,
             } => {
-                (12u8).serialize(bytes)?;
                 name_index.serialize(bytes)?;
                 descriptor_index.serialize(bytes)
             }
             ConstantPoolEntry::Utf8(s) => {
-                (1u8).serialize(bytes)?;
-                (s.len() as u16).serialize(bytes)?;
-                s.serialize(bytes)
+                let encoded = encode_modified_utf8(s);
+                u16::try_from(encoded.len())
+                    .map_err(|_| {
+                        Error::other(format!(
+                            "Utf8 entry too long: {} bytes encoded don't fit in the u16 length.",
+                            encoded.len()
+                        ))
+                    })?
+                    .serialize(bytes)?;
+                bytes.write_all(&encoded)
             }
             ConstantPoolEntry::MethodHandle {
                 reference_kind,
                 reference_index,
             } => {
-                (15u8).serialize(bytes)?;
                 reference_kind.serialize(bytes)?;
                 reference_index.serialize(bytes)
             }
-            ConstantPoolEntry::MethodType { descriptor_index } => {
-                (16u8).serialize(bytes)?;
-                descriptor_index.serialize(bytes)
+            ConstantPoolEntry::MethodType { descriptor_index } => descriptor_index.serialize(bytes),
+            ConstantPoolEntry::Dynamic {
+                bootstrap_method_attr_index,
+                name_and_type_index,
+            } => {
+                bootstrap_method_attr_index.serialize(bytes)?;
+                name_and_type_index.serialize(bytes)
             }
             ConstantPoolEntry::InvokeDynamic {
                 bootstrap_method_attr_index,
                 name_and_type_index,
             } => {
-                (18u8).serialize(bytes)?;
                 bootstrap_method_attr_index.serialize(bytes)?;
                 name_and_type_index.serialize(bytes)
             }
+            ConstantPoolEntry::Module { name_index }
+            | ConstantPoolEntry::Package { name_index } => name_index.serialize(bytes),
         }
     }
 }
 
 impl Serialize for ConstantPool {
     fn serialize(&self, bytes: &mut Vec<u8>) -> Result<(), Error> {
-        self.size().serialize(bytes)?;
-        // all Entries sort by index
-        let mut entries = self.iter().collect::<Vec<(&CPIndex, &ConstantPoolEntry)>>();
-        entries.sort_by(|(a, _), (b, _)| a.cmp(&b));
-
-        for (_, v) in entries.iter() {
+        // push keeps to the limit, but a pool can also come from json
+        u16::try_from(self.inner.len())
+            .map_err(|_| {
+                Error::other(format!(
+                    "Constant pool too large: {} slots don't fit in the u16 count.",
+                    self.inner.len()
+                ))
+            })?
+            .serialize(bytes)?;
+        for (_, v) in self.iter() {
             v.serialize(bytes)?;
         }
 
@@ -192,30 +235,194 @@ impl Serialize for AccessFlags {
     }
 }
 
-impl Serialize for Field {
+impl Serialize for Annotation {
     fn serialize(&self, bytes: &mut Vec<u8>) -> Result<(), Error> {
-        self.access_flags.serialize(bytes)?;
-        self.name_index.serialize(bytes)?;
-        self.descriptor_index.serialize(bytes)?;
-        self.attributes.serialize(bytes)
+        self.type_index.serialize(bytes)?;
+        self.element_value_pairs.serialize(bytes)
     }
 }
 
-impl Serialize for Method {
+impl Serialize for ElementValuePair {
     fn serialize(&self, bytes: &mut Vec<u8>) -> Result<(), Error> {
-        self.access_flags.serialize(bytes)?;
-        self.name_index.serialize(bytes)?;
-        self.descriptor_index.serialize(bytes)?;
-        self.attributes.serialize(bytes)
+        self.element_name_index.serialize(bytes)?;
+        self.value.serialize(bytes)
     }
 }
 
-impl Serialize for ExceptionTableEntry {
+impl Serialize for ElementValue {
     fn serialize(&self, bytes: &mut Vec<u8>) -> Result<(), Error> {
-        self.start.serialize(bytes)?;
-        self.end.serialize(bytes)?;
-        self.handler.serialize(bytes)?;
-        self.catch_type.serialize(bytes)
+        match self {
+            ElementValue::Byte(index) => {
+                b'B'.serialize(bytes)?;
+                index.serialize(bytes)
+            }
+            ElementValue::Char(index) => {
+                b'C'.serialize(bytes)?;
+                index.serialize(bytes)
+            }
+            ElementValue::Double(index) => {
+                b'D'.serialize(bytes)?;
+                index.serialize(bytes)
+            }
+            ElementValue::Float(index) => {
+                b'F'.serialize(bytes)?;
+                index.serialize(bytes)
+            }
+            ElementValue::Int(index) => {
+                b'I'.serialize(bytes)?;
+                index.serialize(bytes)
+            }
+            ElementValue::Long(index) => {
+                b'J'.serialize(bytes)?;
+                index.serialize(bytes)
+            }
+            ElementValue::Short(index) => {
+                b'S'.serialize(bytes)?;
+                index.serialize(bytes)
+            }
+            ElementValue::Boolean(index) => {
+                b'Z'.serialize(bytes)?;
+                index.serialize(bytes)
+            }
+            ElementValue::String(index) => {
+                b's'.serialize(bytes)?;
+                index.serialize(bytes)
+            }
+            ElementValue::Enum {
+                type_name_index,
+                const_name_index,
+            } => {
+                b'e'.serialize(bytes)?;
+                type_name_index.serialize(bytes)?;
+                const_name_index.serialize(bytes)
+            }
+            ElementValue::Class(index) => {
+                b'c'.serialize(bytes)?;
+                index.serialize(bytes)
+            }
+            ElementValue::Annotation(annotation) => {
+                b'@'.serialize(bytes)?;
+                annotation.serialize(bytes)
+            }
+            ElementValue::Array(values) => {
+                b'['.serialize(bytes)?;
+                values.serialize(bytes)
+            }
+        }
+    }
+}
+
+impl Serialize for TargetInfo {
+    fn serialize(&self, bytes: &mut Vec<u8>) -> Result<(), Error> {
+        match self {
+            TargetInfo::ClassTypeParameter(index) => {
+                0x00u8.serialize(bytes)?;
+                index.serialize(bytes)
+            }
+            TargetInfo::MethodTypeParameter(index) => {
+                0x01u8.serialize(bytes)?;
+                index.serialize(bytes)
+            }
+            TargetInfo::Supertype(index) => {
+                0x10u8.serialize(bytes)?;
+                index.serialize(bytes)
+            }
+            TargetInfo::ClassTypeParameterBound {
+                type_parameter_index,
+                bound_index,
+            } => {
+                0x11u8.serialize(bytes)?;
+                type_parameter_index.serialize(bytes)?;
+                bound_index.serialize(bytes)
+            }
+            TargetInfo::MethodTypeParameterBound {
+                type_parameter_index,
+                bound_index,
+            } => {
+                0x12u8.serialize(bytes)?;
+                type_parameter_index.serialize(bytes)?;
+                bound_index.serialize(bytes)
+            }
+            TargetInfo::Field => 0x13u8.serialize(bytes),
+            TargetInfo::MethodReturn => 0x14u8.serialize(bytes),
+            TargetInfo::MethodReceiver => 0x15u8.serialize(bytes),
+            TargetInfo::MethodFormalParameter(index) => {
+                0x16u8.serialize(bytes)?;
+                index.serialize(bytes)
+            }
+            TargetInfo::Throws(index) => {
+                0x17u8.serialize(bytes)?;
+                index.serialize(bytes)
+            }
+            TargetInfo::LocalVariable(table) => {
+                0x40u8.serialize(bytes)?;
+                table.serialize(bytes)
+            }
+            TargetInfo::ResourceVariable(table) => {
+                0x41u8.serialize(bytes)?;
+                table.serialize(bytes)
+            }
+            TargetInfo::ExceptionParameter(index) => {
+                0x42u8.serialize(bytes)?;
+                index.serialize(bytes)
+            }
+            TargetInfo::InstanceOf(offset) => {
+                0x43u8.serialize(bytes)?;
+                offset.serialize(bytes)
+            }
+            TargetInfo::New(offset) => {
+                0x44u8.serialize(bytes)?;
+                offset.serialize(bytes)
+            }
+            TargetInfo::ConstructorReference(offset) => {
+                0x45u8.serialize(bytes)?;
+                offset.serialize(bytes)
+            }
+            TargetInfo::MethodReference(offset) => {
+                0x46u8.serialize(bytes)?;
+                offset.serialize(bytes)
+            }
+            TargetInfo::Cast {
+                offset,
+                type_argument_index,
+            } => {
+                0x47u8.serialize(bytes)?;
+                offset.serialize(bytes)?;
+                type_argument_index.serialize(bytes)
+            }
+            TargetInfo::ConstructorInvocationTypeArgument {
+                offset,
+                type_argument_index,
+            } => {
+                0x48u8.serialize(bytes)?;
+                offset.serialize(bytes)?;
+                type_argument_index.serialize(bytes)
+            }
+            TargetInfo::MethodInvocationTypeArgument {
+                offset,
+                type_argument_index,
+            } => {
+                0x49u8.serialize(bytes)?;
+                offset.serialize(bytes)?;
+                type_argument_index.serialize(bytes)
+            }
+            TargetInfo::ConstructorReferenceTypeArgument {
+                offset,
+                type_argument_index,
+            } => {
+                0x4Au8.serialize(bytes)?;
+                offset.serialize(bytes)?;
+                type_argument_index.serialize(bytes)
+            }
+            TargetInfo::MethodReferenceTypeArgument {
+                offset,
+                type_argument_index,
+            } => {
+                0x4Bu8.serialize(bytes)?;
+                offset.serialize(bytes)?;
+                type_argument_index.serialize(bytes)
+            }
+        }
     }
 }
 
@@ -238,10 +445,7 @@ impl Serialize for AttributeInfo {
             } => {
                 max_stack.serialize(bytes)?;
                 max_locals.serialize(bytes)?;
-                (code.len() as u32).serialize(bytes)?;
-                for i in code.iter() {
-                    i.serialize(bytes)?;
-                }
+                serialize_vec_with_count::<u32, _>(code, bytes)?;
                 exception_table.serialize(bytes)?;
                 attributes.serialize(bytes)
             }
@@ -249,10 +453,28 @@ impl Serialize for AttributeInfo {
                 exception_index_table,
             } => exception_index_table.serialize(bytes),
             AttributeInfo::ConstantValue { index } => index.serialize(bytes),
+            AttributeInfo::SourceDebugExtension(s) => bytes.write_all(&encode_modified_utf8(s)),
+            AttributeInfo::Record { components } => components.serialize(bytes),
+            AttributeInfo::AnnotationDefault(value) => value.serialize(bytes),
+            AttributeInfo::RuntimeVisibleAnnotations(annotations)
+            | AttributeInfo::RuntimeInvisibleAnnotations(annotations) => {
+                annotations.serialize(bytes)
+            }
+            AttributeInfo::RuntimeVisibleParameterAnnotations(parameters)
+            | AttributeInfo::RuntimeInvisibleParameterAnnotations(parameters) => {
+                serialize_vec_with_count::<u8, _>(parameters, bytes)
+            }
+            AttributeInfo::RuntimeVisibleTypeAnnotations(annotations)
+            | AttributeInfo::RuntimeInvisibleTypeAnnotations(annotations) => {
+                annotations.serialize(bytes)
+            }
         }
     }
 }
 
+// Lists of attributes are written in the order of their Vec, which is the order they were read in.
+// Nothing reorders them (stripping removes in place, and the attributes added by javd go at the
+// end), so they round-trip where they were, duplicates included.
 impl Serialize for Attribute {
     fn serialize(&self, bytes: &mut Vec<u8>) -> Result<(), Error> {
         self.name_index.serialize(bytes)?;
@@ -263,7 +485,16 @@ impl Serialize for Attribute {
     }
 }
 
-impl Serialize for JavaClass {
+impl Serialize for LazyAttribute {
+    fn serialize(&self, bytes: &mut Vec<u8>) -> Result<(), Error> {
+        // always the original bytes, a lazy attribute can't be modified
+        self.name_index.serialize(bytes)?;
+        (self.bytes.len() as u32).serialize(bytes)?;
+        bytes.write_all(&self.bytes)
+    }
+}
+
+impl<A: Serialize> Serialize for JavaClass<A> {
     fn serialize(&self, bytes: &mut Vec<u8>) -> Result<(), Error> {
         self.magic_bytes.serialize(bytes)?;
         self.minor_version.serialize(bytes)?;
@@ -271,7 +502,7 @@ impl Serialize for JavaClass {
         self.constant_pool.serialize(bytes)?;
         self.access_flags.serialize(bytes)?;
         self.this_class.serialize(bytes)?;
-        self.super_class.unwrap_or(CPIndex(0)).serialize(bytes)?;
+        self.super_class.map(|c| c.0).serialize(bytes)?;
         self.interfaces.serialize(bytes)?;
         self.fields.serialize(bytes)?;
         self.methods.serialize(bytes)?;
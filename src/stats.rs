@@ -0,0 +1,184 @@
+use std::{collections::BTreeMap, fmt::Display};
+
+use crate::{
+    borrowed::{AttributeRef, ConstantPoolRef, ConstantRef, JavaClassRef},
+    instruction::decode_code,
+    AccessFlags,
+};
+
+// Counts and sizes over one class, or several once merged.
+#[derive(Debug, Default, serde::Serialize)]
+pub struct ClassStats {
+    pub classes: usize,
+    // size of the class files
+    pub bytes: u64,
+    // methods by access: public, protected, package or private
+    pub methods: BTreeMap<&'static str, usize>,
+    // methods with a Code attribute, and the total and largest length of their code
+    pub code_methods: usize,
+    pub code_bytes: u64,
+    pub max_code_bytes: u32,
+    // number of methods for each max_stack and max_locals value
+    pub max_stack: BTreeMap<u16, usize>,
+    pub max_locals: BTreeMap<u16, usize>,
+    // instructions by mnemonic, methods whose code can't be decoded aren't counted
+    pub opcodes: BTreeMap<&'static str, usize>,
+    // constant pool entries by kind
+    pub constants: BTreeMap<&'static str, usize>,
+    // bytes taken by the attributes of each name (header included), the attributes nested in
+    // Code and record components count for their own name only
+    pub attribute_bytes: BTreeMap<String, u64>,
+}
+
+fn access(flags: AccessFlags) -> &'static str {
+    if flags.contains(AccessFlags::PUBLIC) {
+        "public"
+    } else if flags.contains(AccessFlags::PROTECTED) {
+        "protected"
+    } else if flags.contains(AccessFlags::PRIVATE) {
+        "private"
+    } else {
+        "package"
+    }
+}
+
+// adds the bytes of the attributes to out by name, returns their total
+fn attribute_bytes(
+    attributes: &[AttributeRef],
+    cp: &ConstantPoolRef,
+    out: &mut BTreeMap<String, u64>,
+) -> u64 {
+    let mut total = 0;
+    for a in attributes {
+        let name = cp.utf8(a.name_index).unwrap_or("(invalid name)");
+        // name_index and length, then the body
+        let size = 6 + a.bytes.len() as u64;
+        let nested = a
+            .nested(name)
+            .map_or(0, |nested| attribute_bytes(&nested, cp, out));
+        *out.entry(name.to_string()).or_default() += size - nested;
+        total += size;
+    }
+    total
+}
+
+impl JavaClassRef<'_> {
+    pub fn stats(&self) -> ClassStats {
+        let cp = &self.constant_pool;
+        let mut stats = ClassStats {
+            classes: 1,
+            bytes: self.bytes.len() as u64,
+            ..Default::default()
+        };
+
+        for (_, e) in cp.iter() {
+            let kind = match e {
+                ConstantRef::Utf8(_) => "Utf8",
+                ConstantRef::Other(e) => e.kind(),
+            };
+            *stats.constants.entry(kind).or_default() += 1;
+        }
+        for f in self.fields.iter() {
+            attribute_bytes(&f.attributes, cp, &mut stats.attribute_bytes);
+        }
+        for m in self.methods.iter() {
+            *stats.methods.entry(access(m.access_flags)).or_default() += 1;
+            attribute_bytes(&m.attributes, cp, &mut stats.attribute_bytes);
+            for a in m.attributes.iter() {
+                if cp.utf8(a.name_index).ok() != Some("Code") {
+                    continue;
+                }
+                let Some(code) = a.code() else {
+                    continue;
+                };
+                stats.code_methods += 1;
+                stats.code_bytes += code.code.len() as u64;
+                stats.max_code_bytes = stats.max_code_bytes.max(code.code.len() as u32);
+                *stats.max_stack.entry(code.max_stack).or_default() += 1;
+                *stats.max_locals.entry(code.max_locals).or_default() += 1;
+                for (_, instruction) in decode_code(code.code).unwrap_or_default() {
+                    *stats.opcodes.entry(instruction.mnemonic()).or_default() += 1;
+                }
+            }
+        }
+        attribute_bytes(&self.attributes, cp, &mut stats.attribute_bytes);
+        stats
+    }
+}
+
+fn add<K: Ord + Clone, V: std::ops::AddAssign + Copy + Default>(
+    into: &mut BTreeMap<K, V>,
+    from: &BTreeMap<K, V>,
+) {
+    for (k, v) in from {
+        *into.entry(k.clone()).or_default() += *v;
+    }
+}
+
+impl ClassStats {
+    // adds the counts of other to these
+    pub fn merge(&mut self, other: &ClassStats) {
+        self.classes += other.classes;
+        self.bytes += other.bytes;
+        add(&mut self.methods, &other.methods);
+        self.code_methods += other.code_methods;
+        self.code_bytes += other.code_bytes;
+        self.max_code_bytes = self.max_code_bytes.max(other.max_code_bytes);
+        add(&mut self.max_stack, &other.max_stack);
+        add(&mut self.max_locals, &other.max_locals);
+        add(&mut self.opcodes, &other.opcodes);
+        add(&mut self.constants, &other.constants);
+        add(&mut self.attribute_bytes, &other.attribute_bytes);
+    }
+}
+
+// the entries of a map, largest first
+fn by_count<K: Display, V: Ord + Copy>(map: &BTreeMap<K, V>) -> Vec<(&K, V)> {
+    let mut entries = map.iter().map(|(k, v)| (k, *v)).collect::<Vec<_>>();
+    entries.sort_by_key(|(_, v)| std::cmp::Reverse(*v));
+    entries
+}
+
+fn list<K: Display, V: Display>(entries: impl IntoIterator<Item = (K, V)>) -> String {
+    entries
+        .into_iter()
+        .map(|(k, v)| format!("{} {}", k, v))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl Display for ClassStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "classes: {}", self.classes)?;
+        writeln!(f, "bytes: {}", self.bytes)?;
+        writeln!(
+            f,
+            "methods: {} ({})",
+            self.methods.values().sum::<usize>(),
+            list(by_count(&self.methods))
+        )?;
+        let average = self.code_bytes as f64 / self.code_methods.max(1) as f64;
+        writeln!(
+            f,
+            "code: {} methods, {} bytes, {:.1} average, {} max",
+            self.code_methods, self.code_bytes, average, self.max_code_bytes
+        )?;
+        // the values followed by how many methods have them
+        let distribution = |map: &BTreeMap<u16, usize>| {
+            list(map.iter().map(|(value, n)| (value, format!("(x{})", n))))
+        };
+        writeln!(f, "max_stack: {}", distribution(&self.max_stack))?;
+        writeln!(f, "max_locals: {}", distribution(&self.max_locals))?;
+        writeln!(f, "constants: {}", list(by_count(&self.constants)))?;
+        writeln!(f, "attribute bytes:")?;
+        for (name, bytes) in by_count(&self.attribute_bytes) {
+            let percent = bytes as f64 * 100.0 / self.bytes.max(1) as f64;
+            writeln!(f, "    {} {} ({:.1}%)", name, bytes, percent)?;
+        }
+        writeln!(f, "opcodes:")?;
+        for (mnemonic, count) in by_count(&self.opcodes) {
+            writeln!(f, "    {} {}", mnemonic, count)?;
+        }
+        Ok(())
+    }
+}
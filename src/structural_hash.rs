@@ -0,0 +1,596 @@
+use std::io::{Cursor, Error};
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    deserialization::Deserialize,
+    instruction::{decode_code, Instruction},
+    modified_utf8::encode_modified_utf8,
+    serialization::{serialize_vec_with_count, Serialize},
+    AccessFlags, Annotation, Attribute, AttributeInfo, CPIndex, ConstantPool, ConstantPoolEntry,
+    ElementValue, JavaClass, Utf8Index,
+};
+
+// MethodHandle entries point at any entry, this stops loops between them
+const MAX_CONSTANT_DEPTH: usize = 8;
+
+// The class written with every constant pool index replaced by what it resolves to, so that it
+// doesn't depend on the order of the pool (or on unused entries). Every variable length part is
+// prefixed by its length, so different classes can't end up with the same bytes.
+struct Canonical<'a> {
+    cp: &'a ConstantPool,
+    out: Vec<u8>,
+}
+
+type Reader<'b> = Cursor<&'b [u8]>;
+
+impl Canonical<'_> {
+    fn u8(&mut self, v: u8) {
+        self.out.push(v);
+    }
+    fn u16(&mut self, v: u16) {
+        self.out.extend(v.to_be_bytes());
+    }
+    fn u32(&mut self, v: u32) {
+        self.out.extend(v.to_be_bytes());
+    }
+    fn bytes(&mut self, b: &[u8]) {
+        self.u32(b.len() as u32);
+        self.out.extend(b);
+    }
+    fn constant(&mut self, index: CPIndex) {
+        self.constant_nested(index, 0)
+    }
+    fn constant_nested(&mut self, index: CPIndex, depth: usize) {
+        let entry = match self.cp.get(index) {
+            Some(entry) if depth < MAX_CONSTANT_DEPTH => entry,
+            // the index itself when there is nothing to resolve (0 in optional indices)
+            _ => {
+                self.u8(0);
+                self.u16(index.0);
+                return;
+            }
+        };
+        let nested = |this: &mut Self, index: CPIndex| this.constant_nested(index, depth + 1);
+        match entry {
+            ConstantPoolEntry::Utf8(s) => {
+                self.u8(1);
+                // as written, the text alone loses lone surrogates
+                self.bytes(&encode_modified_utf8(s));
+            }
+            ConstantPoolEntry::Integer(v) => {
+                self.u8(3);
+                self.out.extend(v.to_be_bytes());
+            }
+            ConstantPoolEntry::Float(v) => {
+                self.u8(4);
+                self.out.extend(v.0.to_be_bytes());
+            }
+            ConstantPoolEntry::Long(v) => {
+                self.u8(5);
+                self.out.extend(v.to_be_bytes());
+            }
+            ConstantPoolEntry::Double(v) => {
+                self.u8(6);
+                self.out.extend(v.0.to_be_bytes());
+            }
+            ConstantPoolEntry::Class { name_index } => {
+                self.u8(7);
+                nested(self, name_index.0);
+            }
+            ConstantPoolEntry::String { string_index } => {
+                self.u8(8);
+                nested(self, string_index.0);
+            }
+            ConstantPoolEntry::FieldRef {
+                class_index,
+                name_and_type_index,
+            } => {
+                self.u8(9);
+                nested(self, class_index.0);
+                nested(self, name_and_type_index.0);
+            }
+            ConstantPoolEntry::MethodRef {
+                class_index,
+                name_and_type_index,
+            } => {
+                self.u8(10);
+                nested(self, class_index.0);
+                nested(self, name_and_type_index.0);
+            }
+            ConstantPoolEntry::InterfaceMethodRef {
+                class_index,
+                name_and_type_index,
+            } => {
+                self.u8(11);
+                nested(self, class_index.0);
+                nested(self, name_and_type_index.0);
+            }
+            ConstantPoolEntry::NameAndType {
+                name_index,
+                descriptor_index,
+            } => {
+                self.u8(12);
+                nested(self, name_index.0);
+                nested(self, descriptor_index.0);
+            }
+            ConstantPoolEntry::MethodHandle {
+                reference_kind,
+                reference_index,
+            } => {
+                self.u8(15);
+                self.u8(*reference_kind as u8);
+                nested(self, *reference_index);
+            }
+            ConstantPoolEntry::MethodType { descriptor_index } => {
+                self.u8(16);
+                nested(self, descriptor_index.0);
+            }
+            ConstantPoolEntry::Dynamic {
+                bootstrap_method_attr_index,
+                name_and_type_index,
+            } => {
+                self.u8(17);
+                self.u16(*bootstrap_method_attr_index);
+                nested(self, name_and_type_index.0);
+            }
+            ConstantPoolEntry::InvokeDynamic {
+                bootstrap_method_attr_index,
+                name_and_type_index,
+            } => {
+                self.u8(18);
+                self.u16(*bootstrap_method_attr_index);
+                nested(self, name_and_type_index.0);
+            }
+            ConstantPoolEntry::Module { name_index } => {
+                self.u8(19);
+                nested(self, name_index.0);
+            }
+            ConstantPoolEntry::Package { name_index } => {
+                self.u8(20);
+                nested(self, name_index.0);
+            }
+        }
+    }
+    fn member(
+        &mut self,
+        flags: AccessFlags,
+        name: Utf8Index,
+        descriptor: Utf8Index,
+        attributes: &[Attribute],
+    ) {
+        self.u16(flags.bits());
+        self.constant(name.0);
+        self.constant(descriptor.0);
+        self.attributes(attributes);
+    }
+    fn attributes(&mut self, attributes: &[Attribute]) {
+        self.u32(attributes.len() as u32);
+        for a in attributes {
+            self.constant(a.name_index.0);
+            self.attribute(a);
+        }
+    }
+    fn attribute(&mut self, a: &Attribute) {
+        match &a.info {
+            AttributeInfo::Any(b) => {
+                let start = self.out.len();
+                let name = a.name(self.cp).unwrap_or_default();
+                let mut r = Cursor::new(b.as_slice());
+                // raw when the layout isn't known or the bytes don't follow it
+                match self.raw_attribute(name, &mut r) {
+                    Ok(true) if r.position() == b.len() as u64 => (),
+                    _ => {
+                        self.out.truncate(start);
+                        self.u8(0);
+                        self.bytes(b);
+                    }
+                }
+            }
+            AttributeInfo::ConstantValue { index } => self.constant(*index),
+            AttributeInfo::Code {
+                max_stack,
+                max_locals,
+                code,
+                exception_table,
+                attributes,
+            } => {
+                self.u16(*max_stack);
+                self.u16(*max_locals);
+                self.code(&code.iter().map(|b| b.0).collect::<Vec<_>>());
+                self.u32(exception_table.len() as u32);
+                for e in exception_table {
+                    self.u16(e.start);
+                    self.u16(e.end);
+                    self.u16(e.handler);
+                    self.constant(e.catch_type.unwrap_or(CPIndex::NONE));
+                }
+                self.attributes(attributes);
+            }
+            AttributeInfo::Exceptions {
+                exception_index_table,
+            } => {
+                self.u32(exception_index_table.len() as u32);
+                for c in exception_index_table {
+                    self.constant(c.0);
+                }
+            }
+            AttributeInfo::SourceDebugExtension(s) => self.bytes(&encode_modified_utf8(s)),
+            AttributeInfo::Record { components } => {
+                self.u32(components.len() as u32);
+                for c in components {
+                    self.constant(c.name_index.0);
+                    self.constant(c.descriptor_index.0);
+                    self.attributes(&c.attributes);
+                }
+            }
+            AttributeInfo::AnnotationDefault(value) => self.element_value(value),
+            AttributeInfo::RuntimeVisibleAnnotations(annotations)
+            | AttributeInfo::RuntimeInvisibleAnnotations(annotations) => {
+                self.annotations(annotations)
+            }
+            AttributeInfo::RuntimeVisibleParameterAnnotations(parameters)
+            | AttributeInfo::RuntimeInvisibleParameterAnnotations(parameters) => {
+                self.u32(parameters.len() as u32);
+                for annotations in parameters {
+                    self.annotations(annotations);
+                }
+            }
+            AttributeInfo::RuntimeVisibleTypeAnnotations(annotations)
+            | AttributeInfo::RuntimeInvisibleTypeAnnotations(annotations) => {
+                self.u32(annotations.len() as u32);
+                for a in annotations {
+                    // the target and its path hold no index, they are copied
+                    let mut target = Vec::new();
+                    a.target_info
+                        .serialize(&mut target)
+                        .and_then(|_| {
+                            serialize_vec_with_count::<u8, _>(&a.target_path, &mut target)
+                        })
+                        .expect("serializing into a Vec failed");
+                    self.bytes(&target);
+                    self.annotation(&a.annotation);
+                }
+            }
+        }
+    }
+    // the instructions referencing the pool are written with the constant in place of the index,
+    // ldc as ldc_w since the index size depends on the order; the others are copied
+    fn code(&mut self, code: &[u8]) {
+        let Ok(instructions) = decode_code(code) else {
+            self.u8(0);
+            self.bytes(code);
+            return;
+        };
+        self.u8(1);
+        self.u32(instructions.len() as u32);
+        for (offset, instruction) in instructions {
+            let offset = offset as usize;
+            let bytes = &code[offset..offset + instruction.size(offset as u32) as usize];
+            match instruction.references_cp() {
+                Some(index) => {
+                    let (opcode, operands) = match instruction {
+                        Instruction::Ldc(_) => (0x13, &bytes[2..]),
+                        _ => (instruction.opcode(), &bytes[3..]),
+                    };
+                    self.u8(opcode);
+                    self.constant(index);
+                    self.bytes(operands);
+                }
+                None => self.bytes(bytes),
+            }
+        }
+    }
+    fn annotations(&mut self, annotations: &[Annotation]) {
+        self.u32(annotations.len() as u32);
+        for a in annotations {
+            self.annotation(a);
+        }
+    }
+    fn annotation(&mut self, annotation: &Annotation) {
+        self.constant(annotation.type_index.0);
+        self.u32(annotation.element_value_pairs.len() as u32);
+        for pair in annotation.element_value_pairs.iter() {
+            self.constant(pair.element_name_index.0);
+            self.element_value(&pair.value);
+        }
+    }
+    fn element_value(&mut self, value: &ElementValue) {
+        match value {
+            ElementValue::Byte(index)
+            | ElementValue::Char(index)
+            | ElementValue::Double(index)
+            | ElementValue::Float(index)
+            | ElementValue::Int(index)
+            | ElementValue::Long(index)
+            | ElementValue::Short(index)
+            | ElementValue::Boolean(index) => {
+                // the tag is the first byte written by serialize
+                let mut tag = Vec::new();
+                value
+                    .serialize(&mut tag)
+                    .expect("serializing into a Vec failed");
+                self.u8(tag[0]);
+                self.constant(*index);
+            }
+            ElementValue::String(index) => {
+                self.u8(b's');
+                self.constant(index.0);
+            }
+            ElementValue::Enum {
+                type_name_index,
+                const_name_index,
+            } => {
+                self.u8(b'e');
+                self.constant(type_name_index.0);
+                self.constant(const_name_index.0);
+            }
+            ElementValue::Class(index) => {
+                self.u8(b'c');
+                self.constant(index.0);
+            }
+            ElementValue::Annotation(annotation) => {
+                self.u8(b'@');
+                self.annotation(annotation);
+            }
+            ElementValue::Array(values) => {
+                self.u8(b'[');
+                self.u32(values.len() as u32);
+                for v in values {
+                    self.element_value(v);
+                }
+            }
+        }
+    }
+    // a constant pool index from the raw bytes, 0 when optional
+    fn index(&mut self, r: &mut Reader) -> Result<(), Error> {
+        let index = u16::deserialize(r)?;
+        self.constant(CPIndex(index));
+        Ok(())
+    }
+    // a number from the raw bytes, kept as is
+    fn number(&mut self, r: &mut Reader) -> Result<(), Error> {
+        let v = u16::deserialize(r)?;
+        self.u16(v);
+        Ok(())
+    }
+    // a u16 count followed by that many items
+    fn list(
+        &mut self,
+        r: &mut Reader,
+        item: impl Fn(&mut Self, &mut Reader) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let count = u16::deserialize(r)?;
+        self.u16(count);
+        for _ in 0..count {
+            item(self, r)?;
+        }
+        Ok(())
+    }
+    // Writes the attributes that aren't modeled but hold indices, returns false for the others.
+    fn raw_attribute(&mut self, name: &str, r: &mut Reader) -> Result<bool, Error> {
+        self.u8(1);
+        match name {
+            "SourceFile" | "Signature" | "NestHost" | "ModuleMainClass" => self.index(r)?,
+            "NestMembers" | "PermittedSubclasses" | "ModulePackages" => {
+                self.list(r, Self::index)?
+            }
+            "EnclosingMethod" => {
+                self.index(r)?;
+                self.index(r)?;
+            }
+            "InnerClasses" => self.list(r, |this, r| {
+                this.index(r)?;
+                this.index(r)?;
+                this.index(r)?;
+                this.number(r)
+            })?,
+            "LocalVariableTable" | "LocalVariableTypeTable" => self.list(r, |this, r| {
+                this.number(r)?;
+                this.number(r)?;
+                this.index(r)?;
+                this.index(r)?;
+                this.number(r)
+            })?,
+            "MethodParameters" => {
+                let count = u8::deserialize(r)?;
+                self.u8(count);
+                for _ in 0..count {
+                    self.index(r)?;
+                    self.number(r)?;
+                }
+            }
+            "BootstrapMethods" => self.list(r, |this, r| {
+                this.index(r)?;
+                this.list(r, Self::index)
+            })?,
+            "StackMapTable" => self.list(r, Self::stack_map_frame)?,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+    fn stack_map_frame(&mut self, r: &mut Reader) -> Result<(), Error> {
+        let kind = u8::deserialize(r)?;
+        self.u8(kind);
+        match kind {
+            0..=63 => (),
+            64..=127 => self.verification_type(r)?,
+            247 => {
+                self.number(r)?;
+                self.verification_type(r)?;
+            }
+            248..=251 => self.number(r)?,
+            252..=254 => {
+                self.number(r)?;
+                for _ in 251..kind {
+                    self.verification_type(r)?;
+                }
+            }
+            255 => {
+                self.number(r)?;
+                self.list(r, Self::verification_type)?;
+                self.list(r, Self::verification_type)?;
+            }
+            _ => return Err(Error::other(format!("Unknown frame type {}.", kind))),
+        }
+        Ok(())
+    }
+    fn verification_type(&mut self, r: &mut Reader) -> Result<(), Error> {
+        let tag = u8::deserialize(r)?;
+        self.u8(tag);
+        match tag {
+            0..=6 => Ok(()),
+            // Object, of a class
+            7 => self.index(r),
+            // Uninitialized, the offset of the new
+            8 => self.number(r),
+            _ => Err(Error::other(format!("Unknown verification type {}.", tag))),
+        }
+    }
+}
+
+impl JavaClass {
+    // SHA-256 of the class in a form that only depends on what its indices resolve to, so a class
+    // with the same content but another constant pool order (or unused entries) hashes the same.
+    // Meant to find the same class compiled twice, ldc may still turn into ldc_w (and move the
+    // following offsets) with another order.
+    pub fn structural_hash(&self) -> [u8; 32] {
+        let mut c = Canonical {
+            cp: &self.constant_pool,
+            out: Vec::new(),
+        };
+        c.u16(self.minor_version);
+        c.u16(self.major_version);
+        c.u16(self.access_flags.bits());
+        c.constant(self.this_class.0);
+        c.constant(self.super_class.map_or(CPIndex::NONE, |s| s.0));
+        c.u32(self.interfaces.len() as u32);
+        for i in self.interfaces.iter() {
+            c.constant(i.0);
+        }
+        c.u32(self.fields.len() as u32);
+        for f in self.fields.iter() {
+            c.member(
+                f.access_flags,
+                f.name_index,
+                f.descriptor_index,
+                &f.attributes,
+            );
+        }
+        c.u32(self.methods.len() as u32);
+        for m in self.methods.iter() {
+            c.member(
+                m.access_flags,
+                m.name_index,
+                m.descriptor_index,
+                &m.attributes,
+            );
+        }
+        c.attributes(&self.attributes);
+        Sha256::digest(&c.out).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tests::fixture, ClassIndex, Method};
+
+    // A class A with a method m loading the String "s", with its pool holding the entries below
+    // in the given order. ldc_w loads it with ldc_w instead of ldc.
+    fn class(order: [usize; 9], ldc_w: bool) -> JavaClass {
+        // the index of the nth entry
+        let at = |n: usize| CPIndex(order.iter().position(|&o| o == n).unwrap() as u16 + 1);
+        let utf8 = |s: &str| ConstantPoolEntry::Utf8(s.into());
+        let entries = [
+            utf8("A"),
+            ConstantPoolEntry::Class {
+                name_index: Utf8Index(at(0)),
+            },
+            utf8("java/lang/Object"),
+            ConstantPoolEntry::Class {
+                name_index: Utf8Index(at(2)),
+            },
+            utf8("s"),
+            ConstantPoolEntry::String {
+                string_index: Utf8Index(at(4)),
+            },
+            utf8("m"),
+            utf8("()V"),
+            utf8("Code"),
+        ];
+        let mut constant_pool = ConstantPool::new();
+        for n in order {
+            constant_pool.push(entries[n].clone()).unwrap();
+        }
+        let load = match ldc_w {
+            true => Instruction::LdcW(at(5)),
+            false => Instruction::Ldc(at(5)),
+        };
+        let code = vec![load, Instruction::Pop, Instruction::Return];
+        let info = AttributeInfo::new_code(1, 0, code, vec![], vec![]).unwrap();
+        JavaClass {
+            magic_bytes: 0xCAFEBABE,
+            minor_version: 0,
+            major_version: 52,
+            constant_pool,
+            access_flags: AccessFlags::PUBLIC | AccessFlags::SUPER,
+            this_class: ClassIndex(at(1)),
+            super_class: Some(ClassIndex(at(3))),
+            interfaces: vec![],
+            fields: vec![],
+            methods: vec![Method {
+                access_flags: AccessFlags::PUBLIC | AccessFlags::STATIC,
+                name_index: Utf8Index(at(6)),
+                descriptor_index: Utf8Index(at(7)),
+                attributes: vec![Attribute {
+                    name_index: Utf8Index(at(8)),
+                    info,
+                }],
+            }],
+            attributes: vec![],
+        }
+    }
+
+    #[test]
+    fn pool_order() {
+        let a = class([0, 1, 2, 3, 4, 5, 6, 7, 8], false);
+        let b = class([8, 5, 7, 4, 3, 6, 1, 2, 0], true);
+        assert_ne!(a.constant_pool, b.constant_pool);
+        assert_eq!(a.structural_hash(), b.structural_hash());
+    }
+
+    #[test]
+    fn unused_entries() {
+        for name in ["Hello", "Annotated", "Flow", "TypeAnnotated"] {
+            let mut class = fixture(name);
+            let hash = class.structural_hash();
+            for s in ["unused", "another one"] {
+                let entry = ConstantPoolEntry::Utf8(s.into());
+                class.constant_pool.push(entry).unwrap();
+            }
+            assert_eq!(class.structural_hash(), hash, "{}", name);
+        }
+    }
+
+    #[test]
+    fn content_changes() {
+        let order = [0, 1, 2, 3, 4, 5, 6, 7, 8];
+        let hash = class(order, false).structural_hash();
+
+        let mut flags = class(order, false);
+        flags.methods[0].access_flags = AccessFlags::PRIVATE | AccessFlags::STATIC;
+        assert_ne!(flags.structural_hash(), hash);
+
+        // the String loaded, through the Utf8 it points at
+        let mut string = class(order, false);
+        *string.constant_pool.get_mut(CPIndex(5)).unwrap() = ConstantPoolEntry::Utf8("t".into());
+        assert_ne!(string.structural_hash(), hash);
+
+        let names = ["Hello", "Annotated", "Flow", "Point", "Constants"];
+        let hashes: Vec<_> = names.map(|n| fixture(n).structural_hash()).to_vec();
+        for (i, h) in hashes.iter().enumerate() {
+            assert!(!hashes[i + 1..].contains(h), "{}", names[i]);
+        }
+    }
+}
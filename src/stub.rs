@@ -0,0 +1,86 @@
+use std::io::Error;
+
+use crate::{
+    instruction::Instruction,
+    rename::{find_or_push_name_and_type, find_or_push_utf8},
+    AttributeInfo, CPIndex, ClassIndex, ConstantPool, ConstantPoolEntry, JavaClass,
+};
+
+// what the stub methods throw
+const STUB_EXCEPTION: &str = "java/lang/UnsupportedOperationException";
+
+// the Class entry of the exception and the MethodRef of its constructor, added if missing
+fn exception_constructor(cp: &mut ConstantPool) -> Result<(ClassIndex, CPIndex), Error> {
+    let name = find_or_push_utf8(cp, STUB_EXCEPTION)?;
+    let class = cp
+        .iter()
+        .find(|(_, e)| matches!(e, ConstantPoolEntry::Class { name_index } if *name_index == name));
+    let class = ClassIndex(match class {
+        Some((i, _)) => i,
+        None => cp.push(ConstantPoolEntry::Class { name_index: name })?,
+    });
+
+    let init = find_or_push_utf8(cp, "<init>")?;
+    let descriptor = find_or_push_utf8(cp, "()V")?;
+    let name_and_type = find_or_push_name_and_type(cp, init, descriptor)?;
+    let method = cp.iter().find(|(_, e)| {
+        matches!(e, ConstantPoolEntry::MethodRef { class_index, name_and_type_index }
+            if *class_index == class && *name_and_type_index == name_and_type)
+    });
+    let method = match method {
+        Some((i, _)) => i,
+        None => cp.push(ConstantPoolEntry::MethodRef {
+            class_index: class,
+            name_and_type_index: name_and_type,
+        })?,
+    };
+    Ok((class, method))
+}
+
+impl JavaClass {
+    // Replaces the code of every method that has some by a body throwing a new
+    // UnsupportedOperationException, without the attributes nested in Code (LineNumberTable,
+    // StackMapTable...). The rest of the methods (signatures, exceptions, annotations) is kept,
+    // which makes classes to compile against like the ones of android.jar. The constants of the
+    // stub body are added once, and nothing is removed from the constant pool. Returns the number
+    // of methods stubbed.
+    pub fn strip_method_bodies(&mut self) -> Result<usize, Error> {
+        let cp = &mut self.constant_pool;
+        let with_code = self
+            .methods
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| m.attributes.iter().any(|a| a.name(cp) == Some("Code")))
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        if with_code.is_empty() {
+            return Ok(0);
+        }
+
+        let (class, constructor) = exception_constructor(cp)?;
+        let body = || {
+            AttributeInfo::new_code(
+                0,
+                0,
+                vec![
+                    Instruction::New(class),
+                    Instruction::Dup,
+                    Instruction::Invokespecial(constructor),
+                    Instruction::Athrow,
+                ],
+                Vec::new(),
+                Vec::new(),
+            )
+        };
+        for &i in with_code.iter() {
+            let m = &mut self.methods[i];
+            for a in m.attributes.iter_mut() {
+                if a.name(cp) == Some("Code") {
+                    a.info = body()?;
+                }
+            }
+            m.recompute_maxes(cp)?;
+        }
+        Ok(with_code.len())
+    }
+}
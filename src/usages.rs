@@ -0,0 +1,133 @@
+use std::collections::BTreeSet;
+
+use crate::{
+    instruction::decode_code, AttributeInfo, CPIndex, ClassIndex, ConstantPool, ConstantPoolEntry,
+    JavaClass, Method, NameAndTypeIndex,
+};
+
+// an instruction referencing a constant
+#[derive(Debug)]
+pub struct UsageSite {
+    // index of the method in JavaClass::methods
+    pub method: usize,
+    pub offset: u32,
+    pub mnemonic: &'static str,
+    // the index in the instruction, which is either the constant or a String or Class entry
+    // pointing at it
+    pub index: CPIndex,
+}
+
+// class.name and the descriptor of a MethodRef or InterfaceMethodRef entry
+pub fn method_ref(
+    cp: &ConstantPool,
+    class_index: ClassIndex,
+    name_and_type_index: NameAndTypeIndex,
+) -> Option<(String, &str)> {
+    let (name, descriptor) = name_and_type_index.resolve(cp).ok()?;
+    Some((
+        format!("{}.{}", class_index.name(cp).ok()?, name.resolve(cp).ok()?),
+        descriptor.resolve(cp).ok()?,
+    ))
+}
+
+impl ConstantPool {
+    // the MethodRef and InterfaceMethodRef entries for method, written class.name or
+    // class.name:descriptor (like java/io/PrintStream.println)
+    pub fn find_method_refs(&self, method: &str) -> Vec<CPIndex> {
+        self.method_refs()
+            .filter(|(_, class_index, name_and_type_index)| {
+                method_ref(self, *class_index, *name_and_type_index).is_some_and(
+                    |(name, descriptor)| {
+                        method == name || method == format!("{}:{}", name, descriptor)
+                    },
+                )
+            })
+            .map(|(i, _, _)| i)
+            .collect()
+    }
+}
+
+impl JavaClass {
+    // Every instruction referencing target, directly or through the String or Class entry it is
+    // the Utf8 of, in offset order for each method. Methods whose code can't be decoded are
+    // skipped.
+    pub fn find_usages(&self, target: CPIndex) -> Vec<UsageSite> {
+        let cp = &self.constant_pool;
+        let matches = |index: CPIndex| {
+            index == target
+                || match cp.get(index) {
+                    Some(ConstantPoolEntry::String { string_index }) => string_index.0 == target,
+                    Some(ConstantPoolEntry::Class { name_index }) => name_index.0 == target,
+                    _ => false,
+                }
+        };
+
+        let mut res = Vec::new();
+        for (method, m) in self.methods.iter().enumerate() {
+            let code = m.attributes.iter().find_map(|a| match &a.info {
+                AttributeInfo::Code { code, .. } => {
+                    Some(code.iter().map(|b| b.0).collect::<Vec<_>>())
+                }
+                _ => None,
+            });
+            let Some(Ok(instructions)) = code.map(|c| decode_code(&c)) else {
+                continue;
+            };
+            for (offset, instruction) in instructions {
+                if let Some(index) = instruction.references_cp().filter(|i| matches(*i)) {
+                    res.push(UsageSite {
+                        method,
+                        offset,
+                        mnemonic: instruction.mnemonic(),
+                        index,
+                    });
+                }
+            }
+        }
+        res
+    }
+}
+
+impl Method {
+    // The constants referenced by the instructions of the method, none if it has no code. The
+    // instructions after one that can't be decoded are skipped.
+    pub fn cp_references(&self) -> BTreeSet<CPIndex> {
+        self.attributes
+            .iter()
+            .filter_map(|a| a.info.instructions())
+            .flatten()
+            .map_while(Result::ok)
+            .filter_map(|(_, instruction)| instruction.references_cp())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        instruction::{tests::method, Instruction::*},
+        tests::fixture,
+        AccessFlags,
+    };
+
+    #[test]
+    fn method_cp_references() {
+        let class = fixture("Hello");
+        // invokespecial Object.<init>
+        assert_eq!(
+            class.methods[0].cp_references(),
+            BTreeSet::from([CPIndex(1)])
+        );
+        // getstatic System.out, ldc "Hello, world!", invokevirtual println
+        let main = class.methods[1].cp_references();
+        assert_eq!(main, BTreeSet::from([CPIndex(7), CPIndex(13), CPIndex(15)]));
+
+        // each index once, and nothing for code without constants
+        let code = vec![Ldc(CPIndex(5)), Ldc(CPIndex(5)), Pop2, Return];
+        let (m, _) = method("()V", AccessFlags::STATIC, code, vec![]);
+        assert_eq!(m.cp_references(), BTreeSet::from([CPIndex(5)]));
+        let (m, _) = method("()V", AccessFlags::STATIC, vec![Return], vec![]);
+        assert!(m.cp_references().is_empty());
+    }
+}
@@ -0,0 +1,283 @@
+use std::{collections::HashMap, io::Error};
+
+use crate::{
+    api::{unknown_flags, MemberKind},
+    retarget::PREVIEW_MINOR,
+    serialization::Serialize,
+    AccessFlags, Attribute, AttributeInfo, CPIndex, ConstantPool, ConstantPoolEntry, Field,
+    JavaClass, Method, ReferenceKind, Utf8Index,
+};
+
+// The attributes of features that were previews before being part of the language, with the
+// first major version they are read in as a preview and the first one they are read in for every
+// class. In between, only classes compiled with --enable-preview can have them.
+const PREVIEW_ATTRIBUTES: &[(&str, u16, u16)] = &[
+    // records, Java 14 and 15
+    ("Record", 58, 60),
+    // sealed classes, Java 15 and 16
+    ("PermittedSubclasses", 59, 61),
+];
+
+// the kind of constant a ConstantValue attribute of a field with this descriptor must point at
+fn constant_kind(descriptor: &str) -> Option<&'static str> {
+    Some(match descriptor {
+        "I" | "S" | "C" | "B" | "Z" => "Integer",
+        "F" => "Float",
+        "J" => "Long",
+        "D" => "Double",
+        "Ljava/lang/String;" => "String",
+        _ => return None,
+    })
+}
+
+// The kinds of entries a MethodHandle of this kind can point at (JVMS 4.4.8). InvokeStatic and
+// InvokeSpecial handles can point at interface methods from major version 52.
+fn handle_targets(kind: ReferenceKind, major_version: u16) -> &'static [&'static str] {
+    match kind {
+        ReferenceKind::GetField
+        | ReferenceKind::GetStatic
+        | ReferenceKind::PutField
+        | ReferenceKind::PutStatic => &["Fieldref"],
+        ReferenceKind::InvokeVirtual | ReferenceKind::NewInvokeSpecial => &["Methodref"],
+        ReferenceKind::InvokeStatic | ReferenceKind::InvokeSpecial if major_version >= 52 => {
+            &["Methodref", "InterfaceMethodref"]
+        }
+        ReferenceKind::InvokeStatic | ReferenceKind::InvokeSpecial => &["Methodref"],
+        ReferenceKind::InvokeInterface => &["InterfaceMethodref"],
+    }
+}
+
+// the attributes a class, field, method or Code can have at most one of (JVMS 4.7)
+const SINGLE_ATTRIBUTES: &[&str] = &[
+    "ConstantValue",
+    "Code",
+    "StackMapTable",
+    "Exceptions",
+    "InnerClasses",
+    "EnclosingMethod",
+    "Signature",
+    "SourceFile",
+    "SourceDebugExtension",
+    "RuntimeVisibleAnnotations",
+    "RuntimeInvisibleAnnotations",
+    "RuntimeVisibleParameterAnnotations",
+    "RuntimeInvisibleParameterAnnotations",
+    "RuntimeVisibleTypeAnnotations",
+    "RuntimeInvisibleTypeAnnotations",
+    "AnnotationDefault",
+    "BootstrapMethods",
+    "MethodParameters",
+    "Module",
+    "ModulePackages",
+    "ModuleMainClass",
+    "NestHost",
+    "NestMembers",
+    "Record",
+    "PermittedSubclasses",
+];
+
+// errors on the first attribute of attributes that is there more than once but can't be, what
+// is like "method foo()V"
+fn validate_single(attributes: &[Attribute], cp: &ConstantPool, what: &str) -> Result<(), Error> {
+    for &name in SINGLE_ATTRIBUTES {
+        let count = attributes
+            .iter()
+            .filter(|a| a.name(cp) == Some(name))
+            .count();
+        if count > 1 {
+            return Err(Error::other(format!(
+                "{} has {} {} attributes.",
+                what, count, name
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn validate_method(method: &Method, cp: &ConstantPool) -> Result<(), Error> {
+    let what = format!(
+        "method {}{}",
+        method.name_index.resolve(cp)?,
+        method.descriptor_index.resolve(cp)?
+    );
+    validate_single(&method.attributes, cp, &what)?;
+    let code = method
+        .attributes
+        .iter()
+        .find(|a| a.name(cp) == Some("Code"));
+    for flag in [AccessFlags::ABSTRACT, AccessFlags::NATIVE] {
+        if method.access_flags.contains(flag) && code.is_some() {
+            let flag = if flag == AccessFlags::ABSTRACT {
+                "abstract"
+            } else {
+                "native"
+            };
+            return Err(Error::other(format!(
+                "{} {} has a Code attribute.",
+                flag, what
+            )));
+        }
+    }
+    if !method
+        .access_flags
+        .intersects(AccessFlags::ABSTRACT | AccessFlags::NATIVE)
+        && code.is_none()
+    {
+        return Err(Error::other(format!("{} has no Code attribute.", what)));
+    }
+    if let Some(AttributeInfo::Code { attributes, .. }) = code.map(|c| &c.info) {
+        validate_single(attributes, cp, &format!("the Code of {}", what))?;
+    }
+    Ok(())
+}
+
+fn validate_field(field: &Field, cp: &ConstantPool) -> Result<(), Error> {
+    let name = field.name_index.resolve(cp)?;
+    let descriptor = field.descriptor_index.resolve(cp)?;
+    validate_single(&field.attributes, cp, &format!("field {}", name))?;
+    for a in field.attributes.iter() {
+        let AttributeInfo::ConstantValue { index } = &a.info else {
+            continue;
+        };
+        let expected = constant_kind(descriptor).ok_or_else(|| {
+            Error::other(format!(
+                "Field {} of type {} can't have a ConstantValue.",
+                name, descriptor
+            ))
+        })?;
+        let found = cp.get(*index).map_or("nothing", ConstantPoolEntry::kind);
+        if found != expected {
+            return Err(Error::other(format!(
+                "The ConstantValue of field {} ({}) is #{} of kind {} instead of {}.",
+                name, descriptor, index.0, found, expected
+            )));
+        }
+    }
+    Ok(())
+}
+
+impl ConstantPool {
+    // The groups of identical entries, in index order. Compilers don't write the same constant
+    // twice but the format allows it, so this is only checked by validate --strict.
+    pub fn duplicates(&self) -> Vec<Vec<CPIndex>> {
+        let mut groups = HashMap::<Vec<u8>, Vec<CPIndex>>::new();
+        for (i, e) in self.iter() {
+            // entries are compared as written in the class file
+            let mut bytes = Vec::new();
+            e.serialize(&mut bytes)
+                .expect("serializing into a Vec failed");
+            groups.entry(bytes).or_default().push(i);
+        }
+        let mut res = groups
+            .into_values()
+            .filter(|g| g.len() > 1)
+            .collect::<Vec<_>>();
+        res.sort();
+        res
+    }
+}
+
+impl JavaClass {
+    // The class and members with flags reserved for their kind, described like "field x", with
+    // those flags. Like duplicate constants, they are only checked by validate --strict.
+    pub fn unknown_flags(&self) -> Vec<(String, AccessFlags)> {
+        let cp = &self.constant_pool;
+        let name = |index: Utf8Index| index.resolve(cp).unwrap_or("?");
+        let mut res = Vec::new();
+        let mut check = |flags, kind, what: String| {
+            let unknown = unknown_flags(flags, kind);
+            if !unknown.is_empty() {
+                res.push((what, unknown));
+            }
+        };
+        check(
+            self.access_flags,
+            MemberKind::Class,
+            "the class".to_string(),
+        );
+        for f in self.fields.iter() {
+            check(
+                f.access_flags,
+                MemberKind::Field,
+                format!("field {}", name(f.name_index)),
+            );
+        }
+        for m in self.methods.iter() {
+            check(
+                m.access_flags,
+                MemberKind::Method,
+                format!("method {}{}", name(m.name_index), name(m.descriptor_index)),
+            );
+        }
+        res
+    }
+    // Checks what reading a class doesn't, the first problem found is returned:
+    //   - from major version 56, the minor version is 0 or the preview one
+    //   - the attributes of preview features are only in preview classes of the versions where
+    //     they were previews
+    //   - ConstantValue attributes point at a constant of the type of their field
+    //   - MethodHandle entries point at the kind of member their reference kind is for
+    //   - the attributes that can only be there once aren't repeated
+    //   - the methods that aren't abstract or native have a Code attribute, the others don't
+    pub fn validate(&self) -> Result<(), Error> {
+        let cp = &self.constant_pool;
+        self.validate_preview()?;
+        validate_single(&self.attributes, cp, "the class")?;
+        for f in self.fields.iter() {
+            validate_field(f, cp)?;
+        }
+        for m in self.methods.iter() {
+            validate_method(m, cp)?;
+        }
+        self.validate_method_handles()
+    }
+    fn validate_method_handles(&self) -> Result<(), Error> {
+        let cp = &self.constant_pool;
+        for (index, e) in cp.iter() {
+            let ConstantPoolEntry::MethodHandle {
+                reference_kind,
+                reference_index,
+            } = e
+            else {
+                continue;
+            };
+            let expected = handle_targets(*reference_kind, self.major_version);
+            let found = cp
+                .get(*reference_index)
+                .map_or("nothing", ConstantPoolEntry::kind);
+            if !expected.contains(&found) {
+                return Err(Error::other(format!(
+                    "MethodHandle #{} of kind {} points at #{} of kind {} instead of {}.",
+                    index.0,
+                    reference_kind,
+                    reference_index.0,
+                    found,
+                    expected.join(" or ")
+                )));
+            }
+        }
+        Ok(())
+    }
+    fn validate_preview(&self) -> Result<(), Error> {
+        if self.major_version >= 56 && !matches!(self.minor_version, 0 | PREVIEW_MINOR) {
+            return Err(Error::other(format!(
+                "Minor version {} isn't allowed with major version {}, only 0 or {}.",
+                self.minor_version, self.major_version, PREVIEW_MINOR
+            )));
+        }
+        if self.is_preview() {
+            return Ok(());
+        }
+        let names = self.attribute_names();
+        for &(name, preview, since) in PREVIEW_ATTRIBUTES {
+            if (preview..since).contains(&self.major_version) && names.contains(name) {
+                return Err(Error::other(format!(
+                    "The class has a {} attribute, which needs --enable-preview (minor version {}) \
+                     at version {}.",
+                    name, PREVIEW_MINOR, self.major_version
+                )));
+            }
+        }
+        Ok(())
+    }
+}
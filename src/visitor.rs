@@ -0,0 +1,217 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    instruction::Instruction, usages::method_ref, CPIndex, ConstantPool, ConstantPoolEntry, Field,
+    JavaClass, Method,
+};
+
+// Hooks called by JavaClass::accept as it walks a class in the order of the class file: the class
+// itself, its constants, its fields, then each method followed by its instructions. They all do
+// nothing by default, so a visitor only implements the ones it needs.
+pub trait ClassVisitor {
+    fn visit_class(&mut self, _class: &JavaClass) {}
+    fn visit_constant(&mut self, _cp: &ConstantPool, _index: CPIndex, _entry: &ConstantPoolEntry) {}
+    fn visit_field(&mut self, _cp: &ConstantPool, _field: &Field) {}
+    fn visit_method(&mut self, _cp: &ConstantPool, _method: &Method) {}
+    // an instruction of method with its offset, code that can't be decoded is visited up to the
+    // instruction that fails
+    fn visit_instruction(
+        &mut self,
+        _cp: &ConstantPool,
+        _method: &Method,
+        _offset: u32,
+        _instruction: &Instruction,
+    ) {
+    }
+}
+
+impl JavaClass {
+    // walks the class, calling the hooks of visitor (see ClassVisitor)
+    pub fn accept(&self, visitor: &mut impl ClassVisitor) {
+        let cp = &self.constant_pool;
+        visitor.visit_class(self);
+        for (index, entry) in cp.iter() {
+            visitor.visit_constant(cp, index, entry);
+        }
+        for f in self.fields.iter() {
+            visitor.visit_field(cp, f);
+        }
+        for m in self.methods.iter() {
+            visitor.visit_method(cp, m);
+            let code = m.attributes.iter().filter_map(|a| a.info.instructions());
+            for instruction in code.flatten() {
+                let Ok((offset, instruction)) = instruction else {
+                    break;
+                };
+                visitor.visit_instruction(cp, m, offset, &instruction);
+            }
+        }
+    }
+}
+
+// A sample visitor counting the invoke instructions by the method they call, written
+// class.name:descriptor. invokedynamic has no such method and isn't counted.
+#[derive(Debug, Default)]
+pub struct InvocationCounter {
+    pub counts: BTreeMap<String, usize>,
+}
+
+impl ClassVisitor for InvocationCounter {
+    fn visit_instruction(
+        &mut self,
+        cp: &ConstantPool,
+        _method: &Method,
+        _offset: u32,
+        instruction: &Instruction,
+    ) {
+        let (Instruction::Invokevirtual(index)
+        | Instruction::Invokespecial(index)
+        | Instruction::Invokestatic(index)
+        | Instruction::Invokeinterface(index, _)) = instruction
+        else {
+            return;
+        };
+        let Some(
+            ConstantPoolEntry::MethodRef {
+                class_index,
+                name_and_type_index,
+            }
+            | ConstantPoolEntry::InterfaceMethodRef {
+                class_index,
+                name_and_type_index,
+            },
+        ) = cp.get(*index)
+        else {
+            return;
+        };
+        if let Some((name, descriptor)) = method_ref(cp, *class_index, *name_and_type_index) {
+            *self
+                .counts
+                .entry(format!("{}:{}", name, descriptor))
+                .or_default() += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        instruction::tests::method, tests::fixture, AccessFlags, AttributeInfo, ClassIndex,
+        CodeByte,
+    };
+
+    // records what it visits
+    #[derive(Default)]
+    struct Recorder(Vec<String>);
+
+    impl ClassVisitor for Recorder {
+        fn visit_class(&mut self, _class: &JavaClass) {
+            self.0.push("class".to_owned());
+        }
+        fn visit_constant(
+            &mut self,
+            _cp: &ConstantPool,
+            index: CPIndex,
+            _entry: &ConstantPoolEntry,
+        ) {
+            self.0.push(format!("constant {}", index.0));
+        }
+        fn visit_field(&mut self, cp: &ConstantPool, field: &Field) {
+            self.0
+                .push(format!("field {}", field.name_index.resolve(cp).unwrap()));
+        }
+        fn visit_method(&mut self, cp: &ConstantPool, method: &Method) {
+            self.0
+                .push(format!("method {}", method.name_index.resolve(cp).unwrap()));
+        }
+        fn visit_instruction(
+            &mut self,
+            _cp: &ConstantPool,
+            _method: &Method,
+            offset: u32,
+            instruction: &Instruction,
+        ) {
+            self.0.push(format!("{} {:?}", offset, instruction));
+        }
+    }
+
+    #[test]
+    fn invocations() {
+        let mut counter = InvocationCounter::default();
+        fixture("Calls").accept(&mut counter);
+        let counts: Vec<_> = counter
+            .counts
+            .iter()
+            .map(|(m, c)| (m.as_str(), *c))
+            .collect();
+        // the invokedynamic of main isn't there, the lambda's call to fact is
+        assert_eq!(
+            counts,
+            [
+                ("Calls.fact:(I)I", 2),
+                ("Calls.helper:()I", 1),
+                ("Calls.spin:(I)I", 1),
+                ("java/io/PrintStream.println:(I)V", 1),
+                ("java/io/PrintStream.println:(Ljava/lang/String;)V", 1),
+                ("java/lang/Object.<init>:()V", 1),
+                ("java/util/function/IntSupplier.getAsInt:()I", 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn order() {
+        let class = fixture("Fields");
+        let mut recorder = Recorder::default();
+        class.accept(&mut recorder);
+        let events = recorder.0;
+
+        let constants = class.constant_pool.iter().count();
+        assert_eq!(events[0], "class");
+        assert!(events[1..=constants]
+            .iter()
+            .all(|e| e.starts_with("constant ")));
+        let rest = &events[constants + 1..];
+        let fields = class.fields.len();
+        assert!(fields > 0);
+        assert!(rest[..fields].iter().all(|e| e.starts_with("field ")));
+        // each method followed by its instructions
+        let rest = &rest[fields..];
+        assert!(rest[0].starts_with("method "));
+        let methods = rest.iter().filter(|e| e.starts_with("method ")).count();
+        assert_eq!(methods, class.methods.len());
+        assert!(rest
+            .iter()
+            .all(|e| !e.starts_with("field ") && !e.starts_with("constant ")));
+    }
+
+    #[test]
+    fn undecodable_code() {
+        let code = vec![Instruction::Nop, Instruction::Return];
+        let (mut m, cp) = method("()V", AccessFlags::STATIC, code, vec![]);
+        // an unknown opcode then a valid one
+        if let AttributeInfo::Code { code, .. } = &mut m.attributes[0].info {
+            code.extend([CodeByte(0xcb), CodeByte(0x00)]);
+        }
+        let class = JavaClass {
+            magic_bytes: 0xCAFEBABE,
+            minor_version: 0,
+            major_version: 52,
+            constant_pool: cp,
+            access_flags: AccessFlags::PUBLIC,
+            this_class: ClassIndex(CPIndex(0)),
+            super_class: None,
+            interfaces: vec![],
+            fields: vec![],
+            methods: vec![m],
+            attributes: vec![],
+        };
+        let mut recorder = Recorder::default();
+        class.accept(&mut recorder);
+        assert_eq!(
+            recorder.0[recorder.0.len() - 3..],
+            ["method m", "0 Nop", "1 Return"]
+        );
+    }
+}
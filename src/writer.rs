@@ -0,0 +1,411 @@
+use std::io::{Error, Write};
+
+use crate::{
+    retarget::ClassVersion, serialization::Serialize, AccessFlags, Attribute, CPIndex, ClassIndex,
+    ConstantPoolEntry, Field, Method,
+};
+
+// the part of the class the writer is at, with how many items of it are left
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Section {
+    ConstantPoolCount,
+    // the next index, and the size given for the pool
+    ConstantPool { next: u16, size: u16 },
+    Header,
+    FieldsCount,
+    Fields(u16),
+    MethodsCount,
+    Methods(u16),
+    AttributesCount,
+    Attributes(u16),
+}
+
+// Writes a class file part by part as it is built, in the order of the format: the constant pool
+// entry by entry, the header (access flags, this and super class and interfaces), then the
+// fields, methods and attributes, each list preceded by its count. Writing a part out of order,
+// or more or fewer items than announced, is an error.
+pub struct ClassFileWriter<W: Write> {
+    writer: W,
+    section: Section,
+    // reused for serializing each part
+    buf: Vec<u8>,
+}
+
+impl<W: Write> ClassFileWriter<W> {
+    // writes the magic and the version
+    pub fn new(writer: W, version: ClassVersion) -> Result<Self, Error> {
+        let mut res = ClassFileWriter {
+            writer,
+            section: Section::ConstantPoolCount,
+            buf: Vec::new(),
+        };
+        0xCAFEBABEu32.serialize(&mut res.buf)?;
+        version.minor.serialize(&mut res.buf)?;
+        version.major.serialize(&mut res.buf)?;
+        res.flush_buf()?;
+        Ok(res)
+    }
+
+    fn flush_buf(&mut self) -> Result<(), Error> {
+        self.writer.write_all(&self.buf)?;
+        self.buf.clear();
+        Ok(())
+    }
+    // serializes a part and writes it, dropping what it serialized when it fails so that nothing
+    // of it goes out with the next part
+    fn write_part(
+        &mut self,
+        part: impl FnOnce(&mut Vec<u8>) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let len = self.buf.len();
+        if let Err(e) = part(&mut self.buf) {
+            self.buf.truncate(len);
+            return Err(e);
+        }
+        self.flush_buf()
+    }
+    fn expect(&self, section: Section, what: &str) -> Result<(), Error> {
+        if self.section != section {
+            return Err(Error::other(format!(
+                "Can't write {} while at {:?}.",
+                what, self.section
+            )));
+        }
+        Ok(())
+    }
+    // the section after a list of count items, skipping the list when it's empty
+    fn list(count: u16, items: fn(u16) -> Section, after: Section) -> Section {
+        if count == 0 {
+            after
+        } else {
+            items(count)
+        }
+    }
+    // writes one item of the current list
+    fn item(
+        &mut self,
+        what: &str,
+        item: &impl Serialize,
+        list: fn(&Section) -> Option<u16>,
+        items: fn(u16) -> Section,
+        after: Section,
+    ) -> Result<(), Error> {
+        let left = list(&self.section).ok_or_else(|| {
+            Error::other(format!("Can't write {} while at {:?}.", what, self.section))
+        })?;
+        self.write_part(|buf| item.serialize(buf))?;
+        self.section = Self::list(left - 1, items, after);
+        Ok(())
+    }
+
+    // The constant_pool_count as written in the class file: the number of slots (Long and Double
+    // entries take two) plus one.
+    pub fn begin_constant_pool(&mut self, size: u16) -> Result<(), Error> {
+        self.expect(Section::ConstantPoolCount, "the constant pool count")?;
+        if size == 0 {
+            return Err(Error::other("The constant pool count can't be 0."));
+        }
+        size.serialize(&mut self.buf)?;
+        self.flush_buf()?;
+        self.section = match size {
+            1 => Section::Header,
+            size => Section::ConstantPool { next: 1, size },
+        };
+        Ok(())
+    }
+    // writes the next entry, returns its index
+    pub fn constant(&mut self, entry: &ConstantPoolEntry) -> Result<CPIndex, Error> {
+        let Section::ConstantPool { next, size } = self.section else {
+            return Err(Error::other(format!(
+                "Can't write a constant while at {:?}.",
+                self.section
+            )));
+        };
+        let end = next as u32 + entry.size() as u32;
+        if end > size as u32 {
+            return Err(Error::other(format!(
+                "{} at #{} doesn't fit in a constant pool of size {}.",
+                entry.kind(),
+                next,
+                size
+            )));
+        }
+        self.write_part(|buf| entry.serialize(buf))?;
+        self.section = if end == size as u32 {
+            Section::Header
+        } else {
+            Section::ConstantPool {
+                next: end as u16,
+                size,
+            }
+        };
+        Ok(CPIndex(next))
+    }
+    pub fn header(
+        &mut self,
+        access_flags: AccessFlags,
+        this_class: ClassIndex,
+        super_class: Option<ClassIndex>,
+        interfaces: &[ClassIndex],
+    ) -> Result<(), Error> {
+        self.expect(Section::Header, "the header")?;
+        self.write_part(|buf| {
+            access_flags.serialize(buf)?;
+            this_class.serialize(buf)?;
+            super_class.map(|c| c.0).serialize(buf)?;
+            u16::try_from(interfaces.len())
+                .map_err(|_| Error::other("Too many interfaces."))?
+                .serialize(buf)?;
+            for i in interfaces {
+                i.serialize(buf)?;
+            }
+            Ok(())
+        })?;
+        self.section = Section::FieldsCount;
+        Ok(())
+    }
+    pub fn begin_fields(&mut self, count: u16) -> Result<(), Error> {
+        self.expect(Section::FieldsCount, "the fields count")?;
+        count.serialize(&mut self.buf)?;
+        self.flush_buf()?;
+        self.section = Self::list(count, Section::Fields, Section::MethodsCount);
+        Ok(())
+    }
+    pub fn field(&mut self, field: &Field) -> Result<(), Error> {
+        self.item(
+            "a field",
+            field,
+            |s| match s {
+                Section::Fields(left) => Some(*left),
+                _ => None,
+            },
+            Section::Fields,
+            Section::MethodsCount,
+        )
+    }
+    pub fn begin_methods(&mut self, count: u16) -> Result<(), Error> {
+        self.expect(Section::MethodsCount, "the methods count")?;
+        count.serialize(&mut self.buf)?;
+        self.flush_buf()?;
+        self.section = Self::list(count, Section::Methods, Section::AttributesCount);
+        Ok(())
+    }
+    pub fn method(&mut self, method: &Method) -> Result<(), Error> {
+        self.item(
+            "a method",
+            method,
+            |s| match s {
+                Section::Methods(left) => Some(*left),
+                _ => None,
+            },
+            Section::Methods,
+            Section::AttributesCount,
+        )
+    }
+    pub fn begin_attributes(&mut self, count: u16) -> Result<(), Error> {
+        self.expect(Section::AttributesCount, "the attributes count")?;
+        count.serialize(&mut self.buf)?;
+        self.flush_buf()?;
+        // the writer is done once the last attribute is written
+        self.section = Self::list(count, Section::Attributes, Section::Attributes(0));
+        Ok(())
+    }
+    pub fn attribute(&mut self, attribute: &Attribute) -> Result<(), Error> {
+        self.item(
+            "an attribute",
+            attribute,
+            |s| match s {
+                Section::Attributes(left) if *left > 0 => Some(*left),
+                _ => None,
+            },
+            Section::Attributes,
+            Section::Attributes(0),
+        )
+    }
+    // checks that the whole class was written, and returns the writer
+    pub fn finish(mut self) -> Result<W, Error> {
+        self.expect(Section::Attributes(0), "the end of the class")?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tests::fixture, AttributeInfo, JavaClass};
+
+    // writes class part by part
+    fn write(class: &JavaClass) -> Result<Vec<u8>, Error> {
+        let version = ClassVersion {
+            major: class.major_version,
+            minor: class.minor_version,
+        };
+        let mut writer = ClassFileWriter::new(Vec::new(), version)?;
+        writer.begin_constant_pool(class.constant_pool.size())?;
+        for (_, entry) in class.constant_pool.iter() {
+            writer.constant(entry)?;
+        }
+        writer.header(
+            class.access_flags,
+            class.this_class,
+            class.super_class,
+            &class.interfaces,
+        )?;
+        writer.begin_fields(class.fields.len() as u16)?;
+        for field in &class.fields {
+            writer.field(field)?;
+        }
+        writer.begin_methods(class.methods.len() as u16)?;
+        for method in &class.methods {
+            writer.method(method)?;
+        }
+        writer.begin_attributes(class.attributes.len() as u16)?;
+        for attribute in &class.attributes {
+            writer.attribute(attribute)?;
+        }
+        writer.finish()
+    }
+
+    fn serialized(class: &JavaClass) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        class.serialize(&mut bytes).unwrap();
+        bytes
+    }
+
+    // a writer at the fields of an empty pool class
+    fn at_fields() -> ClassFileWriter<Vec<u8>> {
+        let version = ClassVersion {
+            major: 52,
+            minor: 0,
+        };
+        let mut writer = ClassFileWriter::new(Vec::new(), version).unwrap();
+        writer.begin_constant_pool(1).unwrap();
+        writer
+            .header(AccessFlags::PUBLIC, ClassIndex(CPIndex(0)), None, &[])
+            .unwrap();
+        writer
+    }
+
+    #[test]
+    fn same_as_serialize() {
+        for name in ["Hello", "Constants", "Flow", "Annotated", "Point"] {
+            let class = fixture(name);
+            assert_eq!(write(&class).unwrap(), serialized(&class), "{}", name);
+        }
+    }
+
+    #[test]
+    fn out_of_order() {
+        let class = fixture("Hello");
+        let version = ClassVersion {
+            major: 52,
+            minor: 0,
+        };
+        let mut writer = ClassFileWriter::new(Vec::new(), version).unwrap();
+        assert!(writer.method(&class.methods[0]).is_err());
+        assert!(writer
+            .header(AccessFlags::PUBLIC, ClassIndex(CPIndex(0)), None, &[])
+            .is_err());
+        writer.begin_constant_pool(1).unwrap();
+        assert!(writer.begin_constant_pool(1).is_err());
+        assert!(writer
+            .constant(&ConstantPoolEntry::Utf8("a".into()))
+            .is_err());
+        assert!(writer.begin_methods(0).is_err());
+
+        let mut writer = at_fields();
+        writer.begin_fields(0).unwrap();
+        let field = Field {
+            access_flags: AccessFlags::PUBLIC,
+            name_index: class.methods[0].name_index,
+            descriptor_index: class.methods[0].descriptor_index,
+            attributes: Vec::new(),
+        };
+        assert!(writer.field(&field).is_err());
+        assert!(writer.begin_attributes(0).is_err());
+        writer.begin_methods(0).unwrap();
+        assert!(writer.finish().is_err());
+    }
+
+    #[test]
+    fn count_mismatch() {
+        let class = fixture("Hello");
+        let method = &class.methods[0];
+
+        // fewer items than announced
+        let mut writer = at_fields();
+        writer.begin_fields(0).unwrap();
+        writer.begin_methods(2).unwrap();
+        writer.method(method).unwrap();
+        assert!(writer.begin_attributes(0).is_err());
+
+        // more
+        let mut writer = at_fields();
+        writer.begin_fields(0).unwrap();
+        writer.begin_methods(1).unwrap();
+        writer.method(method).unwrap();
+        assert!(writer.method(method).is_err());
+        writer.begin_attributes(1).unwrap();
+        assert!(writer.finish().is_err());
+
+        // constants past the pool size
+        let version = ClassVersion {
+            major: 52,
+            minor: 0,
+        };
+        let mut writer = ClassFileWriter::new(Vec::new(), version).unwrap();
+        writer.begin_constant_pool(2).unwrap();
+        assert!(writer.constant(&ConstantPoolEntry::Long(1)).is_err());
+        writer.constant(&ConstantPoolEntry::Integer(1)).unwrap();
+        assert!(writer.constant(&ConstantPoolEntry::Integer(2)).is_err());
+    }
+
+    #[test]
+    fn failed_item_not_written() {
+        let class = fixture("Hello");
+        let mut bad = class.methods[0].clone();
+        let attribute = Attribute {
+            name_index: bad.name_index,
+            info: AttributeInfo::Any(Vec::new()),
+        };
+        // one more attribute than the count can hold, after the start of the method
+        bad.attributes = vec![attribute; u16::MAX as usize + 1];
+
+        let version = ClassVersion {
+            major: class.major_version,
+            minor: class.minor_version,
+        };
+        let mut writer = ClassFileWriter::new(Vec::new(), version).unwrap();
+        writer
+            .begin_constant_pool(class.constant_pool.size())
+            .unwrap();
+        for (_, entry) in class.constant_pool.iter() {
+            writer.constant(entry).unwrap();
+        }
+        writer
+            .header(
+                class.access_flags,
+                class.this_class,
+                class.super_class,
+                &class.interfaces,
+            )
+            .unwrap();
+        writer.begin_fields(class.fields.len() as u16).unwrap();
+        for field in &class.fields {
+            writer.field(field).unwrap();
+        }
+        writer.begin_methods(class.methods.len() as u16).unwrap();
+        assert!(writer.method(&bad).is_err());
+        for method in &class.methods {
+            writer.method(method).unwrap();
+        }
+        writer
+            .begin_attributes(class.attributes.len() as u16)
+            .unwrap();
+        for attribute in &class.attributes {
+            writer.attribute(attribute).unwrap();
+        }
+        assert_eq!(writer.finish().unwrap(), serialized(&class));
+    }
+}
@@ -0,0 +1,441 @@
+// Output of the inspection subcommands on the fixture classes.
+
+mod common;
+
+use common::{fixture, tmp, write_class};
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+// runs javd with args, the path to the class, then rest
+fn javd(args: &[&str], class: &str, rest: &[&str]) -> String {
+    let output = common::javd(
+        args.iter()
+            .map(|a| a.as_ref())
+            .chain([fixture(class).as_os_str()])
+            .chain(rest.iter().map(|a| a.as_ref())),
+    );
+    assert!(
+        output.status.success(),
+        "javd {:?} {} failed: {}",
+        args,
+        class,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn pool_matches_javap() {
+    let out = javd(&["pool"], "Constants", &[]);
+    for line in [
+        "   #1 = Methodref          #2.#3          // java/lang/Object.\"<init>\":()V",
+        "   #7 = Fieldref           #8.#9          // Constants.counter:J",
+        "  #13 = Double             2.25d",
+        "  #15 = Long               1234567890123l",
+        "  #24 = Float              1.5f",
+        "  #29 = String             #30            // constant",
+        "  #36 = Utf8               Constants.java",
+    ] {
+        assert!(out.lines().any(|l| l == line), "missing {:?} in\n{}", line, out);
+    }
+}
+
+#[test]
+fn uses_by_index() {
+    // the Utf8 is used through the String entry #13
+    let out = javd(&["uses"], "Hello", &["#14"]);
+    assert_eq!(out, "main([Ljava/lang/String;)V 3: ldc #13\n");
+    let out = javd(&["uses"], "Hello", &["#1"]);
+    assert_eq!(out, "<init>()V 1: invokespecial #1\n");
+}
+
+#[test]
+fn uses_by_method() {
+    let out = javd(&["uses", "--method", "java/io/PrintStream.println"], "Hello", &[]);
+    assert_eq!(out, "main([Ljava/lang/String;)V 5: invokevirtual #15\n");
+    let out = javd(
+        &["uses", "--method", "java/io/PrintStream.println:()V"],
+        "Hello",
+        &[],
+    );
+    assert_eq!(out, "");
+}
+
+#[test]
+fn deps_from_descriptors() {
+    let out = javd(&["deps"], "Deps", &[]);
+    assert_eq!(
+        out,
+        "Hello\njava/lang/Object\njava/util/UUID\njava/util/concurrent/TimeUnit\n"
+    );
+}
+
+// writes a jar named name holding the fixture classes, returns its path
+fn jar(name: &str, classes: &[&str]) -> PathBuf {
+    jar_with_manifest(name, classes, None)
+}
+
+fn jar_with_manifest(name: &str, classes: &[&str], manifest: Option<&str>) -> PathBuf {
+    let jar = tmp(&format!("{}.jar", name));
+    let mut zip = zip::ZipWriter::new(fs::File::create(&jar).unwrap());
+    if let Some(manifest) = manifest {
+        zip.start_file("META-INF/MANIFEST.MF", Default::default())
+            .unwrap();
+        zip.write_all(manifest.as_bytes()).unwrap();
+    }
+    for class in classes {
+        zip.start_file(format!("{}.class", class), Default::default())
+            .unwrap();
+        zip.write_all(&fs::read(fixture(class)).unwrap()).unwrap();
+    }
+    zip.finish().unwrap();
+    jar
+}
+
+// runs javd with args then the path to the jar
+fn javd_jar(args: &[&str], jar: &Path) -> String {
+    let output = common::javd(args.iter().map(|a| a.as_ref()).chain([jar.as_os_str()]));
+    assert!(
+        output.status.success(),
+        "javd {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn deps_jar_dot() {
+    let jar = jar("deps", &["Hello", "Deps"]);
+    assert_eq!(
+        javd_jar(&["deps", "--dot", "--jar"], &jar),
+        "digraph dependencies {\n    \"Deps\" -> \"Hello\";\n}\n"
+    );
+}
+
+#[test]
+fn strings() {
+    assert_eq!(javd(&["strings"], "Constants", &[]), "constant\n");
+    let out = javd(&["strings", "--all-utf8", "--grep", "Const"], "Constants", &[]);
+    assert_eq!(out, "Constants\nConstantValue\nConstants.java\n");
+
+    // Hello and Constants both have java/lang/Object
+    let jar = jar("strings", &["Hello", "Constants"]);
+    let out = javd_jar(&["strings", "--all-utf8", "--grep", "Object"], &jar);
+    assert_eq!(out, "Hello: java/lang/Object\nConstants: java/lang/Object\n");
+    let out = javd_jar(
+        &["strings", "--all-utf8", "--grep", "Object", "--unique"],
+        &jar,
+    );
+    assert_eq!(out, "Hello: java/lang/Object\n");
+
+    // entries stored without compression are read in place
+    let stored = tmp("strings_stored.jar");
+    let mut zip = zip::ZipWriter::new(fs::File::create(&stored).unwrap());
+    let options = zip::write::FileOptions::default()
+        .compression_method(zip::CompressionMethod::Stored);
+    for class in ["Hello", "Constants"] {
+        zip.start_file(format!("{}.class", class), options).unwrap();
+        zip.write_all(&fs::read(fixture(class)).unwrap()).unwrap();
+    }
+    zip.finish().unwrap();
+    let out = javd_jar(&["strings", "--all-utf8", "--grep", "Object"], &stored);
+    assert_eq!(out, "Hello: java/lang/Object\nConstants: java/lang/Object\n");
+}
+
+#[test]
+fn piped_input() {
+    // a pipe can't be mapped, so with the mmap feature this goes through the fallback read, and
+    // the input is only read once to tell jars from classes
+    let run = |args: &[&str], input: &[u8]| {
+        let output = common::javd_stdin(args, input);
+        assert!(
+            output.status.success(),
+            "javd {:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    };
+    let constants = fs::read(fixture("Constants")).unwrap();
+    assert_eq!(run(&["strings"], &constants), "constant\n");
+    assert_eq!(run(&["pool"], &constants), javd(&["pool"], "Constants", &[]));
+    assert!(run(&["stats"], &constants).contains("classes: 1\n"));
+
+    let jar = fs::read(jar("piped", &["Hello", "Constants"])).unwrap();
+    let out = run(&["strings", "--all-utf8", "--grep", "Object"], &jar);
+    assert_eq!(out, "Hello: java/lang/Object\nConstants: java/lang/Object\n");
+}
+
+#[test]
+fn stats() {
+    let out = javd(&["stats"], "Hello", &[]);
+    for line in [
+        "methods: 2 (public 2)",
+        "code: 2 methods, 14 bytes, 7.0 average, 9 max",
+        "max_locals: 1 (x2)",
+        "    LineNumberTable 28 (6.7%)",
+        "    return 2",
+        "    ldc 1",
+    ] {
+        assert!(out.lines().any(|l| l == line), "missing {:?} in\n{}", line, out);
+    }
+
+    let jar = jar("stats", &["Hello", "Constants"]);
+    let out = javd_jar(&["stats", "--json"], &jar);
+    let json: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(json["total"]["classes"], 2);
+    assert_eq!(json["total"]["opcodes"]["return"], 3);
+    assert_eq!(json["classes"]["Hello"]["attribute_bytes"]["LineNumberTable"], 28);
+}
+
+#[test]
+fn api_signature() {
+    let out = javd(&["api"], "api1/Api", &[]);
+    assert_eq!(
+        out,
+        "class public super Api
+extends java/lang/Object
+implements java/lang/Runnable
+field public static final VERSION I
+field protected name Ljava/lang/String;
+method public <init> ()V
+method public count (Ljava/lang/String;)I
+method protected hook ()V
+method internal ()V
+method public removed ()V
+method public run ()V
+"
+    );
+}
+
+#[test]
+fn api_diff() {
+    // breaking changes make it fail, after listing every change
+    let output = common::javd([
+        "api".as_ref(),
+        fixture("api1/Api").as_os_str(),
+        "--diff".as_ref(),
+        fixture("api2/Api").as_os_str(),
+    ]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "additive: added the interface java/lang/Comparable
+breaking: changed the descriptor of method count from (Ljava/lang/String;)I to (Ljava/lang/String;)J
+breaking: made method hook ()V package instead of protected
+additive: made method internal ()V public instead of package
+breaking: removed method public removed ()V
+additive: added method public added ()V throws java/io/IOException
+additive: added method public compareTo (LApi;)I
+"
+    );
+    assert!(String::from_utf8_lossy(&output.stderr).contains("3 breaking changes"));
+
+    // the same version has no changes
+    let out = javd(
+        &["api"],
+        "api2/Api",
+        &["--diff", fixture("api2/Api").to_str().unwrap()],
+    );
+    assert_eq!(out, "");
+}
+
+#[test]
+fn info_runnable() {
+    assert!(javd(&["info"], "Hello", &[]).contains("runnable: yes\n"));
+    assert!(javd(&["info"], "Constants", &[]).contains("runnable: no\n"));
+
+    let manifest = "Manifest-Version: 1.0\r\nMain-Class: Hello\r\n\r\n";
+    let jar = jar_with_manifest("runnable", &["Hello", "Constants"], Some(manifest));
+    assert_eq!(
+        javd_jar(&["info"], &jar),
+        "classes: 2\nmain-class: Hello\nrunnable: yes\n"
+    );
+
+    // the value goes on on the next line, after a space
+    let manifest = "Manifest-Version: 1.0\r\nMain-Class: Cons\r\n tants\r\n\r\n";
+    let jar = jar_with_manifest("not-runnable", &["Hello", "Constants"], Some(manifest));
+    assert!(javd_jar(&["info"], &jar).ends_with(
+        "runnable: no (manifest says Constants but that class has no main)\n"
+    ));
+}
+
+#[test]
+fn callgraph() {
+    let out = javd(&["callgraph"], "Calls", &[]);
+    for line in [
+        // recursion
+        "fact(I)I -> fact(I)I",
+        // the invokedynamic leads to the bootstrap method and the lambda body
+        "main([Ljava/lang/String;)V -> java/lang/invoke/LambdaMetafactory.metafactory:(Ljava/lang/invoke/MethodHandles$Lookup;Ljava/lang/String;Ljava/lang/invoke/MethodType;Ljava/lang/invoke/MethodType;Ljava/lang/invoke/MethodHandle;Ljava/lang/invoke/MethodType;)Ljava/lang/invoke/CallSite;",
+        "main([Ljava/lang/String;)V -> lambda$main$0()I",
+        "lambda$main$0()I -> helper()I",
+    ] {
+        assert!(out.lines().any(|l| l == line), "missing {:?} in\n{}", line, out);
+    }
+
+    let out = javd(&["callgraph", "--dot"], "Calls", &[]);
+    assert!(out.starts_with("digraph \"Calls\" {\n"));
+    assert!(out.contains("    \"fact(I)I\" -> \"fact(I)I\";\n"));
+    assert!(out.contains(
+        "    \"unused()V\" -> \"java/io/PrintStream.println:(Ljava/lang/String;)V\" [style=dashed];\n"
+    ));
+
+    // spin only calls itself
+    let out = javd(&["callgraph", "--unreachable"], "Calls", &[]);
+    assert_eq!(out, "spin(I)I\nunused()V\n");
+}
+
+#[test]
+fn fields_access() {
+    let out = javd(&["fields"], "Fields", &[]);
+    assert!(out.starts_with("private static lock:Ljava/lang/Object;\n"));
+    assert!(out.contains("private final name:Ljava/lang/String;\n"));
+
+    let out = javd(&["fields", "--access"], "Fields", &[]);
+    for section in [
+        // static and instance fields only set by their initializer
+        "lock:Ljava/lang/Object; (could be final)\n    read by bump()V at 0\n    written by <clinit>()V at 7\n",
+        "id:I (could be final)\n    read by count()I at 5\n    written by <init>(Ljava/lang/String;I)V at 11\n",
+        // written outside of <clinit>
+        "created:I\n",
+        "count:I\n    read by bump()V at 8\n    read by count()I at 1\n    written by bump()V at 13\n",
+        "lastBump:J (written but never read)\n",
+        "unused:Z (never accessed)\n",
+    ] {
+        assert!(out.contains(section), "missing {:?} in\n{}", section, out);
+    }
+    // other classes' fields come last
+    assert!(out.ends_with("java/lang/System.out:Ljava/io/PrintStream;\n    read by print()V at 0\n"));
+}
+
+#[test]
+fn cfg() {
+    // the loop condition is its own block, the body goes back to it
+    let out = javd(&["cfg"], "Flow", &["--method", "sum"]);
+    assert!(out.contains("    b1 [label=\"4: iload_2\\l5: iload_0\\l6: if_icmpge\\l\"];\n"));
+    for edge in [
+        "b0 -> b1;",
+        "b1 -> b3 [label=\"branch\"];",
+        "b1 -> b2;",
+        "b2 -> b1 [label=\"branch\"];",
+    ] {
+        assert!(out.contains(edge), "missing {:?} in\n{}", edge, out);
+    }
+
+    // every case and the default
+    let out = javd(&["cfg"], "Flow", &["--method", "name(I)Ljava/lang/String;"]);
+    assert_eq!(out.matches("[label=\"switch\"]").count(), 4);
+
+    let out = javd(&["cfg"], "Flow", &["--method", "parse"]);
+    assert!(out.contains("    b0 -> b2 [label=\"exception\", style=dashed];\n"));
+    assert!(!out.contains("b1 -> b2"));
+}
+
+#[test]
+fn stub() {
+    let class = tmp("Hello.stub.class");
+    javd(&["stub"], "Hello", &["-o", class.to_str().unwrap()]);
+    let out = javd_jar(&["stats"], &class);
+    // the constructor and main both throw, without their LineNumberTable
+    for line in [
+        "code: 2 methods, 16 bytes, 8.0 average, 8 max",
+        "max_stack: 2 (x2)",
+        "    athrow 2",
+    ] {
+        assert!(out.lines().any(|l| l == line), "missing {:?} in\n{}", line, out);
+    }
+    assert!(!out.contains("LineNumberTable"));
+
+    let jar = jar("stub", &["Hello", "Point"]);
+    let stubs = jar.with_extension("stubs.jar");
+    javd_jar(&["stub", "-o", stubs.to_str().unwrap()], &jar);
+    let json: serde_json::Value =
+        serde_json::from_str(&javd_jar(&["stats", "--json"], &stubs)).unwrap();
+    let total = &json["total"];
+    assert_eq!(total["opcodes"]["athrow"], total["code_methods"]);
+    assert_eq!(total["opcodes"].as_object().unwrap().len(), 4);
+}
+
+#[test]
+fn stub_full_constant_pool() {
+    // a class A with a method m()V that returns, and Integer entries filling the pool up to the
+    // largest count, so the constants of the stub body don't fit
+    let utf8 = |s: &str| [&[1, 0, s.len() as u8][..], s.as_bytes()].concat();
+    let mut b = Vec::new();
+    b.extend(0xCAFEBABEu32.to_be_bytes());
+    b.extend([0, 0, 0, 52]);
+    b.extend(u16::MAX.to_be_bytes());
+    b.extend(utf8("A"));
+    b.extend([7, 0, 1]);
+    b.extend(utf8("java/lang/Object"));
+    b.extend([7, 0, 3]);
+    b.extend(utf8("m"));
+    b.extend(utf8("()V"));
+    b.extend(utf8("Code"));
+    for i in 8..u16::MAX as u32 {
+        b.push(3);
+        b.extend(i.to_be_bytes());
+    }
+    b.extend([0, 0x21, 0, 2, 0, 4, 0, 0, 0, 0]); // flags, this, super, interfaces, fields
+    b.extend([0, 1, 0, 1, 0, 5, 0, 6, 0, 1]); // one method m()V with one attribute
+    b.extend([0, 7, 0, 0, 0, 13, 0, 0, 0, 1, 0, 0, 0, 1, 0xb1, 0, 0, 0, 0]); // Code: return
+    b.extend([0, 0]); // attributes
+
+    let class = write_class("FullPool", &b);
+    let output = common::javd([
+        "stub".as_ref(),
+        class.as_os_str(),
+        "-o".as_ref(),
+        tmp("FullPool.stub.class").as_os_str(),
+    ]);
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "error: Constant pool too large: no room for a Utf8 after 65535 slots.\n"
+    );
+}
+
+#[test]
+fn print_method_handles() {
+    // the bootstrap method of the lambda, and its body
+    let out = javd(&["print"], "Calls", &[]);
+    for line in [
+        "      0054: (methodhandle invokestatic java/lang/invoke/LambdaMetafactory.metafactory:(Ljava/lang/invoke/MethodHandles$Lookup;Ljava/lang/String;Ljava/lang/invoke/MethodType;Ljava/lang/invoke/MethodType;Ljava/lang/invoke/MethodHandle;Ljava/lang/invoke/MethodType;)Ljava/lang/invoke/CallSite;)",
+        "      0062: (methodhandle invokestatic Calls.lambda$main$0:()I)",
+    ] {
+        assert!(out.lines().any(|l| l == line), "missing {:?} in\n{}", line, out);
+    }
+}
+
+#[test]
+fn print_show_indices() {
+    let out = javd(&["print"], "Hello", &[]);
+    assert!(out.contains(
+        "      0001: (methodref (class 'java/lang/Object') (nameandtype name='<init>' descriptor='()V'))\n"
+    ));
+    assert!(!out.contains('@'));
+
+    let out = javd(&["print", "--show-indices"], "Hello", &[]);
+    for line in [
+        "      0001: (methodref (class 'java/lang/Object'@0004)@0002 (nameandtype name='<init>'@0005 descriptor='()V'@0006)@0003)",
+        "    super_class: (class 'java/lang/Object'@0004)@0002",
+        "    '<init>'@0005: PUBLIC ('()V'@0006) // void <init>()",
+    ] {
+        assert!(out.lines().any(|l| l == line), "missing {:?} in\n{}", line, out);
+    }
+
+    let out = javd(&["print", "--show-indices=only"], "Hello", &[]);
+    for line in [
+        "      0001: (methodref @0002 @0003)",
+        "      0003: (nameandtype name=@0005 descriptor=@0006)",
+        "    super_class: @0002",
+        "    @0005: PUBLIC (@0006) // void <init>()",
+    ] {
+        assert!(out.lines().any(|l| l == line), "missing {:?} in\n{}", line, out);
+    }
+}
@@ -0,0 +1,199 @@
+// Helpers shared by the tests: building class files by hand and running javd on them.
+#![allow(dead_code)]
+
+use std::{
+    ffi::OsStr,
+    fs,
+    path::PathBuf,
+    io::Write,
+    process::{Command, Output, Stdio},
+};
+
+pub fn u16be(v: u16) -> [u8; 2] {
+    v.to_be_bytes()
+}
+
+pub fn u32be(v: u32) -> [u8; 4] {
+    v.to_be_bytes()
+}
+
+// a Utf8 constant pool entry
+pub fn utf8(s: &str) -> Vec<u8> {
+    let mut b = vec![1];
+    b.extend(u16be(s.len() as u16));
+    b.extend(s.as_bytes());
+    b
+}
+
+// Header and constant pool of a class `A extends java/lang/Object`, followed by the `extra`
+// entries (starting at index 5), up to the interfaces. The pool count is one per entry, a Long or
+// Double entry needs it patched.
+pub fn header(extra: &[Vec<u8>]) -> Vec<u8> {
+    let mut b = Vec::new();
+    b.extend(u32be(0xCAFEBABE));
+    b.extend(u16be(0));
+    b.extend(u16be(52));
+    b.extend(u16be(5 + extra.len() as u16));
+    b.extend(utf8("A"));
+    b.extend([7, 0, 1]);
+    b.extend(utf8("java/lang/Object"));
+    b.extend([7, 0, 3]);
+    for e in extra {
+        b.extend(e);
+    }
+    b.extend(u16be(0x0021)); // access flags
+    b.extend(u16be(2)); // this_class
+    b.extend(u16be(4)); // super_class
+    b.extend(u16be(0)); // interfaces
+    b
+}
+
+// the path of a checked-in fixture class
+pub fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(format!("{}.class", name))
+}
+
+// a path in the directory cargo gives integration tests for their files
+pub fn tmp(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name)
+}
+
+// writes class to <name>.class in the tmp directory
+pub fn write_class(name: &str, class: &[u8]) -> PathBuf {
+    let path = tmp(&format!("{}.class", name));
+    fs::write(&path, class).unwrap();
+    path
+}
+
+pub fn javd<I, S>(args: I) -> Output
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    Command::new(env!("CARGO_BIN_EXE_javd"))
+        .args(args)
+        .output()
+        .expect("failed to run javd")
+}
+
+// runs javd with input written to its stdin, which it reads as /dev/stdin
+pub fn javd_stdin<I, S>(args: I, input: &[u8]) -> Output
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let mut child = Command::new(env!("CARGO_BIN_EXE_javd"))
+        .args(args)
+        .arg("/dev/stdin")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to run javd");
+    child.stdin.take().unwrap().write_all(input).unwrap();
+    child.wait_with_output().expect("failed to run javd")
+}
+
+// Builds a class file part by part, in the order ClassFileWriter writes them (which the tests
+// can't use, they only drive the binary): the constant pool, then the fields and the methods, with
+// the counts filled in from what was added, and no class attributes. The class is `A extends
+// java/lang/Object` like the one of header, with the same first 4 entries.
+pub struct ClassBuilder {
+    constants: Vec<u8>,
+    // the index of the next entry
+    next: u16,
+    fields: Vec<Vec<u8>>,
+    methods: Vec<Vec<u8>>,
+}
+
+impl ClassBuilder {
+    pub fn new() -> Self {
+        let mut class = ClassBuilder {
+            constants: Vec::new(),
+            next: 1,
+            fields: Vec::new(),
+            methods: Vec::new(),
+        };
+        class.class("A");
+        class.class("java/lang/Object");
+        class
+    }
+    // adds a constant pool entry (its tag and contents) and returns its index, a Long or Double
+    // takes two
+    pub fn constant(&mut self, entry: &[u8]) -> u16 {
+        let index = self.next;
+        self.constants.extend(entry);
+        self.next += if let 5 | 6 = entry[0] { 2 } else { 1 };
+        index
+    }
+    pub fn utf8(&mut self, s: &str) -> u16 {
+        self.constant(&utf8(s))
+    }
+    // a Class entry along with the Utf8 of its name
+    pub fn class(&mut self, name: &str) -> u16 {
+        let name = self.utf8(name);
+        self.constant(&[&[7][..], &u16be(name)].concat())
+    }
+    // the attributes are made with attribute
+    pub fn field(&mut self, flags: u16, name: u16, descriptor: u16, attributes: &[Vec<u8>]) {
+        self.fields.push(member(flags, name, descriptor, attributes));
+    }
+    pub fn method(&mut self, flags: u16, name: u16, descriptor: u16, attributes: &[Vec<u8>]) {
+        self.methods.push(member(flags, name, descriptor, attributes));
+    }
+    pub fn build(&self) -> Vec<u8> {
+        let mut b = Vec::new();
+        b.extend(u32be(0xCAFEBABE));
+        b.extend(u16be(0));
+        b.extend(u16be(52));
+        b.extend(u16be(self.next));
+        b.extend(&self.constants);
+        b.extend(u16be(0x0021)); // access flags
+        b.extend(u16be(2)); // this_class
+        b.extend(u16be(4)); // super_class
+        b.extend(u16be(0)); // interfaces
+        for list in [&self.fields, &self.methods] {
+            b.extend(u16be(list.len() as u16));
+            b.extend(list.concat());
+        }
+        b.extend(u16be(0)); // attributes
+        b
+    }
+}
+
+fn member(flags: u16, name: u16, descriptor: u16, attributes: &[Vec<u8>]) -> Vec<u8> {
+    let mut b = Vec::new();
+    b.extend(u16be(flags));
+    b.extend(u16be(name));
+    b.extend(u16be(descriptor));
+    b.extend(u16be(attributes.len() as u16));
+    b.extend(attributes.concat());
+    b
+}
+
+// an attribute, its body preceded by its length
+pub fn attribute(name: u16, body: &[u8]) -> Vec<u8> {
+    let mut b = Vec::new();
+    b.extend(u16be(name));
+    b.extend(u32be(body.len() as u32));
+    b.extend(body);
+    b
+}
+
+// the body of a Code attribute without attributes, handlers are start, end, handler and
+// catch_type
+pub fn code(max_stack: u16, max_locals: u16, code: &[u8], handlers: &[[u16; 4]]) -> Vec<u8> {
+    let mut b = Vec::new();
+    b.extend(u16be(max_stack));
+    b.extend(u16be(max_locals));
+    b.extend(u32be(code.len() as u32));
+    b.extend(code);
+    b.extend(u16be(handlers.len() as u16));
+    for handler in handlers {
+        b.extend(handler.iter().flat_map(|v| u16be(*v)));
+    }
+    b.extend(u16be(0)); // attributes
+    b
+}
@@ -0,0 +1,14 @@
+import java.util.List;
+import java.util.Map;
+
+// annotations on the class, a field, a method and a parameter, with generic signatures
+@Config(required = 1, name = "annotated")
+public class Annotated<T extends Comparable<T>> {
+    @Deprecated(since = "1")
+    public Map<String, List<T>> index;
+
+    @SafeVarargs
+    final List<T> of(@Deprecated T first, T... rest) {
+        return List.of(rest);
+    }
+}
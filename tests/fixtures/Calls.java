@@ -0,0 +1,26 @@
+import java.util.function.IntSupplier;
+
+public class Calls {
+    static int fact(int n) {
+        return n <= 1 ? 1 : n * fact(n - 1);
+    }
+
+    private static int helper() {
+        return 2;
+    }
+
+    // only calls itself
+    private static int spin(int n) {
+        return n == 0 ? 0 : spin(n - 1);
+    }
+
+    private static void unused() {
+        System.out.println("unused");
+    }
+
+    public static void main(String[] args) {
+        // the lambda body is only reached through the bootstrap arguments
+        IntSupplier s = () -> fact(helper());
+        System.out.println(s.getAsInt());
+    }
+}
@@ -0,0 +1,20 @@
+import java.lang.annotation.ElementType;
+import java.lang.annotation.Retention;
+import java.lang.annotation.RetentionPolicy;
+import java.lang.annotation.Target;
+
+@Retention(RetentionPolicy.RUNTIME)
+@Target(ElementType.TYPE)
+public @interface Config {
+    int timeout() default 30;
+    long size() default 1L << 40;
+    boolean enabled() default true;
+    char separator() default ',';
+    double ratio() default 0.5;
+    String name() default "config";
+    ElementType kind() default ElementType.FIELD;
+    Class<?> type() default Object.class;
+    String[] tags() default {"a", "b"};
+    Retention retention() default @Retention(RetentionPolicy.CLASS);
+    int required();
+}
@@ -0,0 +1,14 @@
+public class Constants {
+    public static final int INT = 42;
+    public static final long LONG = 1234567890123L;
+    public static final float FLOAT = 1.5f;
+    public static final double DOUBLE = 2.25;
+    public static final String STRING = "constant";
+
+    private long counter;
+
+    public long next(double scale) {
+        counter += (long) (scale * DOUBLE) + LONG;
+        return counter;
+    }
+}
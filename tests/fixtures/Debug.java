@@ -0,0 +1,21 @@
+import java.util.ArrayList;
+import java.util.List;
+
+// Compiled with `javac -g` so it carries LocalVariableTable and LocalVariableTypeTable.
+public class Debug {
+    static int total(List<Integer> values) {
+        int sum = 0;
+        for (int v : values) {
+            sum += v;
+        }
+        return sum;
+    }
+
+    static List<String> names(int n) {
+        List<String> out = new ArrayList<>();
+        for (int i = 0; i < n; i++) {
+            out.add("n" + i);
+        }
+        return out;
+    }
+}
@@ -0,0 +1,8 @@
+public abstract class Deps {
+    // TimeUnit and UUID only appear in this descriptor
+    abstract Hello hello(java.util.concurrent.TimeUnit unit, java.util.UUID[] ids);
+
+    int[] counts() {
+        return new int[] { 1, 2 };
+    }
+}
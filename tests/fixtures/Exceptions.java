@@ -0,0 +1,25 @@
+import java.io.IOException;
+
+public class Exceptions implements Runnable, Comparable<Exceptions> {
+    public void read(String path) throws IOException, InterruptedException {
+        if (path.isEmpty()) {
+            throw new IOException("empty path");
+        }
+    }
+
+    public void run() {
+        try {
+            read("file");
+        } catch (IOException e) {
+            System.out.println("io");
+        } catch (InterruptedException e) {
+            Thread.currentThread().interrupt();
+        } finally {
+            System.out.println("done");
+        }
+    }
+
+    public int compareTo(Exceptions other) {
+        return 0;
+    }
+}
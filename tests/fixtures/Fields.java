@@ -0,0 +1,36 @@
+public class Fields {
+    private static Object lock;
+    private static int created;
+
+    private final String name;
+    // only set by the constructor
+    private int id;
+    private int count;
+    private long lastBump;
+    private boolean unused;
+
+    static {
+        lock = new Object();
+    }
+
+    Fields(String name, int id) {
+        this.name = name;
+        this.id = id;
+        created++;
+    }
+
+    void bump() {
+        if (lock != null) {
+            count++;
+        }
+        lastBump = System.nanoTime();
+    }
+
+    int count() {
+        return count + id;
+    }
+
+    void print() {
+        System.out.println(name);
+    }
+}
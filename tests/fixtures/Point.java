@@ -0,0 +1,4 @@
+import java.util.List;
+
+public record Point(int x, String name, List<String> tags) {
+}
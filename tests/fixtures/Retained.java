@@ -0,0 +1,31 @@
+import java.lang.annotation.ElementType;
+import java.lang.annotation.Retention;
+import java.lang.annotation.RetentionPolicy;
+import java.lang.annotation.Target;
+
+// runtime visible and invisible (class retention) annotations on the class, a field, a method and
+// a parameter, and type annotations of both kinds on a field and in code
+@Retained.Visible
+@Retained.Invisible
+public class Retained {
+    @Visible @Invisible @VisibleType @InvisibleType int count;
+
+    @Visible
+    @Invisible
+    Object make(@Visible @Invisible int n) {
+        @VisibleType Object o = new @InvisibleType Object();
+        return n > 0 ? o : null;
+    }
+
+    @Retention(RetentionPolicy.RUNTIME)
+    @interface Visible {}
+
+    @interface Invisible {}
+
+    @Target(ElementType.TYPE_USE)
+    @Retention(RetentionPolicy.RUNTIME)
+    @interface VisibleType {}
+
+    @Target(ElementType.TYPE_USE)
+    @interface InvisibleType {}
+}
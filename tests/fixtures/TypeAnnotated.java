@@ -0,0 +1,28 @@
+import java.lang.annotation.ElementType;
+import java.lang.annotation.Retention;
+import java.lang.annotation.RetentionPolicy;
+import java.lang.annotation.Target;
+import java.util.ArrayList;
+import java.util.List;
+
+// type annotations on a type parameter bound, a supertype, a field, the return type, a parameter
+// and a thrown exception of a method, and in its code on a local variable, a new, a cast and a
+// type argument (Tainted isn't retained at runtime, so it goes in the invisible attributes)
+public class TypeAnnotated<T extends @NonNull Object> implements @NonNull Runnable {
+    @NonNull String name = "type annotated";
+
+    @NonNull List<@Tainted String> names(@NonNull Object o) throws @NonNull IllegalStateException {
+        @NonNull List<@NonNull String> names = new @Tainted ArrayList<>();
+        names.add((@NonNull String) o);
+        return names;
+    }
+
+    public void run() {}
+}
+
+@Target(ElementType.TYPE_USE)
+@Retention(RetentionPolicy.RUNTIME)
+@interface NonNull {}
+
+@Target(ElementType.TYPE_USE)
+@interface Tainted {}
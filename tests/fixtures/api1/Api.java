@@ -0,0 +1,17 @@
+public class Api implements Runnable {
+    public static final int VERSION = 1;
+    protected String name;
+    private int secret;
+
+    public void run() {}
+
+    public void removed() {}
+
+    public int count(String s) {
+        return 0;
+    }
+
+    protected void hook() {}
+
+    void internal() {}
+}
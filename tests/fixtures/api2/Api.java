@@ -0,0 +1,21 @@
+public class Api implements Runnable, Comparable<Api> {
+    public static final int VERSION = 2;
+    protected String name;
+    private long secret;
+
+    public void run() {}
+
+    public long count(String s) {
+        return 0;
+    }
+
+    void hook() {}
+
+    public void internal() {}
+
+    public void added() throws java.io.IOException {}
+
+    public int compareTo(Api o) {
+        return 0;
+    }
+}
@@ -0,0 +1,4 @@
+// module-info.class, the only kind of class with ACC_MODULE
+module fixtures {
+    requires java.logging;
+}
@@ -0,0 +1,118 @@
+// Feeds arbitrary and mutated class files to `javd json` and checks that it never panics or gets
+// killed: every input must either parse or be rejected with an error (exit code 1).
+//
+// Inputs that used to crash are kept in tests/corpus, the ones too big to check in are generated
+// below.
+
+mod common;
+
+use common::{fixture, javd, write_class};
+use proptest::prelude::*;
+use std::{
+    fs,
+    path::Path,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+const FIXTURES: &[&str] = &["Hello", "Constants", "Exceptions", "Point", "Config", "Deps", "Calls", "Fields", "Flow"];
+
+fn assert_no_crash(class: &[u8]) {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+    let path = write_class(&format!("fuzz-{}-{}", std::process::id(), n), class);
+
+    let output = javd(["json".as_ref(), path.as_os_str(), path.with_extension("json").as_os_str()]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        matches!(output.status.code(), Some(0) | Some(1)) && !stderr.contains("panicked"),
+        "javd crashed ({:?}) on {}: {}",
+        output.status,
+        path.display(),
+        stderr
+    );
+
+    fs::remove_file(&path).ok();
+    fs::remove_file(path.with_extension("json")).ok();
+}
+
+fn class_header(pool_count: u16) -> Vec<u8> {
+    let mut b = vec![0xCA, 0xFE, 0xBA, 0xBE, 0, 0, 0, 52];
+    b.extend(pool_count.to_be_bytes());
+    b
+}
+
+#[test]
+fn corpus() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/corpus");
+    for entry in fs::read_dir(dir).unwrap() {
+        assert_no_crash(&fs::read(entry.unwrap().path()).unwrap());
+    }
+}
+
+#[test]
+fn constant_pool_index_overflow() {
+    // a full pool ending with a Long on the last slot, which used to overflow the index counter
+    let mut class = class_header(0xFFFF);
+    for _ in 1..0xFFFE {
+        class.extend([3, 0, 0, 0, 0]);
+    }
+    class.extend([5, 0, 0, 0, 0, 0, 0, 0, 0]);
+    assert_no_crash(&class);
+}
+
+#[test]
+fn nested_code_attributes() {
+    // Code attributes nested inside each other deep enough to blow the stack if resolved
+    // recursively
+    let depth = 100_000u32;
+    let mut class = class_header(4);
+    class.extend([1, 0, 4]);
+    class.extend(b"Code");
+    class.extend([1, 0, 1, b'A', 7, 0, 2]);
+    class.extend([0, 0x21, 0, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+    for level in (0..depth).rev() {
+        // each level is the 6 byte attribute header and a 12 byte Code body around the next one
+        let len = 12 + (6 + 12) * level;
+        class.extend([0, 1]);
+        class.extend(len.to_be_bytes());
+        class.extend([0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        class.extend(if level == 0 { [0, 0] } else { [0, 1] });
+    }
+    assert_no_crash(&class);
+}
+
+fn mutated_fixture() -> impl Strategy<Value = Vec<u8>> {
+    (
+        prop::sample::select(FIXTURES),
+        prop::collection::vec((any::<prop::sample::Index>(), any::<u8>()), 1..8),
+        any::<prop::sample::Index>(),
+    )
+        .prop_map(|(name, edits, cut)| {
+            let mut class = fs::read(fixture(name)).unwrap();
+            for (at, byte) in edits {
+                let at = at.index(class.len());
+                class[at] = byte;
+            }
+            let keep = cut.index(class.len() + 1).max(class.len() / 2);
+            class.truncate(keep);
+            class
+        })
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn arbitrary_bytes(mut class in prop::collection::vec(any::<u8>(), 0..512)) {
+        // most random inputs die on the magic, give them a valid start
+        if class.len() >= 4 {
+            class[..4].copy_from_slice(&[0xCA, 0xFE, 0xBA, 0xBE]);
+        }
+        assert_no_crash(&class);
+    }
+
+    #[test]
+    fn mutated_fixtures(class in mutated_fixture()) {
+        assert_no_crash(&class);
+    }
+}
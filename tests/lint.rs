@@ -0,0 +1,60 @@
+// Code javac doesn't emit, checked with `javd cfg --lint`.
+
+mod common;
+
+use common::{attribute, javd, write_class, ClassBuilder};
+
+// a class A with one static method foo()V made of code, with a java/lang/Throwable handler for
+// each (start, end, handler) of handlers
+fn with_code(code: &[u8], handlers: &[(u16, u16, u16)]) -> Vec<u8> {
+    let mut class = ClassBuilder::new();
+    let (name, descriptor) = (class.utf8("foo"), class.utf8("()V"));
+    let code_name = class.utf8("Code");
+    let throwable = class.class("java/lang/Throwable");
+    let handlers: Vec<_> = handlers.iter().map(|&(s, e, h)| [s, e, h, throwable]).collect();
+    let body = common::code(1, 0, code, &handlers);
+    class.method(0x0009, name, descriptor, &[attribute(code_name, &body)]);
+    class.build()
+}
+
+fn lint(name: &str, class: &[u8]) -> String {
+    let path = write_class(name, class);
+    let output = javd(["cfg".as_ref(), "--lint".as_ref(), path.as_os_str()]);
+    assert!(
+        output.status.success(),
+        "{}: {}",
+        name,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn after_return() {
+    // return; iconst_0; pop; return
+    let out = lint("after_return", &with_code(&[0xb1, 0x03, 0x57, 0xb1], &[]));
+    assert_eq!(out, "A.foo()V: bytes 1..4 unreachable\n");
+}
+
+#[test]
+fn after_throw() {
+    // aconst_null; athrow; goto 0
+    let out = lint("after_throw", &with_code(&[0x01, 0xbf, 0xa7, 0xff, 0xfe], &[]));
+    assert_eq!(out, "A.foo()V: bytes 2..5 unreachable\n");
+}
+
+#[test]
+fn dead_handler() {
+    // return; iconst_0; pop; return; handler: pop; return
+    let code = [0xb1, 0x03, 0x57, 0xb1, 0x57, 0xb1];
+    let out = lint("dead_handler", &with_code(&code, &[(1, 3, 4)]));
+    assert_eq!(
+        out,
+        "A.foo()V: bytes 1..4 unreachable\n\
+         A.foo()V: handler at 4 protects bytes 1..3 which are unreachable\n"
+    );
+
+    // a range with reachable code is fine
+    let out = lint("live_handler", &with_code(&code, &[(0, 1, 4)]));
+    assert_eq!(out, "A.foo()V: bytes 1..4 unreachable\n");
+}
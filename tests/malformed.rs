@@ -0,0 +1,242 @@
+// Crafted class files with bogus length fields. javd must reject them (or keep the bogus part as
+// raw bytes) without trusting the lengths for allocations. Bogus indices must not crash it either.
+
+mod common;
+
+use common::{header, javd, u16be, u32be, utf8, write_class};
+use std::{fs, path::PathBuf, process::Output};
+
+fn javd_json(name: &str, class: &[u8]) -> (Output, PathBuf) {
+    let path = write_class(name, class);
+    let json = path.with_extension("json");
+    (javd(["json".as_ref(), path.as_os_str(), json.as_os_str()]), json)
+}
+
+fn assert_rejected(name: &str, class: &[u8], message: &str) {
+    let (output, _) = javd_json(name, class);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success(), "{} was accepted", name);
+    assert!(stderr.contains(message), "{}: unexpected error {:?}", name, stderr);
+    assert!(!stderr.contains("panicked"), "{}: javd panicked", name);
+}
+
+#[test]
+fn huge_attribute_length() {
+    let mut class = header(&[utf8("X")]);
+    class.extend(u16be(0)); // fields
+    class.extend(u16be(0)); // methods
+    class.extend(u16be(1)); // attributes
+    class.extend(u16be(5));
+    class.extend(u32be(0xFFFF_FFF0));
+    class.extend([0; 8]);
+    assert_rejected("huge_attribute_length", &class, "exceeds the 8 bytes left");
+}
+
+#[test]
+fn huge_utf8_length() {
+    let mut class = header(&[]);
+    // patch in a constant pool entry claiming way more bytes than the file has
+    class.truncate(10);
+    class.extend([1, 0xFF, 0xFF, b'A']);
+    // the string would start at byte 13
+    assert_rejected(
+        "huge_utf8_length",
+        &class,
+        "exceeds the 1 bytes left in the input (at byte 13).",
+    );
+}
+
+#[test]
+fn huge_constant_pool_count() {
+    let mut class = header(&[]);
+    class[8..10].copy_from_slice(&u16be(0xFFFF));
+    class.truncate(10 + 4);
+    let (output, _) = javd_json("huge_constant_pool_count", &class);
+    assert!(!output.status.success());
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("panicked"));
+}
+
+#[test]
+fn zero_constant_pool_count() {
+    let mut class = header(&[]);
+    class[8..10].copy_from_slice(&u16be(0));
+    assert_rejected("zero_constant_pool_count", &class, "Constant pool count is 0");
+}
+
+#[test]
+fn long_on_last_constant_pool_slot() {
+    // slot 5 is the last one, a Long there would also need slot 6
+    let mut class = header(&[vec![5, 0, 0, 0, 0, 0, 0, 0, 1]]);
+    class.extend([0; 8]);
+    assert_rejected(
+        "long_on_last_constant_pool_slot",
+        &class,
+        "entry at index 5 takes two slots but is the last entry (constant pool count is 6)",
+    );
+}
+
+#[test]
+fn truncated_before_super_class() {
+    // the end of the file must be an error, not a class without a superclass
+    let mut class = header(&[]);
+    class.truncate(class.len() - 4);
+    // super_class would start at byte 43
+    assert_rejected(
+        "truncated_before_super_class",
+        &class,
+        "failed to fill whole buffer (at byte 43)",
+    );
+}
+
+#[test]
+fn unknown_constant_tag() {
+    // the error says where the entry is: after the 10 bytes before the pool and the 4 entries of
+    // the header (4, 3, 19 and 3 bytes)
+    let class = header(&[vec![99, 0, 0]]);
+    assert_rejected("unknown_constant_tag", &class, "Unkown tag on ConstantPoolEntry (at byte 39)");
+}
+
+#[test]
+fn huge_interfaces_count() {
+    // 65535 interfaces claimed with only 3 bytes left
+    let mut class = header(&[]);
+    let len = class.len();
+    class[len - 2..].copy_from_slice(&u16be(0xFFFF));
+    class.extend([0, 2, 0]);
+    assert_rejected("huge_interfaces_count", &class, "failed to fill whole buffer");
+}
+
+#[test]
+fn huge_methods_count() {
+    let mut class = header(&[]);
+    class.extend(u16be(0)); // fields
+    class.extend(u16be(0xFFFF)); // methods
+    class.extend([0, 1, 0, 1]);
+    assert_rejected("huge_methods_count", &class, "failed to fill whole buffer");
+}
+
+#[test]
+fn huge_code_length() {
+    // a Code attribute claiming a 4GB body, it can't be resolved and must be kept as raw bytes
+    let mut class = header(&[utf8("m"), utf8("()V"), utf8("Code")]);
+    class.extend(u16be(0)); // fields
+    class.extend(u16be(1)); // methods
+    class.extend(u16be(0x0001));
+    class.extend(u16be(5));
+    class.extend(u16be(6));
+    class.extend(u16be(1)); // attributes
+    class.extend(u16be(7));
+    class.extend(u32be(9));
+    class.extend(u16be(1)); // max_stack
+    class.extend(u16be(1)); // max_locals
+    class.extend(u32be(0xFFFF_FFFF)); // code_length
+    class.push(0xB1); // return
+    class.extend(u16be(0)); // attributes
+
+    let (output, json) = javd_json("huge_code_length", &class);
+    assert!(output.status.success());
+
+    let out = json.with_extension("out.class");
+    assert!(javd(["class".as_ref(), json.as_os_str(), out.as_os_str()]).status.success());
+    assert!(fs::read(out).unwrap() == class);
+}
+
+// a class with a field `x:I` whose ConstantValue attribute has the given body
+fn constant_value_body(body: &[u8]) -> Vec<u8> {
+    let mut class = header(&[utf8("x"), utf8("I"), utf8("ConstantValue"), vec![3, 0, 0, 0, 1]]);
+    class.extend(u16be(1)); // fields
+    class.extend(u16be(0x0018));
+    class.extend(u16be(5));
+    class.extend(u16be(6));
+    class.extend(u16be(1)); // attributes
+    class.extend(u16be(7));
+    class.extend(u32be(body.len() as u32));
+    class.extend(body);
+    class.extend(u16be(0)); // methods
+    class.extend(u16be(0)); // attributes
+    class
+}
+
+// the attribute is kept as raw bytes, and written back as it was
+fn assert_kept_raw(name: &str, class: &[u8]) {
+    let (output, json) = javd_json(name, class);
+    assert!(output.status.success(), "{} was rejected", name);
+    assert!(fs::read_to_string(&json).unwrap().contains("\"Any\""));
+
+    let out = json.with_extension("out.class");
+    assert!(javd(["class".as_ref(), json.as_os_str(), out.as_os_str()]).status.success());
+    assert!(fs::read(out).unwrap() == class);
+}
+
+#[test]
+fn attribute_length_mismatch() {
+    // the body as it should be is resolved
+    let (output, json) = javd_json("constant_value", &constant_value_body(&u16be(8)));
+    assert!(output.status.success());
+    assert!(!fs::read_to_string(&json).unwrap().contains("\"Any\""));
+
+    // bytes left after the index, and an index cut short
+    assert_kept_raw("constant_value_long", &constant_value_body(&[0, 8, 0, 0]));
+    assert_kept_raw("constant_value_short", &constant_value_body(&[8]));
+}
+
+#[test]
+fn self_referencing_constant_print() {
+    // a Class entry whose name is itself
+    let mut class = header(&[vec![7, 0, 5]]);
+    class.extend(u16be(0)); // fields
+    class.extend(u16be(0)); // methods
+    class.extend(u16be(0)); // attributes
+    let path = write_class("self_referencing_constant", &class);
+    let output = javd(["print".as_ref(), path.as_os_str()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("0005: (class (class (class"), "{}", stdout);
+    assert!(stdout.contains("(...)"), "{}", stdout);
+}
+
+#[test]
+fn utf8_too_long_to_write() {
+    // a string edited in the json past the 65535 bytes a Utf8 entry can hold
+    let mut class = header(&[utf8("placeholder")]);
+    class.extend(u16be(0)); // fields
+    class.extend(u16be(0)); // methods
+    class.extend(u16be(0)); // attributes
+    let (output, json) = javd_json("utf8_too_long", &class);
+    assert!(output.status.success());
+    let text = fs::read_to_string(&json).unwrap();
+    assert!(text.contains("\"placeholder\""), "{}", text);
+    fs::write(&json, text.replace("placeholder", &"x".repeat(70_000))).unwrap();
+
+    let out = json.with_extension("out.class");
+    let output = javd(["class".as_ref(), json.as_os_str(), out.as_os_str()]);
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "error: Utf8 entry too long: 70000 bytes encoded don't fit in the u16 length.\n"
+    );
+    assert!(!out.exists());
+}
+
+#[test]
+fn lone_surrogate_utf8() {
+    // "a\uD800b" as javac writes it, the high surrogate D800 without its low half, which a
+    // String can't hold, and a String entry for it
+    let mut class = header(&[vec![1, 0, 5, b'a', 0xED, 0xA0, 0x80, b'b'], vec![8, 0, 5]]);
+    class.extend(u16be(0)); // fields
+    class.extend(u16be(0)); // methods
+    class.extend(u16be(0)); // attributes
+    let (output, json) = javd_json("lone_surrogate_utf8", &class);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let out = json.with_extension("out.class");
+    assert!(javd(["class".as_ref(), json.as_os_str(), out.as_os_str()]).status.success());
+    assert!(fs::read(out).unwrap() == class);
+
+    // shown with a replacement character
+    let path = write_class("lone_surrogate_utf8", &class);
+    let output = javd(["print".as_ref(), path.as_os_str()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("'a\u{FFFD}b'"), "{}", stdout);
+}
@@ -0,0 +1,415 @@
+// Round-trips the checked-in fixture classes (compiled from tests/fixtures/*.java with javac 17)
+// through `javd json` and `javd class` and checks that the bytes come back unchanged.
+//
+// Exact round-tripping holds as long as every part of the class is either fully modeled or kept
+// as raw bytes:
+//   - every constant pool tag in the fixture must be known to the deserializer,
+//   - the access flags must only use bits defined in AccessFlags,
+//   - attributes that fail to resolve are kept as raw bytes and written back as is.
+//
+// Float/Double constants are kept as their bits, so NaNs (whatever their payload) round-trip too.
+//
+// Besides the fixtures, random classes respecting these rules are generated below.
+
+mod common;
+
+use common::{fixture, tmp, write_class};
+use proptest::prelude::*;
+use std::{
+    fs,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+const FIXTURES: &[&str] = &["Hello", "Constants", "Exceptions", "Point", "Config", "Deps", "Calls", "Fields", "Flow", "Annotated", "TypeAnnotated", "Debug", "Retained", "Color"];
+
+fn javd(args: &[&std::ffi::OsStr]) {
+    let output = common::javd(args);
+    assert!(output.status.success(), "javd {:?} failed", args);
+}
+
+// writes class as <name>.class, converts it to json and back, checks that the bytes are the same
+// and returns the json
+fn roundtrip(name: &str, class: &[u8]) -> String {
+    let input = write_class(name, class);
+    let json = input.with_extension("json");
+    let out = input.with_extension("out.class");
+    javd(&["json".as_ref(), input.as_os_str(), json.as_os_str()]);
+    javd(&["class".as_ref(), json.as_os_str(), out.as_os_str()]);
+    assert!(fs::read(&out).unwrap() == class, "{} did not round-trip", name);
+    fs::read_to_string(json).unwrap()
+}
+
+#[test]
+fn fixtures_roundtrip() {
+    for name in FIXTURES {
+        roundtrip(name, &fs::read(fixture(name)).unwrap());
+    }
+}
+
+#[test]
+fn annotation_defaults_are_parsed() {
+    // every element of Config but one has a default value
+    let json = tmp("Config.defaults.json");
+    javd(&["json".as_ref(), fixture("Config").as_os_str(), json.as_os_str()]);
+    let json = fs::read_to_string(json).unwrap();
+    assert_eq!(json.matches("\"AnnotationDefault\": {").count(), 10);
+}
+
+#[test]
+fn type_annotations_are_parsed() {
+    // the ones in the code of names point at its instructions and local variables
+    let json = tmp("TypeAnnotated.targets.json");
+    javd(&["json".as_ref(), fixture("TypeAnnotated").as_os_str(), json.as_os_str()]);
+    let json = fs::read_to_string(json).unwrap();
+    assert_eq!(json.matches("\"RuntimeVisibleTypeAnnotations\": [").count(), 4);
+    assert_eq!(json.matches("\"RuntimeInvisibleTypeAnnotations\": [").count(), 2);
+    for target in ["\"New\": ", "\"Cast\": {", "\"LocalVariable\": [", "\"Supertype\": "] {
+        assert!(json.contains(target), "no {} target", target);
+    }
+}
+
+#[test]
+fn object_like_class_roundtrip() {
+    // java/lang/Object is the only class with super_class 0
+    let mut class = vec![0xCA, 0xFE, 0xBA, 0xBE, 0, 0, 0, 52, 0, 3];
+    class.extend([1, 0, 16]);
+    class.extend(b"java/lang/Object");
+    class.extend([7, 0, 1]);
+    class.extend([0, 0x21, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+    let json = roundtrip("Object", &class);
+    assert!(json.contains("\"super_class\": null"));
+}
+
+#[test]
+fn attribute_order_roundtrip() {
+    // attributes are written back in the order they were read, duplicates included
+    let mut class = vec![0xCA, 0xFE, 0xBA, 0xBE, 0, 0, 0, 52, 0, 6];
+    class.extend([1, 0, 16]);
+    class.extend(b"java/lang/Object");
+    class.extend([7, 0, 1]);
+    class.extend([1, 0, 10]);
+    class.extend(b"SourceFile");
+    class.extend([1, 0, 6]);
+    class.extend(b"A.java");
+    class.extend([1, 0, 10]);
+    class.extend(b"Deprecated");
+    class.extend([0, 0x21, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 3]);
+    class.extend([0, 3, 0, 0, 0, 2, 0, 4]);
+    class.extend([0, 5, 0, 0, 0, 0]);
+    class.extend([0, 3, 0, 0, 0, 2, 0, 4]);
+
+    roundtrip("attribute-order", &class);
+}
+
+#[test]
+fn module_info_roundtrip() {
+    // the ACC_MODULE flag, and the Module and Package constants module-info classes refer to
+    let mut class = vec![0xCA, 0xFE, 0xBA, 0xBE, 0, 0, 0, 53, 0, 6];
+    class.extend([1, 0, 11]);
+    class.extend(b"module-info");
+    class.extend([7, 0, 1]);
+    class.extend([1, 0, 4]);
+    class.extend(b"demo");
+    class.extend([19, 0, 3]);
+    class.extend([20, 0, 3]);
+    class.extend([0x80, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+    let json = roundtrip("module-info", &class);
+    assert!(json.contains("\"Module\": {"), "{}", json);
+    assert!(json.contains("\"Package\": {"), "{}", json);
+}
+
+#[test]
+fn nan_constants_roundtrip() {
+    // what Float.intBitsToFloat and Double.longBitsToDouble can make: NaNs with a payload, next
+    // to the NaNs of Float.NaN and Double.NaN and a negative zero
+    let mut class = vec![0xCA, 0xFE, 0xBA, 0xBE, 0, 0, 0, 52, 0, 12];
+    class.extend([1, 0, 1, b'A', 7, 0, 1]);
+    class.extend([1, 0, 16]);
+    class.extend(b"java/lang/Object");
+    class.extend([7, 0, 3]);
+    class.extend([4, 0x7f, 0xc0, 0, 1]);
+    class.extend([4, 0x7f, 0xc0, 0, 0]);
+    class.extend([6, 0x7f, 0xf8, 0, 0, 0, 0, 0, 1]);
+    class.extend([6, 0x7f, 0xf8, 0, 0, 0, 0, 0, 0]);
+    class.extend([4, 0x80, 0, 0, 0]);
+    class.extend([0, 0x21, 0, 2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+    roundtrip("NaN", &class);
+
+    let output = common::javd(["print".as_ref(), tmp("NaN.class").as_os_str()]);
+    let printed = String::from_utf8(output.stdout).unwrap();
+    for line in [
+        "      0005: (float NaN(0x7fc00001))",
+        "      0006: (float NaN)",
+        "      0007: (double NaN(0x7ff8000000000001))",
+        "      0009: (double NaN)",
+        "      0011: (float -0)",
+    ] {
+        assert!(printed.lines().any(|l| l == line), "missing {:?} in\n{}", line, printed);
+    }
+}
+
+#[test]
+fn wrong_kind_index_roundtrip() {
+    // this_class points at a Class whose name is an Integer, the kinds are only checked when
+    // resolving indices so the class can still be converted
+    let mut class = vec![0xCA, 0xFE, 0xBA, 0xBE, 0, 0, 0, 52, 0, 3];
+    class.extend([3, 0, 0, 0, 42]);
+    class.extend([7, 0, 1]);
+    class.extend([0, 0x21, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+    roundtrip("WrongKind", &class);
+}
+
+#[test]
+fn dynamic_constant_roundtrip() {
+    // #6 is a Dynamic constant (tag 17) named x of type I, made by bootstrap method 0
+    let mut class = vec![0xCA, 0xFE, 0xBA, 0xBE, 0, 0, 0, 55, 0, 7];
+    class.extend([1, 0, 16]);
+    class.extend(b"java/lang/Object");
+    class.extend([7, 0, 1]);
+    class.extend([1, 0, 1, b'x']);
+    class.extend([1, 0, 1, b'I']);
+    class.extend([12, 0, 3, 0, 4]);
+    class.extend([17, 0, 0, 0, 5]);
+    class.extend([0, 0x21, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+    let json = roundtrip("Dynamic", &class);
+    assert!(json.contains("\"Dynamic\": {"));
+}
+
+// the name index and body of a nested attribute
+type Nested<'a> = (u8, &'a [u8]);
+
+// a class with a static method m()V whose Code attribute holds the nested attributes given by
+// name index and body: #8 is SourceDebugExtension, #9 ConstantValue, #10 LineNumberTable and #11
+// an unknown attribute
+fn code_with_nested(nested: &[Nested]) -> Vec<u8> {
+    let mut class = vec![0xCA, 0xFE, 0xBA, 0xBE, 0, 0, 0, 52, 0, 12];
+    for (i, name) in [
+        "java/lang/Object",
+        "",
+        "A",
+        "",
+        "m",
+        "()V",
+        "Code",
+        "SourceDebugExtension",
+        "ConstantValue",
+        "LineNumberTable",
+        "Foo",
+    ]
+    .iter()
+    .enumerate()
+    {
+        if name.is_empty() {
+            // the Class entry of the name before it
+            class.extend([7, 0, i as u8]);
+        } else {
+            class.extend([1, 0, name.len() as u8]);
+            class.extend(name.as_bytes());
+        }
+    }
+    class.extend([0, 0x21, 0, 4, 0, 2, 0, 0, 0, 0]);
+
+    let mut code = vec![0, 0, 0, 0, 0, 0, 0, 1, 0xB1, 0, 0, 0, nested.len() as u8];
+    for (name, body) in nested {
+        code.extend([0, *name]);
+        code.extend((body.len() as u32).to_be_bytes());
+        code.extend(*body);
+    }
+    class.extend([0, 1, 0, 0x09, 0, 5, 0, 6, 0, 1, 0, 7]);
+    class.extend((code.len() as u32).to_be_bytes());
+    class.extend(code);
+    class.extend([0, 0]);
+    class
+}
+
+#[test]
+fn code_nested_attributes_roundtrip() {
+    let source_debug: Nested = (8, b"abc");
+    let constant_value: Nested = (9, &[0, 3]);
+    let line_numbers: Nested = (10, &[0, 1, 0, 0, 0, 1]);
+    let unknown: Nested = (11, &[1, 2, 3]);
+    // a byte too long, so it stays raw
+    let bad_constant_value: Nested = (9, &[0, 3, 0]);
+
+    // the nested attributes, and how many of them stay raw
+    let cases: &[(&str, &[Nested], usize)] = &[
+        ("none", &[], 0),
+        ("resolved", &[source_debug, constant_value], 0),
+        ("raw", &[line_numbers, unknown, bad_constant_value], 3),
+        (
+            "mixed",
+            &[line_numbers, source_debug, bad_constant_value, constant_value, unknown],
+            3,
+        ),
+    ];
+    for (name, nested, raw) in cases {
+        let class = code_with_nested(nested);
+        let json = roundtrip(&format!("Nested.{}", name), &class);
+        assert!(json.contains("\"Code\": {"), "{}: Code wasn't resolved", name);
+        assert_eq!(json.matches("\"Any\"").count(), *raw, "{}", name);
+    }
+}
+
+// a constant of a generated class, along with a String entry for the Utf8 ones
+#[derive(Debug, Clone)]
+enum Constant {
+    Utf8(String),
+    Integer(i32),
+    // the bits of the values
+    Float(u32),
+    Long(i64),
+    Double(u64),
+}
+
+fn constant() -> impl Strategy<Value = Constant> {
+    prop_oneof![
+        any::<String>().prop_map(Constant::Utf8),
+        any::<i32>().prop_map(Constant::Integer),
+        any::<u32>().prop_map(Constant::Float),
+        any::<i64>().prop_map(Constant::Long),
+        any::<u64>().prop_map(Constant::Double),
+    ]
+}
+
+// a field or method: access flags (only the defined bits) and the bodies of its attributes
+fn member() -> impl Strategy<Value = (u16, Vec<Vec<u8>>)> {
+    (
+        any::<u16>().prop_map(|f| f & 0x7FFF),
+        prop::collection::vec(prop::collection::vec(any::<u8>(), 0..16), 0..3),
+    )
+}
+
+// the modified UTF-8 of the class file: NUL takes two bytes and supplementary characters are
+// written as two surrogates
+fn modified_utf8(s: &str) -> Vec<u8> {
+    let mut out = Vec::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\0' => out.extend([0xC0, 0x80]),
+            c if (c as u32) < 0x10000 => out.extend(c.to_string().as_bytes()),
+            c => {
+                let mut units = [0u16; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    let unit = *unit as u32;
+                    out.extend([
+                        0xE0 | (unit >> 12) as u8,
+                        0x80 | ((unit >> 6) & 0x3F) as u8,
+                        0x80 | (unit & 0x3F) as u8,
+                    ]);
+                }
+            }
+        }
+    }
+    out
+}
+
+fn utf8_entry(class: &mut Vec<u8>, s: &str) {
+    let bytes = modified_utf8(s);
+    class.push(1);
+    class.extend((bytes.len() as u16).to_be_bytes());
+    class.extend(bytes);
+}
+
+fn members(class: &mut Vec<u8>, members: &[(u16, Vec<Vec<u8>>)]) {
+    class.extend((members.len() as u16).to_be_bytes());
+    for (flags, attributes) in members {
+        // named Gen with the descriptor Blob, which are never resolved
+        class.extend(flags.to_be_bytes());
+        class.extend([0, 2, 0, 1]);
+        class.extend((attributes.len() as u16).to_be_bytes());
+        for body in attributes {
+            // an attribute named Blob, which is kept as raw bytes
+            class.extend([0, 1]);
+            class.extend((body.len() as u32).to_be_bytes());
+            class.extend(body);
+        }
+    }
+}
+
+fn class() -> impl Strategy<Value = Vec<u8>> {
+    (
+        any::<(u16, u16)>(),
+        prop::collection::vec(constant(), 0..32),
+        prop::collection::vec(member(), 0..4),
+        prop::collection::vec(member(), 0..4),
+    )
+        .prop_map(|((minor, major), constants, fields, methods)| {
+            // Blob, Gen, the Gen class and its java/lang/Object super class come first
+            let mut pool = Vec::new();
+            utf8_entry(&mut pool, "Blob");
+            utf8_entry(&mut pool, "Gen");
+            pool.extend([7, 0, 2]);
+            utf8_entry(&mut pool, "java/lang/Object");
+            pool.extend([7, 0, 4]);
+            let mut count = 6u16;
+            for c in constants.iter() {
+                match c {
+                    Constant::Utf8(s) => {
+                        utf8_entry(&mut pool, s);
+                        pool.push(8);
+                        pool.extend(count.to_be_bytes());
+                        count += 2;
+                    }
+                    Constant::Integer(i) => {
+                        pool.push(3);
+                        pool.extend(i.to_be_bytes());
+                        count += 1;
+                    }
+                    Constant::Float(f) => {
+                        pool.push(4);
+                        pool.extend(f.to_be_bytes());
+                        count += 1;
+                    }
+                    Constant::Long(l) => {
+                        pool.push(5);
+                        pool.extend(l.to_be_bytes());
+                        count += 2;
+                    }
+                    Constant::Double(d) => {
+                        pool.push(6);
+                        pool.extend(d.to_be_bytes());
+                        count += 2;
+                    }
+                }
+            }
+
+            let mut class = vec![0xCA, 0xFE, 0xBA, 0xBE];
+            class.extend(minor.to_be_bytes());
+            class.extend(major.to_be_bytes());
+            class.extend(count.to_be_bytes());
+            class.extend(pool);
+            // public super, this_class, super_class, no interfaces
+            class.extend([0, 0x21, 0, 3, 0, 5, 0, 0]);
+            members(&mut class, &fields);
+            members(&mut class, &methods);
+            class.extend([0, 0]);
+            class
+        })
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn generated_roundtrip(class in class()) {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let input = tmp(&format!("gen-{}-{}.class", std::process::id(), n));
+        let json = input.with_extension("json");
+        let out = input.with_extension("out.class");
+        fs::write(&input, &class).unwrap();
+
+        javd(&["json".as_ref(), input.as_os_str(), json.as_os_str()]);
+        javd(&["class".as_ref(), json.as_os_str(), out.as_os_str()]);
+        prop_assert!(fs::read(&out).unwrap() == class, "{} did not round-trip", input.display());
+
+        for path in [&input, &json, &out] {
+            fs::remove_file(path).ok();
+        }
+    }
+}
@@ -0,0 +1,89 @@
+// Output of `javd print` on the fixture classes, checked against tests/snapshots/<fixture>.txt, and
+// <fixture>.<style>-flags.txt for the other --flags styles (the bytes written back are checked by
+// roundtrip.rs). After a change to the printer, run the tests with JAVD_UPDATE_SNAPSHOTS=1 to write
+// the snapshots again and review their diff.
+
+mod common;
+
+use common::{fixture, javd};
+use std::{ffi::OsStr, fs, path::PathBuf};
+
+const FIXTURES: &[&str] = &[
+    "Annotated",
+    "Calls",
+    "Color",
+    "Config",
+    "Constants",
+    "Debug",
+    "Deps",
+    "Exceptions",
+    "Fields",
+    "Flow",
+    "Hello",
+    "Point",
+    "Retained",
+    "TypeAnnotated",
+];
+
+fn print(name: &str, args: &[&str]) -> String {
+    let mut command: Vec<&OsStr> = vec!["print".as_ref()];
+    command.extend(args.iter().map(OsStr::new));
+    let path = fixture(name);
+    command.push(path.as_os_str());
+    let output = javd(command);
+    assert!(
+        output.status.success(),
+        "javd print {} failed: {}",
+        name,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+// checks print with args on each fixture against tests/snapshots/<fixture><suffix>.txt
+fn check_snapshots(fixtures: &[&str], args: &[&str], suffix: &str) {
+    let update = std::env::var_os("JAVD_UPDATE_SNAPSHOTS").is_some();
+    let mut changed = Vec::new();
+    for name in fixtures {
+        let out = print(name, args);
+        let snapshot = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/snapshots")
+            .join(format!("{}{}.txt", name, suffix));
+        if update {
+            fs::write(&snapshot, &out).unwrap();
+            continue;
+        }
+        let expected = fs::read_to_string(&snapshot)
+            .unwrap_or_else(|_| panic!("missing {}, see the top of this file", snapshot.display()));
+        if out != expected {
+            let line = out
+                .lines()
+                .zip(expected.lines())
+                .position(|(a, b)| a != b)
+                .unwrap_or(out.lines().count().min(expected.lines().count()));
+            changed.push(format!("{}{} (first difference on line {})", name, suffix, line + 1));
+        }
+    }
+    assert!(
+        changed.is_empty(),
+        "the output changed for {}, see the top of this file",
+        changed.join(", ")
+    );
+}
+
+#[test]
+fn print_snapshots() {
+    check_snapshots(FIXTURES, &[], "");
+}
+
+#[test]
+fn print_flag_styles() {
+    // classes, fields and methods with and without flags
+    let fixtures = ["Fields", "Config", "Color"];
+    check_snapshots(&fixtures, &["--flags", "java"], ".java-flags");
+    check_snapshots(&fixtures, &["--flags", "hex"], ".hex-flags");
+    // debug is the default
+    for name in fixtures {
+        assert_eq!(print(name, &["--flags", "debug"]), print(name, &[]));
+    }
+}
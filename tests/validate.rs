@@ -0,0 +1,247 @@
+// Classes that read fine but break the rules `javd validate` checks.
+
+mod common;
+
+use common::{attribute, fixture, javd, u16be, utf8, write_class, ClassBuilder};
+use std::{fs, process::Output};
+
+// a class A with one static final field x of type descriptor, whose ConstantValue points at the
+// constant entry #8 (a String entry points at the Utf8 right after it)
+fn constant_field(descriptor: &str, constant: &[u8]) -> Vec<u8> {
+    let mut class = ClassBuilder::new();
+    let name = class.utf8("x");
+    let descriptor = class.utf8(descriptor);
+    let constant_value = class.utf8("ConstantValue");
+    let value = class.constant(constant);
+    class.utf8("s");
+    // static final
+    class.field(0x0018, name, descriptor, &[attribute(constant_value, &u16be(value))]);
+    class.build()
+}
+
+fn javd_validate(name: &str, class: &[u8], args: &[&str]) -> Output {
+    let path = write_class(name, class);
+    javd([&["validate", path.to_str().unwrap()], args].concat())
+}
+
+#[test]
+fn constant_value_types() {
+    let integer = [3, 0, 0, 0, 42];
+    let float = [4, 0x3f, 0xc0, 0, 0];
+    let long = [5, 0, 0, 0, 0, 0, 0, 0, 42];
+    let double = [6, 0x40, 0x02, 0, 0, 0, 0, 0, 0];
+    let string = [8, 0, 9];
+    for (descriptor, constant) in [
+        ("I", &integer[..]),
+        ("S", &integer),
+        ("C", &integer),
+        ("B", &integer),
+        ("Z", &integer),
+        ("F", &float),
+        ("J", &long),
+        ("D", &double),
+        ("Ljava/lang/String;", &string),
+    ] {
+        let output = javd_validate("valid", &constant_field(descriptor, constant), &[]);
+        assert!(
+            output.status.success(),
+            "{} rejected: {}",
+            descriptor,
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+}
+
+#[test]
+fn constant_value_mismatch() {
+    let output = javd_validate(
+        "mismatch",
+        &constant_field("I", &[4, 0x3f, 0xc0, 0, 0]),
+        &[],
+    );
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "error: The ConstantValue of field x (I) is #8 of kind Float instead of Integer.\n"
+    );
+}
+
+#[test]
+fn duplicate_constants() {
+    let mut class = Vec::new();
+    class.extend(0xCAFEBABEu32.to_be_bytes());
+    class.extend(u16be(0));
+    class.extend(u16be(52));
+    class.extend(u16be(9));
+    class.extend(utf8("A"));
+    class.extend([7, 0, 1]);
+    class.extend(utf8("java/lang/Object"));
+    class.extend([7, 0, 3]);
+    class.extend([3, 0, 0, 0, 42]);
+    class.extend(utf8("A"));
+    class.extend([3, 0, 0, 0, 42]);
+    class.extend([3, 0, 0, 0, 42]);
+    class.extend(u16be(0x0021)); // access flags
+    class.extend(u16be(2)); // this_class
+    class.extend(u16be(4)); // super_class
+    class.extend([0; 8]); // interfaces, fields, methods, attributes
+
+    // legal, so only rejected in strict mode
+    assert!(javd_validate("duplicates", &class, &[]).status.success());
+    let output = javd_validate("duplicates", &class, &["--strict"]);
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "duplicate constants: #1, #6\nduplicate constants: #5, #7, #8\n"
+    );
+}
+
+fn point(major: u16, minor: u16) -> Vec<u8> {
+    let mut class = fs::read(fixture("Point")).unwrap();
+    class[4..6].copy_from_slice(&u16be(minor));
+    class[6..8].copy_from_slice(&u16be(major));
+    class
+}
+
+#[test]
+fn preview_records() {
+    // records were a preview of Java 14 (58) and 15 (59)
+    let output = javd_validate("preview_record", &point(59, 0), &[]);
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "error: The class has a Record attribute, which needs --enable-preview (minor version \
+         65535) at version 59.\n"
+    );
+    assert!(javd_validate("preview_record", &point(59, 0xFFFF), &[]).status.success());
+    assert!(javd_validate("preview_record", &point(60, 0), &[]).status.success());
+
+    let output = javd_validate("preview_minor", &point(61, 3), &[]);
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "error: Minor version 3 isn't allowed with major version 61, only 0 or 65535.\n"
+    );
+}
+
+#[test]
+fn unknown_access_flags() {
+    let mut class = constant_field("I", &[3, 0, 0, 0, 42]);
+    // the field's flags, followed by 18 bytes of field and class
+    let at = class.len() - 20;
+    class[at..at + 2].copy_from_slice(&u16be(0x8018));
+
+    // still read, so only rejected in strict mode
+    assert!(javd_validate("unknown_flags", &class, &[]).status.success());
+    let output = javd_validate("unknown_flags", &class, &["--strict"]);
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "unknown access flags: 0x8000 on field x\n"
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "error: 1 sets of unknown access flags.\n"
+    );
+}
+
+#[test]
+fn method_handle_kinds() {
+    let mut class = fs::read(fixture("Calls")).unwrap();
+    assert!(javd_validate("handle_kinds", &class, &[]).status.success());
+
+    // the first MethodHandle, the invokestatic of the bootstrap method, turned into an
+    // invokeinterface of its Methodref
+    let at = class.windows(2).position(|w| w == [15, 6]).unwrap();
+    class[at + 1] = 9;
+    let output = javd_validate("handle_kinds", &class, &[]);
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "error: MethodHandle #54 of kind InvokeInterface points at #55 of kind Methodref instead \
+         of InterfaceMethodref.\n"
+    );
+}
+
+#[test]
+fn duplicate_attributes() {
+    let mut class = constant_field("I", &[3, 0, 0, 0, 42]);
+    // the field's ConstantValue, followed by the method and attribute counts, written twice
+    let end = class.len() - 4;
+    let attribute = class[end - 8..end].to_vec();
+    class.splice(end..end, attribute);
+    class[end - 10..end - 8].copy_from_slice(&u16be(2));
+
+    let output = javd_validate("duplicate_attributes", &class, &[]);
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "error: field x has 2 ConstantValue attributes.\n"
+    );
+}
+
+#[test]
+fn abstract_method_code() {
+    let mut class = fs::read(fixture("Calls")).unwrap();
+    // the flags of the constructor, turned from public to public abstract
+    let at = class.windows(6).position(|w| w == [0, 1, 0, 5, 0, 6]).unwrap();
+    class[at] = 0x04;
+    let output = javd_validate("abstract_code", &class, &[]);
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "error: abstract method <init>()V has a Code attribute.\n"
+    );
+}
+
+#[test]
+fn fixture_frame_limits() {
+    // javac's max_stack and max_locals are exactly what the code needs, so recomputing them
+    // gives them back
+    let dir = fixture("Hello").parent().unwrap().to_owned();
+    let mut count = 0;
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension() != Some("class".as_ref()) {
+            continue;
+        }
+        let output = javd(["validate", "--strict", path.to_str().unwrap()]);
+        assert!(
+            output.status.success(),
+            "{}: {}{}",
+            path.display(),
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        count += 1;
+    }
+    assert!(count >= 11);
+}
+
+#[test]
+fn stale_frame_limits() {
+    let json = common::tmp("stale_limits.json");
+    let class = common::tmp("stale_limits.class");
+    let hello = fixture("Hello");
+    assert!(javd(["json".as_ref(), hello.as_os_str(), json.as_os_str()]).status.success());
+    // main's max_stack, raised from 2
+    let text = fs::read_to_string(&json).unwrap();
+    assert!(text.contains("\"max_stack\": 2"), "{}", text);
+    fs::write(&json, text.replace("\"max_stack\": 2", "\"max_stack\": 5")).unwrap();
+    assert!(javd(["class".as_ref(), json.as_os_str(), class.as_os_str()]).status.success());
+
+    // too large is legal, so only rejected in strict mode
+    assert!(javd(["validate".as_ref(), class.as_os_str()]).status.success());
+    let output = javd(["validate".as_ref(), "--strict".as_ref(), class.as_os_str()]);
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "max_stack 5 and max_locals 1 of method main([Ljava/lang/String;)V, the code needs 2 \
+         and 1\n"
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "error: 1 methods with stale frame limits.\n"
+    );
+}
+